@@ -341,6 +341,7 @@ impl<'a> Token<'a> {
             | SyntaxKind::AT_KW
             | SyntaxKind::BASE64_KW
             | SyntaxKind::BASE64WIDE_KW
+            | SyntaxKind::CASE_KW
             | SyntaxKind::CONDITION_KW
             | SyntaxKind::CONTAINS_KW
             | SyntaxKind::DEFINED_KW
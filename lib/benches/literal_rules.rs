@@ -0,0 +1,72 @@
+//! Measures the scanning speed of rulesets where every rule is a list of
+//! literal indicators of compromise, like `$a or $b`.
+//!
+//! The conditions of these rules are evaluated directly from the results
+//! of the pattern search, without executing WASM code. The same ruleset
+//! with a single additional rule that needs WASM is scanned too, for
+//! comparison.
+//!
+//! Run with `cargo bench -p yara-x --bench literal_rules`.
+
+use std::fmt::Write;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const NUM_RULES: usize = 50_000;
+const DATA_SIZE: usize = 1 << 20;
+const ITERATIONS: u32 = 50;
+
+fn main() {
+    let mut source = String::new();
+
+    for i in 0..NUM_RULES {
+        writeln!(
+            source,
+            r#"rule ioc_{i} {{
+  strings:
+    $a = "ioc-{i:08}.example.com"
+    $b = "{i:016x}"
+  condition:
+    $a or $b
+}}"#,
+        )
+        .unwrap();
+    }
+
+    // Data with a few of the indicators, surrounded by bytes that don't
+    // match any of them.
+    let mut data = vec![b'.'; DATA_SIZE];
+    for i in (0..NUM_RULES).step_by(5_000) {
+        let ioc = format!("ioc-{i:08}.example.com");
+        let offset = i * (DATA_SIZE - ioc.len()) / NUM_RULES;
+        data[offset..offset + ioc.len()].copy_from_slice(ioc.as_bytes());
+    }
+
+    let start = Instant::now();
+    let literal_rules = yara_x::compile(source.as_str()).unwrap();
+    println!("compiled {NUM_RULES} rules in {:?}", start.elapsed());
+
+    source.push_str("rule wasm { condition: filesize == 0 }\n");
+    let wasm_rules = yara_x::compile(source.as_str()).unwrap();
+
+    let literal = bench(&literal_rules, &data);
+    let wasm = bench(&wasm_rules, &data);
+
+    println!("without WASM: {:?} per scan", literal);
+    println!("with WASM:    {:?} per scan", wasm);
+}
+
+/// Returns the average time needed for scanning `data` with `rules`.
+fn bench(rules: &yara_x::Rules, data: &[u8]) -> Duration {
+    let mut scanner = yara_x::Scanner::new(rules);
+
+    // The first scan is not measured, it warms up the caches.
+    let matching = scanner.scan(data).unwrap().matching_rules().len();
+    assert_eq!(matching, NUM_RULES / 5_000);
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(scanner.scan(data).unwrap().matching_rules().len());
+    }
+    start.elapsed() / ITERATIONS
+}
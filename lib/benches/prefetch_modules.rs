@@ -0,0 +1,104 @@
+//! Measures the time needed for scanning the PE files in the test data of
+//! the `pe` module, with and without prefetching the module's data (see
+//! `Scanner::prefetch_modules`).
+//!
+//! The rules import the `pe` module and contain enough patterns for the
+//! search to take a significant part of the scan time. Prefetching can
+//! reduce the time only if there are at least two CPU cores, with a single
+//! core it just adds the cost of starting a thread per scan.
+//!
+//! Run with `cargo bench -p yara-x --bench prefetch_modules`.
+
+use std::fmt::Write;
+use std::fs::{self, File};
+use std::hint::black_box;
+use std::io::Read;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const NUM_RULES: usize = 5_000;
+const ITERATIONS: u32 = 10;
+
+fn main() {
+    let mut source = String::from("import \"pe\"\n");
+
+    for i in 0..NUM_RULES {
+        writeln!(
+            source,
+            r#"rule rule_{i} {{
+  strings:
+    $a = "pattern-{i:08}"
+    $b = /{i:06}[a-z]{{4,16}}\.dll/
+  condition:
+    pe.number_of_sections > 0 and ($a or $b)
+}}"#,
+        )
+        .unwrap();
+    }
+
+    let rules = yara_x::compile(source.as_str()).unwrap();
+
+    let testdata = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src/modules/pe/tests/testdata");
+
+    let mut paths: Vec<_> = fs::read_dir(testdata)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.to_string_lossy().ends_with(".in.zip"))
+        .collect();
+
+    paths.sort();
+
+    let files: Vec<_> =
+        paths.iter().map(|path| read_zipped_ihex(path)).collect();
+    let total_size: usize = files.iter().map(|data| data.len()).sum();
+
+    println!("scanning {} files, {} bytes in total", files.len(), total_size);
+
+    let sequential = bench(&rules, &files, false);
+    let prefetched = bench(&rules, &files, true);
+
+    println!("without prefetching: {:?} per iteration", sequential);
+    println!("with prefetching:    {:?} per iteration", prefetched);
+}
+
+/// Returns the average time needed for scanning all the `files` with
+/// `rules`.
+fn bench(
+    rules: &yara_x::Rules,
+    files: &[Vec<u8>],
+    prefetch: bool,
+) -> Duration {
+    let mut scanner = yara_x::Scanner::new(rules);
+    scanner.prefetch_modules(prefetch);
+
+    // The first iteration is not measured, it warms up the caches.
+    for data in files {
+        black_box(scanner.scan(data).unwrap().matching_rules().len());
+    }
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        for data in files {
+            black_box(scanner.scan(data).unwrap().matching_rules().len());
+        }
+    }
+    start.elapsed() / ITERATIONS
+}
+
+/// Returns the content of a file in the test data, which are ZIP archives
+/// containing a single file in Intel HEX format.
+fn read_zipped_ihex(path: &Path) -> Vec<u8> {
+    let mut zip = zip::ZipArchive::new(File::open(path).unwrap()).unwrap();
+    let mut ihex = String::new();
+
+    zip.by_index(0).unwrap().read_to_string(&mut ihex).unwrap();
+
+    let mut data = Vec::new();
+    for record in ihex::Reader::new(&ihex) {
+        if let ihex::Record::Data { value, .. } = record.unwrap() {
+            data.extend(value);
+        }
+    }
+    data
+}
@@ -41,13 +41,25 @@ pub(in crate::compiler) struct CompileContext<'a, 'src, 'sym> {
     /// Allow invalid escape sequences in regular expressions.
     pub relaxed_re_syntax: bool,
 
+    /// If true, regexp patterns are checked for redundant alternatives.
+    pub detect_redundant_alternations: bool,
+
     /// If true, a slow loop produces an error instead of a warning. A slow
     /// rule is one where the upper bound of the loop is potentially large.
     /// Like for example: `for all x in (0..filesize) : (...)`
     pub error_on_slow_loop: bool,
 
+    /// If true, text patterns are case-insensitive unless they use the
+    /// `case` modifier.
+    pub nocase_by_default: bool,
+
     /// Indicates how deep we are inside `for .. of` statements.
     pub(crate) for_of_depth: usize,
+
+    /// Identifiers in the root structure that were referenced by the
+    /// current rule's condition. These are either global variables or
+    /// modules.
+    pub root_idents: &'a mut Vec<String>,
 }
 
 impl<'a, 'src, 'sym> CompileContext<'a, 'src, 'sym> {
@@ -279,6 +279,45 @@ pub(super) fn emit_rule_condition(
     builder.finish_rule();
 }
 
+/// Like [`emit_rule_condition`], but instead of casting the condition to
+/// bool, its value is passed to the scanner, which returns it from
+/// [`crate::Scanner::eval_condition`].
+///
+/// The rule matches only if the condition's value is defined.
+pub(super) fn emit_eval_condition(
+    ctx: &mut EmitContext,
+    builder: &mut WasmModuleBuilder,
+    rule_id: RuleId,
+    condition: &mut Expr,
+) {
+    let mut instr = builder.start_rule(rule_id, ctx.current_rule.is_global);
+
+    catch_undef(
+        ctx,
+        I32,
+        &mut instr,
+        |ctx, instr| {
+            emit_expr(ctx, instr, condition);
+            let eval_result_fn = match condition.ty() {
+                Type::Bool => wasm::export__eval_result_bool.mangled_name,
+                Type::Integer => {
+                    wasm::export__eval_result_integer.mangled_name
+                }
+                Type::Float => wasm::export__eval_result_float.mangled_name,
+                Type::String => wasm::export__eval_result_string.mangled_name,
+                ty => unreachable!("type `{:?}` can't be evaluated", ty),
+            };
+            instr.call(ctx.function_id(eval_result_fn));
+            instr.i32_const(1);
+        },
+        |_, instr| {
+            instr.i32_const(0);
+        },
+    );
+
+    builder.finish_rule();
+}
+
 /// Emits WASM code for `expr` into the instruction sequence `instr`.
 fn emit_expr(
     ctx: &mut EmitContext,
@@ -316,6 +355,24 @@ fn emit_expr(
             instr.global_get(ctx.wasm_symbols.filesize);
         }
 
+        Expr::Entrypoint => {
+            emit_call_and_handle_undef(
+                ctx,
+                instr,
+                ctx.function_id(wasm::export__entrypoint.mangled_name),
+            );
+        }
+
+        Expr::DataSlice { offset, length } => {
+            emit_expr(ctx, instr, offset);
+            emit_expr(ctx, instr, length);
+            emit_call_and_handle_undef(
+                ctx,
+                instr,
+                ctx.function_id(wasm::export__data_slice.mangled_name),
+            );
+        }
+
         Expr::Ident { symbol } => {
             match symbol.kind() {
                 SymbolKind::Rule(rule_id) => {
@@ -1405,6 +1462,18 @@ fn emit_of_pattern_set(
     let mut patterns = patterns.iter().cloned();
     let next_pattern_id = of.stack_frame.new_var(Type::Integer);
 
+    // Variables that hold the anchor's offset, or the bounds of its range.
+    let (lower_bound, upper_bound) = match &of.anchor {
+        MatchAnchor::None => (None, None),
+        MatchAnchor::At(_) => {
+            (Some(of.stack_frame.new_var(Type::Integer)), None)
+        }
+        MatchAnchor::In(_) => (
+            Some(of.stack_frame.new_var(Type::Integer)),
+            Some(of.stack_frame.new_var(Type::Integer)),
+        ),
+    };
+
     // Make sure the pattern search phase is executed, as the `of` statement
     // depends on patterns.
     emit_lazy_pattern_search(ctx, instr);
@@ -1419,6 +1488,25 @@ fn emit_of_pattern_set(
             set_var(ctx, instr, n, |_, instr| {
                 instr.i64_const(num_patterns as i64);
             });
+            // The anchor is evaluated once, before the loop starts. If it is
+            // undefined the whole `of` expression is undefined, instead of
+            // being false for every pattern.
+            match &mut of.anchor {
+                MatchAnchor::None => {}
+                MatchAnchor::At(offset) => {
+                    set_var(ctx, instr, lower_bound.unwrap(), |ctx, instr| {
+                        emit_expr(ctx, instr, offset);
+                    });
+                }
+                MatchAnchor::In(range) => {
+                    set_var(ctx, instr, lower_bound.unwrap(), |ctx, instr| {
+                        emit_expr(ctx, instr, &mut range.lower_bound);
+                    });
+                    set_var(ctx, instr, upper_bound.unwrap(), |ctx, instr| {
+                        emit_expr(ctx, instr, &mut range.upper_bound);
+                    });
+                }
+            }
         },
         // Before each iteration.
         |ctx, instr, i| {
@@ -1441,19 +1529,19 @@ fn emit_of_pattern_set(
             // load_var returns an I64, convert it to I32.
             instr.unop(UnaryOp::I32WrapI64);
 
-            match &mut of.anchor {
-                MatchAnchor::None => {
+            match (lower_bound, upper_bound) {
+                (None, _) => {
                     emit_check_for_pattern_match(ctx, instr);
                 }
-                MatchAnchor::At(offset) => {
-                    emit_expr(ctx, instr, offset);
+                (Some(offset), None) => {
+                    load_var(ctx, instr, offset);
                     instr.call(ctx.function_id(
                         wasm::export__is_pat_match_at.mangled_name,
                     ));
                 }
-                MatchAnchor::In(range) => {
-                    emit_expr(ctx, instr, &mut range.lower_bound);
-                    emit_expr(ctx, instr, &mut range.upper_bound);
+                (Some(lower_bound), Some(upper_bound)) => {
+                    load_var(ctx, instr, lower_bound);
+                    load_var(ctx, instr, upper_bound);
                     instr.call(ctx.function_id(
                         wasm::export__is_pat_match_in.mangled_name,
                     ));
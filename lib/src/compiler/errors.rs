@@ -1,4 +1,7 @@
 #![cfg_attr(any(), rustfmt::skip)]
+// The code generated for `CompileError::EntrypointUnsupported` uses the
+// deprecated variant.
+#![allow(deprecated)]
 
 use std::fmt::{Debug, Display, Formatter};
 use std::io;
@@ -33,6 +36,25 @@ pub enum SerializationError {
     InvalidWASM(#[from] anyhow::Error),
 }
 
+/// Error returned by [`crate::Rules::merge`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MergeError {
+    /// Both sets of rules have rules in the same namespace.
+    #[error("namespace `{namespace}` exists in both sets of rules")]
+    DuplicateNamespace {
+        /// Name of the namespace.
+        namespace: String,
+    },
+
+    /// Both sets of rules define the same global variable, but with
+    /// different types or values.
+    #[error("global variable `{variable}` is defined differently in both sets of rules")]
+    ConflictingGlobal {
+        /// Name of the global variable.
+        variable: String,
+    },
+}
+
 /// Error returned by [`crate::Compiler::emit_wasm_file`].
 #[derive(Error, Debug)]
 #[error(transparent)]
@@ -54,7 +76,9 @@ pub enum CompileError {
     DuplicateRule(Box<DuplicateRule>),
     DuplicateTag(Box<DuplicateTag>),
     EmptyPatternSet(Box<EmptyPatternSet>),
+    #[deprecated(note = "the `entrypoint` keyword is supported again")]
     EntrypointUnsupported(Box<EntrypointUnsupported>),
+    ExcludedRuleDependency(Box<ExcludedRuleDependency>),
     InvalidBase64Alphabet(Box<InvalidBase64Alphabet>),
     InvalidEscapeSequence(Box<InvalidEscapeSequence>),
     InvalidFloat(Box<InvalidFloat>),
@@ -70,6 +94,7 @@ pub enum CompileError {
     MixedGreediness(Box<MixedGreediness>),
     NumberOutOfRange(Box<NumberOutOfRange>),
     PotentiallySlowLoop(Box<PotentiallySlowLoop>),
+    RegexTooComplex(Box<RegexTooComplex>),
     SlowPattern(Box<SlowPattern>),
     SyntaxError(Box<SyntaxError>),
     TooManyPatterns(Box<TooManyPatterns>),
@@ -79,6 +104,7 @@ pub enum CompileError {
     UnknownIdentifier(Box<UnknownIdentifier>),
     UnknownModule(Box<UnknownModule>),
     UnknownPattern(Box<UnknownPattern>),
+    UnsupportedRegexFeature(Box<UnsupportedRegexFeature>),
     UnusedPattern(Box<UnusedPattern>),
     WrongArguments(Box<WrongArguments>),
     WrongType(Box<WrongType>),
@@ -393,14 +419,15 @@ pub struct EmptyPatternSet {
 }
 
 /// The `entrypoint` keyword is not supported.
+///
+/// This error is not produced anymore, as `entrypoint` is now resolved with
+/// the `pe` and `elf` modules. It is kept only for backward compatibility.
 #[derive(ErrorStruct, Clone, Debug, PartialEq, Eq)]
 #[associated_enum(CompileError)]
 #[error(code = "E017", title = "`entrypoint` is unsupported")]
-#[label("the `entrypoint` keyword is not supported anymore", error_loc)]
 #[label(
     "use `pe.entry_point` or `elf.entry_point` or `macho.entry_point`",
-    error_loc,
-    Level::Help
+    error_loc
 )]
 pub struct EntrypointUnsupported {
     report: Report,
@@ -637,6 +664,69 @@ pub struct TooManyPatterns {
     error_loc: CodeLoc,
 }
 
+/// A regular expression uses a feature that is not supported.
+///
+/// YARA-X regular expressions are matched in linear time, which excludes
+/// features like look-around assertions (e.g: `(?=foo)`, `(?<!foo)`) and
+/// backreferences (e.g: `\1`).
+#[derive(ErrorStruct, Clone, Debug, PartialEq, Eq)]
+#[associated_enum(CompileError)]
+#[error(code = "E036", title = "unsupported regular expression feature")]
+#[label("{feature} is not supported", error_loc)]
+pub struct UnsupportedRegexFeature {
+    report: Report,
+    feature: String,
+    error_loc: CodeLoc,
+}
+
+/// A rule depends on another rule that was excluded from compilation.
+///
+/// Rules that don't match the patterns passed to
+/// [`crate::Compiler::include_rules`] are excluded, except when some included
+/// rule in the same source depends on them. Rules from previous sources
+/// that were already excluded can't be included anymore.
+#[derive(ErrorStruct, Clone, Debug, PartialEq, Eq)]
+#[associated_enum(CompileError)]
+#[error(code = "E037", title = "dependency on an excluded rule")]
+#[label("rule `{identifier}` was excluded from compilation", error_loc)]
+pub struct ExcludedRuleDependency {
+    report: Report,
+    identifier: String,
+    error_loc: CodeLoc,
+}
+
+/// A regular expression exceeds the limit set with
+/// [`crate::Compiler::regex_complexity_limit`].
+///
+/// The complexity of a regular expression, or hex pattern, is measured as the
+/// size of the code it compiles to. Large bounded repetitions, like
+/// `a{1,5000}`, are expanded into a copy of the repeated expression for each
+/// repetition, producing very large code that is slow to execute.
+///
+/// # Example
+///
+/// ```text
+/// error[E038]: regular expression too complex
+///  --> line:1:19
+///   |
+/// 1 | rule t { strings: $a = /ab{1,5000}c/ condition: $a }
+///   |                   ^^^^^^^^^^^^^^^^^^ this regexp compiles to 89992 bytes, the limit is 1000
+///   |
+/// ```
+#[derive(ErrorStruct, Clone, Debug, PartialEq, Eq)]
+#[associated_enum(CompileError)]
+#[error(code = "E038", title = "regular expression too complex")]
+#[label(
+    "this regexp compiles to {size} bytes, the limit is {limit}",
+    error_loc
+)]
+pub struct RegexTooComplex {
+    report: Report,
+    size: usize,
+    limit: usize,
+    error_loc: CodeLoc,
+}
+
 /// A custom error has occurred.
 #[derive(ErrorStruct, Clone, Debug, PartialEq, Eq)]
 #[associated_enum(CompileError)]
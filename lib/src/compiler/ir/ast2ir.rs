@@ -14,11 +14,11 @@ use yara_x_parser::Span;
 
 use crate::compiler::errors::{
     AssignmentMismatch, DuplicateModifier, DuplicatePattern, EmptyPatternSet,
-    EntrypointUnsupported, InvalidBase64Alphabet, InvalidModifier,
-    InvalidModifierCombination, InvalidPattern, InvalidRange, InvalidRegexp,
-    MismatchingTypes, MixedGreediness, NumberOutOfRange, SyntaxError,
-    TooManyPatterns, UnexpectedNegativeNumber, UnknownField,
-    UnknownIdentifier, WrongArguments, WrongType,
+    InvalidBase64Alphabet, InvalidModifier, InvalidModifierCombination,
+    InvalidPattern, InvalidRange, InvalidRegexp, MismatchingTypes,
+    MixedGreediness, NumberOutOfRange, SyntaxError, TooManyPatterns,
+    UnexpectedNegativeNumber, UnknownField, UnknownIdentifier,
+    UnsupportedRegexFeature, WrongArguments, WrongType,
 };
 use crate::compiler::ir::hex2hir::hex_pattern_hir_from_ast;
 use crate::compiler::ir::{
@@ -92,6 +92,46 @@ fn pattern_from_ast<'src>(
     }
 }
 
+/// Pairs of modifiers that can't be used together in the same pattern.
+///
+/// Any combination not listed here is valid, as long as each modifier can
+/// be applied to the pattern's kind.
+const INCOMPATIBLE_MODIFIERS: [(&str, &str); 8] = [
+    ("case", "nocase"),
+    ("xor", "nocase"),
+    ("base64", "nocase"),
+    ("base64wide", "nocase"),
+    ("base64", "fullword"),
+    ("base64wide", "fullword"),
+    ("base64", "xor"),
+    ("base64wide", "xor"),
+];
+
+/// Returns an error if `modifiers` contains any of the pairs in
+/// [`INCOMPATIBLE_MODIFIERS`].
+fn check_modifier_combinations(
+    ctx: &mut CompileContext,
+    modifiers: &ast::PatternModifiers,
+) -> Result<(), CompileError> {
+    let find = |name: &str| modifiers.iter().find(|m| m.as_text() == name);
+
+    for (name1, name2) in INCOMPATIBLE_MODIFIERS {
+        if let (Some(modifier1), Some(modifier2)) = (find(name1), find(name2))
+        {
+            return Err(InvalidModifierCombination::build(
+                ctx.report_builder,
+                name1.to_string(),
+                name2.to_string(),
+                modifier1.span().into(),
+                modifier2.span().into(),
+                Some("these two modifiers can't be used together".to_string()),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
 pub(in crate::compiler) fn text_pattern_from_ast<'src>(
     ctx: &mut CompileContext,
     pattern: &ast::TextPattern<'src>,
@@ -99,33 +139,13 @@ pub(in crate::compiler) fn text_pattern_from_ast<'src>(
     let ascii = pattern.modifiers.ascii();
     let xor = pattern.modifiers.xor();
     let nocase = pattern.modifiers.nocase();
+    let case = pattern.modifiers.case();
     let fullword = pattern.modifiers.fullword();
     let base64 = pattern.modifiers.base64();
     let base64wide = pattern.modifiers.base64wide();
     let wide = pattern.modifiers.wide();
 
-    let invalid_combinations = [
-        ("xor", xor, "nocase", nocase),
-        ("base64", base64, "nocase", nocase),
-        ("base64wide", base64wide, "nocase", nocase),
-        ("base64", base64, "fullword", fullword),
-        ("base64wide", base64wide, "fullword", fullword),
-        ("base64", base64, "xor", xor),
-        ("base64wide", base64wide, "xor", xor),
-    ];
-
-    for (name1, modifier1, name2, modifier2) in invalid_combinations {
-        if let (Some(modifier1), Some(modifier2)) = (modifier1, modifier2) {
-            return Err(InvalidModifierCombination::build(
-                ctx.report_builder,
-                name1.to_string(),
-                name2.to_string(),
-                modifier1.span().into(),
-                modifier2.span().into(),
-                Some("these two modifiers can't be used together".to_string()),
-            ));
-        };
-    }
+    check_modifier_combinations(ctx, &pattern.modifiers)?;
 
     let mut flags = PatternFlagSet::none();
 
@@ -137,7 +157,16 @@ pub(in crate::compiler) fn text_pattern_from_ast<'src>(
         flags.set(PatternFlags::Wide);
     }
 
-    if nocase.is_some() {
+    // When `nocase_by_default` is enabled, patterns are case-insensitive
+    // unless they use the `case` modifier, or some modifier that can't be
+    // combined with `nocase`.
+    if nocase.is_some()
+        || (ctx.nocase_by_default
+            && case.is_none()
+            && xor.is_none()
+            && base64.is_none()
+            && base64wide.is_none())
+    {
         flags.set(PatternFlags::Nocase);
     }
 
@@ -277,13 +306,14 @@ pub(in crate::compiler) fn regexp_pattern_from_ast<'src>(
     ctx: &mut CompileContext,
     pattern: &ast::RegexpPattern<'src>,
 ) -> Result<PatternInRule<'src>, CompileError> {
-    // Regular expressions don't accept `base64`, `base64wide` and `xor`
-    // modifiers.
+    // Regular expressions don't accept `base64`, `base64wide`, `xor` and
+    // `case` modifiers.
     for modifier in pattern.modifiers.iter() {
         match modifier {
             ast::PatternModifier::Base64 { .. }
             | ast::PatternModifier::Base64Wide { .. }
-            | ast::PatternModifier::Xor { .. } => {
+            | ast::PatternModifier::Xor { .. }
+            | ast::PatternModifier::Case { .. } => {
                 return Err(InvalidModifier::build(
                     ctx.report_builder,
                     "this modifier can't be applied to a regexp".to_string(),
@@ -367,6 +397,48 @@ pub(in crate::compiler) fn regexp_pattern_from_ast<'src>(
             re_error_to_compile_error(ctx.report_builder, &pattern.regexp, err)
         })?;
 
+    // A regexp that matches a single literal, like `/foo/` or `/a\.b/`, is
+    // better expressed as a text pattern.
+    if let Some(literal) = hir.as_literal_bytes() {
+        ctx.warnings.add(|| {
+            warnings::RegexCouldBeLiteral::build(
+                ctx.report_builder,
+                text_pattern_literal(literal),
+                pattern.regexp.span().into(),
+                Some(
+                    "text patterns are faster than regular expressions"
+                        .to_string(),
+                ),
+            )
+        });
+    }
+
+    if ctx.detect_redundant_alternations {
+        let redundant = re::parser::Parser::new()
+            .relaxed_re_syntax(ctx.relaxed_re_syntax)
+            .redundant_alternatives(&pattern.regexp);
+
+        for (redundant, original) in redundant {
+            ctx.warnings.add(|| {
+                warnings::RedundantAlternation::build(
+                    ctx.report_builder,
+                    pattern
+                        .regexp
+                        .span()
+                        .subspan(redundant.start.offset, redundant.end.offset)
+                        .offset(1)
+                        .into(),
+                    pattern
+                        .regexp
+                        .span()
+                        .subspan(original.start.offset, original.end.offset)
+                        .offset(1)
+                        .into(),
+                )
+            });
+        }
+    }
+
     // TODO: raise warning when .* used, propose using the non-greedy
     // variant .*?
 
@@ -382,14 +454,52 @@ pub(in crate::compiler) fn regexp_pattern_from_ast<'src>(
     })
 }
 
+/// Returns the text pattern that matches `literal`, like in `"a.b"`.
+///
+/// Quotes, backslashes and non-printable characters are escaped.
+fn text_pattern_literal(literal: &[u8]) -> String {
+    let mut s = String::with_capacity(literal.len() + 2);
+    s.push('"');
+    for b in literal {
+        match b {
+            b'"' => s.push_str("\\\""),
+            b'\\' => s.push_str("\\\\"),
+            b'\t' => s.push_str("\\t"),
+            b'\n' => s.push_str("\\n"),
+            b'\r' => s.push_str("\\r"),
+            0x20..=0x7e => s.push(*b as char),
+            _ => s.push_str(format!("\\x{:02x}", b).as_str()),
+        }
+    }
+    s.push('"');
+    s
+}
+
 /// Given the AST for some expression, creates its IR.
 pub(in crate::compiler) fn expr_from_ast(
     ctx: &mut CompileContext,
     expr: &ast::Expr,
 ) -> Result<Expr, CompileError> {
     match expr {
-        ast::Expr::Entrypoint { span } => {
-            Err(EntrypointUnsupported::build(ctx.report_builder, span.into()))
+        ast::Expr::Entrypoint { .. } => Ok(Expr::Entrypoint),
+
+        ast::Expr::DataSlice(s) => Ok(Expr::DataSlice {
+            offset: Box::new(integer_in_range_from_ast(
+                ctx,
+                &s.offset,
+                0..=i64::MAX,
+            )?),
+            length: Box::new(integer_in_range_from_ast(
+                ctx,
+                &s.length,
+                0..=i64::MAX,
+            )?),
+        }),
+        ast::Expr::BufferStartsWith(check) => {
+            Ok(buffer_check_from_ast(ctx, check, true))
+        }
+        ast::Expr::BufferEndsWith(check) => {
+            Ok(buffer_check_from_ast(ctx, check, false))
         }
         ast::Expr::Filesize { .. } => Ok(Expr::Filesize),
 
@@ -465,52 +575,64 @@ pub(in crate::compiler) fn expr_from_ast(
             // expression with an integer constant (e.g: `pe.is_signed == 0`).
             // This is quite common in YARA rules, it is accepted without
             // errors, but a warning is raised.
-            let replacement = match (lhs.type_value(), rhs.type_value()) {
-                (TypeValue::Bool(_), TypeValue::Integer(Value::Const(0))) => {
-                    Some((
-                        Expr::not(lhs),
-                        format!(
-                            "not {}",
-                            ctx.report_builder.get_snippet(&lhs_span.into())
-                        ),
-                    ))
-                }
-                (TypeValue::Integer(Value::Const(0)), TypeValue::Bool(_)) => {
-                    Some((
-                        Expr::not(rhs),
-                        format!(
-                            "not {}",
-                            ctx.report_builder.get_snippet(&rhs_span.into())
-                        ),
-                    ))
-                }
+            let (expr, msg) = match (lhs.type_value(), rhs.type_value()) {
+                (TypeValue::Bool(_), TypeValue::Integer(Value::Const(0))) => (
+                    Expr::not(lhs),
+                    format!(
+                        "not {}",
+                        ctx.report_builder.get_snippet(&lhs_span.into())
+                    ),
+                ),
+                (TypeValue::Integer(Value::Const(0)), TypeValue::Bool(_)) => (
+                    Expr::not(rhs),
+                    format!(
+                        "not {}",
+                        ctx.report_builder.get_snippet(&rhs_span.into())
+                    ),
+                ),
                 (TypeValue::Bool(_), TypeValue::Integer(Value::Const(1))) => {
-                    Some((
-                        lhs,
-                        ctx.report_builder.get_snippet(&lhs_span.into()),
-                    ))
+                    (lhs, ctx.report_builder.get_snippet(&lhs_span.into()))
                 }
                 (TypeValue::Integer(Value::Const(1)), TypeValue::Bool(_)) => {
-                    Some((
-                        rhs,
-                        ctx.report_builder.get_snippet(&rhs_span.into()),
-                    ))
+                    (rhs, ctx.report_builder.get_snippet(&rhs_span.into()))
+                }
+                // Not a comparison between a boolean and an integer. The
+                // operands have been already converted to IR, so they are
+                // not converted again, as that would duplicate any warning
+                // raised while converting them.
+                _ => {
+                    check_operands(
+                        ctx,
+                        lhs.ty(),
+                        rhs.ty(),
+                        lhs_span,
+                        rhs_span,
+                        // Integers, floats and strings can be compared.
+                        &[Type::Integer, Type::Float, Type::String],
+                        // Integers can be compared with floats, but strings
+                        // can be compared only with another string.
+                        &[Type::Integer, Type::Float],
+                    )?;
+
+                    let expr = Expr::eq(lhs, rhs);
+
+                    return if cfg!(feature = "constant-folding") {
+                        expr.fold(ctx, span)
+                    } else {
+                        Ok(expr)
+                    };
                 }
-                _ => None,
             };
 
-            if let Some((expr, msg)) = replacement {
-                ctx.warnings.add(|| {
-                    warnings::BooleanIntegerComparison::build(
-                        ctx.report_builder,
-                        msg,
-                        span.into(),
-                    )
-                });
-                Ok(expr)
-            } else {
-                eq_expr_from_ast(ctx, expr)
-            }
+            ctx.warnings.add(|| {
+                warnings::BooleanIntegerComparison::build(
+                    ctx.report_builder,
+                    msg,
+                    span.into(),
+                )
+            });
+
+            Ok(expr)
         }
         ast::Expr::Ne(expr) => ne_expr_from_ast(ctx, expr),
         ast::Expr::Gt(expr) => gt_expr_from_ast(ctx, expr),
@@ -610,6 +732,13 @@ pub(in crate::compiler) fn expr_from_ast(
             }
 
             let symbol = symbol.unwrap();
+
+            if current_symbol_table.is_none()
+                && matches!(symbol.kind(), SymbolKind::Field(_, true))
+            {
+                ctx.root_idents.push(ident.name.to_string());
+            }
+
             #[cfg(feature = "constant-folding")]
             {
                 let type_value = symbol.type_value();
@@ -933,10 +1062,11 @@ fn of_expr_from_ast(
     of: &ast::Of,
 ) -> Result<Expr, CompileError> {
     let quantifier = quantifier_from_ast(ctx, &of.quantifier)?;
-    // Create new stack frame with 5 slots:
+    // Create new stack frame with 7 slots:
     //   1 slot for the loop variable, a bool in this case.
     //   4 up to slots used for loop control variables (see: emit::emit_for)
-    let stack_frame = ctx.vars.new_frame(5);
+    //   2 slots for the anchor's offset or range bounds.
+    let stack_frame = ctx.vars.new_frame(7);
 
     let (items, num_items) = match &of.items {
         // `x of (<boolean expr>, <boolean expr>, ...)`
@@ -1508,7 +1638,23 @@ fn func_call_from_ast(
     ctx: &mut CompileContext,
     func_call: &ast::FuncCall,
 ) -> Result<Expr, CompileError> {
-    let callable = expr_from_ast(ctx, &func_call.callable)?;
+    // Calls like `uint32(offset, BIG_ENDIAN)` are resolved at compile time
+    // as calls to the function that reads with the given byte order, like
+    // `uint32be(offset)`.
+    let (callable, ast_args) = match int_read_byte_order(ctx, func_call)? {
+        Some(name) => {
+            let symbol =
+                ctx.symbol_table.lookup(name.as_str()).unwrap_or_else(|| {
+                    panic!("`{name}` should be always defined")
+                });
+            (Expr::Ident { symbol }, &func_call.args[..1])
+        }
+        None => (
+            expr_from_ast(ctx, &func_call.callable)?,
+            func_call.args.as_slice(),
+        ),
+    };
+
     let type_value = callable.type_value();
 
     check_type(
@@ -1518,8 +1664,7 @@ fn func_call_from_ast(
         &[Type::Func],
     )?;
 
-    let args = func_call
-        .args
+    let args = ast_args
         .iter()
         .map(|arg| expr_from_ast(ctx, arg))
         .collect::<Result<Vec<Expr>, CompileError>>()?;
@@ -1578,6 +1723,27 @@ fn func_call_from_ast(
 
     let (signature_index, type_value) = matching_signature.unwrap();
 
+    // Raise a warning when the function is one of the built-in functions
+    // that read integers from the scanned data (e.g: `uint32`), and the
+    // offset is such that the result is always undefined.
+    if let Some(size) =
+        int_read_size(func.signatures()[signature_index].mangled_name.as_str())
+    {
+        if reads_past_eof(&args[0], size) {
+            ctx.warnings.add(|| {
+                warnings::AlwaysUndefined::build(
+                    ctx.report_builder,
+                    func_call.span().into(),
+                    Some(format!(
+                        "reading {} byte{} at this offset exceeds the end of the scanned data",
+                        size,
+                        if size > 1 { "s" } else { "" },
+                    )),
+                )
+            });
+        }
+    }
+
     Ok(Expr::FuncCall(Box::new(FuncCall {
         callable,
         type_value,
@@ -1586,6 +1752,205 @@ fn func_call_from_ast(
     })))
 }
 
+/// Given the AST for `buffer startswith { ... }` or `buffer endswith { ... }`,
+/// creates its IR.
+///
+/// The check is translated into comparisons of the bytes at the start (or
+/// the end) of the scanned data, preceded by a check that ensures that the
+/// data is long enough. Consecutive bytes that must match exactly are
+/// compared all at once as a slice of the data, while masked and negated
+/// bytes are read one by one with `uint8`.
+fn buffer_check_from_ast(
+    ctx: &mut CompileContext,
+    check: &ast::BufferCheck,
+    at_start: bool,
+) -> Expr {
+    let len = check.tokens.tokens.len();
+
+    let const_int =
+        |i: usize| Expr::Const(TypeValue::const_integer_from(i as i64));
+
+    // Returns the offset within the scanned data that corresponds to the
+    // byte at `index` in the pattern.
+    let offset = |index: usize| {
+        if at_start {
+            const_int(index)
+        } else {
+            Expr::sub(vec![Expr::Filesize, const_int(len - index)])
+        }
+    };
+
+    let mut operands = vec![Expr::ge(Expr::Filesize, const_int(len))];
+    let mut exact_bytes: Vec<u8> = Vec::new();
+
+    for (index, token) in check.tokens.tokens.iter().enumerate() {
+        let (byte, negated) = match token {
+            ast::HexToken::Byte(byte) => (byte, false),
+            ast::HexToken::NotByte(byte) => (byte, true),
+            // Jumps and alternatives are rejected by the parser.
+            _ => unreachable!(),
+        };
+
+        if byte.mask == 0xFF && !negated {
+            exact_bytes.push(byte.value);
+            continue;
+        }
+
+        if !exact_bytes.is_empty() {
+            operands.push(Expr::eq(
+                Expr::DataSlice {
+                    offset: Box::new(offset(index - exact_bytes.len())),
+                    length: Box::new(const_int(exact_bytes.len())),
+                },
+                Expr::Const(TypeValue::const_string_from(
+                    exact_bytes.as_slice(),
+                )),
+            ));
+            exact_bytes.clear();
+        }
+
+        // Fully masked bytes (i.e: `??`) match anything.
+        if byte.mask == 0x00 {
+            continue;
+        }
+
+        let read_byte = Expr::bitwise_and(
+            uint8_call(ctx, offset(index)),
+            const_int(byte.mask as usize),
+        );
+
+        let value = const_int((byte.value & byte.mask) as usize);
+
+        operands.push(if negated {
+            Expr::ne(read_byte, value)
+        } else {
+            Expr::eq(read_byte, value)
+        });
+    }
+
+    if !exact_bytes.is_empty() {
+        operands.push(Expr::eq(
+            Expr::DataSlice {
+                offset: Box::new(offset(len - exact_bytes.len())),
+                length: Box::new(const_int(exact_bytes.len())),
+            },
+            Expr::Const(TypeValue::const_string_from(exact_bytes.as_slice())),
+        ));
+    }
+
+    Expr::and(operands)
+}
+
+/// Creates the IR for a call to the built-in `uint8` function.
+fn uint8_call(ctx: &mut CompileContext, offset: Expr) -> Expr {
+    let symbol = ctx
+        .symbol_table
+        .lookup("uint8")
+        .expect("`uint8` should be always defined");
+
+    let func = symbol.type_value().as_func();
+
+    let (signature_index, signature) = func
+        .signatures()
+        .iter()
+        .find_position(|signature| {
+            signature.args.len() == 1
+                && signature.args[0].ty() == Type::Integer
+        })
+        .expect("`uint8` should receive an integer");
+
+    Expr::FuncCall(Box::new(FuncCall {
+        type_value: signature.result.clone(),
+        callable: Expr::Ident { symbol },
+        args: vec![offset],
+        signature_index,
+    }))
+}
+
+/// Built-in functions that accept the byte order as their second argument.
+const INT_READ_FUNCTIONS: [&str; 6] =
+    ["int8", "int16", "int32", "uint8", "uint16", "uint32"];
+
+/// If `func_call` is a call like `uint32(offset, BIG_ENDIAN)`, returns the
+/// name of the function that reads the integer with the given byte order,
+/// like `uint32be`.
+///
+/// `LITTLE_ENDIAN` and `BIG_ENDIAN` are not symbols, they are recognized only
+/// as the second argument of these functions.
+fn int_read_byte_order(
+    ctx: &mut CompileContext,
+    func_call: &ast::FuncCall,
+) -> Result<Option<String>, CompileError> {
+    let name = match &func_call.callable {
+        ast::Expr::Ident(ident)
+            if INT_READ_FUNCTIONS.contains(&ident.name)
+                && func_call.args.len() == 2 =>
+        {
+            ident.name
+        }
+        _ => return Ok(None),
+    };
+
+    match &func_call.args[1] {
+        ast::Expr::Ident(ident) if ident.name == "LITTLE_ENDIAN" => {
+            Ok(Some(name.to_string()))
+        }
+        ast::Expr::Ident(ident) if ident.name == "BIG_ENDIAN" => {
+            Ok(Some(format!("{name}be")))
+        }
+        arg => Err(WrongArguments::build(
+            ctx.report_builder,
+            arg.span().into(),
+            Some(
+                "the byte order must be either `LITTLE_ENDIAN` or `BIG_ENDIAN`"
+                    .to_string(),
+            ),
+        )),
+    }
+}
+
+/// If `mangled_name` corresponds to one of the built-in functions that read
+/// an integer from the scanned data (`uint8`, `int32be`, etc.), returns the
+/// number of bytes read by the function.
+fn int_read_size(mangled_name: &str) -> Option<i64> {
+    let name = mangled_name.split('@').next()?;
+    let name = name.strip_suffix("be").unwrap_or(name);
+    let name = name.strip_prefix('u').unwrap_or(name);
+    match name {
+        "int8" => Some(1),
+        "int16" => Some(2),
+        "int32" => Some(4),
+        _ => None,
+    }
+}
+
+/// Returns true if reading `size` bytes at `offset` always exceeds the end
+/// of the scanned data.
+///
+/// This function is conservative, it only returns true for offsets with the
+/// form `filesize`, `filesize + N` and `filesize - N`, where `N` is a
+/// constant, and `filesize - N` is too close to the end of the data for
+/// reading `size` bytes.
+fn reads_past_eof(offset: &Expr, size: i64) -> bool {
+    let constant = |expr: &Expr| match expr {
+        Expr::Const(TypeValue::Integer(Value::Const(n))) => Some(*n),
+        _ => None,
+    };
+    let delta = match offset {
+        Expr::Filesize => Some(0),
+        Expr::Add { operands } => match operands.as_slice() {
+            [Expr::Filesize, n] | [n, Expr::Filesize] => constant(n),
+            _ => None,
+        },
+        Expr::Sub { operands } => match operands.as_slice() {
+            [Expr::Filesize, n] => constant(n).and_then(|n| n.checked_neg()),
+            _ => None,
+        },
+        _ => None,
+    };
+    matches!(delta, Some(delta) if delta > -size)
+}
+
 fn matches_expr_from_ast(
     ctx: &mut CompileContext,
     expr: &ast::BinaryExpr,
@@ -1715,6 +2080,17 @@ fn re_error_to_compile_error(
                 .offset(1)
                 .into(),
         ),
+        Error::UnsupportedFeature { feature, span } => {
+            UnsupportedRegexFeature::build(
+                report_builder,
+                feature,
+                regexp
+                    .span()
+                    .subspan(span.start.offset, span.end.offset)
+                    .offset(1)
+                    .into(),
+            )
+        }
     }
 }
 
@@ -2084,17 +2460,6 @@ gen_binary_op!(
     None
 );
 
-gen_binary_op!(
-    eq_expr_from_ast,
-    eq,
-    // Integers, floats and strings can be compared.
-    Type::Integer | Type::Float | Type::String,
-    // Integers can be compared with floats, but strings can be
-    // compared only with another string.
-    Type::Integer | Type::Float,
-    None
-);
-
 gen_binary_op!(
     ne_expr_from_ast,
     ne,
@@ -106,6 +106,7 @@ impl<'a> Iterator for DepthFirstSearch<'a> {
             match expr {
                 Expr::Const(_) => {}
                 Expr::Filesize => {}
+                Expr::Entrypoint => {}
                 Expr::Ident { .. } => {}
 
                 Expr::Not { operand }
@@ -151,6 +152,11 @@ impl<'a> Iterator for DepthFirstSearch<'a> {
                     self.stack.push(Event::Enter(lhs));
                 }
 
+                Expr::DataSlice { offset, length } => {
+                    self.stack.push(Event::Enter(length));
+                    self.stack.push(Event::Enter(offset));
+                }
+
                 Expr::PatternMatch { anchor, .. }
                 | Expr::PatternMatchVar { anchor, .. } => {
                     push_anchor(anchor, &mut self.stack);
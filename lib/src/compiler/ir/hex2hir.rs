@@ -214,10 +214,13 @@ mod tests {
         let mut symbol_table = StackedSymbolTable::new();
         let mut warnings = Warnings::default();
         let mut rule_patterns = vec![];
+        let mut root_idents = vec![];
 
         let mut ctx = CompileContext {
             relaxed_re_syntax: false,
+            detect_redundant_alternations: false,
             error_on_slow_loop: false,
+            nocase_by_default: false,
             current_symbol_table: None,
             symbol_table: &mut symbol_table,
             report_builder: &mut report_builder,
@@ -225,18 +228,18 @@ mod tests {
             warnings: &mut warnings,
             vars: VarStack::new(),
             for_of_depth: 0,
+            root_idents: &mut root_idents,
         };
 
-        let mut pattern = HexPattern {
-            identifier: Ident::new("test_ident"),
-            tokens: HexTokens {
-                tokens: vec![
-                    HexToken::Byte(ast::HexByte::new(b'a', 0xff)),
-                    HexToken::Byte(ast::HexByte::new(b'b', 0xff)),
-                    HexToken::Byte(ast::HexByte::new(b'c', 0xff)),
-                ],
-            },
-            ..Default::default()
+        let mut pattern = HexPattern::default();
+
+        pattern.identifier = Ident::new("test_ident");
+        pattern.tokens = HexTokens {
+            tokens: vec![
+                HexToken::Byte(ast::HexByte::new(b'a', 0xff)),
+                HexToken::Byte(ast::HexByte::new(b'b', 0xff)),
+                HexToken::Byte(ast::HexByte::new(b'c', 0xff)),
+            ],
         };
 
         assert_eq!(
@@ -38,6 +38,7 @@ use bstr::BString;
 use serde::{Deserialize, Serialize};
 
 use crate::compiler::context::{CompileContext, Var, VarStackFrame};
+use crate::compiler::{PatternCondition, PatternId};
 use crate::symbols::Symbol;
 use crate::types::{Type, TypeValue, Value};
 
@@ -314,6 +315,9 @@ pub(in crate::compiler) enum Expr {
     /// `filesize` expression.
     Filesize,
 
+    /// `entrypoint` expression.
+    Entrypoint,
+
     /// Boolean `not` expression.
     Not {
         operand: Box<Expr>,
@@ -540,6 +544,12 @@ pub(in crate::compiler) enum Expr {
         index: Option<Box<Expr>>,
     },
 
+    /// A slice of the scanned data (e.g. `(0, 16)`).
+    DataSlice {
+        offset: Box<Expr>,
+        length: Box<Expr>,
+    },
+
     /// Function call.
     FuncCall(Box<FuncCall>),
 
@@ -865,6 +875,76 @@ impl Expr {
         None
     }
 
+    /// If this expression depends only on whether some patterns matched,
+    /// like `$a and ($b or $c)` or `any of them`, returns the equivalent
+    /// [`PatternCondition`]. For any other expression returns `None`.
+    ///
+    /// `pattern_id` translates the indexes of the patterns in the rule to
+    /// their [`PatternId`].
+    pub fn pattern_condition(
+        &self,
+        pattern_id: &impl Fn(PatternIdx) -> PatternId,
+    ) -> Option<PatternCondition> {
+        let operands = |operands: &[Expr]| {
+            operands
+                .iter()
+                .map(|operand| operand.pattern_condition(pattern_id))
+                .collect::<Option<Vec<_>>>()
+        };
+        match self {
+            Expr::PatternMatch { pattern, anchor: MatchAnchor::None } => {
+                Some(PatternCondition::Match(pattern_id(*pattern)))
+            }
+            Expr::And { operands: ops } => {
+                Some(PatternCondition::And(operands(ops)?))
+            }
+            Expr::Or { operands: ops } => {
+                Some(PatternCondition::Or(operands(ops)?))
+            }
+            Expr::Of(of) if matches!(of.anchor, MatchAnchor::None) => {
+                let operands = match &of.items {
+                    OfItems::PatternSet(patterns) => patterns
+                        .iter()
+                        .map(|p| PatternCondition::Match(pattern_id(*p)))
+                        .collect(),
+                    OfItems::BoolExprTuple(exprs) => operands(exprs)?,
+                };
+                match of.quantifier {
+                    Quantifier::All => Some(PatternCondition::And(operands)),
+                    Quantifier::Any => Some(PatternCondition::Or(operands)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true if this expression needs to know how many times some
+    /// pattern matched, or where, like in `#a`, `@a[1]`, `!a[1]`,
+    /// `$a at 100` or `any of them in (0..100)`. Expressions that only need
+    /// to know whether the pattern matched, like `$a` or `all of them`,
+    /// return false.
+    pub fn requires_all_matches(&self) -> bool {
+        self.dfs_find(
+            |expr| match expr {
+                Expr::PatternCount { .. }
+                | Expr::PatternCountVar { .. }
+                | Expr::PatternOffset { .. }
+                | Expr::PatternOffsetVar { .. }
+                | Expr::PatternLength { .. }
+                | Expr::PatternLengthVar { .. } => true,
+                Expr::PatternMatch { anchor, .. }
+                | Expr::PatternMatchVar { anchor, .. } => {
+                    !matches!(anchor, MatchAnchor::None)
+                }
+                Expr::Of(of) => !matches!(of.anchor, MatchAnchor::None),
+                _ => false,
+            },
+            |_| false,
+        )
+        .is_some()
+    }
+
     /// Returns the type of this expression.
     pub fn ty(&self) -> Type {
         match self {
@@ -913,6 +993,7 @@ impl Expr {
             }
 
             Expr::Filesize
+            | Expr::Entrypoint
             | Expr::PatternCount { .. }
             | Expr::PatternCountVar { .. }
             | Expr::PatternOffset { .. }
@@ -927,6 +1008,8 @@ impl Expr {
             | Expr::Shl { .. }
             | Expr::Shr { .. } => Type::Integer,
 
+            Expr::DataSlice { .. } => Type::String,
+
             Expr::FieldAccess { operands, .. } => {
                 operands.last().unwrap().ty()
             }
@@ -984,6 +1067,7 @@ impl Expr {
             }
 
             Expr::Filesize
+            | Expr::Entrypoint
             | Expr::PatternCount { .. }
             | Expr::PatternCountVar { .. }
             | Expr::PatternOffset { .. }
@@ -998,6 +1082,8 @@ impl Expr {
             | Expr::Shl { .. }
             | Expr::Shr { .. } => TypeValue::Integer(Value::Unknown),
 
+            Expr::DataSlice { .. } => TypeValue::String(Value::Unknown),
+
             Expr::FieldAccess { operands, .. } => {
                 operands.last().unwrap().type_value()
             }
@@ -1173,6 +1259,7 @@ impl Debug for Expr {
                     match expr {
                         Expr::Const(c) => writeln!(f, "CONST {}", c)?,
                         Expr::Filesize => writeln!(f, "FILESIZE")?,
+                        Expr::Entrypoint => writeln!(f, "ENTRYPOINT")?,
                         Expr::Not { .. } => writeln!(f, "NOT")?,
                         Expr::And { .. } => writeln!(f, "AND")?,
                         Expr::Or { .. } => writeln!(f, "OR")?,
@@ -1205,6 +1292,7 @@ impl Debug for Expr {
                         Expr::Defined { .. } => writeln!(f, "DEFINED")?,
                         Expr::FieldAccess { .. } => writeln!(f, "FIELD_ACCESS")?,
                         Expr::Ident { symbol } => writeln!(f, "IDENT {:?}", symbol)?,
+                        Expr::DataSlice { .. } => writeln!(f, "DATA_SLICE")?,
                         Expr::FuncCall(_) => writeln!(f, "FN_CALL")?,
                         Expr::Of(_) => writeln!(f, "OF")?,
                         Expr::ForOf(_) => writeln!(f, "FOR_OF")?,
@@ -12,8 +12,8 @@ use std::io::Write;
 use std::ops::RangeInclusive;
 use std::path::Path;
 use std::rc::Rc;
-#[cfg(feature = "logging")]
-use std::time::Instant;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 use std::{fmt, iter};
 
 use bincode::Options;
@@ -33,11 +33,14 @@ use yara_x_parser::ast::{Ident, Import, RuleFlag, WithSpan};
 use yara_x_parser::{Parser, Span};
 
 use crate::compiler::base64::base64_patterns;
-use crate::compiler::emit::{emit_rule_condition, EmitContext};
+use crate::compiler::emit::{
+    emit_eval_condition, emit_rule_condition, EmitContext,
+};
 use crate::compiler::errors::{
     CompileError, ConflictingRuleIdentifier, CustomError, DuplicateRule,
-    DuplicateTag, EmitWasmError, InvalidRegexp, InvalidUTF8, UnknownModule,
-    UnusedPattern,
+    DuplicateTag, EmitWasmError, ExcludedRuleDependency, InvalidRegexp,
+    InvalidUTF8, RegexTooComplex, SyntaxError, UnknownIdentifier,
+    UnknownModule, UnusedPattern, WrongType,
 };
 use crate::compiler::report::{CodeLoc, ReportBuilder};
 use crate::compiler::{CompileContext, VarStack};
@@ -48,11 +51,12 @@ use crate::string_pool::{BStringPool, StringPool};
 use crate::symbols::{
     StackedSymbolTable, Symbol, SymbolKind, SymbolLookup, SymbolTable,
 };
-use crate::types::{Func, Struct, TypeValue, Value};
+use crate::types::{Func, Struct, Type, TypeValue, Value};
 use crate::utils::cast;
 use crate::variables::{is_valid_identifier, Variable, VariableError};
 use crate::wasm::builder::WasmModuleBuilder;
 use crate::wasm::{WasmExport, WasmSymbols, WASM_EXPORTS};
+use crate::HexToken;
 
 pub(crate) use crate::compiler::atoms::*;
 pub(crate) use crate::compiler::context::*;
@@ -245,11 +249,60 @@ pub struct Compiler<'a> {
     /// pattern is one with atoms shorter than 2 bytes.
     error_on_slow_pattern: bool,
 
+    /// If true, text patterns without the `case` modifier are
+    /// case-insensitive, as if they had the `nocase` modifier.
+    nocase_by_default: bool,
+
+    /// Maximum number of AST nodes in a rule condition before a
+    /// [`warnings::ComplexCondition`] warning is raised. `None` means that
+    /// the warning is never raised.
+    condition_complexity_limit: Option<usize>,
+
+    /// Maximum size, in bytes, of the code produced for a regexp or hex
+    /// pattern. Patterns that exceed it produce a [`RegexTooComplex`] error.
+    /// `None` means that there's no limit.
+    regex_complexity_limit: Option<usize>,
+
+    /// If true, a [`warnings::RedundantAlternation`] warning is raised for
+    /// regexp patterns with redundant alternatives.
+    detect_redundant_alternations: bool,
+
+    /// If true, rule conditions are compiled so that their value is passed
+    /// to the scanner instead of being casted to bool. This is set only
+    /// while compiling the condition passed to [`crate::Scanner::eval_condition`].
+    eval_condition: bool,
+
+    /// Glob patterns set with [`Compiler::include_rules`]. When not empty,
+    /// only the rules whose names match some of the patterns, and the rules
+    /// they depend on, are compiled.
+    include_rules: Vec<String>,
+
+    /// Names of the rules in the current namespace that were excluded from
+    /// compilation because they don't match [`Compiler::include_rules`].
+    excluded_rules: FxHashSet<String>,
+
+    /// If true, the source code of each rule is stored in the compiled
+    /// rules, and can be retrieved with [`crate::Rule::source`].
+    embed_sources: bool,
+
+    /// If true, the structure of hex patterns is stored in the compiled
+    /// rules, and can be retrieved with [`crate::Pattern::hex_tokens`].
+    hex_pattern_structure: bool,
+
+    /// Determines which metadata entries are kept when a rule has the same
+    /// metadata key more than once.
+    duplicate_metadata: DuplicateMetadata,
+
     /// If true, a slow loop produces an error instead of a warning. A slow
     /// rule is one where the upper bound of the loop is potentially large.
     /// Like for example: `for all x in (0..filesize) : (...)`
     error_on_slow_loop: bool,
 
+    /// If true, the WASM code produced by the compiler is required to use
+    /// only a minimal set of WebAssembly features. See
+    /// [`Compiler::minimal_wasm_features`].
+    minimal_wasm_features: bool,
+
     /// Used for generating error and warning reports.
     report_builder: ReportBuilder,
 
@@ -340,10 +393,27 @@ pub struct Compiler<'a> {
     /// vector. This vector contains both forward and backward code.
     re_code: Vec<u8>,
 
+    /// Statistics about the compilation process.
+    stats: CompilerStats,
+
+    /// Patterns used in conditions that need every match of the pattern,
+    /// like `#a`, `@a[i]` or `$a at 100`. See [`crate::Scanner::fast_mode`].
+    all_matches_required: FxHashSet<PatternId>,
+
     /// Vector with the names of all the imported modules. The vector contains
     /// the [`IdentId`] corresponding to the module's identifier.
     imported_modules: Vec<IdentId>,
 
+    /// Vector with the names of the global variables used in rule
+    /// conditions. The vector contains the [`IdentId`] corresponding to the
+    /// variable's identifier.
+    used_globals: Vec<IdentId>,
+
+    /// Conditions of the rules compiled so far, as long as every rule has
+    /// a condition that depends only on whether some patterns matched. When
+    /// some rule doesn't, this is `None`. See [`Rules::pattern_conditions`].
+    pattern_conditions: Option<Vec<PatternCondition>>,
+
     /// Names of modules that are known, but not supported. When an `import`
     /// statement with one of these modules is found, the statement is accepted
     /// without causing an error, but a warning is raised to let the user know
@@ -436,7 +506,18 @@ impl<'a> Compiler<'a> {
             wasm_exports,
             relaxed_re_syntax: false,
             error_on_slow_pattern: false,
+            nocase_by_default: false,
+            condition_complexity_limit: None,
+            regex_complexity_limit: None,
+            detect_redundant_alternations: false,
+            eval_condition: false,
+            include_rules: Vec::new(),
+            excluded_rules: FxHashSet::default(),
+            embed_sources: false,
+            hex_pattern_structure: false,
+            duplicate_metadata: DuplicateMetadata::default(),
             error_on_slow_loop: false,
+            minimal_wasm_features: false,
             next_pattern_id: PatternId(0),
             current_pattern_id: PatternId(0),
             current_namespace: default_namespace,
@@ -447,7 +528,11 @@ impl<'a> Compiler<'a> {
             anchored_sub_patterns: Vec::new(),
             atoms: Vec::new(),
             re_code: Vec::new(),
+            all_matches_required: FxHashSet::default(),
+            stats: CompilerStats::default(),
             imported_modules: Vec::new(),
+            used_globals: Vec::new(),
+            pattern_conditions: Some(Vec::new()),
             ignored_modules: FxHashSet::default(),
             banned_modules: FxHashMap::default(),
             ignored_rules: FxHashMap::default(),
@@ -488,7 +573,64 @@ impl<'a> Compiler<'a> {
         // Convert `src` into an instance of `SourceCode` if it is something
         // else, like a &str.
         let mut src = src.into();
+        let start = Instant::now();
+        let ast = Self::parse(&mut src);
+        self.stats.parse_time += start.elapsed();
+        self.add_ast(src, ast)
+    }
 
+    /// Compiles `condition` as the condition of a rule whose value is passed
+    /// to the scanner as is. See [`crate::Scanner::eval_condition`].
+    ///
+    /// The condition starts at the second line of the generated source, so
+    /// columns in error reports refer to `condition` itself. If `condition`
+    /// closes the generated rule and declares other rules or imports after
+    /// it, a syntax error is returned.
+    pub(crate) fn add_eval_condition(
+        &mut self,
+        condition: &str,
+    ) -> Result<(), CompileError> {
+        let source = format!("rule __eval__ {{ condition:\n{condition}\n}}");
+        let mut src = SourceCode::from(source.as_str());
+        let ast = Self::parse(&mut src);
+
+        // The only way of producing more than one rule, or any import, is
+        // closing the generated rule with a `}` that is part of `condition`.
+        if let Ok(ast) = &ast {
+            if ast.errors().is_empty()
+                && (ast.rules().len() != 1 || !ast.imports().is_empty())
+            {
+                let end = ast.rules()[0].span().end();
+                self.report_builder.register_source(&src);
+                return Err(SyntaxError::build(
+                    &self.report_builder,
+                    "unexpected `}` in condition".to_string(),
+                    Span(end as u32 - 1..end as u32).into(),
+                ));
+            }
+        }
+
+        self.eval_condition = true;
+        let result = self.add_ast(src, ast).map(|_| ());
+        self.eval_condition = false;
+        result
+    }
+
+    /// Parses the source code, returning its AST, or an error if the
+    /// source code is not valid UTF-8.
+    fn parse<'src>(
+        src: &mut SourceCode<'src>,
+    ) -> Result<ast::AST<'src>, bstr::Utf8Error> {
+        src.as_str().map(|src| Parser::new(src.as_bytes()).into_ast())
+    }
+
+    /// Compiles the AST produced by [`Compiler::parse`] for the given source
+    /// code.
+    fn add_ast<'src>(
+        &mut self,
+        src: SourceCode<'src>,
+        ast: Result<ast::AST<'src>, bstr::Utf8Error>,
+    ) -> Result<&mut Self, CompileError> {
         // Register source code, even before validating that it is UTF-8. In
         // case of UTF-8 encoding errors we want to report that error too,
         // and we need the source code registered for creating the report.
@@ -496,11 +638,8 @@ impl<'a> Compiler<'a> {
 
         // Make sure that the source code is valid UTF-8, or return an error
         // if otherwise.
-        let ast = match src.as_str() {
-            Ok(src) => {
-                // Parse the source code and build the Abstract Syntax Tree.
-                Parser::new(src.as_bytes()).into_ast()
-            }
+        let ast = match ast {
+            Ok(ast) => ast,
             Err(err) => {
                 let span_start = err.valid_up_to();
                 let span_end = if let Some(error_len) = err.error_len() {
@@ -554,9 +693,58 @@ impl<'a> Compiler<'a> {
         // Iterate over the list of declared rules and verify that their
         // conditions are semantically valid. For each rule add a symbol
         // to the current namespace.
+        let included_rules = self.included_rules(&ast);
+
         for rule in ast.rules() {
-            if let Err(err) = self.c_rule(rule) {
-                self.errors.push(err);
+            if let Some(included_rules) = &included_rules {
+                if !included_rules.contains(rule.identifier.name) {
+                    self.excluded_rules
+                        .insert(rule.identifier.name.to_string());
+                    continue;
+                }
+            }
+
+            let suppressions = src
+                .valid
+                .map(|src| warning_suppressions(src, rule))
+                .unwrap_or_default();
+
+            self.warnings
+                .suppress(suppressions.iter().map(|(code, _)| code.as_str()));
+
+            match self.c_rule(rule) {
+                Ok(true) if self.embed_sources => {
+                    if let Some(src) = src.valid {
+                        let source = self
+                            .lit_pool
+                            .get_or_intern(&src[rule.span().range()]);
+                        self.rules.last_mut().unwrap().source = Some(source);
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => self.errors.push(err),
+            }
+
+            let used = self.warnings.unsuppress();
+
+            for ((code, span), used) in suppressions.into_iter().zip(used) {
+                if !used {
+                    self.warnings.add(|| {
+                        warnings::UnusedSuppression::build(
+                            &self.report_builder,
+                            code.clone(),
+                            rule.identifier.name.to_string(),
+                            span.into(),
+                            (!Warnings::is_valid_code(code.as_str())).then(
+                                || {
+                                    format!(
+                                        "`{code}` is not a valid warning code"
+                                    )
+                                },
+                            ),
+                        )
+                    });
+                }
             }
         }
 
@@ -676,6 +864,7 @@ impl<'a> Compiler<'a> {
             symbols: self.symbol_table.push_new(),
         };
         self.ignored_rules.clear();
+        self.excluded_rules.clear();
         self.wasm_mod.new_namespace();
         self
     }
@@ -685,25 +874,34 @@ impl<'a> Compiler<'a> {
     /// This function consumes the compiler and returns an instance of
     /// [`Rules`].
     pub fn build(self) -> Rules {
+        let mut stats = self.statistics();
+        let start = Instant::now();
+
         // Finish building the WASM module.
         let wasm_mod = self.wasm_mod.build().emit_wasm();
 
-        #[cfg(feature = "logging")]
-        let start = Instant::now();
-
         // Compile the WASM module for the current platform. This panics
         // if the WASM code is invalid, which should not happen as the code is
         // emitted by YARA itself. If this ever happens is probably because
-        // wrong WASM code is being emitted.
-        let compiled_wasm_mod = wasmtime::Module::from_binary(
-            &crate::wasm::ENGINE,
-            wasm_mod.as_slice(),
-        )
-        .expect("WASM module is not valid");
+        // wrong WASM code is being emitted. When restricted to the minimal
+        // feature set the module is compiled with an engine that rejects
+        // any WebAssembly proposal not included in that set.
+        let engine: &wasmtime::Engine = if self.minimal_wasm_features {
+            &crate::wasm::MINIMAL_ENGINE
+        } else {
+            &crate::wasm::ENGINE
+        };
+
+        let compiled_wasm_mod =
+            wasmtime::Module::from_binary(engine, wasm_mod.as_slice())
+                .expect("WASM module is not valid");
 
         #[cfg(feature = "logging")]
         info!("WASM module build time: {:?}", Instant::elapsed(&start));
 
+        stats.codegen_time += start.elapsed();
+        stats.wasm_module_size = Some(wasm_mod.len());
+
         // The structure that contains the global variables is serialized before
         // being passed to the `Rules` struct. This is because we want `Rules`
         // to be `Send`, so that it can be shared with scanners running in
@@ -723,25 +921,62 @@ impl<'a> Compiler<'a> {
             serialized_globals,
             wasm_mod,
             compiled_wasm_mod: Some(compiled_wasm_mod),
+            merged: Vec::new(),
             relaxed_re_syntax: self.relaxed_re_syntax,
+            minimal_wasm_features: self.minimal_wasm_features,
             ac: None,
+            memmem: None,
+            rules_by_pattern: Vec::new(),
+            search_strategy: SearchStrategy::default(),
             num_patterns: self.next_pattern_id.0 as usize,
             ident_pool: self.ident_pool,
             regexp_pool: self.regexp_pool,
             lit_pool: self.lit_pool,
             imported_modules: self.imported_modules,
+            used_globals: self.used_globals,
             rules: self.rules,
             sub_patterns: self.sub_patterns,
             anchored_sub_patterns: self.anchored_sub_patterns,
             atoms: self.atoms,
             re_code: self.re_code,
+            all_matches_required: self.all_matches_required,
             warnings: self.warnings.into(),
+            stats: None,
+            content_hash: OnceLock::new(),
+            rule_ids_by_name: OnceLock::new(),
+            pattern_conditions: self.pattern_conditions,
         };
 
         rules.build_ac_automaton();
+
+        stats.automaton_size =
+            Some(rules.ac.as_ref().map(|ac| ac.memory_usage()).unwrap_or(0));
+
+        rules.stats = Some(stats);
         rules
     }
 
+    /// Returns statistics about the rules compiled so far.
+    ///
+    /// The size of the WebAssembly module and the Aho-Corasick automaton are
+    /// not known until the rules are built, so they are `None` here. The
+    /// complete statistics are returned by [`Rules::statistics`].
+    ///
+    /// ```
+    /// # use yara_x::Compiler;
+    /// let mut compiler = Compiler::new();
+    ///
+    /// compiler
+    ///     .add_source(r#"rule test { strings: $a = "foo" condition: $a }"#)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(compiler.statistics().num_rules(), 1);
+    /// assert_eq!(compiler.statistics().num_literal_patterns(), 1);
+    /// ```
+    pub fn statistics(&self) -> CompilerStats {
+        CompilerStats { num_atoms: self.atoms.len(), ..self.stats.clone() }
+    }
+
     /// Tell the compiler that a YARA module is not supported.
     ///
     /// Import statements for ignored modules will be ignored without
@@ -796,6 +1031,9 @@ impl<'a> Compiler<'a> {
     /// `unsupported_module`, etc.). This function allows to enable or disable
     /// a specific type of warning identified by the given code.
     ///
+    /// Warnings can also be disabled for individual rules by putting a
+    /// comment like `// yara-x:allow(slow_pattern)` right before the rule.
+    ///
     /// Returns an error if the given warning code doesn't exist.
     pub fn switch_warning(
         &mut self,
@@ -846,6 +1084,249 @@ impl<'a> Compiler<'a> {
         self
     }
 
+    /// Makes text patterns case-insensitive by default.
+    ///
+    /// When enabled, text patterns (e.g. `$a = "foo"`) are treated as if
+    /// they had the `nocase` modifier, unless they use the `case` modifier,
+    /// which makes them case-sensitive again. Text patterns that use `xor`,
+    /// `base64` or `base64wide`, which can't be combined with `nocase`, are
+    /// not affected, and neither are hex patterns and regular expressions.
+    ///
+    /// Notice that this changes the meaning of existing rules, the same
+    /// source code produces different matches depending on this setting.
+    /// The setting applies to the rules added after calling this function.
+    ///
+    /// This is disabled by default.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use yara_x::{Compiler, Scanner};
+    /// let mut compiler = Compiler::new();
+    ///
+    /// compiler
+    ///     .nocase_by_default(true)
+    ///     .add_source(r#"
+    ///         rule test {
+    ///           strings:
+    ///             $a = "Foo"
+    ///             $b = "Bar" case
+    ///           condition:
+    ///             $a and not $b
+    ///         }"#)
+    ///     .unwrap();
+    ///
+    /// let rules = compiler.build();
+    /// let mut scanner = Scanner::new(&rules);
+    ///
+    /// assert_eq!(scanner.scan(b"foo bar").unwrap().matching_rules().len(), 1);
+    /// ```
+    pub fn nocase_by_default(&mut self, yes: bool) -> &mut Self {
+        self.nocase_by_default = yes;
+        self
+    }
+
+    /// Raises a [`warnings::ComplexCondition`] warning for every rule whose
+    /// condition has more than `limit` nodes in its syntax tree.
+    ///
+    /// The number of nodes is a rough measure of the condition's size. For
+    /// instance, `$a or $b or $c` has four nodes: one for the `or` and one for
+    /// each operand. This is disabled by default, pass `None` for disabling
+    /// it again.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use yara_x::Compiler;
+    /// let mut compiler = Compiler::new();
+    ///
+    /// compiler
+    ///     .condition_complexity_limit(Some(5))
+    ///     .add_source(
+    ///         "rule test { condition: filesize > 0 or filesize < 10 }",
+    ///     )
+    ///     .unwrap();
+    ///
+    /// assert_eq!(compiler.warnings().len(), 1);
+    /// ```
+    pub fn condition_complexity_limit(
+        &mut self,
+        limit: Option<usize>,
+    ) -> &mut Self {
+        self.condition_complexity_limit = limit;
+        self
+    }
+
+    /// Sets the maximum complexity of regexp and hex patterns, measured as
+    /// the size in bytes of the code they compile to.
+    ///
+    /// Patterns that exceed the limit produce a [`RegexTooComplex`]
+    /// error. The code for a pattern is what the scanner executes for
+    /// verifying each potential match, so this also caps the work done per
+    /// match at scan time. Large bounded repetitions like `a{1,5000}` are the
+    /// most common source of large code, as the repeated expression is
+    /// copied once per repetition. This is disabled by default, pass `None`
+    /// for disabling it again.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use yara_x::Compiler;
+    /// let mut compiler = Compiler::new();
+    ///
+    /// compiler.regex_complexity_limit(Some(1000));
+    ///
+    /// assert!(compiler
+    ///     .add_source(r#"rule t { strings: $a = /ab{1,10}c/ condition: $a }"#)
+    ///     .is_ok());
+    ///
+    /// assert!(compiler
+    ///     .add_source(r#"rule u { strings: $a = /ab{1,5000}c/ condition: $a }"#)
+    ///     .is_err());
+    /// ```
+    pub fn regex_complexity_limit(
+        &mut self,
+        limit: Option<usize>,
+    ) -> &mut Self {
+        self.regex_complexity_limit = limit;
+        self
+    }
+
+    /// Raises a [`warnings::RedundantAlternation`] warning for every regexp
+    /// pattern that contains an alternative that is equivalent to some other
+    /// alternative in the same alternation, like in `/(abc|abd|abc)/`.
+    ///
+    /// Such alternatives don't change what the regexp matches, but they are
+    /// common in rules generated by tools, and removing them makes the rules
+    /// easier to read. This is disabled by default.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use yara_x::Compiler;
+    /// let mut compiler = Compiler::new();
+    ///
+    /// compiler
+    ///     .detect_redundant_alternations(true)
+    ///     .add_source(r#"rule test { strings: $a = /(abc|abd|abc)/ condition: $a }"#)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(compiler.warnings().len(), 1);
+    /// ```
+    pub fn detect_redundant_alternations(&mut self, yes: bool) -> &mut Self {
+        self.detect_redundant_alternations = yes;
+        self
+    }
+
+    /// Compiles only the rules whose names match some of the given glob
+    /// patterns.
+    ///
+    /// Patterns can contain `*`, which matches any sequence of characters,
+    /// and `?`, which matches a single character. Rules that don't match
+    /// any pattern are skipped after parsing, so they don't produce any code
+    /// and their patterns are not searched during the scan. However, rules
+    /// that are used in the condition of some included rule are included
+    /// too, as long as they are in the same source. Depending on a rule that
+    /// was excluded while compiling a previous source produces an error.
+    /// Global rules are always included, no matter their names, because
+    /// leaving them out would change the results of the other rules in the
+    /// same namespace.
+    ///
+    /// Each call replaces the patterns passed in previous calls, and they
+    /// apply to the sources added afterwards. An empty slice disables the
+    /// filter.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use yara_x::Compiler;
+    /// let mut compiler = Compiler::new();
+    ///
+    /// compiler
+    ///     .include_rules(&["APT_*"])
+    ///     .add_source(r#"
+    ///         rule APT_foo { condition: true }
+    ///         rule bar { condition: true }
+    ///     "#)
+    ///     .unwrap();
+    ///
+    /// let rules = compiler.build();
+    ///
+    /// assert_eq!(rules.iter().map(|r| r.identifier()).collect::<Vec<_>>(), ["APT_foo"]);
+    /// ```
+    pub fn include_rules<P: AsRef<str>>(
+        &mut self,
+        patterns: &[P],
+    ) -> &mut Self {
+        self.include_rules =
+            patterns.iter().map(|p| p.as_ref().to_string()).collect();
+        self
+    }
+
+    /// When enabled, the source code of each rule is embedded in the
+    /// compiled rules, including when they are serialized.
+    ///
+    /// The source code of a rule can be retrieved later with
+    /// [`crate::Rule::source`]. This is useful for tools that need to show
+    /// the matching rules to the user, but it makes the compiled rules
+    /// larger.
+    ///
+    /// This is disabled by default.
+    pub fn embed_sources(&mut self, yes: bool) -> &mut Self {
+        self.embed_sources = yes;
+        self
+    }
+
+    /// When enabled, the structure of each hex pattern is stored in the
+    /// compiled rules, including when they are serialized.
+    ///
+    /// The structure of a hex pattern can be retrieved later with
+    /// [`crate::Pattern::hex_tokens`]. This is useful for tools that analyze
+    /// or transform hex patterns, but it makes the compiled rules larger.
+    ///
+    /// This is disabled by default.
+    pub fn hex_pattern_structure(&mut self, yes: bool) -> &mut Self {
+        self.hex_pattern_structure = yes;
+        self
+    }
+
+    /// Determines how metadata keys that appear more than once in the same
+    /// rule are exposed by [`crate::Rule::metadata`].
+    ///
+    /// The default is [`DuplicateMetadata::All`], which is compatible with
+    /// YARA.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use yara_x::{Compiler, DuplicateMetadata};
+    /// let mut compiler = Compiler::new();
+    ///
+    /// compiler
+    ///     .duplicate_metadata(DuplicateMetadata::Last)
+    ///     .add_source(r#"
+    ///         rule test {
+    ///           meta:
+    ///             author = "foo"
+    ///             author = "bar"
+    ///           condition:
+    ///             true
+    ///         }"#)
+    ///     .unwrap();
+    ///
+    /// let rules = compiler.build();
+    /// let rule = rules.iter().next().unwrap();
+    ///
+    /// assert_eq!(rule.metadata().len(), 1);
+    /// ```
+    pub fn duplicate_metadata(
+        &mut self,
+        policy: DuplicateMetadata,
+    ) -> &mut Self {
+        self.duplicate_metadata = policy;
+        self
+    }
+
     /// When enabled, potentially slow loops produce an error instead of a
     /// warning.
     ///
@@ -855,6 +1336,30 @@ impl<'a> Compiler<'a> {
         self
     }
 
+    /// Restricts the generated code to a minimal set of WebAssembly
+    /// features.
+    ///
+    /// When enabled, the WASM module produced by the compiler uses only the
+    /// features in the 1.0 version of the WebAssembly specification, without
+    /// SIMD, bulk memory operations, reference types or multi-value, and it
+    /// contains no custom sections. Functions that return multiple values,
+    /// like a value and a flag that indicates whether it is undefined, are
+    /// called through variants that return one value at a time. The module
+    /// is compiled and executed with a configuration that disables SIMD,
+    /// multi-value, multiple memories, 64-bit memories, tail calls and
+    /// extended constant expressions. This makes the compiled rules more
+    /// portable across different versions of `wasmtime`, at the expense of
+    /// some performance. The setting is preserved when the rules are
+    /// serialized, and [`Rules::deserialize`] returns an error if the WASM
+    /// code in the serialized rules uses any of the disabled features.
+    ///
+    /// This is disabled by default.
+    pub fn minimal_wasm_features(&mut self, yes: bool) -> &mut Self {
+        self.minimal_wasm_features = yes;
+        self.wasm_mod.minimal_features(yes);
+        self
+    }
+
     /// Retrieves all errors generated by the compiler.
     ///
     /// This method returns every error encountered during the compilation,
@@ -1053,7 +1558,63 @@ impl<'a> Compiler<'a> {
 }
 
 impl<'a> Compiler<'a> {
-    fn c_rule(&mut self, rule: &ast::Rule) -> Result<(), CompileError> {
+    /// Returns the names of the rules in `ast` that must be compiled
+    /// according to [`Compiler::include_rules`], or `None` if all of them
+    /// must be compiled.
+    ///
+    /// The result includes the rules that match the patterns, and any other
+    /// rule in `ast` they depend on, directly or indirectly.
+    fn included_rules<'src>(
+        &self,
+        ast: &ast::AST<'src>,
+    ) -> Option<FxHashSet<&'src str>> {
+        if self.include_rules.is_empty() {
+            return None;
+        }
+
+        let rules_by_name: FxHashMap<&str, &ast::Rule> = ast
+            .rules()
+            .iter()
+            .map(|rule| (rule.identifier.name, rule))
+            .collect();
+
+        let mut pending: Vec<&ast::Rule> = ast
+            .rules()
+            .iter()
+            .filter(|rule| {
+                rule.flags.contains(RuleFlag::Global)
+                    || self.include_rules.iter().any(|pattern| {
+                        glob_match(pattern, rule.identifier.name)
+                    })
+            })
+            .collect();
+
+        let mut included = FxHashSet::default();
+
+        while let Some(rule) = pending.pop() {
+            if !included.insert(rule.identifier.name) {
+                continue;
+            }
+            // Any identifier in the condition that is the name of a rule in
+            // the same source is a dependency. Identifiers that are actually
+            // fields or loop variables with the same name as some rule only
+            // cause that rule to be included unnecessarily.
+            let mut exprs = vec![&rule.condition];
+            while let Some(expr) = exprs.pop() {
+                if let ast::Expr::Ident(ident) = expr {
+                    if let Some(dependency) = rules_by_name.get(ident.name) {
+                        pending.push(dependency);
+                    }
+                }
+                exprs.extend(expr.children());
+            }
+        }
+
+        Some(included)
+    }
+
+    /// Compiles a rule, returning false if the rule was ignored.
+    fn c_rule(&mut self, rule: &ast::Rule) -> Result<bool, CompileError> {
         // Check if another rule, module or variable has the same identifier
         // and return an error in that case.
         self.check_for_existing_identifier(&rule.identifier)?;
@@ -1090,7 +1651,7 @@ impl<'a> Compiler<'a> {
             .iter()
             .flatten()
             .map(|m| {
-                (
+                Ok((
                     self.ident_pool.get_or_intern(m.identifier.name),
                     match &m.value {
                         ast::MetaValue::Integer(i) => MetaValue::Integer(*i),
@@ -1102,10 +1663,15 @@ impl<'a> Compiler<'a> {
                         ast::MetaValue::Bytes(s) => {
                             MetaValue::Bytes(self.lit_pool.get_or_intern(s))
                         }
+                        ast::MetaValue::Ident(ident) => {
+                            self.meta_value_from_global(ident)?
+                        }
                     },
-                )
+                ))
             })
-            .collect();
+            .collect::<Result<Vec<_>, CompileError>>()?;
+
+        let meta = self.duplicate_metadata.apply(meta);
 
         // Add the new rule to `self.rules`. The only information about the
         // rule that we don't have right now is the PatternId corresponding to
@@ -1128,13 +1694,18 @@ impl<'a> Compiler<'a> {
             is_global: rule.flags.contains(RuleFlag::Global),
             is_private: rule.flags.contains(RuleFlag::Private),
             metadata: meta,
+            hex_patterns: vec![],
+            source: None,
         });
 
         let mut rule_patterns = Vec::new();
+        let mut root_idents = Vec::new();
 
         let mut ctx = CompileContext {
             relaxed_re_syntax: self.relaxed_re_syntax,
+            detect_redundant_alternations: self.detect_redundant_alternations,
             error_on_slow_loop: self.error_on_slow_loop,
+            nocase_by_default: self.nocase_by_default,
             current_symbol_table: None,
             symbol_table: &mut self.symbol_table,
             report_builder: &self.report_builder,
@@ -1142,6 +1713,7 @@ impl<'a> Compiler<'a> {
             warnings: &mut self.warnings,
             vars: VarStack::new(),
             for_of_depth: 0,
+            root_idents: &mut root_idents,
         };
 
         // Convert the patterns from AST to IR. This populates the
@@ -1152,6 +1724,8 @@ impl<'a> Compiler<'a> {
             return Err(err);
         };
 
+        let resolve_start = Instant::now();
+
         // Convert the rule condition's AST to the intermediate representation
         // (IR). Also updates the patterns with information about whether they
         // are used in the condition and if they are anchored or not.
@@ -1248,7 +1822,17 @@ impl<'a> Compiler<'a> {
                     );
                 }
 
-                return Ok(());
+                return Ok(false);
+            }
+            Err(CompileError::UnknownIdentifier(unknown))
+                if self.excluded_rules.contains(unknown.identifier()) =>
+            {
+                self.restore_snapshot(snapshot);
+                return Err(ExcludedRuleDependency::build(
+                    &self.report_builder,
+                    unknown.identifier().to_string(),
+                    unknown.identifier_location().clone(),
+                ));
             }
             Err(err) => {
                 self.restore_snapshot(snapshot);
@@ -1256,6 +1840,17 @@ impl<'a> Compiler<'a> {
             }
         };
 
+        // Identifiers in the root structure that are not modules are global
+        // variables.
+        for ident in root_idents {
+            let ident_id = self.ident_pool.get_or_intern(&ident);
+            if !self.imported_modules.contains(&ident_id)
+                && !self.used_globals.contains(&ident_id)
+            {
+                self.used_globals.push(ident_id);
+            }
+        }
+
         #[cfg(test)]
         if let Some(w) = &mut self.ir_writer {
             writeln!(w, "RULE {}", rule.identifier.name).unwrap();
@@ -1281,6 +1876,26 @@ impl<'a> Compiler<'a> {
             });
         }
 
+        // Complexity is measured on the AST, before any optimization is
+        // applied to the condition.
+        if let Some(limit) = self.condition_complexity_limit {
+            let num_nodes = rule.condition.num_nodes();
+            if num_nodes > limit {
+                self.warnings.add(|| {
+                    warnings::ComplexCondition::build(
+                        &self.report_builder,
+                        num_nodes,
+                        rule.condition.span().into(),
+                        Some(
+                            "consider using `of` quantifiers (e.g. \
+                             `any of ($a*)`) for simplifying the condition"
+                                .to_string(),
+                        ),
+                    )
+                });
+            }
+        }
+
         // Create a new symbol of bool type for the rule.
         let new_symbol = Symbol::new(
             TypeValue::Bool(Value::Unknown),
@@ -1299,6 +1914,25 @@ impl<'a> Compiler<'a> {
         // No other symbol with the same identifier should exist.
         assert!(existing_symbol.is_none());
 
+        // Patterns whose identifiers start with underscore can be unused, but
+        // if the condition doesn't use any pattern it is probably wrong.
+        if !rule_patterns.is_empty()
+            && rule_patterns.iter().all(|pattern| {
+                !pattern.in_use() && pattern.identifier().starts_with("$_")
+            })
+        {
+            let patterns = rule.patterns.as_ref().unwrap();
+            let first = patterns.first().unwrap();
+            let last = patterns.last().unwrap();
+            self.warnings.add(|| {
+                warnings::PatternsNotUsedInCondition::build(
+                    &self.report_builder,
+                    rule.identifier.name.to_string(),
+                    first.span().combine(&last.span()).into(),
+                )
+            });
+        }
+
         let mut pattern_ids = Vec::with_capacity(rule_patterns.len());
         let mut pending_patterns = HashSet::new();
 
@@ -1348,11 +1982,29 @@ impl<'a> Compiler<'a> {
         // from each pattern, adding them to the `self.atoms` vector, it
         // also creates one or more sub-patterns per pattern and add them
         // to `self.sub_patterns`
-        for (pattern_id, pattern, span) in izip!(
+        let mut num_literal_patterns = 0;
+        let mut num_hex_patterns = 0;
+        let mut num_regexp_patterns = 0;
+        let mut hex_patterns = Vec::new();
+
+        for (index, (pattern_id, pattern, ast_pattern)) in izip!(
             pattern_ids.iter(),
             rule_patterns.into_iter(),
-            rule.patterns.iter().flatten().map(|p| p.span())
-        ) {
+            rule.patterns.iter().flatten()
+        )
+        .enumerate()
+        {
+            let span = ast_pattern.span();
+            // Identical patterns in different rules share the same
+            // `PatternId`, but each rule keeps the hex pattern the way it
+            // was written.
+            if let ast::Pattern::Hex(hex) = ast_pattern {
+                hex_patterns.push((
+                    index,
+                    self.hex_pattern_structure
+                        .then(|| hex_tokens_from_ast(&hex.tokens)),
+                ));
+            }
             if pending_patterns.contains(pattern_id) {
                 self.current_pattern_id = *pattern_id;
                 let anchored_at = pattern.anchored_at();
@@ -1369,10 +2021,47 @@ impl<'a> Compiler<'a> {
                         }
                     }
                 };
+                match ast_pattern {
+                    ast::Pattern::Text(_) => num_literal_patterns += 1,
+                    ast::Pattern::Regexp(_) => num_regexp_patterns += 1,
+                    ast::Pattern::Hex(_) => num_hex_patterns += 1,
+                }
                 pending_patterns.remove(pattern_id);
             }
         }
 
+        self.rules.last_mut().unwrap().hex_patterns = hex_patterns;
+
+        self.stats.resolve_time += resolve_start.elapsed();
+        self.stats.num_rules += 1;
+        self.stats.num_literal_patterns += num_literal_patterns;
+        self.stats.num_hex_patterns += num_hex_patterns;
+        self.stats.num_regexp_patterns += num_regexp_patterns;
+
+        // Patterns used in a condition that needs to know how many times
+        // they matched, or where, must track all their matches even if the
+        // scanner runs in fast mode.
+        if condition.requires_all_matches() {
+            self.all_matches_required.extend(pattern_ids.iter());
+        }
+
+        // Global rules affect the result of other rules, and they can't be
+        // evaluated without WASM code. Neither can the conditions whose value
+        // is returned by `Scanner::eval_condition`.
+        let rule_info = self.rules.last().unwrap();
+        let pattern_condition = if rule_info.is_global || self.eval_condition {
+            None
+        } else {
+            condition.pattern_condition(&|index| {
+                rule_info.patterns[index.as_usize()].1
+            })
+        };
+
+        match (&mut self.pattern_conditions, pattern_condition) {
+            (Some(conditions), Some(condition)) => conditions.push(condition),
+            _ => self.pattern_conditions = None,
+        }
+
         // The last step is emitting the WASM code corresponding to the rule's
         // condition. This is done after every fallible function has been called
         // because once the code is emitted it cannot be undone, which means
@@ -1389,14 +2078,93 @@ impl<'a> Compiler<'a> {
             lookup_list: Vec::new(),
         };
 
-        emit_rule_condition(
-            &mut ctx,
-            &mut self.wasm_mod,
-            rule_id,
-            &mut condition,
-        );
+        let codegen_start = Instant::now();
 
-        Ok(())
+        if self.eval_condition {
+            emit_eval_condition(
+                &mut ctx,
+                &mut self.wasm_mod,
+                rule_id,
+                &mut condition,
+            );
+        } else {
+            emit_rule_condition(
+                &mut ctx,
+                &mut self.wasm_mod,
+                rule_id,
+                &mut condition,
+            );
+        }
+
+        self.stats.codegen_time += codegen_start.elapsed();
+
+        Ok(true)
+    }
+
+    /// Resolves a metadata value that refers to a global variable, like
+    /// `author = external_author`, into the value that the variable has
+    /// at compile time.
+    fn meta_value_from_global(
+        &mut self,
+        ident: &Ident,
+    ) -> Result<MetaValue, CompileError> {
+        let type_value =
+            match self
+                .root_struct
+                .field_by_name(ident.name)
+                .map(|field| &field.type_value)
+            {
+                Some(type_value) => type_value,
+                None => return Err(UnknownIdentifier::build(
+                    &self.report_builder,
+                    ident.name.to_string(),
+                    ident.span().into(),
+                    Some(
+                        "metadata values can refer only to global variables"
+                            .to_string(),
+                    ),
+                )),
+            };
+
+        // Only global variables of a primitive type can be used as metadata
+        // values. Imported modules also live in the root structure, but they
+        // are structures.
+        let meta_value = match type_value {
+            TypeValue::Integer(_) => {
+                type_value.try_as_integer().map(MetaValue::Integer)
+            }
+            TypeValue::Float(_) => {
+                type_value.try_as_float().map(MetaValue::Float)
+            }
+            TypeValue::Bool(_) => {
+                type_value.try_as_bool().map(MetaValue::Bool)
+            }
+            TypeValue::String(_) => {
+                type_value.try_as_string().map(|s| match s.to_str() {
+                    Ok(s) => MetaValue::String(self.lit_pool.get_or_intern(s)),
+                    Err(_) => MetaValue::Bytes(
+                        self.lit_pool.get_or_intern(s.as_bstr()),
+                    ),
+                })
+            }
+            _ => None,
+        };
+
+        meta_value.ok_or_else(|| {
+            WrongType::build(
+                &self.report_builder,
+                format!(
+                    "`{}`, `{}`, `{}` or `{}`",
+                    Type::Bool,
+                    Type::Integer,
+                    Type::Float,
+                    Type::String
+                ),
+                format!("`{}`", type_value.ty()),
+                ident.span().into(),
+                None,
+            )
+        })
     }
 
     fn c_import(&mut self, import: &Import) -> Result<(), CompileError> {
@@ -1611,11 +2379,13 @@ impl<'a> Compiler<'a> {
                                     .lit_pool
                                     .get_or_intern(alphabet),
                                 padding,
+                                flags,
                             }
                         } else {
                             SubPattern::Base64 {
                                 pattern: pattern_lit_id,
                                 padding,
+                                flags,
                             }
                         };
 
@@ -1649,11 +2419,13 @@ impl<'a> Compiler<'a> {
                                     .lit_pool
                                     .get_or_intern(alphabet),
                                 padding,
+                                flags,
                             }
                         } else {
                             SubPattern::Base64Wide {
                                 pattern: pattern_lit_id,
                                 padding,
+                                flags,
                             }
                         };
 
@@ -2011,6 +2783,8 @@ impl<'a> Compiler<'a> {
         // for `FastVM` first, if it fails with `Error::FastIncompatible`, the
         // regexp is not compatible for `FastVM` and `PikeVM` must be used
         // instead.
+        let code_start = self.re_code.len();
+
         #[cfg(feature = "fast-regexp")]
         let (result, is_fast_regexp) = match re::fast::Compiler::new()
             .compile(hir, &mut self.re_code)
@@ -2038,6 +2812,18 @@ impl<'a> Compiler<'a> {
             _ => unreachable!(),
         })?;
 
+        if let Some(limit) = self.regex_complexity_limit {
+            let size = self.re_code.len() - code_start;
+            if size > limit {
+                return Err(RegexTooComplex::build(
+                    &self.report_builder,
+                    size,
+                    limit,
+                    (&span).into(),
+                ));
+            }
+        }
+
         if matches!(hir.minimum_len(), Some(0)) {
             return Err(InvalidRegexp::build(
                 &self.report_builder,
@@ -2120,6 +2906,106 @@ impl<'a> Compiler<'a> {
     }
 }
 
+/// Returns true if `name` matches the glob `pattern`, where `*` matches any
+/// sequence of characters and `?` matches a single character.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // Position of the last `*` in the pattern, and the position in the name
+    // where it started matching.
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            // Mismatch, make the last `*` match one more character.
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Returns the warning codes in `// yara-x:allow(...)` comments that
+/// immediately precede the given rule in the source code.
+///
+/// Multiple codes can be separated by commas, like in
+/// `// yara-x:allow(slow_pattern, invariant_expr)`. Each code is returned
+/// with the span of the comment where it appears.
+fn warning_suppressions(src: &str, rule: &ast::Rule) -> Vec<(String, Span)> {
+    let head = src[..rule.span().start()].trim_end();
+
+    let mut suppressions = Vec::new();
+
+    // Process the lines right before the rule, as long as they are comments.
+    for line in head.lines().rev() {
+        let comment = match line.trim().strip_prefix("//") {
+            Some(comment) => comment.trim(),
+            None => break,
+        };
+        let codes = comment
+            .strip_prefix("yara-x:allow(")
+            .and_then(|codes| codes.strip_suffix(')'));
+        if let Some(codes) = codes {
+            // `line` is a slice of `src`, its offset within `src` is
+            // computed from the pointers.
+            let start = line.as_ptr() as usize - src.as_ptr() as usize
+                + (line.len() - line.trim_start().len());
+            let end = start + line.trim().len();
+            for code in codes.split(',') {
+                suppressions.push((
+                    code.trim().to_string(),
+                    Span(start as u32..end as u32),
+                ));
+            }
+        }
+    }
+
+    suppressions
+}
+
+/// Converts the tokens in a hex pattern's AST into the [`HexToken`]s exposed
+/// by [`crate::Pattern::hex_tokens`].
+fn hex_tokens_from_ast(tokens: &ast::HexTokens) -> Vec<HexToken> {
+    tokens
+        .tokens
+        .iter()
+        .map(|token| match token {
+            ast::HexToken::Byte(b) if b.mask == 0xff => {
+                HexToken::Byte(b.value)
+            }
+            ast::HexToken::Byte(b) => {
+                HexToken::MaskedByte { value: b.value, mask: b.mask }
+            }
+            ast::HexToken::NotByte(b) => {
+                HexToken::NotByte { value: b.value, mask: b.mask }
+            }
+            ast::HexToken::Jump(jump) => {
+                HexToken::Jump { start: jump.start, end: jump.end }
+            }
+            ast::HexToken::Alternative(alt) => HexToken::Alternative(
+                alt.alternatives.iter().map(hex_tokens_from_ast).collect(),
+            ),
+        })
+        .collect()
+}
+
 impl fmt::Debug for Compiler<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "Compiler")
@@ -2189,6 +3075,123 @@ impl From<LiteralId> for u64 {
 #[serde(transparent)]
 pub(crate) struct NamespaceId(i32);
 
+/// Determines how metadata keys that appear more than once in the same rule
+/// are handled.
+///
+/// See [`Compiler::duplicate_metadata`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateMetadata {
+    /// All the occurrences of the key are kept, in the same order they
+    /// appear in the source code. This is what YARA does.
+    #[default]
+    All,
+    /// Only the first occurrence of the key is kept.
+    First,
+    /// Only the last occurrence of the key is kept.
+    Last,
+}
+
+impl DuplicateMetadata {
+    /// Removes the metadata entries that must be discarded according to
+    /// this policy. The remaining entries keep their relative order.
+    fn apply(
+        &self,
+        mut meta: Vec<(IdentId, MetaValue)>,
+    ) -> Vec<(IdentId, MetaValue)> {
+        match self {
+            DuplicateMetadata::All => {}
+            DuplicateMetadata::First => {
+                let mut seen = FxHashSet::default();
+                meta.retain(|(ident_id, _)| seen.insert(*ident_id));
+            }
+            DuplicateMetadata::Last => {
+                let mut seen = FxHashSet::default();
+                meta.reverse();
+                meta.retain(|(ident_id, _)| seen.insert(*ident_id));
+                meta.reverse();
+            }
+        }
+        meta
+    }
+}
+
+/// Statistics about the compilation process.
+///
+/// Returned by [`Compiler::statistics`] and [`Rules::statistics`]. Patterns
+/// that are declared multiple times, in the same rule or in different ones,
+/// are counted only once. Rules that failed to compile are not included.
+#[derive(Debug, Default, Clone)]
+pub struct CompilerStats {
+    num_rules: usize,
+    num_literal_patterns: usize,
+    num_hex_patterns: usize,
+    num_regexp_patterns: usize,
+    num_atoms: usize,
+    automaton_size: Option<usize>,
+    wasm_module_size: Option<usize>,
+    parse_time: Duration,
+    resolve_time: Duration,
+    codegen_time: Duration,
+}
+
+impl CompilerStats {
+    /// Number of rules compiled.
+    pub fn num_rules(&self) -> usize {
+        self.num_rules
+    }
+
+    /// Number of text patterns (e.g. `$a = "foo"`).
+    pub fn num_literal_patterns(&self) -> usize {
+        self.num_literal_patterns
+    }
+
+    /// Number of hex patterns (e.g. `$a = { 01 02 03 }`).
+    pub fn num_hex_patterns(&self) -> usize {
+        self.num_hex_patterns
+    }
+
+    /// Number of regular expression patterns (e.g. `$a = /foo/`).
+    pub fn num_regexp_patterns(&self) -> usize {
+        self.num_regexp_patterns
+    }
+
+    /// Number of atoms extracted from the patterns. These are the
+    /// literals searched by the Aho-Corasick automaton.
+    pub fn num_atoms(&self) -> usize {
+        self.num_atoms
+    }
+
+    /// Memory used by the Aho-Corasick automaton, in bytes. `None` until
+    /// the rules are built.
+    pub fn automaton_size(&self) -> Option<usize> {
+        self.automaton_size
+    }
+
+    /// Size of the WebAssembly module, in bytes. `None` until the rules are
+    /// built.
+    pub fn wasm_module_size(&self) -> Option<usize> {
+        self.wasm_module_size
+    }
+
+    /// Time spent parsing the source code.
+    pub fn parse_time(&self) -> Duration {
+        self.parse_time
+    }
+
+    /// Time spent resolving identifiers and types in the rule conditions,
+    /// and processing the patterns.
+    pub fn resolve_time(&self) -> Duration {
+        self.resolve_time
+    }
+
+    /// Time spent generating code. This includes the time spent emitting
+    /// the code for each rule condition and, once the rules are built, the
+    /// time spent building and compiling the WebAssembly module.
+    pub fn codegen_time(&self) -> Duration {
+        self.codegen_time
+    }
+}
+
 /// ID associated to each rule.
 #[derive(Copy, Clone, Debug, Default)]
 pub(crate) struct RuleId(i32);
@@ -2222,7 +3225,8 @@ impl From<RuleId> for i32 {
 }
 
 /// ID associated to each regexp used in a rule condition.
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
 pub(crate) struct RegexpId(i32);
 
 impl From<i32> for RegexpId {
@@ -2328,7 +3332,19 @@ impl From<PatternId> for usize {
 /// For each pattern there's one or more sub-patterns, depending on the pattern
 /// and its modifiers. For example the pattern `"foo" ascii wide` may have one
 /// subpattern for the ascii case and another one for the wide case.
-#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Default,
+    Eq,
+    Hash,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    Serialize,
+    Deserialize,
+)]
 #[serde(transparent)]
 pub(crate) struct SubPatternId(u32);
 
@@ -2418,23 +3434,27 @@ pub(crate) enum SubPattern {
     Base64 {
         pattern: LiteralId,
         padding: u8,
+        flags: SubPatternFlagSet,
     },
 
     Base64Wide {
         pattern: LiteralId,
         padding: u8,
+        flags: SubPatternFlagSet,
     },
 
     CustomBase64 {
         pattern: LiteralId,
         alphabet: LiteralId,
         padding: u8,
+        flags: SubPatternFlagSet,
     },
 
     CustomBase64Wide {
         pattern: LiteralId,
         alphabet: LiteralId,
         padding: u8,
+        flags: SubPatternFlagSet,
     },
 }
 
@@ -2450,6 +3470,91 @@ impl SubPattern {
             _ => None,
         }
     }
+
+    /// Returns a copy of this sub-pattern where literal IDs are translated
+    /// with `literal_id`, and sub-pattern IDs with `sub_pattern_id`.
+    pub(crate) fn remap<L, S>(&self, literal_id: L, sub_pattern_id: S) -> Self
+    where
+        L: Fn(LiteralId) -> LiteralId,
+        S: Fn(SubPatternId) -> SubPatternId,
+    {
+        match self {
+            SubPattern::Literal { pattern, anchored_at, flags } => {
+                SubPattern::Literal {
+                    pattern: literal_id(*pattern),
+                    anchored_at: *anchored_at,
+                    flags: *flags,
+                }
+            }
+            SubPattern::LiteralChainHead { pattern, flags } => {
+                SubPattern::LiteralChainHead {
+                    pattern: literal_id(*pattern),
+                    flags: *flags,
+                }
+            }
+            SubPattern::LiteralChainTail {
+                pattern,
+                chained_to,
+                gap,
+                flags,
+            } => SubPattern::LiteralChainTail {
+                pattern: literal_id(*pattern),
+                chained_to: sub_pattern_id(*chained_to),
+                gap: gap.clone(),
+                flags: *flags,
+            },
+            SubPattern::Regexp { flags } => {
+                SubPattern::Regexp { flags: *flags }
+            }
+            SubPattern::RegexpChainHead { flags } => {
+                SubPattern::RegexpChainHead { flags: *flags }
+            }
+            SubPattern::RegexpChainTail { chained_to, gap, flags } => {
+                SubPattern::RegexpChainTail {
+                    chained_to: sub_pattern_id(*chained_to),
+                    gap: gap.clone(),
+                    flags: *flags,
+                }
+            }
+            SubPattern::Xor { pattern, flags } => SubPattern::Xor {
+                pattern: literal_id(*pattern),
+                flags: *flags,
+            },
+            SubPattern::Base64 { pattern, padding, flags } => {
+                SubPattern::Base64 {
+                    pattern: literal_id(*pattern),
+                    padding: *padding,
+                    flags: *flags,
+                }
+            }
+            SubPattern::Base64Wide { pattern, padding, flags } => {
+                SubPattern::Base64Wide {
+                    pattern: literal_id(*pattern),
+                    padding: *padding,
+                    flags: *flags,
+                }
+            }
+            SubPattern::CustomBase64 { pattern, alphabet, padding, flags } => {
+                SubPattern::CustomBase64 {
+                    pattern: literal_id(*pattern),
+                    alphabet: literal_id(*alphabet),
+                    padding: *padding,
+                    flags: *flags,
+                }
+            }
+            SubPattern::CustomBase64Wide {
+                pattern,
+                alphabet,
+                padding,
+                flags,
+            } => SubPattern::CustomBase64Wide {
+                pattern: literal_id(*pattern),
+                alphabet: literal_id(*alphabet),
+                padding: *padding,
+                flags: *flags,
+            },
+        }
+    }
 }
 
 /// A snapshot that represents the state of the compiler at a particular moment.
@@ -2478,6 +3583,10 @@ pub(crate) struct Warnings {
     warnings: Vec<Warning>,
     max_warnings: usize,
     disabled_warnings: HashSet<String>,
+    /// Warnings suppressed for the rule being compiled. Each entry contains
+    /// the warning code and a boolean indicating whether the suppression
+    /// has been used at least once.
+    suppressed_warnings: Vec<(String, bool)>,
 }
 
 impl Default for Warnings {
@@ -2486,6 +3595,7 @@ impl Default for Warnings {
             warnings: Vec::new(),
             max_warnings: 100,
             disabled_warnings: HashSet::default(),
+            suppressed_warnings: Vec::new(),
         }
     }
 }
@@ -2499,7 +3609,13 @@ impl Warnings {
     pub fn add(&mut self, f: impl FnOnce() -> Warning) {
         if self.warnings.len() < self.max_warnings {
             let warning = f();
-            if !self.disabled_warnings.contains(warning.code()) {
+            if let Some((_, used)) = self
+                .suppressed_warnings
+                .iter_mut()
+                .find(|(code, _)| code == warning.code())
+            {
+                *used = true;
+            } else if !self.disabled_warnings.contains(warning.code()) {
                 self.warnings.push(warning);
             }
         }
@@ -2542,6 +3658,20 @@ impl Warnings {
         }
     }
 
+    /// Suppresses the warnings identified by `codes` until
+    /// [`Warnings::unsuppress`] is called.
+    pub fn suppress<'c>(&mut self, codes: impl Iterator<Item = &'c str>) {
+        self.suppressed_warnings =
+            codes.map(|code| (code.to_string(), false)).collect();
+    }
+
+    /// Removes the suppressions established by [`Warnings::suppress`],
+    /// returning a vector of booleans that indicates whether each of the
+    /// suppressions was used.
+    pub fn unsuppress(&mut self) -> Vec<bool> {
+        self.suppressed_warnings.drain(..).map(|(_, used)| used).collect()
+    }
+
     #[inline]
     pub fn as_slice(&self) -> &[Warning] {
         self.warnings.as_slice()
@@ -1,6 +1,7 @@
 use std::fmt;
 use std::io::{BufWriter, Read, Write};
 use std::slice::Iter;
+use std::sync::OnceLock;
 #[cfg(feature = "logging")]
 use std::time::Instant;
 
@@ -8,20 +9,24 @@ use aho_corasick::AhoCorasick;
 use bincode::Options;
 #[cfg(feature = "logging")]
 use log::*;
+use memchr::memmem;
 use regex_automata::meta::Regex;
+use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
 
 use crate::compiler::atoms::Atom;
-use crate::compiler::errors::SerializationError;
+use crate::compiler::errors::{MergeError, SerializationError};
 use crate::compiler::report::CodeLoc;
 use crate::compiler::warnings::Warning;
 use crate::compiler::{
-    IdentId, Imports, LiteralId, NamespaceId, PatternId, RegexpId, RuleId,
-    SubPattern, SubPatternId,
+    CompilerStats, IdentId, Imports, LiteralId, NamespaceId, PatternId,
+    RegexpId, RuleId, SubPattern, SubPatternId,
 };
-use crate::re::{BckCodeLoc, FwdCodeLoc, RegexpAtom};
+use crate::re::{BckCodeLoc, CodeLoc as _, FwdCodeLoc, RegexpAtom};
 use crate::string_pool::{BStringPool, StringPool};
-use crate::{re, types, Rule};
+use crate::variables::ValueType;
+use crate::{re, types, HexToken, Rule};
 
 /// A set of YARA rules in compiled form.
 ///
@@ -43,6 +48,11 @@ pub struct Rules {
     /// contain invalid escape sequences.
     pub(in crate::compiler) relaxed_re_syntax: bool,
 
+    /// If `true`, the WASM modules use only the minimal set of features
+    /// described in [`crate::Compiler::minimal_wasm_features`], and they
+    /// are compiled with [`crate::wasm::MINIMAL_ENGINE`].
+    pub(in crate::compiler) minimal_wasm_features: bool,
+
     /// Pool with literal strings used in the rules. Each literal has its
     /// own [`LiteralId`], which can be used for retrieving the literal
     /// string as `&BStr`.
@@ -60,10 +70,20 @@ pub struct Rules {
     )]
     pub(in crate::compiler) compiled_wasm_mod: Option<wasmtime::Module>,
 
+    /// WASM modules that evaluate the conditions of rules added with
+    /// [`Rules::merge`]. The module in `wasm_mod` evaluates the conditions
+    /// of the rules that precede the first rule evaluated by these modules.
+    pub(in crate::compiler) merged: Vec<MergedModule>,
+
     /// Vector with the names of all the imported modules. The vector contains
     /// the [`IdentId`] corresponding to the module's identifier.
     pub(in crate::compiler) imported_modules: Vec<IdentId>,
 
+    /// Vector with the names of the global variables used in rule
+    /// conditions. The vector contains the [`IdentId`] corresponding to the
+    /// variable's identifier.
+    pub(in crate::compiler) used_globals: Vec<IdentId>,
+
     /// Vector containing all the compiled rules. A [`RuleId`] is an index
     /// in this vector.
     pub(in crate::compiler) rules: Vec<RuleInfo>,
@@ -101,6 +121,11 @@ pub struct Rules {
     /// vector. This vector contains both forward and backward code.
     pub(in crate::compiler) re_code: Vec<u8>,
 
+    /// Patterns for which all the matches must be tracked, even when the
+    /// scanner is in fast mode, because some rule condition needs the number
+    /// of matches, or their offsets or lengths.
+    pub(in crate::compiler) all_matches_required: FxHashSet<PatternId>,
+
     /// A [`types::Struct`] in serialized form that contains all the global
     /// variables. Each field in the structure corresponds to a global variable
     /// defined at compile time using [`crate::compiler::Compiler`].
@@ -115,11 +140,53 @@ pub struct Rules {
     #[serde(skip)]
     pub(in crate::compiler) ac: Option<AhoCorasick>,
 
+    /// Finder used for searching the atom when the search strategy is
+    /// [`SearchStrategy::Memmem`]. Like the Aho-Corasick automaton, it is
+    /// not serialized, but rebuilt when the rules are deserialized.
+    #[serde(skip)]
+    pub(in crate::compiler) memmem: Option<memmem::Finder<'static>>,
+
+    /// Rules that contain each pattern, indexed by [`PatternId`]. Identical
+    /// patterns declared in different rules share the same [`PatternId`], so
+    /// a pattern can belong to multiple rules. This is built together with
+    /// the Aho-Corasick automaton, and it is not serialized either.
+    #[serde(skip)]
+    pub(in crate::compiler) rules_by_pattern: Vec<Vec<RuleId>>,
+
+    /// Strategy used for searching the atoms in the scanned data.
+    #[serde(skip)]
+    pub(in crate::compiler) search_strategy: SearchStrategy,
+
     /// Warnings that were produced while compiling these rules. These warnings
     /// are not serialized, rules that are obtained by deserializing previously
     /// serialized rules won't have any warnings.
     #[serde(skip)]
     pub(in crate::compiler) warnings: Vec<Warning>,
+
+    /// Statistics about the compilation process. Like warnings, these are
+    /// not serialized.
+    #[serde(skip)]
+    pub(in crate::compiler) stats: Option<CompilerStats>,
+
+    /// SHA-256 hash of the serialized rules, computed the first time that
+    /// [`Rules::content_hash`] is called.
+    #[serde(skip)]
+    pub(in crate::compiler) content_hash: OnceLock<[u8; 32]>,
+
+    /// Rules indexed by identifier, built the first time that
+    /// [`Rules::rule_ids_by_name`] is called. Rules in different namespaces
+    /// can have the same identifier, so each identifier is associated to
+    /// one or more rules.
+    #[serde(skip)]
+    pub(in crate::compiler) rule_ids_by_name:
+        OnceLock<FxHashMap<String, Vec<RuleId>>>,
+
+    /// Conditions of all the rules, indexed by [`RuleId`], when every rule
+    /// has a condition that depends only on whether some patterns matched.
+    /// In that case the scanner evaluates the conditions directly, without
+    /// executing the WASM code. It is `None` if some rule has any other
+    /// kind of condition.
+    pub(in crate::compiler) pattern_conditions: Option<Vec<PatternCondition>>,
 }
 
 impl Rules {
@@ -132,6 +199,46 @@ impl Rules {
         }
     }
 
+    /// Returns the global variables used in rule conditions, together with
+    /// their types.
+    ///
+    /// These are the variables defined with
+    /// [`crate::Compiler::define_global`] that the rules actually depend
+    /// on, which allows the host to know which of them should be set with
+    /// [`crate::Scanner::set_global`] before scanning. Variables that were
+    /// defined but never used are not included.
+    ///
+    /// ```
+    /// # use yara_x::{Compiler, ValueType};
+    /// let mut compiler = Compiler::new();
+    ///
+    /// compiler
+    ///     .define_global("foo", 1)?
+    ///     .define_global("bar", "")?
+    ///     .add_source("rule test { condition: foo == 1 }")?;
+    ///
+    /// assert_eq!(
+    ///     compiler.build().required_externals(),
+    ///     vec![("foo".to_string(), ValueType::Integer)]
+    /// );
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn required_externals(&self) -> Vec<(String, ValueType)> {
+        let globals = self.globals();
+        self.used_globals
+            .iter()
+            .map(|ident_id| {
+                let ident = self.ident_pool.get(*ident_id).unwrap();
+                let field = globals.field_by_name(ident).unwrap();
+                (
+                    ident.to_string(),
+                    ValueType::from_type_value(&field.type_value),
+                )
+            })
+            .collect()
+    }
+
     /// Warnings produced while compiling these rules.
     pub fn warnings(&self) -> &[Warning] {
         self.warnings.as_slice()
@@ -179,14 +286,37 @@ impl Rules {
         //     returned `None`.
         //
         // In both cases we try to build the module again from the data in
-        // `rules.wasm_mode`.
-        if rules.compiled_wasm_mod.is_none() {
+        // `rules.wasm_mode`. The same happens when the compiled module
+        // doesn't belong to the engine required by the rules. If the rules
+        // are restricted to the minimal set of WASM features, compiling
+        // the module fails if it uses any feature outside that set.
+        let engine = rules.wasm_engine();
+
+        if !rules
+            .compiled_wasm_mod
+            .as_ref()
+            .is_some_and(|m| wasmtime::Engine::same(m.engine(), engine))
+        {
             rules.compiled_wasm_mod = Some(wasmtime::Module::from_binary(
-                &crate::wasm::ENGINE,
+                engine,
                 rules.wasm_mod.as_slice(),
             )?);
         }
 
+        for merged in rules.merged.iter_mut() {
+            if !merged
+                .compiled_wasm_mod
+                .as_ref()
+                .is_some_and(|m| wasmtime::Engine::same(m.engine(), engine))
+            {
+                merged.compiled_wasm_mod =
+                    Some(wasmtime::Module::from_binary(
+                        engine,
+                        merged.wasm_mod.as_slice(),
+                    )?);
+            }
+        }
+
         #[cfg(feature = "logging")]
         info!("Deserialization time: {:?}", Instant::elapsed(&start));
 
@@ -226,6 +356,294 @@ impl Rules {
         Self::deserialize(bytes)
     }
 
+    /// Merges the rules in `other` into these rules.
+    ///
+    /// Once merged, scanning with these rules produces the same results as
+    /// scanning with the original rules and with `other` separately, but
+    /// the data is scanned only once. The rules in `other` are not compiled
+    /// again, their code is reused as is.
+    ///
+    /// Rules in different sets are independent from each other. Namespaces
+    /// can't be shared between the two sets, this function fails with
+    /// [`MergeError::DuplicateNamespace`] if some namespace in `other` also
+    /// exists in these rules. This can be avoided by passing some
+    /// `namespace_prefix`, which is prepended to the name of every namespace
+    /// in `other`. Global variables defined in both sets must have the same
+    /// type and initial value, or the result is a
+    /// [`MergeError::ConflictingGlobal`] error. These rules are left
+    /// untouched when some error occurs.
+    ///
+    /// The warnings produced while compiling `other` are not kept in the
+    /// merged rules.
+    ///
+    /// ```rust
+    /// # use yara_x::{compile, Scanner};
+    /// let mut rules = compile("rule foo { strings: $a = \"foo\" condition: $a }").unwrap();
+    /// let other = compile("rule bar { strings: $a = \"bar\" condition: $a }").unwrap();
+    ///
+    /// rules.merge(&other, Some("other_")).unwrap();
+    ///
+    /// let mut scanner = Scanner::new(&rules);
+    /// let results = scanner.scan(b"foobar").unwrap();
+    /// let mut matching = results.matching_rules().map(|r| (r.namespace(), r.identifier()));
+    ///
+    /// assert_eq!(matching.next(), Some(("default", "foo")));
+    /// assert_eq!(matching.next(), Some(("other_default", "bar")));
+    /// ```
+    pub fn merge(
+        &mut self,
+        other: &Rules,
+        namespace_prefix: Option<&str>,
+    ) -> Result<(), MergeError> {
+        let prefix = namespace_prefix.unwrap_or_default();
+
+        let namespaces: FxHashSet<&str> = self
+            .rules
+            .iter()
+            .map(|rule| self.ident_pool.get(rule.namespace_ident_id).unwrap())
+            .collect();
+
+        for rule in &other.rules {
+            let namespace = format!(
+                "{prefix}{}",
+                other.ident_pool.get(rule.namespace_ident_id).unwrap()
+            );
+            if namespaces.contains(namespace.as_str()) {
+                return Err(MergeError::DuplicateNamespace { namespace });
+            }
+        }
+
+        // Global variables and modules are fields in the root structure.
+        // The code in `other` refers to them by their index in the root
+        // structure of `other`, `root_fields` maps those indexes to the
+        // indexes in the merged structure.
+        let mut globals = self.globals();
+        let other_globals = other.globals();
+        let mut root_fields = Vec::new();
+
+        for (name, field) in other_globals.fields() {
+            match globals.field_and_index_by_name(name) {
+                Some((existing, index)) => {
+                    if !same_type_value(
+                        &existing.type_value,
+                        &field.type_value,
+                    ) {
+                        return Err(MergeError::ConflictingGlobal {
+                            variable: name.to_string(),
+                        });
+                    }
+                    root_fields.push(index as i32);
+                }
+                None => {
+                    root_fields.push(globals.fields().count() as i32);
+                    globals.add_field(name, field.type_value.clone());
+                }
+            }
+        }
+
+        // From this point on nothing can fail.
+        let idents = self.ident_pool.merge(&other.ident_pool);
+        let literals = self.lit_pool.merge(&other.lit_pool);
+        let regexps = self.regexp_pool.merge(&other.regexp_pool);
+
+        let ident_id = |id: IdentId| idents[u32::from(id) as usize];
+        let literal_id = |id: LiteralId| literals[u32::from(id) as usize];
+
+        let first_rule = self.rules.len();
+        let first_pattern = self.num_patterns;
+        let first_sub_pattern = self.sub_patterns.len() as u32;
+        let re_code_offset = self.re_code.len();
+
+        let first_namespace = self
+            .rules
+            .iter()
+            .map(|rule| rule.namespace_id.0 + 1)
+            .max()
+            .unwrap_or(0);
+
+        let pattern_id =
+            |id: PatternId| PatternId(id.0 + first_pattern as i32);
+        let sub_pattern_id =
+            |id: SubPatternId| SubPatternId(id.0 + first_sub_pattern);
+
+        for rule in &other.rules {
+            let namespace = format!(
+                "{prefix}{}",
+                other.ident_pool.get(rule.namespace_ident_id).unwrap()
+            );
+            self.rules.push(RuleInfo {
+                namespace_id: NamespaceId(
+                    rule.namespace_id.0 + first_namespace,
+                ),
+                namespace_ident_id: self
+                    .ident_pool
+                    .get_or_intern(namespace.as_str()),
+                ident_id: ident_id(rule.ident_id),
+                tags: rule.tags.iter().map(|tag| ident_id(*tag)).collect(),
+                ident_ref: rule.ident_ref.clone(),
+                metadata: rule
+                    .metadata
+                    .iter()
+                    .map(|(ident, value)| {
+                        (ident_id(*ident), value.remap(literal_id))
+                    })
+                    .collect(),
+                patterns: rule
+                    .patterns
+                    .iter()
+                    .map(|(ident, id)| (ident_id(*ident), pattern_id(*id)))
+                    .collect(),
+                hex_patterns: rule.hex_patterns.clone(),
+                is_global: rule.is_global,
+                is_private: rule.is_private,
+                source: rule.source.map(literal_id),
+            });
+        }
+
+        for (id, sub_pattern) in &other.sub_patterns {
+            self.sub_patterns.push((
+                pattern_id(*id),
+                sub_pattern.remap(literal_id, sub_pattern_id),
+            ));
+        }
+
+        self.anchored_sub_patterns.extend(
+            other.anchored_sub_patterns.iter().map(|id| sub_pattern_id(*id)),
+        );
+
+        self.atoms.extend(other.atoms.iter().map(|atom| SubPatternAtom {
+            sub_pattern_id: sub_pattern_id(atom.sub_pattern_id),
+            atom: atom.atom.clone(),
+            fwd_code:
+                atom.fwd_code.map(|loc| {
+                    FwdCodeLoc::from(loc.location() + re_code_offset)
+                }),
+            bck_code:
+                atom.bck_code.map(|loc| {
+                    BckCodeLoc::from(loc.location() + re_code_offset)
+                }),
+        }));
+
+        self.re_code.extend_from_slice(other.re_code.as_slice());
+
+        self.all_matches_required.extend(
+            other.all_matches_required.iter().map(|id| pattern_id(*id)),
+        );
+
+        for module in other.imports() {
+            let module = self.ident_pool.get_or_intern(module);
+            if !self.imported_modules.contains(&module) {
+                self.imported_modules.push(module);
+            }
+        }
+
+        for ident in other.used_globals.iter() {
+            let ident = other.ident_pool.get(*ident).unwrap();
+            let ident = self.ident_pool.get_or_intern(ident);
+            if !self.used_globals.contains(&ident) {
+                self.used_globals.push(ident);
+            }
+        }
+
+        // The main module in `other` becomes a merged module, and the
+        // modules previously merged into `other` are merged too, with
+        // their IDs translated once more.
+        self.merged.push(MergedModule {
+            wasm_mod: other.wasm_mod.clone(),
+            compiled_wasm_mod: other.compiled_wasm_mod.clone(),
+            first_rule,
+            num_rules: other
+                .merged
+                .first()
+                .map_or(other.rules.len(), |m| m.first_rule),
+            first_pattern,
+            num_patterns: other
+                .merged
+                .first()
+                .map_or(other.num_patterns, |m| m.first_pattern),
+            literals: literals.clone(),
+            regexps: regexps.clone(),
+            root_fields: root_fields.clone(),
+        });
+
+        for merged in &other.merged {
+            self.merged.push(MergedModule {
+                wasm_mod: merged.wasm_mod.clone(),
+                compiled_wasm_mod: merged.compiled_wasm_mod.clone(),
+                first_rule: first_rule + merged.first_rule,
+                num_rules: merged.num_rules,
+                first_pattern: first_pattern + merged.first_pattern,
+                num_patterns: merged.num_patterns,
+                literals: merged
+                    .literals
+                    .iter()
+                    .map(|id| literal_id(*id))
+                    .collect(),
+                regexps: merged
+                    .regexps
+                    .iter()
+                    .map(|id| regexps[usize::from(*id)])
+                    .collect(),
+                root_fields: merged
+                    .root_fields
+                    .iter()
+                    .map(|index| root_fields[*index as usize])
+                    .collect(),
+            });
+        }
+
+        self.num_patterns += other.num_patterns;
+        self.relaxed_re_syntax |= other.relaxed_re_syntax;
+
+        // All the modules must be compiled with the same engine. If only
+        // one of the sets of rules is restricted to the minimal set of WASM
+        // features, the modules compiled with the minimal engine are
+        // compiled again with the default one, which supports a superset
+        // of those features.
+        if self.minimal_wasm_features != other.minimal_wasm_features {
+            self.minimal_wasm_features = false;
+            let engine = self.wasm_engine();
+            let modules =
+                std::iter::once((&self.wasm_mod, &mut self.compiled_wasm_mod))
+                    .chain(
+                        self.merged
+                            .iter_mut()
+                            .map(|m| (&m.wasm_mod, &mut m.compiled_wasm_mod)),
+                    );
+            for (wasm_mod, compiled_wasm_mod) in modules {
+                if !compiled_wasm_mod.as_ref().is_some_and(|m| {
+                    wasmtime::Engine::same(m.engine(), engine)
+                }) {
+                    *compiled_wasm_mod = Some(
+                        wasmtime::Module::from_binary(
+                            engine,
+                            wasm_mod.as_slice(),
+                        )
+                        .expect("WASM module is not valid"),
+                    );
+                }
+            }
+        }
+        self.serialized_globals = bincode::DefaultOptions::new()
+            .serialize(&globals)
+            .expect("failed to serialize global variables");
+
+        // The conditions of the merged rules are evaluated by their own
+        // WASM module, so the scanner can't skip WASM anymore.
+        self.pattern_conditions = None;
+
+        // Statistics don't describe the merged rules anymore, and the
+        // automaton and the content hash must be computed again.
+        self.stats = None;
+        self.content_hash = OnceLock::new();
+        self.rule_ids_by_name = OnceLock::new();
+        self.ac = None;
+        self.memmem = None;
+        self.build_ac_automaton();
+
+        Ok(())
+    }
+
     /// Returns an iterator that yields the compiled rules.
     ///
     /// ```rust
@@ -296,28 +714,44 @@ impl Rules {
     }
 
     /// Given a [`SubPatternId`], returns the [`RuleId`] corresponding to the
-    /// rule that contains the sub-pattern, and the [`IdentId`] for the pattern's
-    /// identifier.
-    ///
-    /// This operation is slow, because it implies iterating over all the rules
-    /// and their sub-patterns until finding the one we are looking for.
+    /// first rule that contains the sub-pattern, and the [`IdentId`] for the
+    /// pattern's identifier in that rule.
     #[cfg(feature = "logging")]
     pub(crate) fn get_rule_and_pattern_by_sub_pattern_id(
         &self,
         sub_pattern_id: SubPatternId,
     ) -> Option<(RuleId, IdentId)> {
         let (target_pattern_id, _) = self.get_sub_pattern(sub_pattern_id);
-        for (rule_id, rule) in self.rules.iter().enumerate() {
-            for (ident_id, pattern_id) in &rule.patterns {
-                if pattern_id == target_pattern_id {
-                    return Some((rule_id.into(), *ident_id));
-                };
-            }
-        }
-        None
+        let rule_id = *self.rules_for_pattern(*target_pattern_id).first()?;
+        self.get(rule_id)
+            .patterns
+            .iter()
+            .find(|(_, pattern_id)| pattern_id == target_pattern_id)
+            .map(|(ident_id, _)| (rule_id, *ident_id))
     }
 
-    #[cfg(feature = "rules-profiling")]
+    /// Returns the rules that contain the given pattern, in the order in
+    /// which they were added to the compiler.
+    #[inline]
+    pub(crate) fn rules_for_pattern(
+        &self,
+        pattern_id: PatternId,
+    ) -> &[RuleId] {
+        self.rules_by_pattern
+            .get(pattern_id.0 as usize)
+            .map_or(&[], |rules| rules.as_slice())
+    }
+
+    /// Returns the conditions of all the rules, indexed by [`RuleId`], if
+    /// all of them can be evaluated without executing WASM code.
+    ///
+    /// See [`PatternCondition`].
+    #[inline]
+    pub(crate) fn pattern_conditions(&self) -> Option<&[PatternCondition]> {
+        self.pattern_conditions.as_deref()
+    }
+
+    #[cfg(any(feature = "rules-profiling", feature = "async"))]
     #[inline]
     pub(crate) fn rules(&self) -> &[RuleInfo] {
         self.rules.as_slice()
@@ -343,11 +777,95 @@ impl Rules {
         self.rules.len()
     }
 
+    /// Returns true if the scanner can stop tracking matches for the given
+    /// pattern after its first match when running in fast mode.
+    #[inline]
+    pub(crate) fn single_match_is_enough(
+        &self,
+        pattern_id: PatternId,
+    ) -> bool {
+        !self.all_matches_required.contains(&pattern_id)
+    }
+
     #[inline]
     pub(crate) fn num_patterns(&self) -> usize {
         self.num_patterns
     }
 
+    /// Returns statistics about the compilation of these rules.
+    ///
+    /// Returns `None` if the rules were obtained by deserializing previously
+    /// serialized rules.
+    pub fn statistics(&self) -> Option<&CompilerStats> {
+        self.stats.as_ref()
+    }
+
+    /// Returns a SHA-256 hash that identifies these rules.
+    ///
+    /// The hash is computed over the serialized form of the rules (see
+    /// [`Rules::serialize`]), so two sets of rules produced from the same
+    /// source code by the same version of YARA-X have the same hash, even
+    /// across different processes. The hash is computed only the first time
+    /// this function is called.
+    pub fn content_hash(&self) -> &[u8; 32] {
+        self.content_hash.get_or_init(|| {
+            let mut hasher = Sha256::new();
+            hasher.update(
+                self.serialize().expect("rules should be serializable"),
+            );
+            hasher.finalize().into()
+        })
+    }
+
+    /// Returns the rules that are named `name`, which can be a rule
+    /// identifier, or a rule identifier qualified with its namespace, like
+    /// in `namespace:rule`.
+    pub(crate) fn rule_ids_by_name<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = RuleId> + 'a {
+        let (namespace, ident) = match name.rsplit_once(':') {
+            Some((namespace, ident)) => (Some(namespace), ident),
+            None => (None, name),
+        };
+
+        let rule_ids = self.rule_ids_by_name.get_or_init(|| {
+            let mut rule_ids = FxHashMap::<String, Vec<RuleId>>::default();
+            for (rule_id, rule) in self.rules.iter().enumerate() {
+                rule_ids
+                    .entry(self.ident_pool.get(rule.ident_id).unwrap().into())
+                    .or_default()
+                    .push(rule_id.into());
+            }
+            rule_ids
+        });
+
+        rule_ids.get(ident).into_iter().flatten().copied().filter(
+            move |rule_id| {
+                namespace.map_or(true, |namespace| {
+                    let rule = self.get(*rule_id);
+                    self.ident_pool.get(rule.namespace_ident_id)
+                        == Some(namespace)
+                })
+            },
+        )
+    }
+
+    /// Returns the strategy used for searching the patterns in the scanned
+    /// data.
+    ///
+    /// The strategy is chosen when the rules are built, or deserialized,
+    /// according to the number of atoms extracted from the patterns.
+    ///
+    /// ```rust
+    /// # use yara_x::{compile, SearchStrategy};
+    /// let rules = compile(r#"rule test { strings: $a = "foobar" condition: $a }"#).unwrap();
+    /// assert_eq!(rules.search_strategy(), SearchStrategy::Memmem);
+    /// ```
+    pub fn search_strategy(&self) -> SearchStrategy {
+        self.search_strategy
+    }
+
     /// Returns the Aho-Corasick automaton that allows to search for pattern
     /// atoms.
     #[inline]
@@ -355,8 +873,37 @@ impl Rules {
         self.ac.as_ref().expect("Aho-Corasick automaton not compiled")
     }
 
+    /// Returns the finder that searches for the only atom in the rules
+    /// when the search strategy is [`SearchStrategy::Memmem`].
+    #[inline]
+    pub(crate) fn memmem_finder(&self) -> &memmem::Finder<'static> {
+        self.memmem.as_ref().expect("memmem finder not built")
+    }
+
     pub(crate) fn build_ac_automaton(&mut self) {
-        if self.ac.is_some() {
+        if self.ac.is_some() || self.memmem.is_some() {
+            return;
+        }
+
+        self.rules_by_pattern = vec![Vec::new(); self.num_patterns];
+
+        for (rule_id, rule) in self.rules.iter().enumerate() {
+            for (_, pattern_id) in &rule.patterns {
+                let rules = &mut self.rules_by_pattern[pattern_id.0 as usize];
+                // The same pattern can appear twice in a rule, with different
+                // identifiers.
+                if rules.last().map(|id| usize::from(*id)) != Some(rule_id) {
+                    rules.push(rule_id.into());
+                }
+            }
+        }
+
+        self.search_strategy = SearchStrategy::plan(self.atoms.as_slice());
+
+        if self.search_strategy == SearchStrategy::Memmem {
+            self.memmem = Some(
+                memmem::Finder::new(self.atoms[0].atom.as_ref()).into_owned(),
+            );
             return;
         }
 
@@ -447,6 +994,142 @@ impl Rules {
     pub(crate) fn wasm_mod(&self) -> &wasmtime::Module {
         self.compiled_wasm_mod.as_ref().unwrap()
     }
+
+    /// Returns the WASM engine used for compiling and running the WASM
+    /// modules of these rules.
+    #[inline]
+    pub(crate) fn wasm_engine(&self) -> &'static wasmtime::Engine {
+        if self.minimal_wasm_features {
+            &crate::wasm::MINIMAL_ENGINE
+        } else {
+            &crate::wasm::ENGINE
+        }
+    }
+
+    /// Returns the modules that evaluate the conditions of rules added with
+    /// [`Rules::merge`].
+    #[inline]
+    pub(crate) fn merged_modules(&self) -> &[MergedModule] {
+        self.merged.as_slice()
+    }
+}
+
+/// Returns true if two global variables have the same type and value.
+fn same_type_value(a: &types::TypeValue, b: &types::TypeValue) -> bool {
+    let options = bincode::DefaultOptions::new();
+    options.serialize(a).ok() == options.serialize(b).ok()
+}
+
+/// A WASM module that evaluates the conditions of rules that were added
+/// with [`Rules::merge`].
+///
+/// The code in the module was generated for another set of rules, and it
+/// uses the IDs that rules, patterns, literals, regular expressions and
+/// fields in the root structure had in that set. When the code passes any
+/// of these IDs to the host, they must be translated to the IDs in the
+/// merged rules.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct MergedModule {
+    /// WASM module in raw form.
+    wasm_mod: Vec<u8>,
+
+    /// WASM module compiled into native code, see
+    /// [`Rules::compiled_wasm_mod`].
+    #[serde(
+        serialize_with = "serialize_wasm_mod",
+        deserialize_with = "deserialize_wasm_mod"
+    )]
+    compiled_wasm_mod: Option<wasmtime::Module>,
+
+    /// [`RuleId`] of the first rule evaluated by this module. Rules are
+    /// consecutive, the rule with ID = N in the module is the rule with
+    /// ID = `first_rule` + N in the merged rules.
+    first_rule: usize,
+
+    /// Number of rules evaluated by this module.
+    num_rules: usize,
+
+    /// [`PatternId`] of the first pattern used by this module. Like rules,
+    /// patterns are consecutive.
+    first_pattern: usize,
+
+    /// Number of patterns used by this module.
+    num_patterns: usize,
+
+    /// The N-th item is the ID in the merged rules for the literal with
+    /// ID = N in the module.
+    literals: Vec<LiteralId>,
+
+    /// The N-th item is the ID in the merged rules for the regular
+    /// expression with ID = N in the module.
+    regexps: Vec<RegexpId>,
+
+    /// The N-th item is the index in the merged root structure for the
+    /// field with index N in the root structure used by the module.
+    root_fields: Vec<i32>,
+}
+
+impl MergedModule {
+    #[inline]
+    pub(crate) fn wasm_mod(&self) -> &wasmtime::Module {
+        self.compiled_wasm_mod.as_ref().unwrap()
+    }
+
+    #[inline]
+    pub(crate) fn num_rules(&self) -> usize {
+        self.num_rules
+    }
+
+    #[inline]
+    pub(crate) fn num_patterns(&self) -> usize {
+        self.num_patterns
+    }
+
+    #[inline]
+    pub(crate) fn rule_id(&self, rule_id: RuleId) -> RuleId {
+        RuleId::from(self.first_rule + usize::from(rule_id))
+    }
+
+    #[inline]
+    pub(crate) fn pattern_id(&self, pattern_id: PatternId) -> PatternId {
+        PatternId::from(self.first_pattern + usize::from(pattern_id))
+    }
+
+    #[inline]
+    pub(crate) fn literal_id(&self, literal_id: LiteralId) -> LiteralId {
+        self.literals[u32::from(literal_id) as usize]
+    }
+
+    #[inline]
+    pub(crate) fn regexp_id(&self, regexp_id: RegexpId) -> RegexpId {
+        self.regexps[usize::from(regexp_id)]
+    }
+
+    #[inline]
+    pub(crate) fn root_field(&self, index: i32) -> i32 {
+        self.root_fields[index as usize]
+    }
+
+    /// Given a [`RuleId`] in the merged rules, returns the ID that the rule
+    /// has in this module, if this module evaluates the rule.
+    #[inline]
+    pub(crate) fn local_rule_id(&self, rule_id: RuleId) -> Option<usize> {
+        usize::from(rule_id)
+            .checked_sub(self.first_rule)
+            .filter(|id| *id < self.num_rules)
+    }
+
+    /// Given a [`PatternId`] in the merged rules, returns the ID that the
+    /// pattern has in this module, if this module uses the pattern.
+    #[inline]
+    pub(crate) fn local_pattern_id(
+        &self,
+        pattern_id: PatternId,
+    ) -> Option<usize> {
+        usize::from(pattern_id)
+            .checked_sub(self.first_pattern)
+            .filter(|id| *id < self.num_patterns)
+    }
 }
 
 #[cfg(feature = "native-code-serialization")]
@@ -487,8 +1170,18 @@ where
 {
     let bytes: Option<&[u8]> = Deserialize::deserialize(deserializer)?;
     let module = if let Some(bytes) = bytes {
+        // The module may have been compiled by any of the engines, the
+        // one actually required by the rules is checked later, in
+        // `Rules::deserialize`.
         unsafe {
-            wasmtime::Module::deserialize(&crate::wasm::ENGINE, bytes).ok()
+            wasmtime::Module::deserialize(&crate::wasm::ENGINE, bytes)
+                .or_else(|_| {
+                    wasmtime::Module::deserialize(
+                        &crate::wasm::MINIMAL_ENGINE,
+                        bytes,
+                    )
+                })
+                .ok()
         }
     } else {
         None
@@ -557,6 +1250,20 @@ pub(crate) enum MetaValue {
     Bytes(LiteralId),
 }
 
+impl MetaValue {
+    /// Returns a copy of this value where literal IDs are translated with
+    /// `literal_id`.
+    fn remap<F: Fn(LiteralId) -> LiteralId>(&self, literal_id: F) -> Self {
+        match self {
+            Self::Bool(b) => Self::Bool(*b),
+            Self::Integer(i) => Self::Integer(*i),
+            Self::Float(f) => Self::Float(*f),
+            Self::String(id) => Self::String(literal_id(*id)),
+            Self::Bytes(id) => Self::Bytes(literal_id(*id)),
+        }
+    }
+}
+
 /// Information about each of the individual rules included in [`Rules`].
 #[derive(Serialize, Deserialize)]
 pub(crate) struct RuleInfo {
@@ -578,10 +1285,73 @@ pub(crate) struct RuleInfo {
     pub(crate) metadata: Vec<(IdentId, MetaValue)>,
     /// Vector with all the patterns defined by this rule.
     pub(crate) patterns: Vec<(IdentId, PatternId)>,
+    /// Indexes in `patterns` of the hex patterns, accompanied by their
+    /// structure if [`crate::Compiler::hex_pattern_structure`] was enabled
+    /// while compiling the rule.
+    pub(crate) hex_patterns: Vec<(usize, Option<Vec<HexToken>>)>,
     /// True if the rule is global.
     pub(crate) is_global: bool,
     /// True if the rule is private.
     pub(crate) is_private: bool,
+    /// The rule's source code, if [`crate::Compiler::embed_sources`] was
+    /// enabled while compiling the rule.
+    pub(crate) source: Option<LiteralId>,
+}
+
+/// A rule condition that depends only on whether some patterns matched,
+/// like `$a and ($b or $c)`.
+///
+/// These conditions are very common in rules generated from lists of
+/// indicators of compromise, and they can be evaluated from the results of
+/// the pattern search alone.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) enum PatternCondition {
+    /// True if the pattern matched.
+    Match(PatternId),
+    /// True if all the operands are true.
+    And(Vec<PatternCondition>),
+    /// True if any of the operands is true.
+    Or(Vec<PatternCondition>),
+}
+
+impl PatternCondition {
+    /// Evaluates the condition, where `is_match` tells whether a pattern
+    /// matched.
+    pub(crate) fn eval(&self, is_match: &impl Fn(PatternId) -> bool) -> bool {
+        match self {
+            Self::Match(pattern_id) => is_match(*pattern_id),
+            Self::And(operands) => operands.iter().all(|op| op.eval(is_match)),
+            Self::Or(operands) => operands.iter().any(|op| op.eval(is_match)),
+        }
+    }
+}
+
+/// Strategy used for searching the patterns in the scanned data.
+///
+/// Patterns are not searched directly. Instead, atoms (short literals
+/// extracted from the patterns) are searched first and each occurrence of
+/// an atom is later verified against the pattern it came from. The strategy
+/// determines how atoms are searched.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SearchStrategy {
+    /// All the atoms are searched at the same time with an Aho-Corasick
+    /// automaton.
+    #[default]
+    AhoCorasick,
+    /// The rules have a single atom, which is searched with a substring
+    /// search algorithm that is faster than Aho-Corasick for a single
+    /// literal.
+    Memmem,
+}
+
+impl SearchStrategy {
+    /// Picks the search strategy that best suits the given atoms.
+    fn plan(atoms: &[SubPatternAtom]) -> Self {
+        match atoms {
+            [atom] if !atom.atom.as_ref().is_empty() => Self::Memmem,
+            _ => Self::AhoCorasick,
+        }
+    }
 }
 
 /// Represents an atom extracted from a pattern and added to the Aho-Corasick
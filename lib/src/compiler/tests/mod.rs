@@ -3,11 +3,16 @@ use serde_json::json;
 use std::fs;
 use std::io::Write;
 use std::mem::size_of;
+use std::time::Duration;
 
 use crate::compiler::{SubPattern, Var, VarStack};
-use crate::errors::{SerializationError, VariableError};
+use crate::errors::{CompileError, SerializationError, VariableError};
 use crate::types::Type;
-use crate::{compile, Compiler, Rules, Scanner, SourceCode};
+use crate::warnings::Warning;
+use crate::{
+    compile, Compiler, DuplicateMetadata, HexToken, MetaValue, Rules, Scanner,
+    SearchStrategy, SourceCode, ValueType,
+};
 
 #[test]
 fn serialization() {
@@ -41,6 +46,507 @@ fn serialization() {
     assert_eq!(size_of::<SubPattern>(), 24);
 }
 
+#[test]
+fn shared_patterns() {
+    let rules = compile(
+        r#"
+        rule test_1 { strings: $a = "MZ" condition: $a }
+        rule test_2 { strings: $b = "MZ" condition: #b == 2 }
+        rule test_3 { strings: $c = "MZ" nocase condition: $c }
+        "#,
+    )
+    .unwrap();
+
+    // Identical patterns in `test_1` and `test_2` share the same ID, but the
+    // pattern in `test_3` is different, as it has the `nocase` modifier.
+    assert_eq!(rules.num_patterns(), 2);
+
+    let patterns: Vec<_> =
+        rules.iter().map(|rule| rule.rule_info.patterns[0].1).collect();
+
+    assert_eq!(patterns[0], patterns[1]);
+    assert_ne!(patterns[0], patterns[2]);
+
+    let mut scanner = Scanner::new(&rules);
+    let scan_results = scanner.scan(b"MZ..MZ").expect("scan should not fail");
+
+    // Matches for the shared pattern are reported for every rule that
+    // uses it, each one under its own identifier.
+    let matches: Vec<_> = scan_results
+        .matching_rules()
+        .map(|rule| {
+            let pattern = rule.patterns().next().unwrap();
+            (
+                rule.identifier(),
+                pattern.identifier(),
+                pattern.matches().map(|m| m.range()).collect::<Vec<_>>(),
+            )
+        })
+        .collect();
+
+    assert_eq!(
+        matches,
+        vec![
+            ("test_1", "$a", vec![0..2, 4..6]),
+            ("test_2", "$b", vec![0..2, 4..6]),
+            ("test_3", "$c", vec![0..2, 4..6]),
+        ]
+    );
+}
+
+#[test]
+fn hex_pattern_structure() {
+    let src = r#"
+        rule test {
+          strings:
+            $a = { 4D 5A ?? [2-4] (01|02) }
+            $b = "MZ"
+          condition:
+            $a and $b
+        }
+        rule other {
+          strings:
+            $a = { 4D 5A ?? [2-4] (02|01) }
+          condition:
+            $a
+        }
+        "#;
+
+    // The structure is not stored unless explicitly requested.
+    let rules = compile(src).unwrap();
+    let rule = rules.iter().next().unwrap();
+
+    assert_eq!(rule.patterns().next().unwrap().hex_tokens(), None);
+
+    let mut compiler = Compiler::new();
+
+    compiler.hex_pattern_structure(true).add_source(src).unwrap();
+
+    let rules = compiler.build();
+    let mut rules_iter = rules.iter();
+    let rule = rules_iter.next().unwrap();
+    let mut patterns = rule.patterns();
+
+    let expected = [
+        HexToken::Byte(0x4D),
+        HexToken::Byte(0x5A),
+        HexToken::MaskedByte { value: 0x00, mask: 0x00 },
+        HexToken::Jump { start: Some(2), end: Some(4) },
+        HexToken::Alternative(vec![
+            vec![HexToken::Byte(0x01)],
+            vec![HexToken::Byte(0x02)],
+        ]),
+    ];
+
+    assert_eq!(
+        patterns.next().unwrap().hex_tokens(),
+        Some(expected.as_slice())
+    );
+
+    // Text patterns don't have a hex structure.
+    assert_eq!(patterns.next().unwrap().hex_tokens(), None);
+
+    // The pattern in `other` is equivalent to the one in `test`, and they
+    // are stored only once, but each rule keeps its own structure.
+    let pattern = rules_iter.next().unwrap().patterns().next().unwrap();
+
+    assert_eq!(pattern.rules().count(), 2);
+    assert_eq!(
+        pattern.hex_tokens().map(|tokens| &tokens[4]),
+        Some(&HexToken::Alternative(vec![
+            vec![HexToken::Byte(0x02)],
+            vec![HexToken::Byte(0x01)],
+        ]))
+    );
+
+    // The structure survives serialization.
+    let rules = Rules::deserialize(rules.serialize().unwrap()).unwrap();
+    let rule = rules.iter().next().unwrap();
+
+    assert_eq!(
+        rule.patterns().next().unwrap().hex_tokens(),
+        Some(expected.as_slice())
+    );
+}
+
+#[test]
+fn embed_sources() {
+    let first = r#"private rule first {
+      strings:
+        $a = "foo"
+      condition:
+        $a
+    }"#;
+
+    let second = r#"rule second : tag { condition: first }"#;
+
+    let src = format!("// comment\n{first}\n\n{second}\n");
+
+    let mut compiler = Compiler::new();
+    compiler.embed_sources(true).add_source(src.as_str()).unwrap();
+
+    let rules =
+        Rules::deserialize(compiler.build().serialize().unwrap()).unwrap();
+
+    let mut iter = rules.iter();
+
+    assert_eq!(iter.next().unwrap().source(), Some(first));
+    assert_eq!(iter.next().unwrap().source(), Some(second));
+
+    // Sources are not embedded by default.
+    let rules = compile(src.as_str()).unwrap();
+
+    assert!(rules.iter().all(|rule| rule.source().is_none()));
+
+    // Ignored rules don't have a source, and they don't change the source
+    // of other rules.
+    let ignored = "rule ignored_1 { condition: foo_module.bar == 1 }";
+    let kept = "rule kept { condition: true }";
+    let ignored_2 = "rule ignored_2 { condition: foo_module.bar == 2 }";
+
+    let mut compiler = Compiler::new();
+    compiler
+        .ignore_module("foo_module")
+        .embed_sources(true)
+        .add_source(format!("{ignored}\n{kept}\n{ignored_2}\n").as_str())
+        .unwrap();
+
+    let rules = compiler.build();
+    let mut iter = rules.iter();
+
+    assert_eq!(iter.next().unwrap().source(), Some(kept));
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn duplicate_metadata() {
+    let src = r#"
+rule test {
+  meta:
+    author = "foo"
+    version = 1
+    author = "bar"
+  condition:
+    true
+}"#;
+
+    let metadata = |policy| {
+        let mut compiler = Compiler::new();
+        compiler.duplicate_metadata(policy).add_source(src).unwrap();
+        let rules = compiler.build();
+        let rule = rules.iter().next().unwrap();
+        rule.metadata()
+            .map(|(ident, value)| match value {
+                MetaValue::String(s) => format!("{ident}={s}"),
+                MetaValue::Integer(i) => format!("{ident}={i}"),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        metadata(DuplicateMetadata::All),
+        ["author=foo", "version=1", "author=bar"]
+    );
+
+    assert_eq!(
+        metadata(DuplicateMetadata::First),
+        ["author=foo", "version=1"]
+    );
+
+    assert_eq!(metadata(DuplicateMetadata::Last), ["version=1", "author=bar"]);
+
+    assert_eq!(DuplicateMetadata::default(), DuplicateMetadata::All);
+}
+
+#[test]
+fn nocase_by_default() {
+    let src = r#"
+rule test {
+  strings:
+    $a = "Foo"
+  condition:
+    $a
+}"#;
+
+    let num_matches = |nocase_by_default, src: &str| {
+        let mut compiler = Compiler::new();
+        compiler.nocase_by_default(nocase_by_default).add_source(src).unwrap();
+        let rules = compiler.build();
+        let mut scanner = Scanner::new(&rules);
+        scanner.scan(b"foo").unwrap().matching_rules().len()
+    };
+
+    assert_eq!(num_matches(false, src), 0);
+    assert_eq!(num_matches(true, src), 1);
+
+    // The `case` modifier makes the pattern case-sensitive again.
+    let src = r#"
+rule test {
+  strings:
+    $a = "Foo" case
+  condition:
+    $a
+}"#;
+
+    assert_eq!(num_matches(false, src), 0);
+    assert_eq!(num_matches(true, src), 0);
+
+    // Patterns with modifiers that are not compatible with `nocase` are not
+    // affected.
+    let src = r#"
+rule test {
+  strings:
+    $a = "Foo" xor(0)
+  condition:
+    $a
+}"#;
+
+    assert_eq!(num_matches(true, src), 0);
+}
+
+#[test]
+fn complex_condition() {
+    let terms = (0..2000)
+        .map(|i| format!("filesize == {i}"))
+        .collect::<Vec<_>>()
+        .join(" or ");
+
+    let mut compiler = Compiler::new();
+
+    compiler
+        .condition_complexity_limit(Some(1000))
+        .add_source(format!("rule big {{ condition: {terms} }}").as_str())
+        .unwrap()
+        .add_source("rule small { condition: filesize == 0 or filesize == 1 }")
+        .unwrap();
+
+    let warnings = compiler.warnings();
+
+    assert_eq!(warnings.len(), 1);
+
+    match &warnings[0] {
+        Warning::ComplexCondition(w) => {
+            // One node for the `or`, plus three nodes for each
+            // `filesize == N`.
+            assert_eq!(w.num_nodes(), 6001);
+        }
+        w => panic!("unexpected warning: {w}"),
+    }
+
+    // The warning is not raised when no limit is set.
+    let mut compiler = Compiler::new();
+    compiler
+        .add_source(format!("rule big {{ condition: {terms} }}").as_str())
+        .unwrap();
+
+    assert!(compiler.warnings().is_empty());
+}
+
+#[test]
+fn redundant_alternation() {
+    let redundant = |regexp: &str| {
+        let mut compiler = Compiler::new();
+        compiler
+            .detect_redundant_alternations(true)
+            .add_source(
+                format!(
+                    "rule test {{ strings: $a = {regexp} condition: $a }}"
+                )
+                .as_str(),
+            )
+            .unwrap();
+        compiler
+            .warnings()
+            .iter()
+            .filter(|w| matches!(w, Warning::RedundantAlternation(_)))
+            .count()
+    };
+
+    assert_eq!(redundant(r#"/(abc|abc|abd)/"#), 1);
+    assert_eq!(redundant(r#"/(abc|abd|abc|abd)/"#), 2);
+    assert_eq!(redundant(r#"/(a[bc]|\x61d|a[cb])/"#), 1);
+    assert_eq!(redundant(r#"/x(a|b)|y(b|b)/"#), 1);
+
+    assert_eq!(redundant(r#"/(abc|abd|abe)/"#), 0);
+    assert_eq!(redundant(r#"/((?i)abc|abc)/"#), 0);
+    assert_eq!(redundant(r#"/(abc|ab(c))/"#), 1);
+
+    // The warning is not raised unless explicitly enabled.
+    let mut compiler = Compiler::new();
+    compiler
+        .add_source(r#"rule test { strings: $a = /(abc|abc)/ condition: $a }"#)
+        .unwrap();
+
+    assert!(compiler.warnings().is_empty());
+}
+
+#[test]
+fn search_strategy() {
+    let rules =
+        compile(r#"rule test { strings: $a = "aa" condition: #a == 3 }"#)
+            .unwrap();
+
+    assert_eq!(rules.search_strategy(), SearchStrategy::Memmem);
+
+    // Overlapping occurrences of the atom are found.
+    let mut scanner = Scanner::new(&rules);
+    assert_eq!(scanner.scan(b"aaaa").unwrap().matching_rules().len(), 1);
+
+    // The strategy is chosen again after deserialization.
+    let rules = Rules::deserialize(rules.serialize().unwrap()).unwrap();
+    assert_eq!(rules.search_strategy(), SearchStrategy::Memmem);
+
+    let mut src = String::new();
+
+    for i in 0..5000 {
+        src.push_str(
+            format!(
+                r#"rule test_{i} {{ strings: $a = "foo{i}" condition: $a }}"#
+            )
+            .as_str(),
+        );
+    }
+
+    let rules = compile(src.as_str()).unwrap();
+
+    assert_eq!(rules.search_strategy(), SearchStrategy::AhoCorasick);
+
+    let mut scanner = Scanner::new(&rules);
+    assert_eq!(scanner.scan(b"foo1234").unwrap().matching_rules().len(), 4);
+}
+
+#[test]
+fn statistics() {
+    let mut compiler = Compiler::new();
+
+    compiler
+        .add_source(
+            r#"
+            rule test_1 {
+              strings:
+                $a = "foo"
+                $b = { 01 02 03 }
+                $c = /bar\d+/
+              condition:
+                $a and $b and $c
+            }
+            rule test_2 {
+              strings:
+                $a = "foo"
+                $b = "baz"
+              condition:
+                $a or $b
+            }"#,
+        )
+        .unwrap();
+
+    // Rules that fail to compile are not included.
+    assert!(compiler
+        .add_source(r#"rule test_3 { strings: $a = "qux" condition: true }"#)
+        .is_err());
+
+    let stats = compiler.statistics();
+
+    assert_eq!(stats.num_rules(), 2);
+    // `$a = "foo"` is declared twice, but counted once.
+    assert_eq!(stats.num_literal_patterns(), 2);
+    assert_eq!(stats.num_hex_patterns(), 1);
+    assert_eq!(stats.num_regexp_patterns(), 1);
+    assert!(stats.num_atoms() > 0);
+    assert!(stats.parse_time() > Duration::ZERO);
+    assert!(stats.resolve_time() > Duration::ZERO);
+    assert!(stats.codegen_time() > Duration::ZERO);
+    assert_eq!(stats.automaton_size(), None);
+    assert_eq!(stats.wasm_module_size(), None);
+
+    let rules = compiler.build();
+    let rules_stats = rules.statistics().unwrap();
+
+    assert_eq!(rules_stats.num_rules(), 2);
+    assert!(rules_stats.codegen_time() > stats.codegen_time());
+    assert!(rules_stats.automaton_size().unwrap() > 0);
+    assert!(rules_stats.wasm_module_size().unwrap() > 0);
+
+    // Statistics are not serialized.
+    let rules = Rules::deserialize(rules.serialize().unwrap()).unwrap();
+
+    assert!(rules.statistics().is_none());
+}
+
+#[test]
+#[cfg(feature = "test_proto2-module")]
+fn minimal_wasm_features() {
+    let mut compiler = Compiler::new();
+
+    compiler
+        .minimal_wasm_features(true)
+        .add_source(
+            r#"
+            import "test_proto2"
+            rule test {
+              strings:
+                $a = "foo"
+              condition:
+                $a and uint8(@a[1]) == 0x66 and
+                for any i in (0..#a) : ( !a[i] == 3 ) and
+                test_proto2.map_string_int64["one"] == 1 and
+                for any key, value in test_proto2.map_string_int64 : (
+                  key == "one" and value == 1
+                ) and
+                not defined test_proto2.map_string_int64["two"]
+            }"#,
+        )
+        .unwrap();
+
+    let rules = compiler.build();
+
+    // The module validates with the features in the 1.0 version of the
+    // WebAssembly specification, plus mutable globals, which are imported
+    // from the host. No SIMD, bulk memory, reference types or multi-value.
+    wasmparser::Validator::new_with_features(
+        wasmparser::WasmFeatures::WASM1
+            | wasmparser::WasmFeatures::MUTABLE_GLOBAL,
+    )
+    .validate_all(rules.wasm_mod.as_slice())
+    .expect("WASM module should be valid with minimal features");
+
+    let rules = rules.serialize().unwrap();
+    let mut rules = Rules::deserialize(rules).unwrap();
+
+    // The setting survives serialization, and the deserialized rules are
+    // compiled with the minimal engine.
+    assert!(wasmtime::Engine::same(
+        rules.wasm_engine(),
+        &crate::wasm::MINIMAL_ENGINE
+    ));
+
+    assert_eq!(
+        Scanner::new(&rules)
+            .scan(b"foo")
+            .expect("scan should not fail")
+            .matching_rules()
+            .len(),
+        1
+    );
+
+    // Merging with rules that are not restricted to the minimal set of
+    // features moves all the modules to the default engine.
+    let other = compile("rule other { condition: true }").unwrap();
+    rules.merge(&other, Some("other_")).unwrap();
+
+    assert!(wasmtime::Engine::same(rules.wasm_engine(), &crate::wasm::ENGINE));
+
+    assert_eq!(
+        Scanner::new(&rules)
+            .scan(b"foo")
+            .expect("scan should not fail")
+            .matching_rules()
+            .len(),
+        2
+    );
+}
+
 #[test]
 fn namespaces() {
     // `foo` and `bar` are both in the default namespace, this compiles
@@ -498,6 +1004,39 @@ fn globals_json() {
     );
 }
 
+#[test]
+fn required_externals() {
+    let mut compiler = Compiler::new();
+
+    compiler
+        .define_global("some_int", 1)
+        .unwrap()
+        .define_global("some_struct", json!({"some_string": "foo"}))
+        .unwrap()
+        .define_global("unused", true)
+        .unwrap()
+        .add_source(
+            r#"
+            rule foo {
+            condition:
+                some_struct.some_string == "foo"
+            }
+            rule bar {
+            condition:
+                for any i in (0..some_int) : ( i == some_int )
+            }"#,
+        )
+        .unwrap();
+
+    assert_eq!(
+        compiler.build().required_externals(),
+        vec![
+            ("some_struct".to_string(), ValueType::Struct),
+            ("some_int".to_string(), ValueType::Integer),
+        ]
+    );
+}
+
 #[test]
 fn relaxed_re_syntax() {
     let mut compiler = Compiler::new();
@@ -580,6 +1119,96 @@ fn unsupported_modules() {
     );
 }
 
+#[test]
+fn include_rules() {
+    let mut compiler = Compiler::new();
+
+    compiler
+        .include_rules(&["APT_*", "?_suspicious"])
+        .add_source(
+            r#"
+            // Included because `APT_foo` depends on them.
+            rule other_helper { condition: true }
+            rule helper { condition: other_helper }
+
+            rule APT_foo { condition: helper }
+            rule APT_bar { condition: true }
+            rule x_suspicious { condition: true }
+
+            // Not included.
+            rule apt_lowercase { condition: true }
+            rule xx_suspicious { condition: true }
+            rule unrelated { condition: true }
+            "#,
+        )
+        .unwrap();
+
+    // Rules excluded from a previous source can't be used.
+    assert_eq!(
+        compiler
+            .add_source(r#"rule APT_baz { condition: unrelated }"#)
+            .expect_err("expected error")
+            .to_string(),
+        "error[E037]: dependency on an excluded rule
+ --> line:1:27
+  |
+1 | rule APT_baz { condition: unrelated }
+  |                           ^^^^^^^^^ rule `unrelated` was excluded from compilation
+  |"
+    );
+
+    let rules = compiler.build();
+
+    let mut identifiers: Vec<_> =
+        rules.iter().map(|rule| rule.identifier()).collect();
+
+    identifiers.sort();
+
+    assert_eq!(
+        identifiers,
+        ["APT_bar", "APT_foo", "helper", "other_helper", "x_suspicious"]
+    );
+
+    assert_eq!(
+        Scanner::new(&rules)
+            .scan(&[])
+            .expect("scan should not fail")
+            .matching_rules()
+            .len(),
+        5
+    );
+
+    // Global rules are included even if their names don't match, together
+    // with the rules they depend on.
+    let mut compiler = Compiler::new();
+
+    compiler
+        .include_rules(&["APT_*"])
+        .add_source(
+            r#"
+            rule not_empty { condition: filesize > 0 }
+            global rule global_not_empty { condition: not_empty }
+            rule APT_foo { condition: true }
+            rule unrelated { condition: true }
+            "#,
+        )
+        .unwrap();
+
+    let rules = compiler.build();
+
+    let mut identifiers: Vec<_> =
+        rules.iter().map(|rule| rule.identifier()).collect();
+
+    identifiers.sort();
+
+    assert_eq!(identifiers, ["APT_foo", "global_not_empty", "not_empty"]);
+
+    let mut scanner = Scanner::new(&rules);
+
+    assert_eq!(scanner.scan(&[]).unwrap().matching_rules().len(), 0);
+    assert_eq!(scanner.scan(b"foo").unwrap().matching_rules().len(), 3);
+}
+
 #[cfg(feature = "test_proto2-module")]
 #[test]
 fn banned_modules() {
@@ -800,6 +1429,67 @@ condition:
     );
 }
 
+#[test]
+fn modifier_combinations() {
+    let modifiers = [
+        "ascii",
+        "wide",
+        "nocase",
+        "case",
+        "private",
+        "fullword",
+        "base64",
+        "base64wide",
+        "xor",
+    ];
+
+    // Pairs in the same order as in `modifiers`.
+    let incompatible_pairs = [
+        ("nocase", "case"),
+        ("nocase", "base64"),
+        ("nocase", "base64wide"),
+        ("nocase", "xor"),
+        ("fullword", "base64"),
+        ("fullword", "base64wide"),
+        ("base64", "xor"),
+        ("base64wide", "xor"),
+    ];
+
+    for (i, modifier1) in modifiers.iter().enumerate() {
+        for modifier2 in &modifiers[i + 1..] {
+            let incompatible =
+                incompatible_pairs.contains(&(*modifier1, *modifier2));
+
+            let src = format!(
+                r#"rule test {{
+                    strings:
+                      $a = "foobar" {modifier1} {modifier2}
+                    condition:
+                      $a
+                }}"#
+            );
+
+            let result = Compiler::new().add_source(src.as_str()).map(|_| ());
+
+            if incompatible {
+                assert!(
+                    matches!(
+                        result,
+                        Err(CompileError::InvalidModifierCombination(_))
+                    ),
+                    "`{modifier1} {modifier2}` should be rejected"
+                );
+            } else {
+                assert!(
+                    result.is_ok(),
+                    "`{modifier1} {modifier2}` should be accepted: {:?}",
+                    result
+                );
+            }
+        }
+    }
+}
+
 #[test]
 fn utf8_errors() {
     let mut src =
@@ -891,7 +1581,19 @@ fn test_errors() {
 
         src.push_str(rules.as_str());
 
-        let err = compile(src.as_str()).expect_err(
+        let mut compiler = Compiler::new();
+
+        // Files starting with "// regex-complexity-limit: N" are compiled
+        // with that limit.
+        if let Some(limit) = rules
+            .lines()
+            .next()
+            .and_then(|line| line.strip_prefix("// regex-complexity-limit: "))
+        {
+            compiler.regex_complexity_limit(Some(limit.parse().unwrap()));
+        }
+
+        let err = compiler.add_source(src.as_str()).expect_err(
             format!("file {:?} should have failed", in_path).as_str(),
         );
 
@@ -943,3 +1645,46 @@ fn test_warnings() {
         }
     }
 }
+
+#[test]
+fn regex_complexity_limit() {
+    let mut compiler = Compiler::new();
+
+    compiler.regex_complexity_limit(Some(1000));
+
+    assert_eq!(
+        compiler
+            .add_source(
+                r#"rule t { strings: $a = /ab{1,5000}c/ condition: $a }"#
+            )
+            .expect_err("expected error")
+            .to_string(),
+        "error[E038]: regular expression too complex
+ --> line:1:19
+  |
+1 | rule t { strings: $a = /ab{1,5000}c/ condition: $a }
+  |                   ^^^^^^^^^^^^^^^^^^ this regexp compiles to 89992 bytes, the limit is 1000
+  |"
+    );
+
+    // Regexps below the limit are accepted, and the compiler can still be
+    // used after the error.
+    compiler
+        .add_source(r#"rule v { strings: $a = /ab{1,10}c/ condition: $a }"#)
+        .unwrap();
+
+    // Without a limit the regexp is accepted.
+    compiler
+        .regex_complexity_limit(None)
+        .add_source(r#"rule w { strings: $a = /ab{1,5000}c/ condition: $a }"#)
+        .unwrap();
+
+    let rules = compiler.build();
+    let mut scanner = Scanner::new(&rules);
+    let results = scanner.scan(b"abbbc").unwrap();
+
+    assert_eq!(
+        results.matching_rules().map(|r| r.identifier()).collect::<Vec<_>>(),
+        ["v", "w"]
+    );
+}
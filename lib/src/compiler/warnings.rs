@@ -28,6 +28,12 @@ pub enum Warning {
     SlowPattern(Box<SlowPattern>),
     IgnoredModule(Box<IgnoredModule>),
     IgnoredRule(Box<IgnoredRule>),
+    AlwaysUndefined(Box<AlwaysUndefined>),
+    UnusedSuppression(Box<UnusedSuppression>),
+    ComplexCondition(Box<ComplexCondition>),
+    RegexCouldBeLiteral(Box<RegexCouldBeLiteral>),
+    PatternsNotUsedInCondition(Box<PatternsNotUsedInCondition>),
+    RedundantAlternation(Box<RedundantAlternation>),
 }
 
 /// A hex pattern contains two or more consecutive jumps.
@@ -424,4 +430,219 @@ pub struct IgnoredRule {
     ignored_rule_loc: CodeLoc,
 }
 
+/// An expression is always undefined.
+///
+/// This happens, for instance, when some function like `uint32` reads data
+/// at an offset that is known to be beyond the end of the scanned data.
+/// Boolean expressions that depend on undefined values are always false.
+///
+/// ## Example
+///
+/// ```text
+/// warning[always_undefined]: expression is always undefined
+/// --> line:3:5
+///   |
+/// 3 |     uint32(filesize) == 1
+///   |     ---------------- this expression is always undefined
+///   |
+///   = note: reading 4 bytes at this offset exceeds the end of the scanned data
+/// ```
+#[derive(ErrorStruct, Debug, PartialEq, Eq)]
+#[associated_enum(Warning)]
+#[warning(
+    code = "always_undefined",
+    title = "expression is always undefined",
+)]
+#[label(
+    "this expression is always undefined",
+    expr_loc
+)]
+#[footer(note)]
+pub struct AlwaysUndefined {
+    report: Report,
+    expr_loc: CodeLoc,
+    note: Option<String>,
+}
+
+/// A `yara-x:allow(...)` comment suppresses a warning that is not raised.
+///
+/// Warnings can be suppressed for a given rule by putting a comment like
+/// `// yara-x:allow(invariant_expr)` just before the rule. This warning is
+/// raised when the suppressed warning is not raised for that rule, which
+/// means that the suppression is not necessary.
+///
+/// ## Example
+///
+/// ```text
+/// warning[unused_suppression]: unused suppression for warning `slow_pattern`
+/// --> line:1:1
+///   |
+/// 1 | // yara-x:allow(slow_pattern)
+///   | ----------------------------- warning `slow_pattern` is not raised for rule `test`
+///   |
+/// ```
+#[derive(ErrorStruct, Debug, PartialEq, Eq)]
+#[associated_enum(Warning)]
+#[warning(
+    code = "unused_suppression",
+    title = "unused suppression for warning `{warning_code}`",
+)]
+#[label(
+    "warning `{warning_code}` is not raised for rule `{rule_ident}`",
+    suppression_loc
+)]
+#[footer(note)]
+pub struct UnusedSuppression {
+    report: Report,
+    warning_code: String,
+    rule_ident: String,
+    suppression_loc: CodeLoc,
+    note: Option<String>,
+}
+
+/// A rule condition is too complex.
+///
+/// This warning is raised when the number of nodes in a condition's syntax
+/// tree exceeds the limit set with
+/// [`crate::Compiler::condition_complexity_limit`]. Such conditions are
+/// usually hard to read and slow to compile, and in most cases they can be
+/// simplified with `of` quantifiers. For instance, a long chain like
+/// `$a1 or $a2 or ... or $a100` is equivalent to `any of ($a*)`.
+///
+/// ## Example
+///
+/// ```text
+/// warning[complex_condition]: condition is too complex
+/// --> line:5:5
+///   |
+/// 5 |     $a1 or $a2 or $a3 or $a4
+///   |     ------------------------ this condition has 5 nodes
+///   |
+///   = note: consider using `of` quantifiers (e.g. `any of ($a*)`) for simplifying the condition
+/// ```
+#[derive(ErrorStruct, Debug, PartialEq, Eq)]
+#[associated_enum(Warning)]
+#[warning(
+    code = "complex_condition",
+    title = "condition is too complex",
+)]
+#[label(
+    "this condition has {num_nodes} nodes",
+    condition_loc
+)]
+#[footer(note)]
+pub struct ComplexCondition {
+    report: Report,
+    num_nodes: usize,
+    condition_loc: CodeLoc,
+    note: Option<String>,
+}
+
+impl ComplexCondition {
+    /// Number of nodes in the condition's syntax tree.
+    #[inline]
+    pub fn num_nodes(&self) -> usize {
+        self.num_nodes
+    }
+}
 
+/// A regular expression matches a single literal string.
+///
+/// Regular expressions without metacharacters, like `/foobar/` or `/a\.b/`,
+/// are equivalent to text patterns, but text patterns are matched more
+/// efficiently.
+///
+/// ## Example
+///
+/// ```text
+/// warning[regex_could_be_literal]: regular expression could be a text pattern
+/// --> line:3:10
+///   |
+/// 3 |     $a = /a\.b/
+///   |          ------ this is equivalent to "a.b"
+///   |
+///   = note: text patterns are faster than regular expressions
+/// ```
+#[derive(ErrorStruct, Debug, PartialEq, Eq)]
+#[associated_enum(Warning)]
+#[warning(
+    code = "regex_could_be_literal",
+    title = "regular expression could be a text pattern",
+)]
+#[label(
+    "this is equivalent to {text_pattern}",
+    regexp_loc
+)]
+#[footer(note)]
+pub struct RegexCouldBeLiteral {
+    report: Report,
+    text_pattern: String,
+    regexp_loc: CodeLoc,
+    note: Option<String>,
+}
+
+/// A rule declares patterns, but none of them is used in the condition.
+///
+/// Patterns must be used in the condition, unless their identifiers start
+/// with `$_`. However, when the condition doesn't use any pattern at all,
+/// searching for them is pointless, and most likely the condition is not
+/// the intended one.
+///
+/// ## Example
+///
+/// ```text
+/// warning[patterns_not_used]: patterns not used in condition
+/// --> line:3:5
+///   |
+/// 3 |       $_a = "foo"
+///   |  _____-
+/// 4 | |     $_b = "bar"
+///   | |_______________- none of these patterns is used in the condition of rule `test`
+///   |
+/// ```
+#[derive(ErrorStruct, Debug, PartialEq, Eq)]
+#[associated_enum(Warning)]
+#[warning(
+    code = "patterns_not_used",
+    title = "patterns not used in condition",
+)]
+#[label(
+    "none of these patterns is used in the condition of rule `{rule_ident}`",
+    patterns_loc
+)]
+pub struct PatternsNotUsedInCondition {
+    report: Report,
+    rule_ident: String,
+    patterns_loc: CodeLoc,
+}
+
+/// A regular expression has an alternative that is equivalent to another
+/// one in the same alternation.
+///
+/// This is raised only when [`crate::Compiler::detect_redundant_alternations`]
+/// is enabled.
+///
+/// ## Example
+///
+/// ```text
+/// warning[redundant_alternation]: redundant alternative in regular expression
+/// --> line:3:20
+///   |
+/// 3 |     $a = /(abc|abd|abc)/
+///   |                    --- this alternative is redundant
+///   |            --- it is equivalent to this one
+///   |
+/// ```
+#[derive(ErrorStruct, Debug, PartialEq, Eq)]
+#[associated_enum(Warning)]
+#[warning(
+    code = "redundant_alternation",
+    title = "redundant alternative in regular expression",
+)]
+#[label("this alternative is redundant", redundant_loc)]
+#[label("it is equivalent to this one", original_loc)]
+pub struct RedundantAlternation {
+    report: Report,
+    redundant_loc: CodeLoc,
+    original_loc: CodeLoc,
+}
@@ -45,24 +45,53 @@ assert_eq!(results.matching_rules().len(), 1);
 
 pub use compiler::compile;
 pub use compiler::Compiler;
+pub use compiler::CompilerStats;
+pub use compiler::DuplicateMetadata;
 pub use compiler::Rules;
 pub use compiler::RulesIter;
+pub use compiler::SearchStrategy;
 pub use compiler::SourceCode;
+pub use models::Case;
+pub use models::Encoding;
+pub use models::HexToken;
 pub use models::Match;
+pub use models::MatchOrigin;
 pub use models::Matches;
 pub use models::MetaValue;
 pub use models::Metadata;
 pub use models::Pattern;
 pub use models::Patterns;
 pub use models::Rule;
+pub use models::Value;
 pub use modules::mods;
+pub use scanner::AutomatonKind;
+pub use scanner::ClassicText;
+pub use scanner::ClassicTextSink;
+pub use scanner::EveJson;
+pub use scanner::FileResult;
+pub use scanner::JsonSink;
+pub use scanner::LruScanCache;
 pub use scanner::MatchingRules;
 pub use scanner::ModuleOutputs;
+pub use scanner::NdjsonSink;
 pub use scanner::NonMatchingRules;
+pub use scanner::OutputSink;
+pub use scanner::OwnedPattern;
+pub use scanner::OwnedRule;
+pub use scanner::OwnedScanResults;
+pub use scanner::ScanCache;
+pub use scanner::ScanCacheKey;
+pub use scanner::ScanCursor;
 pub use scanner::ScanError;
 pub use scanner::ScanOptions;
 pub use scanner::ScanResults;
+#[cfg(feature = "async")]
+pub use scanner::ScanStream;
+pub use scanner::ScanSummary;
 pub use scanner::Scanner;
+pub use scanner::Suppression;
+pub use scanner::TailOptions;
+pub use variables::ValueType;
 pub use variables::Variable;
 
 mod compiler;
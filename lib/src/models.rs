@@ -1,8 +1,12 @@
-use crate::compiler::{IdentId, PatternId, RuleInfo};
+use crate::compiler::{
+    IdentId, PatternId, RuleInfo, SubPattern, SubPatternFlagSet,
+    SubPatternFlags,
+};
 use crate::scanner::{ScanContext, ScannedData};
 use crate::{compiler, scanner, Rules};
-use bstr::{BStr, ByteSlice};
-use serde::Serialize;
+use bstr::{BStr, BString, ByteSlice};
+use serde::{Deserialize, Serialize};
+use std::iter::Enumerate;
 use std::ops::Range;
 use std::slice::Iter;
 
@@ -43,13 +47,29 @@ impl<'a, 'r> Rule<'a, 'r> {
         }
     }
 
+    /// Returns the rule's source code.
+    ///
+    /// The source code is available only if the rules were compiled with
+    /// [`crate::Compiler::embed_sources`] enabled. Otherwise, this returns
+    /// `None`.
+    pub fn source(&self) -> Option<&'r str> {
+        self.rule_info
+            .source
+            .map(|id| self.rules.lit_pool().get_str(id).unwrap())
+    }
+
     /// Returns the patterns defined by this rule.
+    ///
+    /// Patterns are returned in the same order they were declared in the
+    /// rule, which means that when two patterns match at the same offset,
+    /// the one declared first is always reported first.
     pub fn patterns(&self) -> Patterns<'a, 'r> {
         Patterns {
             ctx: self.ctx,
             rules: self.rules,
             data: self.data,
-            iterator: self.rule_info.patterns.iter(),
+            rule_info: self.rule_info,
+            iterator: self.rule_info.patterns.iter().enumerate(),
             len: self.rule_info.patterns.len(),
         }
     }
@@ -72,6 +92,22 @@ pub enum MetaValue<'r> {
     Bytes(&'r BStr),
 }
 
+/// Value of a condition evaluated with [`crate::Scanner::eval_condition`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// Bool value.
+    Bool(bool),
+    /// Integer value.
+    Integer(i64),
+    /// Float value.
+    Float(f64),
+    /// String value. It may contain invalid UTF-8 characters.
+    String(BString),
+    /// The value is undefined, for instance because the condition reads
+    /// past the end of the data.
+    Undefined,
+}
+
 /// Iterator that returns the metadata associated to a rule.
 ///
 /// The iterator returns (`&str`, [`MetaValue`]) pairs, where the first item
@@ -219,7 +255,8 @@ pub struct Patterns<'a, 'r> {
     ctx: Option<&'a ScanContext<'r>>,
     data: Option<&'a ScannedData<'a>>,
     rules: &'r Rules,
-    iterator: Iter<'a, (IdentId, PatternId)>,
+    rule_info: &'r RuleInfo,
+    iterator: Enumerate<Iter<'a, (IdentId, PatternId)>>,
     len: usize,
 }
 
@@ -227,11 +264,13 @@ impl<'a, 'r> Iterator for Patterns<'a, 'r> {
     type Item = Pattern<'a, 'r>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let (ident_id, pattern_id) = self.iterator.next()?;
+        let (index, (ident_id, pattern_id)) = self.iterator.next()?;
         Some(Pattern {
             ctx: self.ctx,
             rules: self.rules,
             data: self.data,
+            rule_info: self.rule_info,
+            index,
             pattern_id: *pattern_id,
             ident_id: *ident_id,
         })
@@ -250,6 +289,9 @@ pub struct Pattern<'a, 'r> {
     ctx: Option<&'a ScanContext<'r>>,
     data: Option<&'a ScannedData<'a>>,
     rules: &'r Rules,
+    rule_info: &'r RuleInfo,
+    /// Index of the pattern within the rule.
+    index: usize,
     pattern_id: PatternId,
     ident_id: IdentId,
 }
@@ -260,10 +302,108 @@ impl<'a, 'r> Pattern<'a, 'r> {
         self.rules.ident_pool().get(self.ident_id).unwrap()
     }
 
+    /// Returns the structure of the pattern if it is a hex pattern, or
+    /// `None` if otherwise.
+    ///
+    /// The structure is available only if the rules were compiled with
+    /// [`crate::Compiler::hex_pattern_structure`] enabled. Otherwise, this
+    /// returns `None`.
+    ///
+    /// ```rust
+    /// # use yara_x;
+    /// # use yara_x::HexToken;
+    /// let mut compiler = yara_x::Compiler::new();
+    ///
+    /// compiler.hex_pattern_structure(true).add_source(r#"
+    /// rule test {
+    ///   strings:
+    ///     $a = { 4D [2] 5A }
+    ///   condition:
+    ///     $a
+    /// }
+    /// "#).unwrap();
+    ///
+    /// let rules = compiler.build();
+    /// let rule = rules.iter().next().unwrap();
+    /// let pattern = rule.patterns().next().unwrap();
+    ///
+    /// assert_eq!(
+    ///     pattern.hex_tokens(),
+    ///     Some([
+    ///         HexToken::Byte(0x4D),
+    ///         HexToken::Jump { start: Some(2), end: Some(2) },
+    ///         HexToken::Byte(0x5A),
+    ///     ].as_slice())
+    /// );
+    /// ```
+    pub fn hex_tokens(&self) -> Option<&'r [HexToken]> {
+        self.hex_pattern()?.as_deref()
+    }
+
+    /// Returns true if this is a hex pattern.
+    pub(crate) fn is_hex(&self) -> bool {
+        self.hex_pattern().is_some()
+    }
+
+    fn hex_pattern(&self) -> Option<&'r Option<Vec<HexToken>>> {
+        self.rule_info
+            .hex_patterns
+            .iter()
+            .find(|(index, _)| *index == self.index)
+            .map(|(_, tokens)| tokens)
+    }
+
+    /// Returns the rules that contain this pattern.
+    ///
+    /// Identical patterns declared in different rules are stored only once,
+    /// and they share the same matches. This returns every rule where the
+    /// pattern is declared, including the one this pattern was obtained
+    /// from, in the order in which the rules were added to the compiler.
+    /// Notice that the pattern may have a different identifier in each rule.
+    ///
+    /// ```rust
+    /// # use yara_x;
+    /// let rules = yara_x::compile(r#"
+    /// rule foo { strings: $a = "mz" condition: $a }
+    /// rule bar { strings: $b = "mz" condition: $b }
+    /// "#).unwrap();
+    ///
+    /// let rule = rules.iter().next().unwrap();
+    /// let pattern = rule.patterns().next().unwrap();
+    ///
+    /// assert_eq!(
+    ///     pattern.rules().map(|r| r.identifier()).collect::<Vec<_>>(),
+    ///     ["foo", "bar"]
+    /// );
+    /// ```
+    pub fn rules(&self) -> impl Iterator<Item = Rule<'a, 'r>> + 'a {
+        let (ctx, data, rules) = (self.ctx, self.data, self.rules);
+        rules.rules_for_pattern(self.pattern_id).iter().map(move |rule_id| {
+            Rule { ctx, data, rules, rule_info: rules.get(*rule_id) }
+        })
+    }
+
+    /// If this pattern has a match that starts at `offset`, returns the
+    /// [`PatternId`] and the length of the match.
+    pub(crate) fn match_at(
+        &self,
+        offset: usize,
+    ) -> Option<(PatternId, usize)> {
+        let matches = self.ctx?.pattern_matches.get(self.pattern_id)?;
+        let index = matches.search(offset).ok()?;
+        Some((self.pattern_id, matches.get(index)?.range.len()))
+    }
+
     /// Returns the matches found for this pattern.
+    ///
+    /// Matches are sorted by starting offset in ascending order, and there's
+    /// at most one match per offset. If the pattern matches at the same
+    /// offset in multiple ways, the first match found is reported, except
+    /// for greedy regular expressions, where the longest match is reported.
     pub fn matches(&self) -> Matches<'a> {
         Matches {
             data: self.data,
+            rules: self.rules,
             iterator: self.ctx.and_then(|ctx| {
                 ctx.pattern_matches
                     .get(self.pattern_id)
@@ -276,6 +416,7 @@ impl<'a, 'r> Pattern<'a, 'r> {
 /// Iterator that returns the matches for a pattern.
 pub struct Matches<'a> {
     data: Option<&'a ScannedData<'a>>,
+    rules: &'a Rules,
     iterator: Option<Iter<'a, scanner::Match>>,
 }
 
@@ -284,7 +425,11 @@ impl<'a> Iterator for Matches<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         let iter = self.iterator.as_mut()?;
-        Some(Match { data: self.data?, inner: iter.next()? })
+        Some(Match {
+            data: self.data?,
+            rules: self.rules,
+            inner: iter.next()?,
+        })
     }
 }
 
@@ -297,6 +442,7 @@ impl<'a> ExactSizeIterator for Matches<'a> {
 /// Represents a match.
 pub struct Match<'a> {
     data: &'a ScannedData<'a>,
+    rules: &'a Rules,
     inner: &'a scanner::Match,
 }
 
@@ -315,8 +461,190 @@ impl<'a> Match<'a> {
 
     /// XOR key used for decrypting the data if the pattern had the `xor`
     /// modifier, or `None` if otherwise.
+    ///
+    /// This is the same key returned in [`MatchOrigin::Xor`] by
+    /// [`Match::origin`].
     #[inline]
     pub fn xor_key(&self) -> Option<u8> {
         self.inner.xor_key
     }
+
+    /// Returns the variant of the pattern that produced this match.
+    ///
+    /// Modifiers like `wide`, `nocase`, `xor` and `base64` make a pattern
+    /// match in multiple forms. The returned [`MatchOrigin`] tells which
+    /// of them matched, which is all that is needed for reconstructing the
+    /// pattern's text from the matching data.
+    ///
+    /// ```rust
+    /// # use yara_x;
+    /// # use yara_x::{Case, Encoding, MatchOrigin, Scanner};
+    /// let rules = yara_x::compile(r#"
+    /// rule test {
+    ///   strings:
+    ///     $a = "foo" ascii wide
+    ///   condition:
+    ///     $a
+    /// }
+    /// "#).unwrap();
+    ///
+    /// let mut scanner = Scanner::new(&rules);
+    /// let results = scanner.scan(b"f\x00o\x00o\x00").unwrap();
+    /// let rule = results.matching_rules().next().unwrap();
+    /// let pattern = rule.patterns().next().unwrap();
+    ///
+    /// assert_eq!(
+    ///     pattern.matches().next().unwrap().origin(),
+    ///     MatchOrigin::Plain { encoding: Encoding::Wide, case: Case::Exact }
+    /// );
+    /// ```
+    pub fn origin(&self) -> MatchOrigin<'a> {
+        let encoding = |flags: SubPatternFlagSet| {
+            if flags.contains(SubPatternFlags::Wide) {
+                Encoding::Wide
+            } else {
+                Encoding::Ascii
+            }
+        };
+
+        let alphabet = |id| Some(self.rules.lit_pool().get_str(id).unwrap());
+
+        match &self.rules.get_sub_pattern(self.inner.sub_pattern_id).1 {
+            SubPattern::Literal { flags, .. }
+            | SubPattern::LiteralChainHead { flags, .. }
+            | SubPattern::LiteralChainTail { flags, .. }
+            | SubPattern::Regexp { flags }
+            | SubPattern::RegexpChainHead { flags }
+            | SubPattern::RegexpChainTail { flags, .. } => {
+                MatchOrigin::Plain {
+                    encoding: encoding(*flags),
+                    case: if flags.contains(SubPatternFlags::Nocase) {
+                        Case::NoCase
+                    } else {
+                        Case::Exact
+                    },
+                }
+            }
+            SubPattern::Xor { flags, .. } => MatchOrigin::Xor {
+                encoding: encoding(*flags),
+                // Matches produced by `Xor` sub-patterns always have a key.
+                key: self.inner.xor_key.unwrap_or_default(),
+            },
+            SubPattern::Base64 { flags, .. } => MatchOrigin::Base64 {
+                encoding: encoding(*flags),
+                base64wide: false,
+                alphabet: None,
+            },
+            SubPattern::Base64Wide { flags, .. } => MatchOrigin::Base64 {
+                encoding: encoding(*flags),
+                base64wide: true,
+                alphabet: None,
+            },
+            SubPattern::CustomBase64 { flags, alphabet: id, .. } => {
+                MatchOrigin::Base64 {
+                    encoding: encoding(*flags),
+                    base64wide: false,
+                    alphabet: alphabet(*id),
+                }
+            }
+            SubPattern::CustomBase64Wide { flags, alphabet: id, .. } => {
+                MatchOrigin::Base64 {
+                    encoding: encoding(*flags),
+                    base64wide: true,
+                    alphabet: alphabet(*id),
+                }
+            }
+        }
+    }
+}
+
+/// Variant of a pattern that produced a match.
+///
+/// See [`Match::origin`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatchOrigin<'a> {
+    /// The data matched the pattern as it is, or in the form given by the
+    /// `wide` and `nocase` modifiers. This is the case for every match of
+    /// hex patterns and regular expressions.
+    Plain {
+        /// Encoding of the pattern's text.
+        encoding: Encoding,
+        /// Whether the match is case-insensitive.
+        case: Case,
+    },
+    /// The data matched the pattern XORed with `key`, which may be zero.
+    Xor {
+        /// Encoding of the pattern's text before applying the key.
+        encoding: Encoding,
+        /// The XOR key.
+        key: u8,
+    },
+    /// The data matched the base64 encoding of the pattern.
+    Base64 {
+        /// Encoding of the pattern's text before encoding it as base64.
+        encoding: Encoding,
+        /// True if the base64 string is itself wide, as produced by the
+        /// `base64wide` modifier.
+        base64wide: bool,
+        /// Custom base64 alphabet, or `None` if the standard alphabet was
+        /// used.
+        alphabet: Option<&'a str>,
+    },
+}
+
+/// Encoding of a pattern's text in a [`MatchOrigin`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// One byte per character.
+    Ascii,
+    /// Two bytes per character, where the second one is zero, as produced
+    /// by the `wide` modifier.
+    Wide,
+}
+
+/// Case sensitivity of a match in a [`MatchOrigin`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Case {
+    /// The data matched the pattern with exactly the same case.
+    Exact,
+    /// The data matched the pattern ignoring case, as with the `nocase`
+    /// modifier or the `/i` flag in regular expressions.
+    NoCase,
+}
+
+/// Each of the elements in a hex pattern.
+///
+/// See [`Pattern::hex_tokens`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HexToken {
+    /// A literal byte (e.g: `4D`).
+    Byte(u8),
+    /// A byte where some bits are ignored (e.g: `??`, `4?`). Only the bits
+    /// that are set to 1 in `mask` are taken into account while matching.
+    /// Ignored bits are always set to 0 in `value`.
+    MaskedByte {
+        /// Value of the byte.
+        value: u8,
+        /// Mask that indicates the bits that are taken into account.
+        mask: u8,
+    },
+    /// A negated byte (e.g: `~4D`, `~?D`). The mask has the same meaning
+    /// as in [`HexToken::MaskedByte`].
+    NotByte {
+        /// Value of the byte.
+        value: u8,
+        /// Mask that indicates the bits that are taken into account.
+        mask: u8,
+    },
+    /// A jump (e.g: `[2-4]`, `[4]`, `[-]`). Missing bounds are represented
+    /// by `None`. In `[4]` both bounds are equal.
+    Jump {
+        /// Minimum number of bytes skipped.
+        start: Option<u16>,
+        /// Maximum number of bytes skipped.
+        end: Option<u16>,
+    },
+    /// A set of alternatives (e.g: `(01|02 03)`). Each alternative is a
+    /// sequence of tokens.
+    Alternative(Vec<Vec<HexToken>>),
 }
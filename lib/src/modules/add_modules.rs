@@ -20,6 +20,8 @@ add_module!(modules, "magic", magic, "magic.Magic", Some("magic"), Some(magic::_
 add_module!(modules, "math", math, "math.Math", Some("math"), Some(math::__main__ as MainFn));
 #[cfg(feature = "pe-module")]
 add_module!(modules, "pe", pe, "pe.PE", Some("pe"), Some(pe::__main__ as MainFn));
+#[cfg(feature = "ssdeep-module")]
+add_module!(modules, "ssdeep", ssdeep, "ssdeep.Ssdeep", Some("ssdeep"), Some(ssdeep::__main__ as MainFn));
 #[cfg(feature = "string-module")]
 add_module!(modules, "string", string, "string.String", Some("string"), Some(string::__main__ as MainFn));
 #[cfg(feature = "test_proto2-module")]
@@ -1,6 +1,5 @@
 use std::borrow::Cow;
 use std::cell::OnceCell;
-use std::ffi::CStr;
 use std::fmt::{Display, Formatter, Write};
 use std::str::from_utf8;
 
@@ -21,6 +20,7 @@ use uuid::Uuid;
 
 use crate::modules::pe::parser::{DirEntry, PE};
 use crate::modules::protos;
+use crate::modules::utils::read_cstring;
 
 type NomError<'a> = nom::Err<nom::error::Error<&'a [u8]>>;
 
@@ -263,11 +263,12 @@ impl<'a> Dotnet<'a> {
 
     /// Given an index into the `#Strings` stream, returns the string.
     fn get_string(&self, index: StringIndex) -> Option<&'a str> {
-        CStr::from_bytes_until_nul(
-            self.get_stream(self.strings_stream?)?.get(index.0 as usize..)?,
+        read_cstring(
+            self.get_stream(self.strings_stream?)?,
+            index.0 as usize,
+            usize::MAX,
         )
-        .ok()
-        .and_then(|s| s.to_str().ok())
+        .and_then(|s| from_utf8(s).ok())
     }
 
     /// Given an index into the `#Blob` stream, returns the blob's data.
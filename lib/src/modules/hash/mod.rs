@@ -61,7 +61,7 @@ fn md5_data(
     let data = ctx.scanned_data().get(range)?;
     let mut hasher = Md5::new();
 
-    hasher.update(data);
+    ctx.for_each_chunk(data, |chunk| hasher.update(chunk))?;
 
     let digest = format!("{:x}", hasher.finalize());
 
@@ -101,7 +101,7 @@ fn sha1_data(
     let data = ctx.scanned_data().get(range)?;
     let mut hasher = Sha1::new();
 
-    hasher.update(data);
+    ctx.for_each_chunk(data, |chunk| hasher.update(chunk))?;
 
     let digest = format!("{:x}", hasher.finalize());
 
@@ -141,7 +141,7 @@ fn sha256_data(
     let data = ctx.scanned_data().get(range)?;
     let mut hasher = Sha256::new();
 
-    hasher.update(data);
+    ctx.for_each_chunk(data, |chunk| hasher.update(chunk))?;
 
     let digest = format!("{:x}", hasher.finalize());
 
@@ -175,7 +175,11 @@ fn crc_data(ctx: &ScanContext, offset: i64, size: i64) -> Option<i64> {
 
     let range = offset.try_into().ok()?..(offset + size).try_into().ok()?;
     let data = ctx.scanned_data().get(range)?;
-    let crc = crc32fast::hash(data);
+    let mut hasher = crc32fast::Hasher::new();
+
+    ctx.for_each_chunk(data, |chunk| hasher.update(chunk))?;
+
+    let crc = hasher.finalize();
 
     CRC32_CACHE.with(|cache| {
         cache.borrow_mut().insert((offset, size), crc.into());
@@ -204,9 +208,11 @@ fn checksum_data(ctx: &ScanContext, offset: i64, size: i64) -> Option<i64> {
     let data = ctx.scanned_data().get(range)?;
     let mut checksum = 0_u32;
 
-    for byte in data {
-        checksum = checksum.wrapping_add(*byte as u32)
-    }
+    ctx.for_each_chunk(data, |chunk| {
+        for byte in chunk {
+            checksum = checksum.wrapping_add(*byte as u32)
+        }
+    })?;
 
     CHECKSUM32_CACHE.with(|cache| {
         cache.borrow_mut().insert((offset, size), checksum.into());
@@ -113,7 +113,7 @@ fn percentage_range(
 
 #[module_export(name = "mode")]
 fn mode_global(ctx: &ScanContext) -> Option<i64> {
-    mode(ctx.scanned_data())
+    mode(ctx, ctx.scanned_data())
 }
 
 #[module_export(name = "mode")]
@@ -121,7 +121,7 @@ fn mode_range(ctx: &ScanContext, offset: i64, length: i64) -> Option<i64> {
     let length: usize = length.try_into().ok()?;
     let start: usize = offset.try_into().ok()?;
     let end = cmp::min(ctx.scanned_data().len(), start.saturating_add(length));
-    mode(ctx.scanned_data().get(start..end)?)
+    mode(ctx, ctx.scanned_data().get(start..end)?)
 }
 
 #[module_export(name = "count")]
@@ -135,12 +135,12 @@ fn entropy_data(ctx: &ScanContext, offset: i64, length: i64) -> Option<f64> {
     let length: usize = length.try_into().ok()?;
     let start: usize = offset.try_into().ok()?;
     let end = cmp::min(ctx.scanned_data().len(), start.saturating_add(length));
-    Some(entropy(ctx.scanned_data().get(start..end)?))
+    entropy(ctx, ctx.scanned_data().get(start..end)?)
 }
 
 #[module_export(name = "entropy")]
 fn entropy_string(ctx: &ScanContext, s: RuntimeString) -> Option<f64> {
-    Some(entropy(s.as_bstr(ctx).as_bytes()))
+    entropy(ctx, s.as_bstr(ctx).as_bytes())
 }
 
 #[module_export(name = "deviation")]
@@ -153,7 +153,7 @@ fn deviation_data(
     let length: usize = length.try_into().ok()?;
     let start: usize = offset.try_into().ok()?;
     let end = cmp::min(ctx.scanned_data().len(), start.saturating_add(length));
-    deviation(ctx.scanned_data().get(start..end)?, mean)
+    deviation(ctx, ctx.scanned_data().get(start..end)?, mean)
 }
 
 #[module_export(name = "deviation")]
@@ -162,7 +162,7 @@ fn deviation_string(
     s: RuntimeString,
     mean: f64,
 ) -> Option<f64> {
-    deviation(s.as_bstr(ctx).as_bytes(), mean)
+    deviation(ctx, s.as_bstr(ctx).as_bytes(), mean)
 }
 
 #[module_export(name = "mean")]
@@ -170,12 +170,12 @@ fn mean_data(ctx: &ScanContext, offset: i64, length: i64) -> Option<f64> {
     let length: usize = length.try_into().ok()?;
     let start: usize = offset.try_into().ok()?;
     let end = cmp::min(ctx.scanned_data().len(), start.saturating_add(length));
-    mean(ctx.scanned_data().get(start..end)?)
+    mean(ctx, ctx.scanned_data().get(start..end)?)
 }
 
 #[module_export(name = "mean")]
 fn mean_string(ctx: &ScanContext, s: RuntimeString) -> Option<f64> {
-    mean(s.as_bstr(ctx).as_bytes())
+    mean(ctx, s.as_bstr(ctx).as_bytes())
 }
 
 #[module_export(name = "serial_correlation")]
@@ -215,15 +215,26 @@ fn monte_carlo_pi_string(ctx: &ScanContext, s: RuntimeString) -> Option<f64> {
     monte_carlo_pi(s.as_bstr(ctx).as_bytes())
 }
 
-fn entropy(data: &[u8]) -> f64 {
+/// Returns the number of occurrences of each byte value in `data`.
+///
+/// Returns `None` if the scan's deadline is reached before processing the
+/// whole data.
+fn distribution(ctx: &ScanContext, data: &[u8]) -> Option<[u64; 256]> {
+    let mut distribution = [0u64; 256];
+    ctx.for_each_chunk(data, |chunk| {
+        for byte in chunk {
+            distribution[*byte as usize] += 1;
+        }
+    })?;
+    Some(distribution)
+}
+
+fn entropy(ctx: &ScanContext, data: &[u8]) -> Option<f64> {
     if data.is_empty() {
-        return 0.0;
+        return Some(0.0);
     }
 
-    let mut distribution = [0u64; 256];
-    for byte in data {
-        distribution[*byte as usize] += 1;
-    }
+    let distribution = distribution(ctx, data)?;
 
     let mut entropy: f64 = 0.0;
     for value in &distribution {
@@ -233,18 +244,15 @@ fn entropy(data: &[u8]) -> f64 {
         }
     }
 
-    entropy
+    Some(entropy)
 }
 
-fn deviation(data: &[u8], mean: f64) -> Option<f64> {
+fn deviation(ctx: &ScanContext, data: &[u8], mean: f64) -> Option<f64> {
     if data.is_empty() {
         return None;
     }
 
-    let mut distribution = [0u64; 256];
-    for byte in data {
-        distribution[*byte as usize] += 1;
-    }
+    let distribution = distribution(ctx, data)?;
 
     let mut sum: f64 = 0.0;
     for (i, value) in distribution.iter().enumerate() {
@@ -254,15 +262,12 @@ fn deviation(data: &[u8], mean: f64) -> Option<f64> {
     Some(sum / data.len() as f64)
 }
 
-fn mean(data: &[u8]) -> Option<f64> {
+fn mean(ctx: &ScanContext, data: &[u8]) -> Option<f64> {
     if data.is_empty() {
         return None;
     }
 
-    let mut distribution = [0u64; 256];
-    for byte in data {
-        distribution[*byte as usize] += 1;
-    }
+    let distribution = distribution(ctx, data)?;
 
     let mut sum: f64 = 0.0;
     for (i, value) in distribution.iter().enumerate() {
@@ -272,15 +277,12 @@ fn mean(data: &[u8]) -> Option<f64> {
     Some(sum / data.len() as f64)
 }
 
-fn mode(data: &[u8]) -> Option<i64> {
+fn mode(ctx: &ScanContext, data: &[u8]) -> Option<i64> {
     if data.is_empty() {
         return None;
     }
 
-    let mut distribution = [0u64; 256];
-    for byte in data {
-        distribution[*byte as usize] += 1;
-    }
+    let distribution = distribution(ctx, data)?;
 
     let mut mode = 0;
     for (i, x) in distribution.iter().enumerate() {
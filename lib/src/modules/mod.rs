@@ -8,7 +8,9 @@ pub mod protos {
 }
 
 #[cfg(test)]
-mod tests;
+pub(crate) mod tests;
+
+pub(crate) mod utils;
 
 #[allow(unused_imports)]
 pub(crate) mod prelude {
@@ -24,7 +26,7 @@ pub(crate) mod prelude {
 include!("modules.rs");
 
 /// Type of module's main function.
-type MainFn = fn(&[u8], Option<&[u8]>) -> Box<dyn MessageDyn>;
+pub(crate) type MainFn = fn(&[u8], Option<&[u8]>) -> Box<dyn MessageDyn>;
 
 /// Describes a YARA module.
 pub(crate) struct Module {
@@ -40,6 +42,15 @@ pub(crate) struct Module {
     pub root_struct_descriptor: MessageDescriptor,
 }
 
+/// Modules whose main function resets or initializes some thread-local
+/// state that is used later by the module's functions.
+///
+/// The main function of these modules must be called in the same thread
+/// where the rule conditions are evaluated. See
+/// [`crate::Scanner::prefetch_modules`].
+pub(crate) const THREAD_LOCAL_MODULES: &[&str] =
+    &["cuckoo", "hash", "magic", "ssdeep"];
+
 /// Macro that adds a module to the `BUILTIN_MODULES` map.
 ///
 /// This macro is used by `add_modules.rs`, a file that is automatically
@@ -118,6 +129,27 @@ lazy_static! {
     };
 }
 
+#[cfg(test)]
+thread_local! {
+    /// Modules that are handled as if they were not included in the current
+    /// build, which allows testing what happens when rules that import them
+    /// are used with a build where they are missing. See [`available_module`].
+    pub(crate) static UNAVAILABLE_MODULES: std::cell::RefCell<rustc_hash::FxHashSet<&'static str>> =
+        std::cell::RefCell::new(rustc_hash::FxHashSet::default());
+}
+
+/// Returns the module with the given name if it is available for scanning.
+///
+/// Compiled rules can import modules that are not available, if they were
+/// compiled by a different build of YARA-X that included more modules.
+pub(crate) fn available_module(name: &str) -> Option<&'static Module> {
+    #[cfg(test)]
+    if UNAVAILABLE_MODULES.with(|modules| modules.borrow().contains(name)) {
+        return None;
+    }
+    BUILTIN_MODULES.get(name)
+}
+
 pub mod mods {
     /*! Utility functions and structures for invoking YARA modules directly.
 
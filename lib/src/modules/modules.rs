@@ -19,6 +19,8 @@ mod magic;
 mod math;
 #[cfg(feature = "pe-module")]
 mod pe;
+#[cfg(feature = "ssdeep-module")]
+mod ssdeep;
 #[cfg(feature = "string-module")]
 mod string;
 #[cfg(feature = "test_proto2-module")]
@@ -278,8 +278,10 @@ fn rich_version_impl(
 ) -> Option<i64> {
     assert!(toolid.is_some() || version.is_some());
 
+    // Without a rich signature the result is undefined, instead of zero.
     let count = pe
         .rich_signature
+        .as_ref()?
         .tools
         .iter()
         .filter_map(|t| {
@@ -26,6 +26,36 @@ fn rich_signature() {
         "#,
         &pe
     );
+
+    // The functions are undefined for files without a rich signature.
+    let pe = create_binary_from_zipped_ihex(
+        "src/modules/pe/tests/testdata/0885cff80dd6e14559c6429a89d724d9f5553a34e0716a660376728b8987c61b.in.zip",
+    );
+
+    rule_true!(
+        r#"
+        import "pe"
+        rule test {
+          condition:
+            not defined pe.rich_signature.clear_data and
+            not defined pe.rich_signature.toolid(157) and
+            not defined pe.rich_signature.version(30319) and
+            not defined pe.rich_signature.toolid(157, 40219)
+        }
+        "#,
+        &pe
+    );
+
+    rule_true!(
+        r#"
+        import "pe"
+        rule test {
+          condition:
+            not defined pe.rich_signature.toolid(1)
+        }
+        "#,
+        b"not a PE"
+    );
 }
 
 #[test]
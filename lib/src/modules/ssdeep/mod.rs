@@ -0,0 +1,340 @@
+/*! YARA module that computes and compares [ssdeep][1] fuzzy hashes.
+
+ssdeep hashes are context triggered piecewise hashes: two inputs that share
+large chunks of data produce similar hashes, and the similarity between two
+hashes can be measured with a score that goes from 0 (unrelated) to 100
+(identical, or very similar). This allows writing rules that match files
+similar to a known sample, like in:
+
+```text
+ssdeep.compare(ssdeep.hash(), "96:YTZxvH1jvU7DzrvJ+hduz+D5XLVdNY4+UW:YXvVjvU7DzrB+hdRQq4+UW") > 80
+```
+
+The hashes produced by this module are compatible with the ones produced by
+the `ssdeep` tool.
+
+[1]: https://ssdeep-project.github.io/ssdeep/
+ */
+
+use std::cell::RefCell;
+
+use crate::modules::prelude::*;
+use crate::modules::protos::ssdeep::*;
+
+#[cfg(test)]
+mod tests;
+
+/// Maximum length of the first part of the hash.
+const SPAMSUM_LENGTH: usize = 64;
+/// Minimum block size.
+const MIN_BLOCK_SIZE: u64 = 3;
+/// Size of the window used by the rolling hash.
+const ROLLING_WINDOW: usize = 7;
+
+const HASH_PRIME: u32 = 0x01000193;
+const HASH_INIT: u32 = 0x28021967;
+
+const B64: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+thread_local!(
+    static HASH_CACHE: RefCell<Option<String>> = const { RefCell::new(None) };
+);
+
+#[module_main]
+fn main(_data: &[u8], _meta: Option<&[u8]>) -> Ssdeep {
+    // With every scanned file the cache must be cleared.
+    HASH_CACHE.with(|cache| cache.borrow_mut().take());
+    Ssdeep::new()
+}
+
+/// Returns the ssdeep hash of the scanned data.
+///
+/// Computing the hash requires a full pass over the data, so it is computed
+/// only once per scan, no matter how many times the function is called.
+#[module_export(name = "hash")]
+fn hash_data(ctx: &mut ScanContext) -> Option<RuntimeString> {
+    let hash = HASH_CACHE.with(|cache| {
+        cache
+            .borrow_mut()
+            .get_or_insert_with(|| fuzzy_hash(ctx.scanned_data()))
+            .clone()
+    });
+
+    Some(RuntimeString::new(hash))
+}
+
+#[module_export(name = "hash")]
+fn hash_str(ctx: &mut ScanContext, s: RuntimeString) -> Option<RuntimeString> {
+    Some(RuntimeString::new(fuzzy_hash(s.as_bstr(ctx))))
+}
+
+/// Returns the similarity between two ssdeep hashes, as a score between 0
+/// and 100.
+///
+/// The result is undefined if any of the hashes is not a valid ssdeep hash.
+#[module_export]
+fn compare(
+    ctx: &mut ScanContext,
+    a: RuntimeString,
+    b: RuntimeString,
+) -> Option<i64> {
+    let a = a.to_str(ctx).ok()?;
+    let b = b.to_str(ctx).ok()?;
+    fuzzy_compare(a, b).map(i64::from)
+}
+
+/// Rolling hash used for deciding where the data is split into chunks.
+#[derive(Default)]
+struct RollingHash {
+    window: [u8; ROLLING_WINDOW],
+    h1: u32,
+    h2: u32,
+    h3: u32,
+    n: usize,
+}
+
+impl RollingHash {
+    fn update(&mut self, c: u8) {
+        self.h2 = self
+            .h2
+            .wrapping_sub(self.h1)
+            .wrapping_add(ROLLING_WINDOW as u32 * c as u32);
+        self.h1 = self
+            .h1
+            .wrapping_add(c as u32)
+            .wrapping_sub(self.window[self.n] as u32);
+        self.window[self.n] = c;
+        self.n = (self.n + 1) % ROLLING_WINDOW;
+        self.h3 = (self.h3 << 5) ^ c as u32;
+    }
+
+    fn sum(&self) -> u32 {
+        self.h1.wrapping_add(self.h2).wrapping_add(self.h3)
+    }
+}
+
+/// Appends `c` to `digest` if its length is below `max_len`, or replaces its
+/// last character otherwise. Returns true if `c` was appended.
+fn push_or_replace(digest: &mut Vec<u8>, max_len: usize, c: u8) -> bool {
+    if digest.len() < max_len {
+        digest.push(c);
+        true
+    } else {
+        digest[max_len - 1] = c;
+        false
+    }
+}
+
+/// Computes the two parts of the hash for a given block size.
+///
+/// Returns the two parts, plus the number of chunks found while computing
+/// the first one.
+fn digest(data: &[u8], block_size: u64) -> (Vec<u8>, Vec<u8>, usize) {
+    let mut roll = RollingHash::default();
+    let mut h1 = HASH_INIT;
+    let mut h2 = HASH_INIT;
+    let mut digest1 = Vec::with_capacity(SPAMSUM_LENGTH);
+    let mut digest2 = Vec::with_capacity(SPAMSUM_LENGTH / 2);
+
+    for &c in data {
+        h1 = h1.wrapping_mul(HASH_PRIME) ^ c as u32;
+        h2 = h2.wrapping_mul(HASH_PRIME) ^ c as u32;
+
+        roll.update(c);
+
+        let sum = roll.sum() as u64;
+
+        // Once the digest reaches its maximum length the hash is not reset
+        // anymore, and the last character covers the remaining data.
+        if sum % block_size == block_size - 1 {
+            let c = B64[h1 as usize % 64];
+            if push_or_replace(&mut digest1, SPAMSUM_LENGTH, c)
+                && digest1.len() < SPAMSUM_LENGTH
+            {
+                h1 = HASH_INIT;
+            }
+        }
+
+        if sum % (block_size * 2) == block_size * 2 - 1 {
+            let c = B64[h2 as usize % 64];
+            if push_or_replace(&mut digest2, SPAMSUM_LENGTH / 2, c)
+                && digest2.len() < SPAMSUM_LENGTH / 2
+            {
+                h2 = HASH_INIT;
+            }
+        }
+    }
+
+    let chunks = digest1.len().min(SPAMSUM_LENGTH - 1);
+
+    if roll.sum() != 0 {
+        push_or_replace(&mut digest1, SPAMSUM_LENGTH, B64[h1 as usize % 64]);
+        push_or_replace(
+            &mut digest2,
+            SPAMSUM_LENGTH / 2,
+            B64[h2 as usize % 64],
+        );
+    }
+
+    (digest1, digest2, chunks)
+}
+
+/// Computes the ssdeep hash of `data`.
+pub(crate) fn fuzzy_hash(data: &[u8]) -> String {
+    let mut block_size = MIN_BLOCK_SIZE;
+
+    while block_size * (SPAMSUM_LENGTH as u64) < data.len() as u64 {
+        block_size *= 2;
+    }
+
+    loop {
+        let (digest1, digest2, chunks) = digest(data, block_size);
+
+        // If the block size produced too few chunks, try again with a
+        // smaller one.
+        if block_size > MIN_BLOCK_SIZE && chunks < SPAMSUM_LENGTH / 2 {
+            block_size /= 2;
+            continue;
+        }
+
+        // Both digests contain only characters from `B64`.
+        return format!(
+            "{}:{}:{}",
+            block_size,
+            String::from_utf8(digest1).unwrap(),
+            String::from_utf8(digest2).unwrap()
+        );
+    }
+}
+
+/// Removes any sequence of more than three identical characters, leaving
+/// only the first three. Such sequences don't tell much about the data, and
+/// would inflate the similarity score.
+fn eliminate_sequences(s: &str) -> Vec<u8> {
+    let mut result: Vec<u8> = Vec::with_capacity(s.len());
+    for &c in s.as_bytes() {
+        let len = result.len();
+        if len >= 3 && result[len - 3..].iter().all(|&p| p == c) {
+            continue;
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Returns true if `a` and `b` have a common substring with the length of
+/// the rolling window.
+fn has_common_substring(a: &[u8], b: &[u8]) -> bool {
+    a.windows(ROLLING_WINDOW)
+        .any(|w| b.windows(ROLLING_WINDOW).any(|x| x == w))
+}
+
+/// Weighted edit distance between `a` and `b`, where insertions and
+/// deletions cost 1 and substitutions cost 2.
+fn edit_distance(a: &[u8], b: &[u8]) -> usize {
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+
+    for (i, ca) in a.iter().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + if ca == cb { 0 } else { 2 };
+            curr[j + 1] = substitution.min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[b.len()]
+}
+
+/// Computes the similarity score between two digests that were computed with
+/// the same block size.
+fn score_digests(a: &[u8], b: &[u8], block_size: u64) -> u32 {
+    if a.len() > SPAMSUM_LENGTH || b.len() > SPAMSUM_LENGTH {
+        return 0;
+    }
+
+    if !has_common_substring(a, b) {
+        return 0;
+    }
+
+    let distance = edit_distance(a, b) as u64;
+    let distance = distance * SPAMSUM_LENGTH as u64 / (a.len() + b.len()) as u64;
+    let distance = 100 * distance / SPAMSUM_LENGTH as u64;
+
+    if distance >= 100 {
+        return 0;
+    }
+
+    let score = 100 - distance;
+
+    // With small block sizes, short digests would produce exaggerated
+    // scores. Cap the score according to the digests' length.
+    let threshold =
+        (99 + ROLLING_WINDOW as u64) / ROLLING_WINDOW as u64 * MIN_BLOCK_SIZE;
+
+    if block_size >= threshold {
+        return score as u32;
+    }
+
+    let cap = block_size / MIN_BLOCK_SIZE * a.len().min(b.len()) as u64;
+
+    score.min(cap) as u32
+}
+
+/// Splits a hash into its block size and its two digests.
+fn parse_hash(hash: &str) -> Option<(u64, &str, &str)> {
+    let mut parts = hash.splitn(3, ':');
+    let block_size = parts.next()?.parse::<u64>().ok()?;
+    let digest1 = parts.next()?;
+    let digest2 = parts.next()?;
+
+    // The `ssdeep` tool appends the file name after a comma.
+    let digest2 = digest2.split(',').next()?;
+
+    if block_size < MIN_BLOCK_SIZE
+        || digest1.len() > SPAMSUM_LENGTH
+        || digest2.len() > SPAMSUM_LENGTH
+    {
+        return None;
+    }
+
+    Some((block_size, digest1, digest2))
+}
+
+/// Returns the similarity between two ssdeep hashes, as a score between 0
+/// and 100, or [`None`] if any of them is not a valid hash.
+pub(crate) fn fuzzy_compare(a: &str, b: &str) -> Option<u32> {
+    let (block_size_a, a1, a2) = parse_hash(a)?;
+    let (block_size_b, b1, b2) = parse_hash(b)?;
+
+    // Hashes can be compared only if their block sizes are equal, or one is
+    // twice the other one.
+    if block_size_a != block_size_b
+        && block_size_a != block_size_b * 2
+        && block_size_b != block_size_a * 2
+    {
+        return Some(0);
+    }
+
+    let a1 = eliminate_sequences(a1);
+    let a2 = eliminate_sequences(a2);
+    let b1 = eliminate_sequences(b1);
+    let b2 = eliminate_sequences(b2);
+
+    if block_size_a == block_size_b && a1 == b1 {
+        return Some(100);
+    }
+
+    let score = if block_size_a == block_size_b {
+        score_digests(&a1, &b1, block_size_a)
+            .max(score_digests(&a2, &b2, block_size_a * 2))
+    } else if block_size_a == block_size_b * 2 {
+        score_digests(&a1, &b2, block_size_a)
+    } else {
+        score_digests(&a2, &b1, block_size_b)
+    };
+
+    Some(score)
+}
@@ -0,0 +1,114 @@
+use crate::modules::ssdeep::{fuzzy_compare, fuzzy_hash};
+use crate::tests;
+use tests::*;
+
+/// Returns `len` bytes of pseudo-random data, which are the same for the
+/// same `seed`.
+fn random_data(seed: u32, len: usize) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect()
+}
+
+#[test]
+fn fuzzy_hash_self_compare() {
+    let data = random_data(1, 16384);
+    let hash = fuzzy_hash(&data);
+
+    let (block_size, rest) = hash.split_once(':').unwrap();
+    let (digest1, digest2) = rest.split_once(':').unwrap();
+
+    assert!(block_size.parse::<u64>().unwrap() >= 3);
+    assert!(!digest1.is_empty() && digest1.len() <= 64);
+    assert!(!digest2.is_empty() && digest2.len() <= 32);
+
+    assert_eq!(fuzzy_hash(&data), hash);
+    assert_eq!(fuzzy_compare(&hash, &hash), Some(100));
+
+    // Altering a small portion of the data keeps the hashes similar.
+    let mut modified = data.clone();
+    modified[8000..8100].fill(0);
+
+    let score = fuzzy_compare(&hash, &fuzzy_hash(&modified)).unwrap();
+    assert!(score > 50 && score < 100, "score: {}", score);
+
+    // Unrelated data produces unrelated hashes.
+    let unrelated = fuzzy_hash(&random_data(2, 16384));
+    assert_eq!(fuzzy_compare(&hash, &unrelated), Some(0));
+
+    assert_eq!(fuzzy_compare(&hash, "foo"), None);
+    assert_eq!(fuzzy_compare("x:abc:abc", &hash), None);
+}
+
+#[test]
+fn fuzzy_hash_reference() {
+    // Reference hashes produced by the `ssdeep` library.
+    assert_eq!(fuzzy_hash(b""), "3::");
+    assert_eq!(fuzzy_hash(b"Hello there!"), "3:aNRn:aNRn");
+
+    let hash1 = fuzzy_hash(
+        b"Also called fuzzy hashes, Ctph can match inputs that have \
+          homologies.",
+    );
+
+    let hash2 = fuzzy_hash(
+        b"Also called fuzzy hashes, CTPH can match inputs that have \
+          homologies.",
+    );
+
+    assert_eq!(hash1, "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C");
+    assert_eq!(hash2, "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2C");
+    assert_eq!(fuzzy_compare(&hash1, &hash2), Some(22));
+}
+
+#[test]
+#[cfg(feature = "ssdeep-module")]
+fn test_ssdeep_module() {
+    let data = random_data(1, 16384);
+    let hash = fuzzy_hash(&data);
+
+    rule_true!(
+        format!(
+            r#"
+            import "ssdeep"
+            rule test {{
+              condition:
+                ssdeep.hash() == "{hash}" and
+                ssdeep.compare(ssdeep.hash(), ssdeep.hash()) == 100 and
+                ssdeep.compare(ssdeep.hash(), "{hash}") == 100
+            }}
+            "#
+        )
+        .as_str(),
+        &data
+    );
+
+    rule_false!(
+        r#"
+        import "ssdeep"
+        rule test {
+          condition:
+            ssdeep.compare(ssdeep.hash(), "invalid") >= 0
+        }
+        "#,
+        &data
+    );
+
+    rule_true!(
+        r#"
+        import "ssdeep"
+        rule test {
+          condition:
+            ssdeep.hash() == "3:aNRn:aNRn" and
+            ssdeep.compare(
+              "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+              "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2C") == 22
+        }
+        "#,
+        b"Hello there!"
+    );
+}
@@ -65,6 +65,24 @@ fn to_int(ctx: &ScanContext, string: RuntimeString) -> Option<i64> {
     string.parse::<i64>().ok()
 }
 
+#[module_export]
+fn pattern_matches(
+    ctx: &mut ScanContext,
+    identifier: RuntimeString,
+) -> Option<i64> {
+    let identifier = identifier.to_str(ctx).ok()?.to_string();
+    Some(ctx.pattern_match_ranges(identifier.as_str())?.count() as i64)
+}
+
+#[module_export]
+fn is_pattern_match(
+    ctx: &mut ScanContext,
+    identifier: RuntimeString,
+) -> Option<bool> {
+    let identifier = identifier.to_str(ctx).ok()?.to_string();
+    ctx.is_pattern_match(identifier.as_str())
+}
+
 #[module_main]
 fn main(data: &[u8], _meta: Option<&[u8]>) -> TestProto2 {
     let mut test = TestProto2::new();
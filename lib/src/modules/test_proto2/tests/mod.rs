@@ -17,6 +17,26 @@ fn test_proto2_module() {
         &[0x01, 0x02, 0x03, 0x04]
     );
 
+    // The module functions are called before the condition uses `$a`, so
+    // they must trigger the search for patterns by themselves.
+    rule_true!(
+        r#"
+        import "test_proto2"
+        rule test {
+          strings:
+            $a = "foo"
+            $b = "bar"
+          condition:
+            test_proto2.pattern_matches("$a") == 3 and
+            test_proto2.pattern_matches("$b") == 0 and
+            test_proto2.is_pattern_match("$a") and
+            not test_proto2.is_pattern_match("$b") and
+            not defined test_proto2.pattern_matches("$c") and
+            #a == 3 and not $b
+        }"#,
+        b"foo foo foo"
+    );
+
     condition_false!(r#"test_proto2.undef_i64() == 0"#);
     condition_false!(r#"test_proto2.undef_i64() != 0"#);
 
@@ -9,7 +9,8 @@ fn main(_data: &[u8], _meta: Option<&[u8]>) -> Time {
 }
 
 #[module_export]
-fn now(_ctx: &ScanContext) -> Option<i64> {
+fn now(ctx: &ScanContext) -> Option<i64> {
+    debug_assert!(!ctx.deterministic, "time.now() called in deterministic mode");
     Some(SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs() as i64)
 }
 
@@ -0,0 +1,47 @@
+//! Utility functions shared by the parsers in YARA modules.
+
+use bstr::ByteSlice;
+
+/// Reads a null-terminated string from `data`, starting at `offset`.
+///
+/// The returned slice doesn't include the null terminator. At most `max_len`
+/// bytes are read, if the terminator is not found within the first `max_len`
+/// bytes the string is truncated to that length. Returns `None` if `offset`
+/// is beyond the end of `data`, or if the end of `data` is reached before
+/// finding the terminator or reading `max_len` bytes.
+#[cfg_attr(not(feature = "dotnet-module"), allow(dead_code))]
+pub(crate) fn read_cstring(
+    data: &[u8],
+    offset: usize,
+    max_len: usize,
+) -> Option<&[u8]> {
+    let data = data.get(offset..)?;
+    let limit = data.len().min(max_len);
+    match data[..limit].find_byte(0) {
+        Some(len) => Some(&data[..len]),
+        None if limit == max_len => Some(&data[..limit]),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_cstring;
+
+    #[test]
+    fn cstring() {
+        let data = b"foo\0bar\0baz";
+
+        assert_eq!(read_cstring(data, 0, 16), Some(b"foo".as_slice()));
+        assert_eq!(read_cstring(data, 4, 16), Some(b"bar".as_slice()));
+        assert_eq!(read_cstring(data, 3, 16), Some(b"".as_slice()));
+        // The string is truncated to the maximum length.
+        assert_eq!(read_cstring(data, 0, 2), Some(b"fo".as_slice()));
+        assert_eq!(read_cstring(data, 8, 3), Some(b"baz".as_slice()));
+        // The string runs off the end of the data without a terminator.
+        assert_eq!(read_cstring(data, 8, 16), None);
+        // The offset is out of bounds.
+        assert_eq!(read_cstring(data, 11, 16), None);
+        assert_eq!(read_cstring(data, 100, 16), None);
+    }
+}
@@ -1,5 +1,6 @@
 use std::borrow::Cow;
 use std::collections::VecDeque;
+use std::convert::Infallible;
 use std::fmt::{Debug, Display, Formatter};
 use std::mem::replace;
 
@@ -13,6 +14,7 @@ use crate::re::hir::Hir;
 use crate::types;
 
 #[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub(crate) enum Error {
     SyntaxError {
         msg: String,
@@ -25,6 +27,10 @@ pub(crate) enum Error {
         span_1: re::ast::Span,
         span_2: re::ast::Span,
     },
+    UnsupportedFeature {
+        feature: String,
+        span: re::ast::Span,
+    },
 }
 
 impl Display for Error {
@@ -32,6 +38,9 @@ impl Display for Error {
         match self {
             Error::SyntaxError { msg, .. } => write!(f, "{}", msg),
             Error::MixedGreediness { .. } => write!(f, "mixed greediness"),
+            Error::UnsupportedFeature { feature, .. } => {
+                write!(f, "{} is not supported", feature)
+            }
         }
     }
 }
@@ -138,28 +147,113 @@ impl Parser {
 
     /// Parses the regexp and returns its HIR.
     pub fn parse(&self, regexp: &impl Regexp) -> Result<Hir, Error> {
-        let mut re_src = Cow::Borrowed(regexp.source());
-        let mut span_delta = 0_isize;
+        let (ast, re_src, span_delta) = self.parse_ast(regexp)?;
+
+        let ast = Transformer::new().transform(ast);
+        let greedy = Validator::new().validate(&ast);
+
+        // `greedy` is set to Some(true) if all regexp quantifiers are greedy,
+        // Some(false) if all are non-greedy, and None if there's a mix of
+        // greedy and non-greedy quantifiers, like in `foo.*bar.*?baz`. Mixed
+        // greediness is allowed only if allow_mixed_greediness is true, an
+        // error is returned if otherwise.
+        let greedy = if self.allow_mixed_greediness {
+            greedy.unwrap_or(None)
+        } else {
+            greedy?
+        };
+
+        let hir = self
+            .translator(regexp)
+            .build()
+            .translate(re_src.as_ref(), &ast)
+            .map_err(|err| Error::SyntaxError {
+                msg: err.kind().to_string(),
+                span: adjust_span(err.span(), span_delta),
+                note: None,
+            })?;
+
+        Ok(Hir { inner: hir, greedy })
+    }
+
+    /// Returns the alternatives in the regexp that are redundant, because
+    /// they are equivalent to some previous alternative in the same
+    /// alternation. For instance, in `/(abc|abd|abc)/` the second `abc` is
+    /// redundant.
+    ///
+    /// Alternatives are compared after translating them to HIR, which
+    /// normalizes things like escape sequences and the order of the items
+    /// in classes. Each item in the result is a pair with the span of a
+    /// redundant alternative and the span of the alternative it is
+    /// equivalent to.
+    ///
+    /// Regexps that can't be parsed have no redundant alternatives.
+    pub fn redundant_alternatives(
+        &self,
+        regexp: &impl Regexp,
+    ) -> Vec<(re::ast::Span, re::ast::Span)> {
+        let Ok((ast, re_src, span_delta)) = self.parse_ast(regexp) else {
+            return vec![];
+        };
+
+        let ast = Transformer::new().transform(ast);
+
+        let mut checker = AlternationChecker {
+            src: re_src.as_ref(),
+            translator: self.translator(regexp),
+            redundant: vec![],
+        };
+
+        let _ = re::ast::visit(&ast, &mut checker);
+
+        checker
+            .redundant
+            .iter()
+            .map(|(redundant, original)| {
+                (
+                    adjust_span(redundant, span_delta),
+                    adjust_span(original, span_delta),
+                )
+            })
+            .collect()
+    }
 
-        // Utility function that given a span and a `delta` amount, adds that
-        // amount to both the starting and ending points of the span. It will
-        // be used for adjusting error spans after we have modified the
-        // original regular expression. See comment below.
-        let adjust_span = |span: &re::ast::Span, delta| {
-            re::ast::Span::new(
-                re::ast::Position::new(
-                    span.start.offset.saturating_add_signed(delta),
-                    span.start.line,
-                    span.start.column.saturating_add_signed(delta),
-                ),
-                re::ast::Position::new(
-                    span.end.offset.saturating_add_signed(delta),
-                    span.end.line,
-                    span.end.column.saturating_add_signed(delta),
-                ),
-            )
+    /// Returns a builder for the translator that converts the regexp's AST
+    /// into HIR.
+    fn translator(
+        &self,
+        regexp: &impl Regexp,
+    ) -> re::hir::translate::TranslatorBuilder {
+        let case_insensitive = if self.force_case_insensitive {
+            true
+        } else {
+            regexp.case_insensitive()
         };
 
+        let mut builder = re::hir::translate::TranslatorBuilder::new();
+
+        builder
+            .case_insensitive(case_insensitive)
+            .dot_matches_new_line(regexp.dot_matches_new_line())
+            .unicode(false)
+            .utf8(false);
+
+        builder
+    }
+
+    /// Parses the regexp and returns its AST, together with the source code
+    /// that was actually parsed and the difference between spans in that
+    /// source code and spans in the original one.
+    ///
+    /// The source code actually parsed differs from the original one only
+    /// when `relaxed_re_syntax` is enabled, and the regexp needs to be fixed.
+    fn parse_ast<'a>(
+        &self,
+        regexp: &'a impl Regexp,
+    ) -> Result<(Ast, Cow<'a, str>, isize), Error> {
+        let mut re_src = Cow::Borrowed(regexp.source());
+        let mut span_delta = 0_isize;
+
         // YARA-X enforces stricter regular expression syntax compared to YARA.
         // For instance, YARA accepts invalid escape sequences and treats them
         // as literal characters (e.g., \R is interpreted as 'R'). It also
@@ -230,6 +324,22 @@ impl Parser {
         }
         .map_err(|err| {
             let span = err.span();
+
+            // Constructs that are valid in other regexp engines, but not
+            // supported by YARA-X, are reported with a specific error.
+            let feature = match err.kind() {
+                ErrorKind::UnsupportedLookAround => Some("look-around"),
+                ErrorKind::UnsupportedBackreference => Some("backreference"),
+                _ => None,
+            };
+
+            if let Some(feature) = feature {
+                return Error::UnsupportedFeature {
+                    feature: feature.to_string(),
+                    span: adjust_span(span, span_delta),
+                };
+            }
+
             let note = match err.kind() {
                 ErrorKind::EscapeUnrecognized => {
                     let esc_seq = &re_src[span.start.offset..span.end.offset];
@@ -254,46 +364,28 @@ impl Parser {
             }
         })?;
 
-        let ast = Transformer::new().transform(ast);
-        let greedy = Validator::new().validate(&ast);
-
-        // `greedy` is set to Some(true) if all regexp quantifiers are greedy,
-        // Some(false) if all are non-greedy, and None if there's a mix of
-        // greedy and non-greedy quantifiers, like in `foo.*bar.*?baz`. Mixed
-        // greediness is allowed only if allow_mixed_greediness is true, an
-        // error is returned if otherwise.
-        let greedy = if self.allow_mixed_greediness {
-            greedy.unwrap_or(None)
-        } else {
-            greedy?
-        };
-
-        let case_insensitive = if self.force_case_insensitive {
-            true
-        } else {
-            regexp.case_insensitive()
-        };
-
-        let mut translator = re::hir::translate::TranslatorBuilder::new()
-            .case_insensitive(case_insensitive)
-            .dot_matches_new_line(regexp.dot_matches_new_line())
-            .unicode(false)
-            .utf8(false)
-            .build();
-
-        let hir =
-            translator.translate(re_src.as_ref(), &ast).map_err(|err| {
-                Error::SyntaxError {
-                    msg: err.kind().to_string(),
-                    span: adjust_span(err.span(), span_delta),
-                    note: None,
-                }
-            })?;
-
-        Ok(Hir { inner: hir, greedy })
+        Ok((ast, re_src, span_delta))
     }
 }
 
+/// Given a span and a `delta` amount, adds that amount to both the starting
+/// and ending points of the span. This is used for adjusting spans after we
+/// have modified the original regular expression. See [`Parser::parse_ast`].
+fn adjust_span(span: &re::ast::Span, delta: isize) -> re::ast::Span {
+    re::ast::Span::new(
+        re::ast::Position::new(
+            span.start.offset.saturating_add_signed(delta),
+            span.start.line,
+            span.start.column.saturating_add_signed(delta),
+        ),
+        re::ast::Position::new(
+            span.end.offset.saturating_add_signed(delta),
+            span.end.line,
+            span.end.column.saturating_add_signed(delta),
+        ),
+    )
+}
+
 struct Validator {
     first_rep: Option<(bool, re::ast::Span)>,
 }
@@ -335,6 +427,73 @@ impl re::ast::Visitor for &mut Validator {
     }
 }
 
+/// Finds alternatives that are equivalent to some previous alternative in
+/// the same alternation. See [`Parser::redundant_alternatives`].
+struct AlternationChecker<'a> {
+    src: &'a str,
+    translator: re::hir::translate::TranslatorBuilder,
+    redundant: Vec<(re::ast::Span, re::ast::Span)>,
+}
+
+impl re::ast::Visitor for &mut AlternationChecker<'_> {
+    type Output = ();
+    type Err = Infallible;
+
+    fn finish(self) -> Result<Self::Output, Self::Err> {
+        Ok(())
+    }
+
+    fn visit_pre(&mut self, ast: &Ast) -> Result<(), Self::Err> {
+        if let Ast::Alternation(alternation) = ast {
+            let mut seen: Vec<(re::hir::Hir, re::ast::Span)> = Vec::new();
+            for alternative in alternation.asts.iter() {
+                // Each alternative is translated in isolation by a new
+                // translator, because flags like `(?i)` set by one
+                // alternative would affect the translation of the next
+                // ones. Flags set by the regexp before the alternation are
+                // ignored too, so alternatives that are equivalent only
+                // under those flags are not considered equal.
+                let Ok(hir) =
+                    self.translator.build().translate(self.src, alternative)
+                else {
+                    continue;
+                };
+                let hir = without_captures(&hir);
+                match seen.iter().find(|(other, _)| *other == hir) {
+                    Some((_, span)) => {
+                        self.redundant.push((*alternative.span(), *span))
+                    }
+                    None => seen.push((hir, *alternative.span())),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns a copy of `hir` where capture groups are replaced by their
+/// contents.
+///
+/// Groups don't change what a regexp matches, as YARA doesn't use the
+/// captured text, therefore `ab(c)` and `abc` are equivalent.
+fn without_captures(hir: &re::hir::Hir) -> re::hir::Hir {
+    use re::hir::{Hir, HirKind, Repetition};
+    match hir.kind() {
+        HirKind::Capture(capture) => without_captures(&capture.sub),
+        HirKind::Repetition(rep) => Hir::repetition(Repetition {
+            sub: Box::new(without_captures(&rep.sub)),
+            ..rep.clone()
+        }),
+        HirKind::Concat(hirs) => {
+            Hir::concat(hirs.iter().map(without_captures).collect())
+        }
+        HirKind::Alternation(hirs) => {
+            Hir::alternation(hirs.iter().map(without_captures).collect())
+        }
+        _ => hir.clone(),
+    }
+}
+
 /// Performs some transformations to the regexp AST.
 ///
 /// This type takes an AST produced by the `regex_syntax` crate and returns
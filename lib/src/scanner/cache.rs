@@ -0,0 +1,262 @@
+/*! Caching of scan results.
+
+Scanning the same data with the same rules always produces the same results,
+so pipelines that scan unchanged files over and over again can save time by
+caching the results. [`Scanner::scan_cached`] looks up the results in a
+[`ScanCache`] before scanning the data, and stores them in the cache after
+a successful scan.
+*/
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::models::Rule;
+use crate::scanner::ScanResults;
+use crate::Rules;
+
+#[cfg(doc)]
+use crate::Scanner;
+
+/// Key that identifies the results of scanning some data with some rules.
+///
+/// The key is composed of a hash of the rules (see [`Rules::content_hash`])
+/// and a SHA-256 hash of the scanned data.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ScanCacheKey {
+    rules_hash: [u8; 32],
+    data_hash: [u8; 32],
+}
+
+impl ScanCacheKey {
+    /// Creates the key for scanning `data` with `rules`.
+    pub fn new(rules: &Rules, data: &[u8]) -> Self {
+        Self {
+            rules_hash: *rules.content_hash(),
+            data_hash: Sha256::digest(data).into(),
+        }
+    }
+
+    /// Hash that identifies the rules.
+    pub fn rules_hash(&self) -> &[u8; 32] {
+        &self.rules_hash
+    }
+
+    /// Hash of the scanned data.
+    pub fn data_hash(&self) -> &[u8; 32] {
+        &self.data_hash
+    }
+}
+
+/// Trait implemented by the caches used by [`Scanner::scan_cached`].
+///
+/// This crate provides [`LruScanCache`], an in-memory cache, but this trait
+/// can be implemented for caches backed by some other storage, like disk or
+/// a key-value database. [`OwnedScanResults`] implements [`Serialize`] and
+/// [`Deserialize`] for this purpose.
+pub trait ScanCache {
+    /// Returns the results associated to `key`, if any.
+    fn get(&mut self, key: &ScanCacheKey) -> Option<OwnedScanResults>;
+
+    /// Stores the results associated to `key`.
+    fn put(&mut self, key: ScanCacheKey, results: OwnedScanResults);
+}
+
+/// In-memory [`ScanCache`] that holds a limited number of results.
+///
+/// When the cache is full, the least recently used results are evicted for
+/// making room for the new ones.
+pub struct LruScanCache {
+    capacity: usize,
+    entries: HashMap<ScanCacheKey, (OwnedScanResults, u64)>,
+    clock: u64,
+    hits: usize,
+    misses: usize,
+}
+
+impl LruScanCache {
+    /// Creates a new cache that holds up to `capacity` results.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::with_capacity(capacity),
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Number of results currently in the cache.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of lookups that found the results in the cache.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Number of lookups that didn't find the results in the cache.
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+}
+
+impl ScanCache for LruScanCache {
+    fn get(&mut self, key: &ScanCacheKey) -> Option<OwnedScanResults> {
+        self.clock += 1;
+        match self.entries.get_mut(key) {
+            Some((results, last_used)) => {
+                *last_used = self.clock;
+                self.hits += 1;
+                Some(results.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn put(&mut self, key: ScanCacheKey, results: OwnedScanResults) {
+        if self.capacity == 0 {
+            return;
+        }
+        // Evicting an entry is O(n), but this is negligible compared with
+        // the cost of the scan that produced the results.
+        if self.entries.len() >= self.capacity
+            && !self.entries.contains_key(&key)
+        {
+            let lru = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key.clone())
+                .unwrap();
+            self.entries.remove(&lru);
+        }
+        self.clock += 1;
+        self.entries.insert(key, (results, self.clock));
+    }
+}
+
+/// Results of a scan that don't borrow from the [`Scanner`].
+///
+/// Only the matching rules are included, with their patterns and the ranges
+/// where each pattern matched. Private rules are not included.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OwnedScanResults {
+    matching_rules: Vec<OwnedRule>,
+}
+
+impl OwnedScanResults {
+    /// Returns the matching rules.
+    pub fn matching_rules(&self) -> &[OwnedRule] {
+        self.matching_rules.as_slice()
+    }
+
+    /// Adds `offset` to the ranges of all matches, and then keeps only the
+    /// matches that end after `end`.
+    ///
+    /// Rules that are left without matches are removed, unless they didn't
+    /// have any match in the first place and `keep` returns true for them.
+    pub(crate) fn rebase<F>(&mut self, offset: usize, end: usize, mut keep: F)
+    where
+        F: FnMut(&OwnedRule) -> bool,
+    {
+        self.matching_rules.retain_mut(|rule| {
+            let mut had_matches = false;
+            rule.patterns.retain_mut(|pattern| {
+                had_matches |= !pattern.matches.is_empty();
+                pattern.matches.retain_mut(|range| {
+                    *range = range.start + offset..range.end + offset;
+                    range.end > end
+                });
+                !pattern.matches.is_empty()
+            });
+            if had_matches {
+                !rule.patterns.is_empty()
+            } else {
+                keep(rule)
+            }
+        });
+    }
+}
+
+impl From<&ScanResults<'_, '_>> for OwnedScanResults {
+    fn from(results: &ScanResults<'_, '_>) -> Self {
+        Self {
+            matching_rules: results
+                .matching_rules()
+                .map(|rule| OwnedRule::from(&rule))
+                .collect(),
+        }
+    }
+}
+
+impl From<&Rule<'_, '_>> for OwnedRule {
+    fn from(rule: &Rule<'_, '_>) -> Self {
+        Self {
+            namespace: rule.namespace().to_string(),
+            identifier: rule.identifier().to_string(),
+            patterns: rule
+                .patterns()
+                .map(|pattern| OwnedPattern {
+                    identifier: pattern.identifier().to_string(),
+                    matches: pattern.matches().map(|m| m.range()).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A matching rule in [`OwnedScanResults`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OwnedRule {
+    namespace: String,
+    identifier: String,
+    patterns: Vec<OwnedPattern>,
+}
+
+impl OwnedRule {
+    /// Returns the rule's name.
+    pub fn identifier(&self) -> &str {
+        self.identifier.as_str()
+    }
+
+    /// Returns the rule's namespace.
+    pub fn namespace(&self) -> &str {
+        self.namespace.as_str()
+    }
+
+    /// Returns the patterns defined by the rule.
+    pub fn patterns(&self) -> &[OwnedPattern] {
+        self.patterns.as_slice()
+    }
+}
+
+/// A pattern in an [`OwnedRule`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OwnedPattern {
+    identifier: String,
+    matches: Vec<Range<usize>>,
+}
+
+impl OwnedPattern {
+    /// Returns the pattern's identifier (e.g: $a, $b).
+    pub fn identifier(&self) -> &str {
+        self.identifier.as_str()
+    }
+
+    /// Returns the ranges where the pattern matched.
+    pub fn matches(&self) -> &[Range<usize>] {
+        self.matches.as_slice()
+    }
+}
@@ -0,0 +1,209 @@
+/*! Scan results in the text format used by the original YARA command-line tool.
+
+Many scripts were written for parsing the output of the `yara` command-line
+tool. [`ClassicText`] renders the rules that matched during a scan in the
+same format, so that those scripts keep working with YARA-X.
+
+This is independent of the text produced by the YARA-X command-line tool,
+which has its own format.
+*/
+
+use std::fmt::Write;
+
+use crate::models::{MetaValue, Rule};
+use crate::scanner::ScanResults;
+
+/// Maximum number of bytes shown for matches of hex patterns. Longer
+/// matches are truncated and followed by `...`.
+const MAX_HEX_BYTES: usize = 64;
+
+/// Renders scan results in the format used by the original `yara` tool.
+///
+/// There's one line for each matching rule, with the rule's identifier
+/// followed by the path of the scanned file. Tags, metadata and namespace
+/// are included only if requested, like with the `-g`, `-m` and `-e`
+/// options in `yara`:
+///
+/// ```text
+/// default:foo [bar,baz] [author="qux",version=2] /path/to/file
+/// ```
+///
+/// When [`ClassicText::show_strings`] is enabled, the rule's line is
+/// followed by one line per match, like with the `-s` option in `yara`:
+///
+/// ```text
+/// 0x10:$a: matched data
+/// 0x24:$b: 4D 5A 90 00
+/// ```
+///
+/// Matches of hex patterns are shown as hex bytes, while any other match is
+/// shown verbatim, except for non-printable bytes, which are escaped as
+/// `\xNN`.
+///
+/// # Example
+///
+/// ```rust
+/// # use yara_x::{ClassicText, Scanner};
+/// let rules = yara_x::compile(r#"
+///     rule foo : bar { strings: $a = "foo" condition: $a }
+/// "#).unwrap();
+///
+/// let mut scanner = Scanner::new(&rules);
+/// let results = scanner.scan(b"foofoo").unwrap();
+///
+/// assert_eq!(
+///     ClassicText::new().show_strings(true).render(&results, "file.bin"),
+///     "foo file.bin\n0x0:$a: foo\n0x3:$a: foo\n"
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ClassicText {
+    show_tags: bool,
+    show_meta: bool,
+    show_namespace: bool,
+    show_strings: bool,
+}
+
+impl ClassicText {
+    /// Creates a new [`ClassicText`] that shows only the rule identifiers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shows the tags of each matching rule, like `-g` in `yara`.
+    pub fn show_tags(&mut self, yes: bool) -> &mut Self {
+        self.show_tags = yes;
+        self
+    }
+
+    /// Shows the metadata of each matching rule, like `-m` in `yara`.
+    pub fn show_meta(&mut self, yes: bool) -> &mut Self {
+        self.show_meta = yes;
+        self
+    }
+
+    /// Prefixes rule identifiers with their namespace, like `-e` in `yara`.
+    pub fn show_namespace(&mut self, yes: bool) -> &mut Self {
+        self.show_namespace = yes;
+        self
+    }
+
+    /// Shows the matches of each matching rule, like `-s` in `yara`.
+    pub fn show_strings(&mut self, yes: bool) -> &mut Self {
+        self.show_strings = yes;
+        self
+    }
+
+    /// Renders the rules that matched in `results`, which were obtained by
+    /// scanning the file at `path`.
+    ///
+    /// Private rules are not included, as they are not included in
+    /// [`ScanResults::matching_rules`] either.
+    pub fn render(&self, results: &ScanResults, path: &str) -> String {
+        let mut output = String::new();
+        for rule in results.matching_rules() {
+            self.render_rule(&mut output, &rule, path);
+        }
+        output
+    }
+
+    /// Renders a single matching rule, appending the result to `output`.
+    fn render_rule(&self, output: &mut String, rule: &Rule, path: &str) {
+        if self.show_namespace {
+            output.push_str(rule.namespace());
+            output.push(':');
+        }
+
+        output.push_str(rule.identifier());
+        output.push(' ');
+
+        if self.show_tags {
+            let tags: Vec<&str> =
+                rule.tags().map(|t| t.identifier()).collect();
+            output.push('[');
+            output.push_str(tags.join(",").as_str());
+            output.push_str("] ");
+        }
+
+        if self.show_meta {
+            output.push('[');
+            for (i, (ident, value)) in rule.metadata().enumerate() {
+                if i > 0 {
+                    output.push(',');
+                }
+                output.push_str(ident);
+                output.push('=');
+                match value {
+                    MetaValue::Integer(i) => write!(output, "{}", i).unwrap(),
+                    MetaValue::Float(f) => write!(output, "{}", f).unwrap(),
+                    MetaValue::Bool(b) => write!(output, "{}", b).unwrap(),
+                    MetaValue::String(s) => {
+                        output.push('"');
+                        escape(output, s.as_bytes());
+                        output.push('"');
+                    }
+                    MetaValue::Bytes(s) => {
+                        output.push('"');
+                        escape(output, s);
+                        output.push('"');
+                    }
+                }
+            }
+            output.push_str("] ");
+        }
+
+        output.push_str(path);
+        output.push('\n');
+
+        if !self.show_strings {
+            return;
+        }
+
+        for pattern in rule.patterns() {
+            let is_hex = pattern.is_hex();
+            for m in pattern.matches() {
+                write!(
+                    output,
+                    "{:#x}:{}: ",
+                    m.range().start,
+                    pattern.identifier()
+                )
+                .unwrap();
+                if is_hex {
+                    hex(output, m.data());
+                } else {
+                    escape(output, m.data());
+                }
+                output.push('\n');
+            }
+        }
+    }
+}
+
+/// Appends `data` to `output`, escaping non-printable bytes as `\xNN`, and
+/// newlines, tabs and backslashes as `\n`, `\t` and `\\`, respectively.
+fn escape(output: &mut String, data: &[u8]) {
+    for b in data {
+        match b {
+            b'\n' => output.push_str("\\n"),
+            b'\t' => output.push_str("\\t"),
+            b'\\' => output.push_str("\\\\"),
+            0x20..=0x7e => output.push(*b as char),
+            _ => write!(output, "\\x{:02X}", b).unwrap(),
+        }
+    }
+}
+
+/// Appends `data` to `output` as hex bytes separated by spaces, like in
+/// `4D 5A 90`. Only the first [`MAX_HEX_BYTES`] bytes are shown.
+fn hex(output: &mut String, data: &[u8]) {
+    for (i, b) in data.iter().take(MAX_HEX_BYTES).enumerate() {
+        if i > 0 {
+            output.push(' ');
+        }
+        write!(output, "{:02X}", b).unwrap();
+    }
+    if data.len() > MAX_HEX_BYTES {
+        output.push_str(" ...");
+    }
+}
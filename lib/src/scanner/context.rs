@@ -1,9 +1,11 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::VecDeque;
 use std::ops::{Range, RangeInclusive};
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::ptr::NonNull;
 use std::rc::Rc;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 #[cfg(feature = "logging")]
 use log::*;
@@ -12,20 +14,28 @@ use std::time::Duration;
 #[cfg(any(feature = "logging", feature = "rules-profiling"))]
 use std::time::Instant;
 
+use aho_corasick::AhoCorasick;
 use base64::Engine;
 use bitvec::order::Lsb0;
 use bitvec::slice::BitSlice;
+#[cfg(feature = "async")]
+use bitvec::vec::BitVec;
 use bstr::{BString, ByteSlice};
 use indexmap::IndexMap;
+use memchr::memmem;
+use protobuf::reflect::ReflectValueRef;
 use protobuf::{MessageDyn, MessageFull};
 use regex_automata::meta::Regex;
 use rustc_hash::{FxHashMap, FxHashSet};
-use wasmtime::Store;
+use wasmtime::{Store, TypedFunc, ValRaw};
 
 use crate::compiler::{
-    NamespaceId, PatternId, RegexpId, RuleId, Rules, SubPattern,
-    SubPatternAtom, SubPatternFlagSet, SubPatternFlags, SubPatternId,
+    MergedModule, NamespaceId, PatternId, RegexpId, RuleId, Rules,
+    SearchStrategy, SubPattern, SubPatternAtom, SubPatternFlagSet,
+    SubPatternFlags, SubPatternId,
 };
+use crate::models;
+use crate::modules::BUILTIN_MODULES;
 use crate::re::fast::FastVM;
 use crate::re::thompson::PikeVM;
 use crate::re::Action;
@@ -35,6 +45,17 @@ use crate::scanner::HEARTBEAT_COUNTER;
 use crate::types::{Array, Map, Struct};
 use crate::wasm::MATCHING_RULES_BITMAP_BASE;
 
+/// An instance of a WASM module produced by the compiler.
+pub(crate) struct WasmInstance {
+    /// Function that evaluates the conditions of all the rules in the
+    /// module.
+    pub main_func: TypedFunc<(), i32>,
+    /// Module's main memory.
+    pub main_memory: wasmtime::Memory,
+    /// Global variable that contains the ID of the rule being evaluated.
+    pub current_rule: wasmtime::Global,
+}
+
 /// Structure that holds information about the current scan.
 pub(crate) struct ScanContext<'r> {
     /// Pointer to the WASM store.
@@ -62,6 +83,10 @@ pub(crate) struct ScanContext<'r> {
     /// because we want to keep the insertion order, so that rules in
     /// namespaces that were declared first, appear first in scan results.
     pub matching_rules: IndexMap<NamespaceId, Vec<RuleId>>,
+    /// Global rules that didn't match during the current scan, together
+    /// with the number of rules in the same namespace that had matched
+    /// before, and whose matches were discarded because of it.
+    pub global_rule_no_matches: Vec<(RuleId, usize)>,
     /// Compiled rules for this scan.
     pub compiled_rules: &'r Rules,
     /// Structure that contains top-level symbols, like module names
@@ -74,6 +99,23 @@ pub(crate) struct ScanContext<'r> {
     pub current_struct: Option<Rc<Struct>>,
     /// Module's main memory.
     pub main_memory: Option<wasmtime::Memory>,
+    /// WASM global variable that contains the [`RuleId`] of the rule whose
+    /// condition is being evaluated, or -1 if no rule condition has been
+    /// evaluated yet. The WASM code updates it before evaluating each rule.
+    pub current_rule: Option<wasmtime::Global>,
+    /// Instances of the WASM modules that evaluate the conditions of rules
+    /// added with [`Rules::merge`], in the same order as the modules
+    /// returned by [`Rules::merged_modules`].
+    pub merged_instances: Vec<WasmInstance>,
+    /// Index in `merged_instances` of the instance whose code is being
+    /// executed, or `None` while executing the main WASM module. IDs
+    /// received from the code in a merged module must be translated with
+    /// [`ScanContext::merged_module`].
+    pub current_module: Option<usize>,
+    /// True after the search for patterns was executed during the current
+    /// scan. When rules are merged each WASM module tracks this on its own,
+    /// but the patterns for all of them are searched at the same time.
+    pub pattern_search_done: bool,
     /// Hash map that contains the protobuf messages returned by YARA modules.
     /// Keys are the fully qualified protobuf message name, and values are
     /// the message returned by the main function of the corresponding module.
@@ -97,18 +139,63 @@ pub(crate) struct ScanContext<'r> {
     pub unconfirmed_matches: FxHashMap<SubPatternId, Vec<UnconfirmedMatch>>,
     /// Set that contains the PatternId for those patterns that have reached
     /// the maximum number of matches indicated by `max_matches_per_pattern`.
+    /// In fast mode, it also contains the patterns that don't need more than
+    /// one match.
     pub limit_reached: FxHashSet<PatternId>,
+    /// If true, patterns that are used only for determining whether they
+    /// matched or not stop producing matches after the first one.
+    pub fast_mode: bool,
+    /// If true, the scan must not depend on nondeterministic sources, like
+    /// the current time. See [`crate::Scanner::deterministic`].
+    pub deterministic: bool,
     /// When [`HEARTBEAT_COUNTER`] is larger than this value, the scan is
     /// aborted due to a timeout.
     pub deadline: u64,
+    /// Set to true when some module function finds that the deadline was
+    /// reached, see [`ScanContext::deadline_reached`]. The scan fails with
+    /// a timeout even if the WASM code completes.
+    pub deadline_exceeded: Cell<bool>,
+    /// When set to true by some other thread, the scan is aborted as if the
+    /// deadline was reached.
+    pub abort: Option<Arc<AtomicBool>>,
+    /// Aho-Corasick automaton used instead of the one in the compiled rules,
+    /// when some specific kind was requested with
+    /// [`crate::Scanner::automaton_kind`].
+    pub ac_automaton: Option<AhoCorasick>,
     /// Hash map that serves as a cache for regexps used in expressions like
     /// `some_var matches /foobar/`. Compiling a regexp is a expensive
     /// operation. Instead of compiling the regexp each time the expression
     /// is evaluated, it is compiled the first time and stored in this hash
     /// map.
     pub regexp_cache: RefCell<FxHashMap<RegexpId, Regex>>,
+    /// Value of `entrypoint` for the current scan. It is `None` until
+    /// computed for the first time by [`ScanContext::entrypoint`].
+    pub entrypoint: Option<Option<i64>>,
     /// Callback invoked every time a YARA rule calls `console.log`.
     pub console_log: Option<Box<dyn FnMut(String) + 'r>>,
+    /// Reports the progress of the pattern search phase. This is set only
+    /// while running [`crate::Scanner::scan_with_progress`].
+    pub progress: Option<Progress<'r>>,
+    /// Reports the rules that are known to match while the scan is still
+    /// running. This is set only by [`crate::Scanner::scan_stream_async`].
+    #[cfg(feature = "async")]
+    pub decided_rules: Option<DecidedRules<'r>>,
+    /// Offset where the search for patterns starts. Atoms that start before
+    /// this offset were already processed by a previous call to
+    /// [`crate::Scanner::scan_partial`], and the matches they produced are
+    /// restored from the [`crate::ScanCursor`].
+    pub search_start: usize,
+    /// If set, the search for patterns stops after processing the atoms
+    /// that start before this offset. This is set only while running
+    /// [`crate::Scanner::scan_partial`].
+    pub search_end: Option<usize>,
+    /// Value produced by the condition evaluated with
+    /// [`crate::Scanner::eval_condition`]. It remains `None` if the value is
+    /// undefined.
+    pub eval_result: Option<models::Value>,
+    /// Results put aside by functions called through their single-result
+    /// variant, in reverse order. See [`crate::wasm::extra_result_i32`].
+    pub extra_results: Vec<ValRaw>,
     /// Hash map that tracks the time spend on each pattern. Keys are pattern
     /// PatternIds and values are the cumulative time spent on verifying each
     /// pattern.
@@ -147,7 +234,105 @@ impl<'r> ScanContext<'r> {
     }
 }
 
+impl<'r> ScanContext<'r> {
+    /// Returns the module merged with [`Rules::merge`] whose code is being
+    /// executed, or `None` if the code being executed belongs to the main
+    /// WASM module.
+    #[inline]
+    pub(crate) fn merged_module(&self) -> Option<&'r MergedModule> {
+        self.current_module
+            .map(|index| &self.compiled_rules.merged_modules()[index])
+    }
+
+    /// Returns the main memory of the WASM module whose code is being
+    /// executed.
+    #[inline]
+    pub(crate) fn current_memory(&self) -> wasmtime::Memory {
+        match self.current_module {
+            Some(index) => self.merged_instances[index].main_memory,
+            None => self.main_memory.unwrap(),
+        }
+    }
+
+    /// Sets the bit for a rule or pattern in the bitmaps of a merged
+    /// module. `rule_id` and `pattern_id` are local to the module, and
+    /// only one of them must be `Some`.
+    fn set_merged_bitmap_bit(
+        &mut self,
+        index: usize,
+        rule_id: Option<usize>,
+        pattern_id: Option<usize>,
+        value: bool,
+    ) {
+        let module = &self.compiled_rules.merged_modules()[index];
+        let wasm_store = unsafe { self.wasm_store.as_mut() };
+        let mem =
+            self.merged_instances[index].main_memory.data_mut(wasm_store);
+
+        let num_rules = module.num_rules();
+        let base = MATCHING_RULES_BITMAP_BASE as usize;
+        let bits = BitSlice::<u8, Lsb0>::from_slice_mut(
+            &mut mem[base..base
+                + num_rules.div_ceil(8)
+                + module.num_patterns().div_ceil(8)],
+        );
+
+        match (rule_id, pattern_id) {
+            (Some(rule_id), None) => bits.set(rule_id, value),
+            (None, Some(pattern_id)) => {
+                bits.set(num_rules.div_ceil(8) * 8 + pattern_id, value)
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
 impl ScanContext<'_> {
+    /// Size of the chunks in which [`ScanContext::for_each_chunk`] splits
+    /// the data.
+    const CHUNK_SIZE: usize = 1 << 20;
+
+    /// Returns true if the scan's deadline was reached.
+    ///
+    /// The timeout can interrupt the WASM code, but not the module functions
+    /// called from it. Functions that can take long, like those that compute
+    /// hashes over large portions of the data, must call this periodically
+    /// and return as soon as possible when it returns true. Once the deadline
+    /// is reached the scan fails with [`crate::ScanError::Timeout`], so the
+    /// value returned by the function doesn't matter.
+    pub(crate) fn deadline_reached(&self) -> bool {
+        if HEARTBEAT_COUNTER.load(Ordering::Relaxed) >= self.deadline
+            || self.abort_requested()
+        {
+            self.deadline_exceeded.set(true);
+        }
+        self.deadline_exceeded.get()
+    }
+
+    /// Returns true if the flag in [`ScanContext::abort`] was set.
+    fn abort_requested(&self) -> bool {
+        self.abort.as_ref().is_some_and(|abort| abort.load(Ordering::Relaxed))
+    }
+
+    /// Calls `f` with consecutive chunks of `data`, checking the deadline
+    /// before each of them.
+    ///
+    /// Returns `None` if the deadline was reached before processing all
+    /// the chunks.
+    pub(crate) fn for_each_chunk(
+        &self,
+        data: &[u8],
+        mut f: impl FnMut(&[u8]),
+    ) -> Option<()> {
+        for chunk in data.chunks(Self::CHUNK_SIZE) {
+            if self.deadline_reached() {
+                return None;
+            }
+            f(chunk);
+        }
+        Some(())
+    }
+
     /// Returns a slice with the data being scanned.
     pub(crate) fn scanned_data<'a>(&self) -> &'a [u8] {
         unsafe {
@@ -158,6 +343,74 @@ impl ScanContext<'_> {
         }
     }
 
+    /// Returns the [`RuleId`] of the rule whose condition is being
+    /// evaluated, if any.
+    pub(crate) fn current_rule_id(&mut self) -> Option<RuleId> {
+        let current_rule = match self.current_module {
+            Some(index) => self.merged_instances[index].current_rule,
+            None => self.current_rule?,
+        };
+
+        let wasm_store = unsafe { self.wasm_store.as_mut() };
+        let rule_id = current_rule.get(wasm_store).i32()?;
+
+        // `current_rule` is -1 until the first rule is evaluated.
+        let rule_id = RuleId::from(usize::try_from(rule_id).ok()?);
+
+        Some(match self.merged_module() {
+            Some(module) => module.rule_id(rule_id),
+            None => rule_id,
+        })
+    }
+
+    /// Returns the ranges where the pattern with the given identifier
+    /// (e.g: `$a`) matched, sorted by starting offset. The pattern must be
+    /// declared in the rule whose condition is being evaluated.
+    ///
+    /// This is intended for module functions that need to know where some
+    /// pattern matched. Patterns are searched lazily, when a rule condition
+    /// uses them for the first time, so the search is done here if it was
+    /// not done before. Returns `None` if the current rule doesn't have a
+    /// pattern with that identifier, or if the search times out.
+    pub(crate) fn pattern_match_ranges(
+        &mut self,
+        identifier: &str,
+    ) -> Option<impl Iterator<Item = Range<usize>> + '_> {
+        let rules = self.compiled_rules;
+        let (_, pattern_id) =
+            rules.get(self.current_rule_id()?).patterns.iter().find(
+                |(ident_id, _)| {
+                    rules.ident_pool().get(*ident_id) == Some(identifier)
+                },
+            )?;
+        let pattern_id = *pattern_id;
+
+        if !self.pattern_search_done {
+            self.search_for_patterns().ok()?;
+            self.pattern_search_done = true;
+        }
+        Some(
+            self.pattern_matches
+                .get(pattern_id)
+                .into_iter()
+                .flat_map(|matches| matches.iter())
+                .map(|m| m.range.clone()),
+        )
+    }
+
+    /// Returns true if the pattern with the given identifier matched.
+    ///
+    /// Like [`ScanContext::pattern_match_ranges`], this searches for
+    /// patterns if it was not done before, and returns `None` if the current
+    /// rule doesn't have a pattern with that identifier, or if the search
+    /// times out.
+    pub(crate) fn is_pattern_match(
+        &mut self,
+        identifier: &str,
+    ) -> Option<bool> {
+        Some(self.pattern_match_ranges(identifier)?.next().is_some())
+    }
+
     /// Returns true of the regexp identified by the given [`RegexpId`]
     /// matches `haystack`.
     pub(crate) fn regexp_matches(
@@ -193,6 +446,62 @@ impl ScanContext<'_> {
         <dyn MessageDyn>::downcast_ref(m)
     }
 
+    /// Returns the file offset of the entry point for the scanned data.
+    ///
+    /// The entry point is obtained from the output of the first of these
+    /// modules that reports one: `pe`, `elf`. This means that for a file
+    /// that is a valid PE the result is always the PE's entry point. If
+    /// none of these modules reports an entry point, the result is `None`
+    /// (undefined). Modules imported by the rules are not parsed again, for
+    /// the rest the module's main function is invoked, and the result is
+    /// cached until the next scan.
+    pub(crate) fn entrypoint(&mut self) -> Option<i64> {
+        if let Some(entrypoint) = self.entrypoint {
+            return entrypoint;
+        }
+
+        let mut entrypoint = None;
+
+        for module_name in ["pe", "elf"] {
+            // The module may not be available if the corresponding feature
+            // is disabled.
+            let Some(module) = BUILTIN_MODULES.get(module_name) else {
+                continue;
+            };
+
+            let root_struct_name = module.root_struct_descriptor.full_name();
+
+            let parsed;
+            let output = match self.module_outputs.get(root_struct_name) {
+                Some(output) => output.as_ref(),
+                None => match module.main_fn {
+                    Some(main_fn) => {
+                        parsed = main_fn(self.scanned_data(), None);
+                        parsed.as_ref()
+                    }
+                    None => continue,
+                },
+            };
+
+            entrypoint = output
+                .descriptor_dyn()
+                .field_by_name("entry_point")
+                .and_then(|field| field.get_singular(output))
+                .and_then(|value| match value {
+                    ReflectValueRef::U32(v) => Some(v as i64),
+                    ReflectValueRef::U64(v) => v.try_into().ok(),
+                    _ => None,
+                });
+
+            if entrypoint.is_some() {
+                break;
+            }
+        }
+
+        self.entrypoint = Some(entrypoint);
+        entrypoint
+    }
+
     /// Writes a log before starting evaluating the condition for the rule
     /// identified by `rule_id`.
     #[cfg(feature = "logging")]
@@ -261,7 +570,12 @@ impl ScanContext<'_> {
         // namespace as the non-matching rule, must be removed from the
         // `matching_rules` map. Also, their corresponding bits in
         // the matching rules bitmap must be cleared.
+        let mut merged_bits = Vec::new();
+        let mut discarded = 0;
+
         if let Some(rules) = self.matching_rules.get_mut(&rule.namespace_id) {
+            discarded = rules.len();
+
             let wasm_store = unsafe { self.wasm_store.as_mut() };
             let main_mem = self.main_memory.unwrap().data_mut(wasm_store);
 
@@ -272,10 +586,28 @@ impl ScanContext<'_> {
                 &mut main_mem[base..base + num_rules.div_ceil(8)],
             );
 
+            let current_module = self.current_module;
+            let merged = current_module.map(|index| {
+                (index, &self.compiled_rules.merged_modules()[index])
+            });
+
             for rule_id in rules.drain(0..) {
                 bits.set(rule_id.into(), false);
+                // The code in merged modules reads the bitmap in its own
+                // memory, where rules have local IDs.
+                if let Some((index, module)) = merged {
+                    if let Some(local_id) = module.local_rule_id(rule_id) {
+                        merged_bits.push((index, local_id));
+                    }
+                }
             }
         }
+
+        for (index, local_id) in merged_bits {
+            self.set_merged_bitmap_bit(index, Some(local_id), None, false);
+        }
+
+        self.global_rule_no_matches.push((rule_id, discarded));
     }
 
     /// Called during the scan process when a rule has matched for tracking
@@ -310,6 +642,101 @@ impl ScanContext<'_> {
 
         // The RuleId-th bit in the `rule_matches` bit vector is set to 1.
         bits.set(rule_id.into(), true);
+
+        if let Some(index) = self.current_module {
+            let module = &self.compiled_rules.merged_modules()[index];
+            if let Some(local_id) = module.local_rule_id(rule_id) {
+                self.set_merged_bitmap_bit(index, Some(local_id), None, true);
+            }
+        }
+
+        #[cfg(feature = "async")]
+        self.report_decided_rules(rule_id);
+    }
+
+    /// Called after `rule_id` matched, reports the rules in the same
+    /// namespace that can't be discarded anymore to [`DecidedRules`].
+    ///
+    /// Rules are evaluated in order, and a global rule that doesn't match
+    /// discards the rules in its namespace that matched before it, so the
+    /// rules that matched are decided once the last global rule in the
+    /// namespace has matched.
+    #[cfg(feature = "async")]
+    fn report_decided_rules(&mut self, rule_id: RuleId) {
+        let Some(decided_rules) = &self.decided_rules else {
+            return;
+        };
+
+        let namespace_id = self.compiled_rules.get(rule_id).namespace_id;
+
+        if let Some(last_global) =
+            decided_rules.last_global_rule.get(&namespace_id)
+        {
+            if usize::from(rule_id) < usize::from(*last_global) {
+                return;
+            }
+        }
+
+        self.report_matching_rules(namespace_id, false);
+    }
+
+    /// Reports the rules that matched and were not reported while the scan
+    /// was running. Called when the scan completes successfully.
+    #[cfg(feature = "async")]
+    pub(crate) fn report_pending_rules(&mut self) {
+        if self.decided_rules.is_none() {
+            return;
+        }
+        let namespaces: Vec<_> = self.matching_rules.keys().copied().collect();
+        for namespace_id in namespaces {
+            self.report_matching_rules(namespace_id, true);
+        }
+    }
+
+    /// Passes the non-private rules in `namespace_id` that matched, and
+    /// were not reported yet, to the callback in [`DecidedRules`].
+    ///
+    /// The matches for the rule's patterns are known only after the search
+    /// for patterns is done, which happens only when some condition needs
+    /// it. Until then, the rules with patterns are not reported, unless
+    /// `scan_complete` is true.
+    #[cfg(feature = "async")]
+    fn report_matching_rules(
+        &mut self,
+        namespace_id: NamespaceId,
+        scan_complete: bool,
+    ) {
+        let Some(mut decided_rules) = self.decided_rules.take() else {
+            return;
+        };
+
+        let data = crate::scanner::ScannedData::Slice(self.scanned_data());
+
+        for rule_id in
+            self.matching_rules.get(&namespace_id).into_iter().flatten()
+        {
+            let rule_info = self.compiled_rules.get(*rule_id);
+
+            if rule_info.is_private
+                || decided_rules.reported[usize::from(*rule_id)]
+                || !(scan_complete
+                    || self.pattern_search_done
+                    || rule_info.patterns.is_empty())
+            {
+                continue;
+            }
+
+            decided_rules.reported.set(usize::from(*rule_id), true);
+
+            (decided_rules.callback)(&models::Rule {
+                ctx: Some(self),
+                data: Some(&data),
+                rules: self.compiled_rules,
+                rule_info,
+            });
+        }
+
+        self.decided_rules = Some(decided_rules);
     }
 
     /// Called during the scan process when a pattern match has been found.
@@ -337,7 +764,21 @@ impl ScanContext<'_> {
 
         bits.set(pattern_id.into(), true);
 
-        if !self.pattern_matches.add(pattern_id, match_, replace_if_longer) {
+        // Patterns can be found while any of the modules is being executed,
+        // so the module that uses the pattern must be looked up.
+        for (index, module) in
+            self.compiled_rules.merged_modules().iter().enumerate()
+        {
+            if let Some(local_id) = module.local_pattern_id(pattern_id) {
+                self.set_merged_bitmap_bit(index, None, Some(local_id), true);
+                break;
+            }
+        }
+
+        if !self.pattern_matches.add(pattern_id, match_, replace_if_longer)
+            || (self.fast_mode
+                && self.compiled_rules.single_match_is_enough(pattern_id))
+        {
             self.limit_reached.insert(pattern_id);
         }
     }
@@ -346,9 +787,10 @@ impl ScanContext<'_> {
     ///
     /// The pattern search phase is when YARA scans the data looking for the
     /// patterns declared in rules. All the patterns are searched simultaneously
-    /// using the Aho-Corasick algorithm. This phase is triggered lazily during
-    /// the evaluation of the rule conditions, when some of the conditions need
-    /// to know if a pattern matched or not.
+    /// using the Aho-Corasick algorithm, except when the rules have a single
+    /// atom, which is searched with `memmem` (see [`SearchStrategy`]). This
+    /// phase is triggered lazily during the evaluation of the rule conditions,
+    /// when some of the conditions need to know if a pattern matched or not.
     ///
     /// This function won't be called if the conditions can be fully evaluated
     /// without looking for any of the patterns. If it must be called, it will be
@@ -357,10 +799,52 @@ impl ScanContext<'_> {
         let scanned_data = self.scanned_data();
 
         // Verify the anchored pattern first. These are patterns that can match
-        // at a single known offset within the data.
-        self.verify_anchored_patterns();
+        // at a single known offset within the data. When resuming a partial
+        // scan they were already verified.
+        if self.search_start == 0 {
+            self.verify_anchored_patterns();
+        }
 
-        let ac = self.compiled_rules.ac_automaton();
+        let search_start = self.search_start.min(scanned_data.len());
+
+        // When the search stops at `search_end`, the atoms that start before
+        // that offset must be found, even if they end after it.
+        let search_end = match self.search_end {
+            Some(end) => {
+                let max_atom_len = self
+                    .compiled_rules
+                    .atoms()
+                    .iter()
+                    .map(|atom| atom.len())
+                    .max()
+                    .unwrap_or(0);
+                end.saturating_add(max_atom_len)
+                    .clamp(search_start, scanned_data.len())
+            }
+            None => scanned_data.len(),
+        };
+
+        // The automaton is cloned because the iterator below must not borrow
+        // `self`. Cloning an automaton is cheap, its internal state is shared.
+        let compiled_rules = self.compiled_rules;
+        let ac_automaton = self.ac_automaton.clone();
+
+        let atom_iter = match self.compiled_rules.search_strategy() {
+            SearchStrategy::AhoCorasick => AtomMatches::AhoCorasick(
+                ac_automaton
+                    .as_ref()
+                    .unwrap_or_else(|| compiled_rules.ac_automaton())
+                    .find_overlapping_iter(
+                        aho_corasick::Input::new(scanned_data)
+                            .range(search_start..search_end),
+                    ),
+            ),
+            SearchStrategy::Memmem => AtomMatches::Memmem {
+                finder: self.compiled_rules.memmem_finder(),
+                data: &scanned_data[..search_end],
+                pos: search_start,
+            },
+        };
 
         let mut vm = VM {
             pike_vm: PikeVM::new(self.compiled_rules.re_code()),
@@ -375,13 +859,19 @@ impl ScanContext<'_> {
         #[cfg(feature = "logging")]
         let mut atom_matches = 0_usize;
 
-        for ac_match in ac.find_overlapping_iter(scanned_data) {
+        for (atom_index, atom_start) in atom_iter {
             #[cfg(feature = "logging")]
             {
                 atom_matches += 1;
             }
 
-            if HEARTBEAT_COUNTER.load(Ordering::Relaxed) >= self.deadline {
+            if let Some(progress) = &mut self.progress {
+                progress.update(atom_start, Some(scanned_data.len()));
+            }
+
+            if HEARTBEAT_COUNTER.load(Ordering::Relaxed) >= self.deadline
+                || self.abort_requested()
+            {
                 #[cfg(feature = "logging")]
                 info!(
                     "Scan timeout after: {:?}",
@@ -390,14 +880,19 @@ impl ScanContext<'_> {
                 return Err(ScanError::Timeout);
             }
 
-            let atom =
-                unsafe { atoms.get_unchecked(ac_match.pattern().as_usize()) };
+            // Atoms that start at `search_end` or after it are left for the
+            // scan that resumes from that offset.
+            if self.search_end.is_some_and(|end| atom_start >= end) {
+                continue;
+            }
+
+            let atom = unsafe { atoms.get_unchecked(atom_index) };
 
             // Subtract the backtrack value from the offset where the atom
             // matched. If the result is negative the atom can't be inside
             // the scanned data and therefore is not a possible match.
             let atom_pos = if let Some(atom_pos) =
-                ac_match.start().checked_sub(atom.backtrack())
+                atom_start.checked_sub(atom.backtrack())
             {
                 atom_pos
             } else {
@@ -445,7 +940,11 @@ impl ScanContext<'_> {
                         sub_pattern_id,
                         sub_pattern,
                         *pattern_id,
-                        Match { range: match_range, xor_key: None },
+                        Match {
+                            range: match_range,
+                            sub_pattern_id,
+                            xor_key: None,
+                        },
                     );
                 }
 
@@ -463,6 +962,7 @@ impl ScanContext<'_> {
                             .unwrap(),
                         scanned_data,
                         atom_pos,
+                        sub_pattern_id,
                         *flags,
                     ) {
                         self.handle_sub_pattern_match(
@@ -481,6 +981,7 @@ impl ScanContext<'_> {
                         scanned_data,
                         atom_pos,
                         atom,
+                        sub_pattern_id,
                         *flags,
                         |match_| {
                             self.handle_sub_pattern_match(
@@ -502,6 +1003,7 @@ impl ScanContext<'_> {
                         scanned_data,
                         atom_pos,
                         atom,
+                        sub_pattern_id,
                         *flags,
                     ) {
                         self.handle_sub_pattern_match(
@@ -513,8 +1015,8 @@ impl ScanContext<'_> {
                     }
                 }
 
-                SubPattern::Base64 { pattern, padding }
-                | SubPattern::Base64Wide { pattern, padding } => {
+                SubPattern::Base64 { pattern, padding, .. }
+                | SubPattern::Base64Wide { pattern, padding, .. } => {
                     if let Some(match_) = verify_base64_match(
                         self.compiled_rules
                             .lit_pool()
@@ -523,6 +1025,7 @@ impl ScanContext<'_> {
                         scanned_data,
                         (*padding).into(),
                         atom_pos,
+                        sub_pattern_id,
                         None,
                         matches!(sub_pattern, SubPattern::Base64Wide { .. }),
                     ) {
@@ -535,11 +1038,17 @@ impl ScanContext<'_> {
                     }
                 }
 
-                SubPattern::CustomBase64 { pattern, alphabet, padding }
+                SubPattern::CustomBase64 {
+                    pattern,
+                    alphabet,
+                    padding,
+                    ..
+                }
                 | SubPattern::CustomBase64Wide {
                     pattern,
                     alphabet,
                     padding,
+                    ..
                 } => {
                     let alphabet = self
                         .compiled_rules
@@ -564,6 +1073,7 @@ impl ScanContext<'_> {
                         scanned_data,
                         (*padding).into(),
                         atom_pos,
+                        sub_pattern_id,
                         alphabet,
                         matches!(
                             sub_pattern,
@@ -630,6 +1140,7 @@ impl ScanContext<'_> {
                             .unwrap(),
                         self.scanned_data(),
                         *offset,
+                        *sub_pattern_id,
                         *flags,
                     ) {
                         self.handle_sub_pattern_match(
@@ -781,6 +1292,7 @@ impl ScanContext<'_> {
                             pattern_id,
                             Match {
                                 range: match_range.start..tail_match_range.end,
+                                sub_pattern_id: id,
                                 xor_key: None,
                             },
                             flags.contains(SubPatternFlags::GreedyRegexp),
@@ -867,6 +1379,7 @@ fn verify_literal_match(
     pattern: &[u8],
     scanned_data: &[u8],
     atom_pos: usize,
+    sub_pattern_id: SubPatternId,
     flags: SubPatternFlagSet,
 ) -> Option<Match> {
     // Offset where the match should end (exclusive).
@@ -898,6 +1411,7 @@ fn verify_literal_match(
         Some(Match {
             // The end of the range is exclusive.
             range: atom_pos..match_end,
+            sub_pattern_id,
             xor_key: None,
         })
     } else {
@@ -963,6 +1477,7 @@ fn verify_regexp_match(
     scanned_data: &[u8],
     atom_pos: usize,
     atom: &SubPatternAtom,
+    sub_pattern_id: SubPatternId,
     flags: SubPatternFlagSet,
     mut f: impl FnMut(Match),
 ) {
@@ -1019,7 +1534,7 @@ fn verify_regexp_match(
                     let range =
                         atom_pos - bck_match_len..atom_pos + fwd_match_len;
                     if verify_full_word(scanned_data, &range, flags, None) {
-                        f(Match { range, xor_key: None });
+                        f(Match { range, sub_pattern_id, xor_key: None });
                     }
                     Action::Continue
                 },
@@ -1034,7 +1549,7 @@ fn verify_regexp_match(
                     let range =
                         atom_pos - bck_match_len..atom_pos + fwd_match_len;
                     if verify_full_word(scanned_data, &range, flags, None) {
-                        f(Match { range, xor_key: None });
+                        f(Match { range, sub_pattern_id, xor_key: None });
                     }
                     Action::Continue
                 },
@@ -1043,7 +1558,7 @@ fn verify_regexp_match(
     } else {
         let range = atom_pos..atom_pos + fwd_match_len;
         if verify_full_word(scanned_data, &range, flags, None) {
-            f(Match { range, xor_key: None });
+            f(Match { range, sub_pattern_id, xor_key: None });
         }
     }
 }
@@ -1057,6 +1572,7 @@ fn verify_xor_match(
     scanned_data: &[u8],
     atom_pos: usize,
     atom: &SubPatternAtom,
+    sub_pattern_id: SubPatternId,
     flags: SubPatternFlagSet,
 ) -> Option<Match> {
     // Offset where the match should end (exclusive).
@@ -1090,7 +1606,7 @@ fn verify_xor_match(
     }
 
     if &scanned_data[match_range.clone()] == pattern.as_bytes() {
-        Some(Match { range: match_range, xor_key: Some(key) })
+        Some(Match { range: match_range, sub_pattern_id, xor_key: Some(key) })
     } else {
         None
     }
@@ -1105,6 +1621,7 @@ fn verify_base64_match(
     scanned_data: &[u8],
     padding: usize,
     atom_pos: usize,
+    sub_pattern_id: SubPatternId,
     alphabet: Option<base64::alphabet::Alphabet>,
     wide: bool,
 ) -> Option<Match> {
@@ -1237,6 +1754,7 @@ fn verify_base64_match(
         {
             Some(Match {
                 range: atom_pos..atom_pos + match_len,
+                sub_pattern_id,
                 xor_key: None,
             })
         } else {
@@ -1295,6 +1813,35 @@ impl RuntimeObject {
     }
 }
 
+/// Iterator over the occurrences of atoms in the scanned data.
+///
+/// Yields `(atom_index, offset)` pairs, where `atom_index` is the index of the
+/// atom in [`Rules::atoms`] and `offset` is the offset where the atom was
+/// found. Overlapping occurrences are reported, regardless of the search
+/// strategy.
+enum AtomMatches<'a> {
+    AhoCorasick(aho_corasick::FindOverlappingIter<'a, 'a>),
+    Memmem { finder: &'a memmem::Finder<'static>, data: &'a [u8], pos: usize },
+}
+
+impl Iterator for AtomMatches<'_> {
+    type Item = (usize, usize);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::AhoCorasick(iter) => {
+                iter.next().map(|m| (m.pattern().as_usize(), m.start()))
+            }
+            Self::Memmem { finder, data, pos } => {
+                let start = *pos + finder.find(data.get(*pos..)?)?;
+                *pos = start + 1;
+                Some((0, start))
+            }
+        }
+    }
+}
+
 /// A runtime object handle is an opaque integer value that identifies a
 /// runtime object.
 #[derive(Copy, Clone, Hash, Eq, PartialEq, Default)]
@@ -1315,3 +1862,104 @@ impl From<i64> for RuntimeObjectHandle {
         Self(value)
     }
 }
+
+/// Invokes the callback passed to [`crate::Scanner::scan_with_progress`].
+pub(crate) struct Progress<'r> {
+    callback: Box<dyn FnMut(usize, Option<usize>) + 'r>,
+    interval: usize,
+    /// The callback is invoked when the number of bytes scanned reaches
+    /// this value.
+    next: usize,
+    /// Last value passed to the callback.
+    last: Option<usize>,
+    /// Set to true if the callback panics. In that case it is not invoked
+    /// anymore.
+    panicked: bool,
+}
+
+impl<'r> Progress<'r> {
+    pub fn new(
+        interval: usize,
+        callback: Box<dyn FnMut(usize, Option<usize>) + 'r>,
+    ) -> Self {
+        let interval = interval.max(1);
+        Self {
+            callback,
+            interval,
+            next: interval,
+            last: None,
+            panicked: false,
+        }
+    }
+
+    /// Reports that the search has reached offset `bytes_scanned`. The
+    /// callback is invoked only if the offset crossed the next interval
+    /// boundary.
+    #[inline]
+    pub fn update(&mut self, bytes_scanned: usize, total: Option<usize>) {
+        if bytes_scanned >= self.next {
+            self.next = (bytes_scanned / self.interval + 1) * self.interval;
+            self.invoke(bytes_scanned, total);
+        }
+    }
+
+    /// Reports that the whole data was scanned.
+    pub fn finish(&mut self, total: usize) {
+        if self.last != Some(total) {
+            self.invoke(total, Some(total));
+        }
+    }
+
+    fn invoke(&mut self, bytes_scanned: usize, total: Option<usize>) {
+        if self.panicked {
+            return;
+        }
+        self.last = Some(bytes_scanned);
+        // A panic in the callback must not leave the scanner in an
+        // inconsistent state, so it is caught here and the callback is
+        // disabled for the rest of the scan.
+        self.panicked = catch_unwind(AssertUnwindSafe(|| {
+            (self.callback)(bytes_scanned, total)
+        }))
+        .is_err();
+    }
+}
+
+/// Invokes the callback passed to [`crate::Scanner::on_rule_decided`].
+#[cfg(feature = "async")]
+pub(crate) struct DecidedRules<'r> {
+    callback: Box<dyn FnMut(&models::Rule<'_, 'r>) + 'r>,
+    /// Last global rule in each namespace that has global rules. The rules
+    /// in those namespaces can be discarded until this rule matches.
+    last_global_rule: FxHashMap<NamespaceId, RuleId>,
+    /// Bit vector where the RuleId-th bit is set for the rules that were
+    /// already reported during the current scan.
+    reported: BitVec,
+}
+
+#[cfg(feature = "async")]
+impl<'r> DecidedRules<'r> {
+    pub fn new(
+        rules: &Rules,
+        callback: Box<dyn FnMut(&models::Rule<'_, 'r>) + 'r>,
+    ) -> Self {
+        let mut last_global_rule = FxHashMap::default();
+
+        for (rule_id, rule) in rules.rules().iter().enumerate() {
+            if rule.is_global {
+                last_global_rule.insert(rule.namespace_id, rule_id.into());
+            }
+        }
+
+        Self {
+            callback,
+            last_global_rule,
+            reported: BitVec::repeat(false, rules.num_rules()),
+        }
+    }
+
+    /// Forgets the rules reported during the previous scan.
+    pub fn reset(&mut self) {
+        self.reported.fill(false);
+    }
+}
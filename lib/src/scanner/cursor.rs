@@ -0,0 +1,152 @@
+/*! Scans that can be interrupted and resumed later.
+
+Searching for patterns in very large inputs, like disk images, can take a
+long time. [`Scanner::scan_partial`] searches for patterns only up to a
+given offset and returns a [`ScanCursor`] with the state of the search at
+that point. The cursor can be serialized, stored, and passed later to
+[`Scanner::scan_partial`] again for advancing the search a bit more, or to
+[`Scanner::scan_resume`] for completing the search and evaluating the rule
+conditions.
+*/
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::compiler::{PatternId, SubPatternId};
+use crate::scanner::matches::{Match, UnconfirmedMatch};
+use crate::scanner::ScanContext;
+
+#[cfg(doc)]
+use crate::Scanner;
+
+/// A match, represented by its range, sub-pattern and XOR key.
+type SavedMatch = (Range<usize>, SubPatternId, Option<u8>);
+
+/// An unconfirmed match, represented by its range and chain length.
+type SavedUnconfirmedMatch = (Range<usize>, usize);
+
+/// State of a scan that was interrupted at some offset.
+///
+/// See [`Scanner::scan_partial`] and [`Scanner::scan_resume`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanCursor {
+    /// Hash of the rules used in the scan.
+    rules_hash: [u8; 32],
+    /// Size of the scanned data.
+    data_len: usize,
+    /// All the atoms that start before this offset were already processed.
+    offset: usize,
+    /// Matches found so far, sorted by pattern.
+    matches: Vec<(PatternId, Vec<SavedMatch>)>,
+    /// Matches for chained sub-patterns that are not confirmed yet, because
+    /// the rest of the chain may appear after `offset`.
+    unconfirmed_matches: Vec<(SubPatternId, Vec<SavedUnconfirmedMatch>)>,
+    /// Patterns that don't need more matches.
+    limit_reached: Vec<PatternId>,
+}
+
+impl ScanCursor {
+    /// Offset where the search for patterns will be resumed.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns true if the search for patterns reached the end of the data.
+    pub fn is_complete(&self) -> bool {
+        self.offset >= self.data_len
+    }
+
+    /// Returns true if this cursor can be used for resuming a scan of
+    /// `data_len` bytes with rules that have the given hash.
+    pub(crate) fn is_valid_for(
+        &self,
+        rules_hash: &[u8; 32],
+        data_len: usize,
+    ) -> bool {
+        self.rules_hash == *rules_hash
+            && self.data_len == data_len
+            && self.offset <= data_len
+    }
+
+    /// Captures the state of the search for patterns in `ctx`, which stopped
+    /// at `offset`.
+    pub(crate) fn from_context(ctx: &ScanContext, offset: usize) -> Self {
+        let mut matches = ctx
+            .pattern_matches
+            .iter()
+            .filter(|(_, list)| !list.is_empty())
+            .map(|(pattern_id, list)| {
+                (
+                    pattern_id,
+                    list.iter()
+                        .map(|m| {
+                            (m.range.clone(), m.sub_pattern_id, m.xor_key)
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        matches.sort_by_key(|(pattern_id, _)| usize::from(*pattern_id));
+
+        let mut unconfirmed_matches = ctx
+            .unconfirmed_matches
+            .iter()
+            .map(|(sub_pattern_id, list)| {
+                (
+                    *sub_pattern_id,
+                    list.iter()
+                        .map(|m| (m.range.clone(), m.chain_length))
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        unconfirmed_matches.sort_by_key(|(sub_pattern_id, _)| *sub_pattern_id);
+
+        let mut limit_reached =
+            ctx.limit_reached.iter().copied().collect::<Vec<_>>();
+
+        limit_reached.sort_by_key(|pattern_id| usize::from(*pattern_id));
+
+        Self {
+            rules_hash: *ctx.compiled_rules.content_hash(),
+            data_len: ctx.scanned_data_len,
+            offset: offset.min(ctx.scanned_data_len),
+            matches,
+            unconfirmed_matches,
+            limit_reached,
+        }
+    }
+
+    /// Restores the state of the search for patterns into `ctx`, so that the
+    /// search continues where it stopped.
+    pub(crate) fn restore(self, ctx: &mut ScanContext) {
+        for (pattern_id, matches) in self.matches {
+            for (range, sub_pattern_id, xor_key) in matches {
+                ctx.track_pattern_match(
+                    pattern_id,
+                    Match { range, sub_pattern_id, xor_key },
+                    false,
+                );
+            }
+        }
+
+        for (sub_pattern_id, matches) in self.unconfirmed_matches {
+            ctx.unconfirmed_matches.insert(
+                sub_pattern_id,
+                matches
+                    .into_iter()
+                    .map(|(range, chain_length)| UnconfirmedMatch {
+                        range,
+                        chain_length,
+                    })
+                    .collect(),
+            );
+        }
+
+        ctx.limit_reached.extend(self.limit_reached);
+        ctx.search_start = self.offset;
+    }
+}
@@ -0,0 +1,166 @@
+/*! Scan results in the shape of Suricata's EVE JSON records.
+
+Many log pipelines already know how to ingest the EVE JSON records produced
+by Suricata. [`EveJson`] renders the rules that matched during a scan as
+records with the same general shape, one record per matching rule, so that
+they can be sent to those pipelines without further conversion.
+
+This is independent of the JSON produced by the command-line tool, which
+has its own format.
+*/
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::json;
+
+use crate::models::Rule;
+use crate::scanner::ScanResults;
+
+/// Value of the `event_type` field in the records produced by [`EveJson`].
+const EVENT_TYPE: &str = "yara";
+
+/// Produces EVE JSON records for the rules that matched during a scan.
+///
+/// Each record looks like this:
+///
+/// ```json
+/// {
+///   "timestamp": "2024-05-14T09:21:43.512034+0000",
+///   "event_type": "yara",
+///   "yara": {
+///     "rule": "foo",
+///     "namespace": "default",
+///     "tags": ["bar", "baz"],
+///     "patterns": [
+///       { "identifier": "$a", "offsets": [0, 16] }
+///     ]
+///   }
+/// }
+/// ```
+///
+/// The timestamp is obtained from the system clock by default, but a
+/// different clock can be provided with [`EveJson::with_clock`].
+///
+/// # Example
+///
+/// ```rust
+/// # use yara_x::{EveJson, Scanner};
+/// let rules = yara_x::compile(r#"
+///     rule foo : bar { strings: $a = "foo" condition: $a }
+/// "#).unwrap();
+///
+/// let mut scanner = Scanner::new(&rules);
+/// let results = scanner.scan(b"foofoo").unwrap();
+///
+/// for record in EveJson::new().records(&results) {
+///     println!("{}", record);
+/// }
+/// ```
+pub struct EveJson {
+    clock: Box<dyn Fn() -> SystemTime + Send + Sync>,
+}
+
+impl Default for EveJson {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EveJson {
+    /// Creates a new [`EveJson`] that timestamps records with the system
+    /// clock.
+    pub fn new() -> Self {
+        Self::with_clock(SystemTime::now)
+    }
+
+    /// Creates a new [`EveJson`] that timestamps records with the time
+    /// returned by `clock`.
+    ///
+    /// `clock` is called once per record. This is mostly useful for
+    /// producing reproducible records in tests.
+    pub fn with_clock<F>(clock: F) -> Self
+    where
+        F: Fn() -> SystemTime + Send + Sync + 'static,
+    {
+        Self { clock: Box::new(clock) }
+    }
+
+    /// Returns one record for each rule that matched in `results`.
+    ///
+    /// Private rules are not included, as they are not included in
+    /// [`ScanResults::matching_rules`] either.
+    pub fn records(&self, results: &ScanResults) -> Vec<serde_json::Value> {
+        results.matching_rules().map(|rule| self.record(&rule)).collect()
+    }
+
+    /// Returns the record for a single matching rule.
+    pub fn record(&self, rule: &Rule) -> serde_json::Value {
+        let tags: Vec<&str> = rule.tags().map(|t| t.identifier()).collect();
+
+        let patterns: Vec<serde_json::Value> = rule
+            .patterns()
+            .filter_map(|p| {
+                let offsets: Vec<usize> =
+                    p.matches().map(|m| m.range().start).collect();
+                if offsets.is_empty() {
+                    return None;
+                }
+                Some(json!({
+                    "identifier": p.identifier(),
+                    "offsets": offsets,
+                }))
+            })
+            .collect();
+
+        json!({
+            "timestamp": timestamp((self.clock)()),
+            "event_type": EVENT_TYPE,
+            "yara": {
+                "rule": rule.identifier(),
+                "namespace": rule.namespace(),
+                "tags": tags,
+                "patterns": patterns,
+            }
+        })
+    }
+}
+
+/// Formats `time` as Suricata does, like in `2024-05-14T09:21:43.512034+0000`.
+///
+/// Times are always expressed in UTC. Times before the Unix epoch are
+/// clamped to the epoch.
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let secs_of_day = secs % 86400;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}+0000",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        (secs_of_day % 3600) / 60,
+        secs_of_day % 60,
+        since_epoch.subsec_micros(),
+    )
+}
+
+/// Converts a number of days since 1970-01-01 into a (year, month, day)
+/// tuple in the proleptic Gregorian calendar.
+///
+/// This is the algorithm described in
+/// <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
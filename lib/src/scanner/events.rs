@@ -0,0 +1,120 @@
+/*! Callbacks invoked at different points of a scan.
+
+See [`Scanner::on_scan_start`], [`Scanner::on_rule_match`],
+[`Scanner::on_rule_not_match`] and [`Scanner::on_scan_complete`].
+*/
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::models::Rule;
+use crate::scanner::{ScanError, ScanResults};
+
+#[cfg(doc)]
+use crate::Scanner;
+
+/// Type of the callbacks registered with [`Scanner::on_scan_start`].
+type ScanStartFn<'r> = dyn FnMut(usize) + 'r;
+
+/// Type of the callbacks registered with [`Scanner::on_rule_match`] and
+/// [`Scanner::on_rule_not_match`].
+type RuleFn<'r> = dyn for<'a> FnMut(&Rule<'a, 'r>) + 'r;
+
+/// Type of the callbacks registered with [`Scanner::on_scan_complete`].
+type ScanCompleteFn<'r> =
+    dyn for<'a> FnMut(Result<&ScanResults<'a, 'r>, &ScanError>) + 'r;
+
+/// A registered callback.
+///
+/// A panic in the callback must not leave the scanner in an inconsistent
+/// state, so it is caught and the callback is not invoked anymore during
+/// the current scan.
+struct Listener<F: ?Sized> {
+    callback: Box<F>,
+    panicked: bool,
+}
+
+impl<F: ?Sized> Listener<F> {
+    fn new(callback: Box<F>) -> Self {
+        Self { callback, panicked: false }
+    }
+
+    fn invoke(&mut self, f: impl FnOnce(&mut F)) {
+        if self.panicked {
+            return;
+        }
+        self.panicked =
+            catch_unwind(AssertUnwindSafe(|| f(&mut self.callback))).is_err();
+    }
+}
+
+/// Callbacks registered for each of the scan events.
+#[derive(Default)]
+pub(crate) struct ScanEvents<'r> {
+    scan_start: Vec<Listener<ScanStartFn<'r>>>,
+    rule_match: Vec<Listener<RuleFn<'r>>>,
+    rule_not_match: Vec<Listener<RuleFn<'r>>>,
+    scan_complete: Vec<Listener<ScanCompleteFn<'r>>>,
+}
+
+impl<'r> ScanEvents<'r> {
+    pub fn add_scan_start(&mut self, callback: Box<ScanStartFn<'r>>) {
+        self.scan_start.push(Listener::new(callback));
+    }
+
+    pub fn add_rule_match(&mut self, callback: Box<RuleFn<'r>>) {
+        self.rule_match.push(Listener::new(callback));
+    }
+
+    pub fn add_rule_not_match(&mut self, callback: Box<RuleFn<'r>>) {
+        self.rule_not_match.push(Listener::new(callback));
+    }
+
+    pub fn add_scan_complete(&mut self, callback: Box<ScanCompleteFn<'r>>) {
+        self.scan_complete.push(Listener::new(callback));
+    }
+
+    /// Invokes the callbacks for the start of a scan of `data_len` bytes.
+    ///
+    /// Callbacks that panicked during the previous scan are enabled again.
+    pub fn scan_start(&mut self, data_len: usize) {
+        self.rule_match.iter_mut().for_each(|l| l.panicked = false);
+        self.rule_not_match.iter_mut().for_each(|l| l.panicked = false);
+        self.scan_complete.iter_mut().for_each(|l| l.panicked = false);
+
+        for listener in self.scan_start.iter_mut() {
+            listener.panicked = false;
+            listener.invoke(|callback| callback(data_len));
+        }
+    }
+
+    /// Invokes the callbacks for the end of a scan.
+    ///
+    /// When the scan succeeded, the callbacks registered with
+    /// [`Scanner::on_rule_match`] and [`Scanner::on_rule_not_match`] are
+    /// invoked first, for each matching and non-matching rule, respectively.
+    pub fn scan_complete(
+        &mut self,
+        result: Result<&ScanResults<'_, 'r>, &ScanError>,
+    ) {
+        if let Ok(results) = result {
+            if !self.rule_match.is_empty() {
+                for rule in results.matching_rules() {
+                    for listener in self.rule_match.iter_mut() {
+                        listener.invoke(|callback| callback(&rule));
+                    }
+                }
+            }
+            if !self.rule_not_match.is_empty() {
+                for rule in results.non_matching_rules() {
+                    for listener in self.rule_not_match.iter_mut() {
+                        listener.invoke(|callback| callback(&rule));
+                    }
+                }
+            }
+        }
+
+        for listener in self.scan_complete.iter_mut() {
+            listener.invoke(|callback| callback(result));
+        }
+    }
+}
@@ -4,13 +4,16 @@ use std::ops::{Range, RangeInclusive};
 use core::slice::Iter;
 use rustc_hash::FxHashMap;
 
-use crate::compiler::PatternId;
+use crate::compiler::{PatternId, SubPatternId};
 
 /// Represents the match of a pattern.
 #[derive(Debug, Clone)]
 pub(crate) struct Match {
     /// Range within the scanned data where the match was found.
     pub range: Range<usize>,
+    /// Sub-pattern that produced the match. For chained sub-patterns this
+    /// is the head of the chain.
+    pub sub_pattern_id: SubPatternId,
     /// For patterns that have the `xor` modifier this is always `Some(k)`
     /// where `k` is the XOR key (it may be 0). For any other type of
     /// pattern this is `None`.
@@ -61,7 +64,7 @@ impl MatchList {
                 // longer and `replace_if_longer` is true.
                 if replace_if_longer && existing_match.range.end < m.range.end
                 {
-                    existing_match.range.end = m.range.end;
+                    *existing_match = m;
                 }
                 return;
             }
@@ -87,38 +90,41 @@ impl MatchList {
     }
 
     /// Returns the number of matches that start within the given range.
+    ///
+    /// This operation is O(log(N)), as it takes advantage of the fact that
+    /// matches are sorted by starting offset.
     pub fn matches_in_range(&self, range: RangeInclusive<isize>) -> i64 {
+        let (lower, upper) = self.range_bounds(range);
+        (upper - lower).try_into().unwrap()
+    }
+
+    /// Returns true if some match starts within the given range.
+    ///
+    /// This is equivalent to `matches_in_range(range) > 0`, and it is also
+    /// O(log(N)).
+    pub fn is_match_in_range(&self, range: RangeInclusive<isize>) -> bool {
+        let (lower, upper) = self.range_bounds(range);
+        lower < upper
+    }
+
+    /// Returns the indexes of the first match that starts within the given
+    /// range, and the first match that starts after the range. If no match
+    /// starts within the range both indexes are equal.
+    fn range_bounds(&self, range: RangeInclusive<isize>) -> (usize, usize) {
         // If the end of the range is negative there can't be any matches in
         // that range.
-        if range.end().is_negative() {
-            return 0;
+        if range.end().is_negative() || range.start() > range.end() {
+            return (0, 0);
         }
 
         let start: usize = (*range.start()).try_into().unwrap_or(0);
         let end: usize = (*range.end()).try_into().unwrap();
 
-        // Find the index of the match that starts at `start`, or the index
-        // where it should be. Any match starting at some offset >= `start`,
-        // must be located at `index` or higher in the matches array.
-        // Notice that the fact that two matches can't have the same starting
-        // offset is very helpful in this case. Because of this, we don't need
-        // to take into account matches at `index-1`, `index-2`, etc. Otherwise,
-        // we would like to take into account matches at those indexes because
-        // the `search` function does not guarantee that it returns the *first*
-        // match with a given offset, but *any* match with that offset.
-        match self.search(start) {
-            Ok(index) | Err(index) => {
-                let mut count = 0;
-                for m in &self.matches.as_slice()[index..] {
-                    if (start..=end).contains(&m.range.start) {
-                        count += 1;
-                    } else {
-                        break;
-                    }
-                }
-                count
-            }
-        }
+        let lower = self.matches.partition_point(|m| m.range.start < start);
+        let upper = lower
+            + self.matches[lower..].partition_point(|m| m.range.start <= end);
+
+        (lower, upper)
     }
 
     #[inline]
@@ -136,6 +142,11 @@ impl MatchList {
         self.matches.len()
     }
 
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.matches.is_empty()
+    }
+
     #[inline]
     pub fn iter(&self) -> Iter<'_, Match> {
         self.matches.iter()
@@ -218,6 +229,14 @@ impl PatternMatches {
         self.matches.is_empty()
     }
 
+    /// Returns an iterator over the patterns and their lists of matches.
+    ///
+    /// The lists of some patterns may be empty, as lists are not removed
+    /// from the map when cleared.
+    pub fn iter(&self) -> impl Iterator<Item = (PatternId, &MatchList)> {
+        self.matches.iter().map(|(pattern_id, list)| (*pattern_id, list))
+    }
+
     /// Clears the matches for all patterns.
     ///
     /// To optimize performance, the memory allocated for storing matches
@@ -284,22 +303,157 @@ impl PatternMatches {
 
 #[cfg(test)]
 mod test {
+    use crate::compiler::SubPatternId;
     use crate::scanner::matches::{Match, MatchList};
-    use std::ops::Range;
+    use std::ops::{Range, RangeInclusive};
 
     #[test]
     fn match_list() {
         let mut ml = MatchList::with_capacity(5);
+        let sub_pattern_id = SubPatternId::default();
 
-        ml.add(Match { range: (2..10), xor_key: None }, false);
-        ml.add(Match { range: (1..10), xor_key: None }, false);
-        ml.add(Match { range: (4..10), xor_key: None }, false);
-        ml.add(Match { range: (3..10), xor_key: None }, false);
-        ml.add(Match { range: (5..10), xor_key: None }, false);
+        ml.add(Match { range: (2..10), sub_pattern_id, xor_key: None }, false);
+        ml.add(Match { range: (1..10), sub_pattern_id, xor_key: None }, false);
+        ml.add(Match { range: (4..10), sub_pattern_id, xor_key: None }, false);
+        ml.add(Match { range: (3..10), sub_pattern_id, xor_key: None }, false);
+        ml.add(Match { range: (5..10), sub_pattern_id, xor_key: None }, false);
 
         assert_eq!(
             ml.iter().map(|m| m.range.clone()).collect::<Vec<Range<usize>>>(),
             vec![(1..10), (2..10), (3..10), (4..10), (5..10)]
         )
     }
+
+    #[test]
+    fn match_list_queries() {
+        let mut ml = MatchList::with_capacity(0);
+        let sub_pattern_id = SubPatternId::default();
+
+        // Matches are added out of order, and some of them start at the
+        // same offset than a previous one.
+        for (range, replace_if_longer) in [
+            (20..22, false),
+            (5..7, false),
+            (10..11, false),
+            (5..9, true),
+            (10..15, false),
+            (3..4, false),
+        ] {
+            ml.add(
+                Match { range, sub_pattern_id, xor_key: None },
+                replace_if_longer,
+            );
+        }
+
+        assert_eq!(
+            ml.iter().map(|m| m.range.clone()).collect::<Vec<Range<usize>>>(),
+            vec![(3..4), (5..9), (10..11), (20..22)]
+        );
+
+        assert_eq!(ml.len(), 4);
+        assert_eq!(ml.get(1).map(|m| m.range.clone()), Some(5..9));
+        assert!(ml.get(4).is_none());
+
+        assert_eq!(ml.search(10), Ok(2));
+        assert_eq!(ml.search(0), Err(0));
+        assert_eq!(ml.search(6), Err(2));
+        assert_eq!(ml.search(30), Err(4));
+
+        for (range, expected) in [
+            (0..=100, 4),
+            (3..=3, 1),
+            (4..=4, 0),
+            (4..=10, 2),
+            (5..=20, 3),
+            (21..=100, 0),
+            (-10..=3, 1),
+            (-10..=-1, 0),
+            (RangeInclusive::new(10, 5), 0),
+        ] {
+            assert_eq!(ml.matches_in_range(range.clone()), expected);
+            assert_eq!(ml.is_match_in_range(range), expected > 0);
+        }
+    }
+
+    /// A simple xorshift generator, good enough for producing random match
+    /// sets in a reproducible way.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self, max: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % max as u64) as usize
+        }
+    }
+
+    #[test]
+    fn match_list_queries_oracle() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let sub_pattern_id = SubPatternId::default();
+
+        for _ in 0..200 {
+            let mut ml = MatchList::with_capacity(0);
+            let mut oracle: Vec<(usize, usize)> = Vec::new();
+
+            // Add matches in random order, with offsets that can repeat.
+            for _ in 0..rng.next(64) {
+                let start = rng.next(256);
+                let len = rng.next(16) + 1;
+                let replace_if_longer = rng.next(2) == 1;
+
+                ml.add(
+                    Match {
+                        range: start..start + len,
+                        sub_pattern_id,
+                        xor_key: None,
+                    },
+                    replace_if_longer,
+                );
+
+                match oracle.iter_mut().find(|(s, _)| *s == start) {
+                    Some((_, end)) => {
+                        if replace_if_longer && *end < start + len {
+                            *end = start + len;
+                        }
+                    }
+                    None => oracle.push((start, start + len)),
+                }
+            }
+
+            oracle.sort();
+
+            // `[i]`: matches are sorted by offset.
+            assert_eq!(ml.len(), oracle.len());
+            for (i, (start, end)) in oracle.iter().enumerate() {
+                assert_eq!(ml.get(i).unwrap().range, *start..*end);
+            }
+            assert!(ml.get(oracle.len()).is_none());
+
+            // `at`: matches starting at a given offset.
+            for offset in 0..280 {
+                assert_eq!(
+                    ml.search(offset).is_ok(),
+                    oracle.iter().any(|(s, _)| *s == offset)
+                );
+            }
+
+            // `in`: matches starting within a given range.
+            for _ in 0..100 {
+                let lower = rng.next(300) as isize - 20;
+                let upper = rng.next(300) as isize - 20;
+                let expected = oracle
+                    .iter()
+                    .filter(|(s, _)| (lower..=upper).contains(&(*s as isize)))
+                    .count();
+
+                assert_eq!(
+                    ml.matches_in_range(lower..=upper),
+                    expected as i64
+                );
+                assert_eq!(ml.is_match_in_range(lower..=upper), expected > 0);
+            }
+        }
+    }
 }
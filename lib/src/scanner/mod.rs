@@ -3,47 +3,82 @@
 The scanner takes the rules produces by the compiler and scans data with them.
 */
 
-use std::cell::RefCell;
-use std::collections::{hash_map, HashMap};
-use std::io::Read;
+use std::borrow::Cow;
+use std::cell::{Cell, OnceCell, RefCell};
+use std::collections::{hash_map, BTreeMap, HashMap};
+use std::io::{self, Read, Seek, SeekFrom};
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::ptr::{null, NonNull};
 use std::rc::Rc;
 use std::slice::Iter;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+#[cfg(feature = "async")]
+use std::sync::Arc;
 use std::sync::Once;
 use std::time::Duration;
-use std::{cmp, fs, thread};
+use std::{cmp, fs, mem, thread};
 
+use aho_corasick::{AhoCorasick, AhoCorasickKind};
 use bitvec::prelude::*;
 use fmmap::{MmapFile, MmapFileExt};
 use indexmap::IndexMap;
 use protobuf::{CodedInputStream, MessageDyn};
 use rustc_hash::{FxHashMap, FxHashSet};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 use wasmtime::{
     AsContext, AsContextMut, Global, GlobalType, MemoryType, Mutability,
-    Store, TypedFunc, Val, ValType,
+    Store, TypedFunc, UpdateDeadline, Val, ValType,
 };
 
-use crate::compiler::{RuleId, Rules};
-use crate::models::Rule;
-use crate::modules::{Module, BUILTIN_MODULES};
+use crate::compiler::{
+    Compiler, PatternCondition, RuleId, Rules, SearchStrategy,
+};
+use crate::errors::CompileError;
+use crate::models::{Pattern, Rule};
+use crate::modules::{MainFn, Module, BUILTIN_MODULES};
 use crate::scanner::matches::PatternMatches;
-use crate::types::{Struct, TypeValue};
+use crate::types::{Struct, TypeValue, Value};
 use crate::variables::VariableError;
-use crate::wasm::{ENGINE, MATCHING_RULES_BITMAP_BASE};
-use crate::{modules, wasm, Variable};
+use crate::wasm::{ENGINE, MATCHING_RULES_BITMAP_BASE, MINIMAL_ENGINE};
+use crate::{models, modules, wasm, Variable};
 
+#[cfg(feature = "async")]
+use crate::scanner::context::DecidedRules;
 pub(crate) use crate::scanner::context::RuntimeObject;
 pub(crate) use crate::scanner::context::RuntimeObjectHandle;
 pub(crate) use crate::scanner::context::ScanContext;
+use crate::scanner::context::{Progress, WasmInstance};
 pub(crate) use crate::scanner::matches::Match;
 
+pub use crate::scanner::cache::{
+    LruScanCache, OwnedPattern, OwnedRule, OwnedScanResults, ScanCache,
+    ScanCacheKey,
+};
+pub use crate::scanner::classic::ClassicText;
+pub use crate::scanner::cursor::ScanCursor;
+pub use crate::scanner::eve::EveJson;
+use crate::scanner::events::ScanEvents;
+pub use crate::scanner::sink::{
+    ClassicTextSink, FileResult, JsonSink, NdjsonSink, OutputSink,
+};
+#[cfg(feature = "async")]
+pub use crate::scanner::stream::ScanStream;
+pub use crate::scanner::tail::TailOptions;
+
+mod cache;
+mod classic;
 mod context;
+mod cursor;
+mod eve;
+mod events;
 mod matches;
+mod sink;
+#[cfg(feature = "async")]
+mod stream;
+mod tail;
 
 #[cfg(test)]
 mod tests;
@@ -84,6 +119,39 @@ pub enum ScanError {
         /// Module name.
         module: String,
     },
+    /// Some global variable passed to the scan is not valid, either because
+    /// it was not defined or because it has the wrong type.
+    #[error(transparent)]
+    InvalidGlobal(#[from] VariableError),
+    /// The preprocessor set with [`Scanner::with_preprocessor`] was declared
+    /// as offset-preserving, but it changed the size of the data.
+    #[error("preprocessor changed the size of the data from {original_size} to {transformed_size} bytes")]
+    PreprocessorChangedSize {
+        /// Size of the data before being preprocessed.
+        original_size: usize,
+        /// Size of the data after being preprocessed.
+        transformed_size: usize,
+    },
+    /// The [`ScanCursor`] passed to [`Scanner::scan_partial`] or
+    /// [`Scanner::scan_resume`] was produced with different rules, or for
+    /// data of a different size.
+    #[error("scan cursor doesn't correspond to these rules and data")]
+    InvalidCursor,
+    /// The condition passed to [`Scanner::eval_condition`] could not be
+    /// compiled.
+    #[error(transparent)]
+    InvalidCondition(CompileError),
+    /// The WASM code trapped while evaluating the condition of some rule,
+    /// for instance due to an integer overflow.
+    #[error("error while evaluating rule `{namespace}:{rule}`: {message}")]
+    WasmTrap {
+        /// Namespace of the rule that was being evaluated.
+        namespace: String,
+        /// Identifier of the rule that was being evaluated.
+        rule: String,
+        /// Description of the trap.
+        message: String,
+    },
 }
 
 /// Global counter that gets incremented every 1 second by a dedicated thread.
@@ -147,8 +215,49 @@ pub struct Scanner<'r> {
     wasm_main_func: TypedFunc<(), i32>,
     filesize: Global,
     timeout: Option<Duration>,
+    /// Original values of global variables overridden during the current
+    /// scan. See [`Scanner::scan_with_externals`].
+    overridden_globals: Vec<(String, TypeValue)>,
+    /// True if the value of `filepath` was set with [`Scanner::set_global`].
+    /// Otherwise, `filepath` is undefined while scanning in-memory data.
+    filepath_set: bool,
+    /// Function that transforms the data before scanning it, together with
+    /// a flag that indicates whether it preserves offsets. See
+    /// [`Scanner::with_preprocessor`].
+    preprocessor: Option<(Box<Preprocessor<'r>>, bool)>,
+    /// Cursor of a partial scan that must be restored at the beginning of
+    /// the next scan. See [`Scanner::scan_resume`].
+    cursor: Option<ScanCursor>,
+    /// Callbacks registered with [`Scanner::on_scan_start`] and similar
+    /// functions.
+    events: ScanEvents<'r>,
+    /// If true, modules parse the data while patterns are searched. See
+    /// [`Scanner::prefetch_modules`].
+    prefetch_modules: bool,
+}
+
+/// Kind of Aho-Corasick automaton used for searching patterns.
+///
+/// See [`Scanner::automaton_kind`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum AutomatonKind {
+    /// Use the automaton built while compiling the rules. It is a DFA when
+    /// the rules have a small number of patterns, and an NFA otherwise.
+    #[default]
+    Auto,
+    /// A compact NFA. It uses little memory, but scanning is slower than
+    /// with a DFA, as it may need to follow multiple transitions per byte.
+    /// This is the best option when memory is scarce.
+    Nfa,
+    /// A DFA. Scanning is faster, as it follows exactly one transition per
+    /// byte, but it uses much more memory than the NFA, and takes longer to
+    /// build. This is the best option when latency matters.
+    Dfa,
 }
 
+/// Type of the functions accepted by [`Scanner::with_preprocessor`].
+type Preprocessor<'r> = dyn for<'a> Fn(&'a [u8]) -> Cow<'a, [u8]> + 'r;
+
 impl<'r> Scanner<'r> {
     const DEFAULT_SCAN_TIMEOUT: u64 = 315_360_000;
 
@@ -167,12 +276,19 @@ impl<'r> Scanner<'r> {
         // make sure that it doesn't move from its original memory address and
         // the pointer remains valid.
         let mut wasm_store = Box::pin(Store::new(
-            &crate::wasm::ENGINE,
+            rules.wasm_engine(),
             ScanContext {
                 wasm_store: NonNull::dangling(),
                 runtime_objects: IndexMap::new(),
                 compiled_rules: rules,
                 console_log: None,
+                progress: None,
+                #[cfg(feature = "async")]
+                decided_rules: None,
+                search_start: 0,
+                search_end: None,
+                eval_result: None,
+                extra_results: Vec::new(),
                 current_struct: None,
                 root_struct: rules.globals().make_root(),
                 scanned_data: null(),
@@ -180,14 +296,25 @@ impl<'r> Scanner<'r> {
                 private_matching_rules: Vec::new(),
                 non_private_matching_rules: Vec::new(),
                 matching_rules: IndexMap::new(),
+                global_rule_no_matches: Vec::new(),
                 main_memory: None,
+                current_rule: None,
+                merged_instances: Vec::new(),
+                current_module: None,
+                pattern_search_done: false,
                 module_outputs: FxHashMap::default(),
                 user_provided_module_outputs: FxHashMap::default(),
                 pattern_matches: PatternMatches::new(),
                 unconfirmed_matches: FxHashMap::default(),
                 deadline: 0,
+                deadline_exceeded: Cell::new(false),
+                abort: None,
+                ac_automaton: None,
                 limit_reached: FxHashSet::default(),
+                fast_mode: false,
+                deterministic: false,
                 regexp_cache: RefCell::new(FxHashMap::default()),
+                entrypoint: None,
                 #[cfg(feature = "rules-profiling")]
                 time_spent_in_pattern: FxHashMap::default(),
             },
@@ -208,6 +335,75 @@ impl<'r> Scanner<'r> {
         )
         .unwrap();
 
+        // Global variable that is set to `true` when a timeout occurs during
+        // the scanning phase.
+        let timeout_occurred = Global::new(
+            wasm_store.as_context_mut(),
+            GlobalType::new(ValType::I32, Mutability::Var),
+            Val::I32(0),
+        )
+        .unwrap();
+
+        // Instantiate the main module, and the modules that evaluate rules
+        // added with `Rules::merge`, if any. All of them share `filesize`
+        // and `timeout_occurred`.
+        let main_instance = Self::instantiate(
+            &mut wasm_store,
+            rules.wasm_mod(),
+            num_rules,
+            num_patterns,
+            filesize,
+            timeout_occurred,
+        );
+
+        let merged_instances = rules
+            .merged_modules()
+            .iter()
+            .map(|module| {
+                Self::instantiate(
+                    &mut wasm_store,
+                    module.wasm_mod(),
+                    module.num_rules() as u32,
+                    module.num_patterns() as u32,
+                    filesize,
+                    timeout_occurred,
+                )
+            })
+            .collect();
+
+        let ctx = wasm_store.data_mut();
+
+        ctx.main_memory = Some(main_instance.main_memory);
+        ctx.current_rule = Some(main_instance.current_rule);
+        ctx.merged_instances = merged_instances;
+
+        Self {
+            wasm_store,
+            wasm_main_func: main_instance.main_func,
+            filesize,
+            timeout: None,
+            overridden_globals: Vec::new(),
+            filepath_set: false,
+            preprocessor: None,
+            cursor: None,
+            events: ScanEvents::default(),
+            prefetch_modules: false,
+        }
+    }
+
+    /// Instantiates a WASM module that evaluates the conditions of
+    /// `num_rules` rules with `num_patterns` patterns.
+    ///
+    /// Each instance has its own main memory, but global variables shared by
+    /// all the instances, like `filesize`, are received as arguments.
+    fn instantiate(
+        wasm_store: &mut Store<ScanContext<'r>>,
+        module: &wasmtime::Module,
+        num_rules: u32,
+        num_patterns: u32,
+        filesize: Global,
+        timeout_occurred: Global,
+    ) -> WasmInstance {
         // Global variable that is set to `true` when the Aho-Corasick pattern
         // search phase has been executed.
         let pattern_search_done = Global::new(
@@ -217,12 +413,13 @@ impl<'r> Scanner<'r> {
         )
         .unwrap();
 
-        // Global variable that is set to `true` when a timeout occurs during
-        // the scanning phase.
-        let timeout_occurred = Global::new(
+        // Global variable that contains the ID of the rule whose condition
+        // is being evaluated. Used for reporting which rule was running when
+        // the WASM code traps.
+        let current_rule = Global::new(
             wasm_store.as_context_mut(),
             GlobalType::new(ValType::I32, Mutability::Var),
-            Val::I32(0),
+            Val::I32(-1),
         )
         .unwrap();
 
@@ -254,10 +451,19 @@ impl<'r> Scanner<'r> {
         )
         .unwrap();
 
-        // Instantiate the module. This takes the wasm code provided by the
-        // `wasm_mod` function and links its imported functions with the
+        // Instantiate the module, linking its imported functions with the
         // implementations that YARA provides.
-        let wasm_instance = wasm::new_linker()
+        let mut linker = wasm::new_linker(wasm_store.engine());
+
+        // Functions imported from modules that are not available in this
+        // build are replaced with stubs.
+        wasm::define_missing_imports(
+            &mut linker,
+            wasm_store.as_context_mut(),
+            module,
+        );
+
+        let wasm_instance = linker
             .define(wasm_store.as_context(), "yara_x", "filesize", filesize)
             .unwrap()
             .define(
@@ -274,6 +480,13 @@ impl<'r> Scanner<'r> {
                 timeout_occurred,
             )
             .unwrap()
+            .define(
+                wasm_store.as_context(),
+                "yara_x",
+                "current_rule",
+                current_rule,
+            )
+            .unwrap()
             .define(
                 wasm_store.as_context(),
                 "yara_x",
@@ -288,17 +501,15 @@ impl<'r> Scanner<'r> {
                 main_memory,
             )
             .unwrap()
-            .instantiate(wasm_store.as_context_mut(), rules.wasm_mod())
+            .instantiate(wasm_store.as_context_mut(), module)
             .unwrap();
 
         // Obtain a reference to the "main" function exported by the module.
-        let wasm_main_func = wasm_instance
+        let main_func = wasm_instance
             .get_typed_func::<(), i32>(wasm_store.as_context_mut(), "main")
             .unwrap();
 
-        wasm_store.data_mut().main_memory = Some(main_memory);
-
-        Self { wasm_store, wasm_main_func, filesize, timeout: None }
+        WasmInstance { main_func, main_memory, current_rule }
     }
 
     /// Sets a timeout for scan operations.
@@ -323,6 +534,114 @@ impl<'r> Scanner<'r> {
         self
     }
 
+    /// Sets the kind of automaton used for searching patterns.
+    ///
+    /// All the patterns are searched at the same time with an Aho-Corasick
+    /// automaton, which can be implemented either as an NFA or a DFA. The DFA
+    /// is faster, but it can use many times the memory used by the NFA when
+    /// the rules have a large number of patterns. See [`AutomatonKind`] for
+    /// details.
+    ///
+    /// With [`AutomatonKind::Auto`] the scanner uses the automaton built
+    /// while compiling the rules, which is shared by all the scanners. With
+    /// any other kind, this scanner builds its own automaton, which takes
+    /// some time and memory. If the automaton of the requested kind can't
+    /// be built, for instance because a DFA would be too large, the one in
+    /// the compiled rules is used instead.
+    ///
+    /// The kind of automaton affects only the speed of the scan and the
+    /// memory used, the scan results are exactly the same.
+    ///
+    /// The default is [`AutomatonKind::Auto`].
+    pub fn automaton_kind(&mut self, kind: AutomatonKind) -> &mut Self {
+        let ctx = self.wasm_store.data_mut();
+        let rules = ctx.compiled_rules;
+
+        let kind = match kind {
+            AutomatonKind::Auto => None,
+            AutomatonKind::Nfa => Some(AhoCorasickKind::ContiguousNFA),
+            AutomatonKind::Dfa => Some(AhoCorasickKind::DFA),
+        };
+
+        // When the search strategy is not Aho-Corasick, the rules don't use
+        // an automaton at all.
+        ctx.ac_automaton = match kind {
+            Some(kind)
+                if rules.search_strategy() == SearchStrategy::AhoCorasick =>
+            {
+                AhoCorasick::builder()
+                    .kind(Some(kind))
+                    .build(rules.atoms().iter().map(|atom| atom.as_slice()))
+                    .ok()
+            }
+            _ => None,
+        };
+
+        self
+    }
+
+    /// Enables or disables the fast mode.
+    ///
+    /// In fast mode, the scanner stops looking for a pattern once it has
+    /// found the first match, as long as the conditions using the pattern
+    /// only need to know whether it matched or not (e.g. `$a`, `any of them`).
+    /// This reduces the work done while scanning and the number of matches
+    /// stored for broad patterns that match many times. Patterns used in
+    /// conditions that depend on the number of matches, or their offsets or
+    /// lengths, like `#a > 10`, `@a[2] == 0` or `$a at 100`, are not affected.
+    ///
+    /// The scan results are the same in both modes, except that
+    /// [`crate::Pattern::matches`] may report a single match for patterns
+    /// that matched multiple times.
+    ///
+    /// This is disabled by default.
+    pub fn fast_mode(&mut self, yes: bool) -> &mut Self {
+        self.wasm_store.data_mut().fast_mode = yes;
+        self
+    }
+
+    /// Enables or disables the deterministic mode.
+    ///
+    /// Scanning the same data with the same rules always produces the same
+    /// results, unless the rules depend on some nondeterministic source,
+    /// like the current time returned by `time.now()`, or the scan is
+    /// aborted by a timeout (see [`Scanner::set_timeout`]). In deterministic
+    /// mode the scanner panics when it touches any of these sources, which
+    /// helps to catch them in tests that rely on reproducible results.
+    ///
+    /// The check is performed only in debug builds, in release builds this
+    /// setting has no effect.
+    ///
+    /// This is disabled by default.
+    pub fn deterministic(&mut self, yes: bool) -> &mut Self {
+        self.wasm_store.data_mut().deterministic = yes;
+        self
+    }
+
+    /// Enables or disables the prefetching of module data.
+    ///
+    /// Before evaluating the conditions, the scanner calls the main function
+    /// of each imported module, which parses the data and produces the
+    /// structure that the rules use, like `pe.sections`. Patterns are
+    /// searched later, when some condition needs them. With prefetching,
+    /// modules parse the data in a separate thread while the patterns are
+    /// searched in the current one, which reduces the time needed for
+    /// scanning files where both steps are expensive, like PE files scanned
+    /// with rules that import the `pe` module.
+    ///
+    /// The scan results are the same with and without prefetching, but
+    /// with prefetching patterns are searched even if no condition needs
+    /// them. Also notice that a new thread is started for each scan, so
+    /// prefetching is worth it only if there's some idle CPU core where the
+    /// modules can run. Modules that keep some state in thread-local
+    /// storage, like `hash`, are not prefetched.
+    ///
+    /// This is disabled by default.
+    pub fn prefetch_modules(&mut self, yes: bool) -> &mut Self {
+        self.prefetch_modules = yes;
+        self
+    }
+
     /// Sets a callback that is invoked every time a YARA rule calls the
     /// `console` module.
     ///
@@ -338,7 +657,138 @@ impl<'r> Scanner<'r> {
         self
     }
 
+    /// Registers a callback that is invoked when a scan starts.
+    ///
+    /// The callback receives the size of the data being scanned, after
+    /// being transformed by the preprocessor set with
+    /// [`Scanner::with_preprocessor`], if any. It is invoked before any YARA
+    /// module processes the data.
+    ///
+    /// This, as well as [`Scanner::on_rule_match`],
+    /// [`Scanner::on_rule_not_match`] and [`Scanner::on_scan_complete`], can
+    /// be called multiple times for registering multiple callbacks, which
+    /// are invoked in registration order. If a callback panics, the panic is
+    /// caught and the callback is not invoked anymore during the current
+    /// scan, but the scan continues normally.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use std::cell::RefCell;
+    /// # use yara_x::Scanner;
+    /// let rules = yara_x::compile(r#"
+    ///     rule foo { strings: $a = "foo" condition: $a }
+    ///     rule bar { strings: $a = "bar" condition: $a }
+    /// "#).unwrap();
+    ///
+    /// let events = RefCell::new(Vec::new());
+    /// let mut scanner = Scanner::new(&rules);
+    ///
+    /// scanner
+    ///     .on_scan_start(|len| events.borrow_mut().push(format!("start {len}")))
+    ///     .on_rule_match(|rule| {
+    ///         events.borrow_mut().push(format!("match {}", rule.identifier()))
+    ///     })
+    ///     .on_scan_complete(|_| events.borrow_mut().push("complete".to_string()));
+    ///
+    /// scanner.scan(b"foo").unwrap();
+    /// drop(scanner);
+    ///
+    /// assert_eq!(events.into_inner(), ["start 3", "match foo", "complete"]);
+    /// ```
+    pub fn on_scan_start<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: FnMut(usize) + 'r,
+    {
+        self.events.add_scan_start(Box::new(callback));
+        self
+    }
+
+    /// Registers a callback that is invoked for each rule that matched.
+    ///
+    /// The callbacks are invoked after all the rules have been evaluated,
+    /// just before the scan completes. This guarantees that only rules that
+    /// are part of the final results are reported, as a rule that matched
+    /// can be discarded later if some global rule in the same namespace
+    /// doesn't match. Private rules are not reported.
+    ///
+    /// See [`Scanner::on_scan_start`] for more details about callbacks.
+    pub fn on_rule_match<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: for<'a> FnMut(&Rule<'a, 'r>) + 'r,
+    {
+        self.events.add_rule_match(Box::new(callback));
+        self
+    }
+
+    /// Registers a callback that is invoked for each rule that didn't match.
+    ///
+    /// The callbacks are invoked after the ones registered with
+    /// [`Scanner::on_rule_match`]. Private rules are not reported.
+    ///
+    /// See [`Scanner::on_scan_start`] for more details about callbacks.
+    pub fn on_rule_not_match<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: for<'a> FnMut(&Rule<'a, 'r>) + 'r,
+    {
+        self.events.add_rule_not_match(Box::new(callback));
+        self
+    }
+
+    /// Registers a callback that is invoked when a scan completes.
+    ///
+    /// The callback receives the results of the scan, or the error that
+    /// caused it to fail. This is the last callback invoked during a scan.
+    ///
+    /// See [`Scanner::on_scan_start`] for more details about callbacks.
+    pub fn on_scan_complete<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: for<'a> FnMut(Result<&ScanResults<'a, 'r>, &ScanError>) + 'r,
+    {
+        self.events.add_scan_complete(Box::new(callback));
+        self
+    }
+
+    /// Sets a function that transforms the data before scanning it.
+    ///
+    /// The preprocessor receives the data passed to any of the scan
+    /// functions (or the content of the scanned file) and returns the data
+    /// that is actually scanned. This allows decoding the data (e.g:
+    /// decompressing it, or removing some XOR obfuscation) before the rules
+    /// are evaluated. Returning [`Cow::Borrowed`] avoids copying the data
+    /// when no transformation is needed.
+    ///
+    /// Everything in the scan results refers to the transformed data: match
+    /// offsets and lengths are relative to the transformed data, the data
+    /// returned by [`crate::Match::data`] is taken from it, and `filesize`
+    /// and YARA modules see it instead of the original data.
+    ///
+    /// `preserves_offsets` indicates whether the transformation maps every
+    /// byte in the original data to a byte at the same offset in the
+    /// transformed data, like single-byte XOR decoding does. In that case
+    /// the match offsets are valid in the original data too. The scanner
+    /// makes sure that offset-preserving preprocessors don't change the size
+    /// of the data, and returns [`ScanError::PreprocessorChangedSize`] if
+    /// they do. When `preserves_offsets` is false, offsets are meaningful
+    /// only for the transformed data, as there's no general way of mapping
+    /// them back to the original data.
+    pub fn with_preprocessor<F>(
+        &mut self,
+        preprocessor: F,
+        preserves_offsets: bool,
+    ) -> &mut Self
+    where
+        F: for<'a> Fn(&'a [u8]) -> Cow<'a, [u8]> + 'r,
+    {
+        self.preprocessor = Some((Box::new(preprocessor), preserves_offsets));
+        self
+    }
+
     /// Scans in-memory data.
+    ///
+    /// If the rules define a `filepath` global variable, it will be
+    /// undefined while scanning in-memory data, unless its value was set
+    /// with [`Scanner::set_global`].
     pub fn scan<'a>(
         &'a mut self,
         data: &'a [u8],
@@ -346,7 +796,97 @@ impl<'r> Scanner<'r> {
         self.scan_impl(ScannedData::Slice(data), None)
     }
 
+    /// Scans in-memory data that is split into multiple slices, like the
+    /// buffers filled by a vectored read.
+    ///
+    /// The slices are handled as a single buffer with the content of all
+    /// of them one after the other, so patterns that span two or more
+    /// slices are found too. Offsets, like the ones in matches or the
+    /// value of `filesize`, refer to that buffer.
+    ///
+    /// Rule conditions and modules can access any offset within the data,
+    /// so the slices are copied into a contiguous buffer, except when only
+    /// one of them is non-empty.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use yara_x::Scanner;
+    /// let rules = yara_x::compile(r#"
+    ///     rule test { strings: $a = "foobar" condition: $a at 2 }
+    /// "#).unwrap();
+    ///
+    /// let mut scanner = Scanner::new(&rules);
+    /// let results = scanner.scan_vectored(&[b"..foo", b"bar.."]).unwrap();
+    ///
+    /// assert_eq!(results.len(), 1);
+    /// ```
+    pub fn scan_vectored<'a>(
+        &'a mut self,
+        slices: &[&'a [u8]],
+    ) -> Result<ScanResults<'a, 'r>, ScanError> {
+        let mut non_empty = slices.iter().filter(|slice| !slice.is_empty());
+
+        let data = match (non_empty.next(), non_empty.next()) {
+            (None, _) => ScannedData::Slice(&[]),
+            (Some(slice), None) => ScannedData::Slice(slice),
+            _ => ScannedData::Vec(slices.concat()),
+        };
+
+        self.scan_impl(data, None)
+    }
+
+    /// Like [`Scanner::scan`], but looks up the results in `cache` first.
+    ///
+    /// If the cache contains results for the same rules and data, they are
+    /// returned without scanning the data. Otherwise the data is scanned and
+    /// the results are stored in the cache. Failed scans are not cached.
+    ///
+    /// The cache key doesn't take into account the values of global
+    /// variables, nor any other scanner setting that can alter the results
+    /// (e.g: [`Scanner::set_global`], [`Scanner::with_preprocessor`]), so a
+    /// cache shouldn't be shared among scanners configured differently.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use yara_x::{LruScanCache, Scanner};
+    /// let rules = yara_x::compile(r#"rule test { strings: $a = "foo" condition: $a }"#).unwrap();
+    /// let mut scanner = Scanner::new(&rules);
+    /// let mut cache = LruScanCache::new(100);
+    ///
+    /// let results = scanner.scan_cached(b"foo", &mut cache).unwrap();
+    /// assert_eq!(results.matching_rules()[0].identifier(), "test");
+    ///
+    /// // The second time the results come from the cache.
+    /// scanner.scan_cached(b"foo", &mut cache).unwrap();
+    /// assert_eq!(cache.hits(), 1);
+    /// ```
+    pub fn scan_cached(
+        &mut self,
+        data: &[u8],
+        cache: &mut dyn ScanCache,
+    ) -> Result<OwnedScanResults, ScanError> {
+        let key =
+            ScanCacheKey::new(self.wasm_store.data().compiled_rules, data);
+
+        if let Some(results) = cache.get(&key) {
+            return Ok(results);
+        }
+
+        let results = OwnedScanResults::from(&self.scan(data)?);
+        cache.put(key, results.clone());
+
+        Ok(results)
+    }
+
     /// Scans a file.
+    ///
+    /// If the rules define a string global variable named `filepath` (see
+    /// [`crate::Compiler::define_global`]), its value is set to the path of
+    /// the scanned file, exactly as provided in `target`. The path is not
+    /// canonicalized nor converted to an absolute path. After the scan, the
+    /// variable recovers the value it had before.
     pub fn scan_file<'a, P>(
         &'a mut self,
         target: P,
@@ -354,7 +894,9 @@ impl<'r> Scanner<'r> {
     where
         P: AsRef<Path>,
     {
-        self.scan_impl(Self::load_file(target.as_ref())?, None)
+        let data = Self::load_file(target.as_ref())?;
+        self.set_filepath(Some(target.as_ref()));
+        self.scan_impl(data, None)
     }
 
     /// Like [`Scanner::scan`], but allows to specify additional scan options.
@@ -366,86 +908,623 @@ impl<'r> Scanner<'r> {
         self.scan_impl(ScannedData::Slice(data), Some(options))
     }
 
-    /// Like [`Scanner::scan_file`], but allows to specify additional scan
-    /// options.
-    pub fn scan_file_with_options<'a, 'opts, P>(
+    /// Like [`Scanner::scan`], but sets the values of some global variables
+    /// only for this scan.
+    ///
+    /// Each entry in `externals` sets the value of the global variable with
+    /// the same name, as [`Scanner::set_global`] does. After the scan, the
+    /// variables recover the values they had before calling this function.
+    ///
+    /// Returns [`ScanError::InvalidGlobal`] if some of the variables was not
+    /// defined with [`crate::Compiler::define_global`], or if the new value
+    /// doesn't have the same type. In that case the scan is not performed
+    /// and none of the variables is modified.
+    pub fn scan_with_externals<'a>(
         &'a mut self,
-        target: P,
-        options: ScanOptions<'opts>,
+        data: &'a [u8],
+        externals: &HashMap<String, serde_json::Value>,
+    ) -> Result<ScanResults<'a, 'r>, ScanError> {
+        // Process the variables in a fixed order, so that the error returned
+        // when more than one of them is invalid doesn't depend on the order
+        // of the hash map.
+        let mut externals = externals.iter().collect::<Vec<_>>();
+        externals.sort_by_key(|(ident, _)| *ident);
+
+        for (ident, value) in externals {
+            let previous = self
+                .wasm_store
+                .data()
+                .root_struct
+                .field_by_name(ident)
+                .map(|field| field.type_value.clone());
+
+            if let Err(err) = self.set_variable(ident, value) {
+                self.restore_globals();
+                return Err(err.into());
+            }
+
+            // `set_variable` succeeded, so the variable exists.
+            self.overridden_globals.push((ident.clone(), previous.unwrap()));
+        }
+
+        self.scan_impl(ScannedData::Slice(data), None)
+    }
+
+    /// Like [`Scanner::scan`], but invokes `callback` periodically for
+    /// reporting the progress of the scan.
+    ///
+    /// The callback receives the number of bytes scanned so far and the
+    /// total size of the data, or `None` if the size is unknown. It is
+    /// invoked every time the search for patterns advances `interval` bytes
+    /// or more, and once more when the scan finishes. Progress is measured
+    /// at the positions where patterns may match, so consecutive reports can
+    /// be further than `interval` bytes apart in regions of the data where
+    /// nothing matches. If the callback panics, the panic is caught and the
+    /// callback is not invoked anymore, but the scan continues normally.
+    ///
+    /// The callback is invoked from the scanning loop itself, so it should
+    /// return quickly.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use yara_x::Scanner;
+    /// let rules = yara_x::compile(
+    ///     r#"rule test { strings: $a = "foo" condition: $a }"#,
+    /// ).unwrap();
+    ///
+    /// let mut scanner = Scanner::new(&rules);
+    /// let data = b"foo".repeat(1000);
+    ///
+    /// let results = scanner
+    ///     .scan_with_progress(data.as_slice(), 1024, |scanned, total| {
+    ///         println!("{} of {:?} bytes", scanned, total);
+    ///     })
+    ///     .unwrap();
+    ///
+    /// assert_eq!(results.matching_rules().len(), 1);
+    /// ```
+    pub fn scan_with_progress<'a, F>(
+        &'a mut self,
+        data: &'a [u8],
+        interval: usize,
+        callback: F,
     ) -> Result<ScanResults<'a, 'r>, ScanError>
     where
-        P: AsRef<Path>,
+        F: FnMut(usize, Option<usize>) + 'r,
     {
-        self.scan_impl(Self::load_file(target.as_ref())?, Some(options))
+        self.wasm_store.data_mut().progress =
+            Some(Progress::new(interval, Box::new(callback)));
+        self.scan_impl(ScannedData::Slice(data), None)
     }
 
-    /// Sets the value of a global variable.
+    /// Searches for patterns in `data`, but only up to `end`, and returns a
+    /// [`ScanCursor`] that allows resuming the search at that offset.
     ///
-    /// The variable must has been previously defined by calling
-    /// [`crate::Compiler::define_global`], and the type it has during the
-    /// definition must match the type of the new value (`T`).
+    /// If `cursor` is `None` the search starts at the beginning of the data,
+    /// if not, it starts where the search that produced the cursor stopped.
+    /// Rule conditions are not evaluated, the returned cursor must be passed
+    /// to [`Scanner::scan_resume`] for completing the scan and obtaining the
+    /// results. In between, the cursor can be serialized and the scan can be
+    /// resumed later, even in a different process, as long as the rules and
+    /// the data are the same.
     ///
-    /// The variable will retain the new value in subsequent scans, unless this
-    /// function is called again for setting a new value.
-    pub fn set_global<T: TryInto<Variable>>(
+    /// `data` must always be the whole data to scan, not only the part that
+    /// will be searched, because patterns that start before `end` may extend
+    /// past it. The search for each block overlaps the next one as much as
+    /// needed for finding such matches, and every match is reported only
+    /// once, by the block where it starts.
+    ///
+    /// Returns [`ScanError::InvalidCursor`] if `cursor` was produced with
+    /// different rules or for data of a different size.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use yara_x::Scanner;
+    /// let rules = yara_x::compile(
+    ///     r#"rule test { strings: $a = "foo" condition: #a == 2 }"#,
+    /// ).unwrap();
+    ///
+    /// let data = b"foo-----foo";
+    /// let mut scanner = Scanner::new(&rules);
+    ///
+    /// let cursor = scanner.scan_partial(data, None, 6).unwrap();
+    /// assert_eq!(cursor.offset(), 6);
+    ///
+    /// let results = scanner.scan_resume(data, cursor).unwrap();
+    /// assert_eq!(results.matching_rules().len(), 1);
+    /// ```
+    pub fn scan_partial(
         &mut self,
-        ident: &str,
-        value: T,
-    ) -> Result<&mut Self, VariableError>
-    where
-        VariableError: From<<T as TryInto<Variable>>::Error>,
-    {
+        data: &[u8],
+        cursor: Option<ScanCursor>,
+        end: usize,
+    ) -> Result<ScanCursor, ScanError> {
+        self.reset();
+        self.check_cursor(cursor.as_ref(), data)?;
+
+        let data = self.preprocess(ScannedData::Slice(data))?;
+        let timeout_secs = self.arm_timeout();
         let ctx = self.wasm_store.data_mut();
 
-        if let Some(field) = ctx.root_struct.field_by_name_mut(ident) {
-            let variable: Variable = value.try_into()?;
-            let type_value: TypeValue = variable.into();
-            // The new type must match the old one.
-            if type_value.eq_type(&field.type_value) {
-                field.type_value = type_value;
-            } else {
-                return Err(VariableError::InvalidType {
-                    variable: ident.to_string(),
-                    expected_type: field.type_value.ty().to_string(),
-                    actual_type: type_value.ty().to_string(),
-                });
-            }
-        } else {
-            return Err(VariableError::Undefined(ident.to_string()));
+        if let Some(cursor) = cursor {
+            cursor.restore(ctx);
         }
 
-        Ok(self)
+        // The search stops at `end`, but it never moves backwards.
+        let end = cmp::max(end, ctx.search_start);
+
+        ctx.deadline =
+            HEARTBEAT_COUNTER.load(Ordering::Relaxed) + timeout_secs;
+        ctx.scanned_data = data.as_ref().as_ptr();
+        ctx.scanned_data_len = data.as_ref().len();
+        ctx.search_end = Some(end);
+
+        let result = ctx
+            .search_for_patterns()
+            .map(|_| ScanCursor::from_context(ctx, end));
+
+        ctx.scanned_data = null();
+        ctx.scanned_data_len = 0;
+        ctx.search_end = None;
+
+        result
     }
 
-    /// Sets the output data for a YARA module.
+    /// Completes a scan started with [`Scanner::scan_partial`].
     ///
-    /// Each YARA module generates an output consisting of a data structure that
-    /// contains information about the scanned file. This data structure is
-    /// represented by a Protocol Buffer message. Typically, you won't need to
-    /// provide this data yourself, as the YARA module automatically generates
-    /// different outputs for each file it scans.
+    /// The search for patterns continues at the offset where the cursor
+    /// stopped, taking into account the matches that were found before
+    /// that offset, and then the rule conditions are evaluated as in
+    /// [`Scanner::scan`]. The results are the same that would be obtained
+    /// by scanning the whole data at once. Like in [`Scanner::scan_partial`],
+    /// `data` must be the whole data to scan.
     ///
-    /// However, there are two scenarios in which you may want to provide the
-    /// output for a module yourself:
+    /// Returns [`ScanError::InvalidCursor`] if `cursor` was produced with
+    /// different rules or for data of a different size.
+    pub fn scan_resume<'a>(
+        &'a mut self,
+        data: &'a [u8],
+        cursor: ScanCursor,
+    ) -> Result<ScanResults<'a, 'r>, ScanError> {
+        self.check_cursor(Some(&cursor), data)?;
+        self.cursor = Some(cursor);
+        self.scan_impl(ScannedData::Slice(data), None)
+    }
+
+    /// Evaluates a single condition against `data` and returns its value.
     ///
-    /// 1) When the module does not produce any output on its own.
-    /// 2) When you already know the output of the module for the upcoming file
-    ///    to be scanned, and you prefer to reuse this data instead of generating
-    ///    it again.
+    /// The condition is written as in the `condition` section of a rule, but
+    /// its value is returned as is, without casting it to bool. This is
+    /// useful for experimenting with conditions, for instance in a REPL.
     ///
-    /// Case 1) applies to certain modules lacking a main function, thus
-    /// incapable of producing any output on their own. For such modules, you
-    /// must set the output before scanning the associated data. Since the
-    /// module's output typically varies with each scanned file, you need to
-    /// call [`Scanner::set_module_output`] prior to each invocation of
-    /// [`Scanner::scan`]. Once [`Scanner::scan`] is executed, the module's
-    /// output is consumed and will be empty unless set again before the
-    /// subsequent call.
+    /// The condition is compiled on its own, without patterns and without
+    /// any other rule, so it can't use patterns, rules, modules, or global
+    /// variables. Returns [`ScanError::InvalidCondition`] if the condition
+    /// doesn't compile.
     ///
-    /// Case 2) applies when you have previously stored the module's output for
-    /// certain scanned data. In such cases, when rescanning the data, you can
-    /// utilize this function to supply the module's output, thereby preventing
-    /// redundant computation by the module. This optimization enhances
+    /// # Example
+    ///
+    /// ```rust
+    /// # use yara_x::{Scanner, Value};
+    /// assert_eq!(
+    ///     Scanner::eval_condition("uint8(0) + 1", b"\x01").unwrap(),
+    ///     Value::Integer(2)
+    /// );
+    /// ```
+    pub fn eval_condition(
+        condition: &str,
+        data: &[u8],
+    ) -> Result<models::Value, ScanError> {
+        let mut compiler = Compiler::new();
+
+        compiler
+            .add_eval_condition(condition)
+            .map_err(ScanError::InvalidCondition)?;
+
+        let rules = compiler.build();
+        let mut scanner = Scanner::new(&rules);
+
+        scanner.scan(data)?;
+
+        Ok(scanner
+            .wasm_store
+            .data_mut()
+            .eval_result
+            .take()
+            .unwrap_or(models::Value::Undefined))
+    }
+
+    /// Like [`Scanner::scan_file`], but allows to specify additional scan
+    /// options.
+    pub fn scan_file_with_options<'a, 'opts, P>(
+        &'a mut self,
+        target: P,
+        options: ScanOptions<'opts>,
+    ) -> Result<ScanResults<'a, 'r>, ScanError>
+    where
+        P: AsRef<Path>,
+    {
+        let data = Self::load_file(target.as_ref())?;
+        self.set_filepath(Some(target.as_ref()));
+        self.scan_impl(data, Some(options))
+    }
+
+    /// Scans a file that keeps growing, like a log file that is still being
+    /// written.
+    ///
+    /// The existing content is scanned first. Then the file is polled for
+    /// appended bytes, and every time the file grows the new bytes are
+    /// scanned, together with the last bytes of the previously scanned
+    /// content, as specified by [`TailOptions::overlap`]. This continues
+    /// until `cancel` is set to `true`.
+    ///
+    /// Rule conditions are evaluated for each scanned region independently,
+    /// so `filesize` and offsets used in conditions are relative to the
+    /// region, not to the file. However, `callback` receives the results
+    /// with the offsets of every match relative to the start of the file,
+    /// and it only receives matches that were not reported before.
+    /// Rules that match without any pattern matching are reported only
+    /// the first time they match.
+    ///
+    /// If the file shrinks, it is assumed that it was truncated and is
+    /// being written again from the beginning, so it's scanned again as if
+    /// it was a new file.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use std::sync::atomic::{AtomicBool, Ordering};
+    /// # use yara_x::{Scanner, TailOptions};
+    /// let rules = yara_x::compile(
+    ///     r#"rule test { strings: $a = "foo" condition: $a }"#,
+    /// ).unwrap();
+    ///
+    /// let path = std::env::temp_dir().join("scan_tail_example.log");
+    /// std::fs::write(&path, b"--foo--").unwrap();
+    ///
+    /// let cancel = AtomicBool::new(false);
+    /// let mut scanner = Scanner::new(&rules);
+    ///
+    /// scanner
+    ///     .scan_tail(&path, &TailOptions::new(), &cancel, |results| {
+    ///         let rule = &results.matching_rules()[0];
+    ///         assert_eq!(rule.patterns()[0].matches(), &[2..5]);
+    ///         cancel.store(true, Ordering::Relaxed);
+    ///     })
+    ///     .unwrap();
+    /// # std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn scan_tail<P, F>(
+        &mut self,
+        path: P,
+        options: &TailOptions,
+        cancel: &AtomicBool,
+        mut callback: F,
+    ) -> Result<(), ScanError>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&OwnedScanResults),
+    {
+        let path = path.as_ref();
+        let open_error = |err| ScanError::OpenError {
+            path: path.to_path_buf(),
+            source: err,
+        };
+
+        let mut file = fs::File::open(path).map_err(open_error)?;
+
+        // The last bytes of the file that were read so far, `buf_offset` is
+        // the offset within the file where `buf` starts.
+        let mut buf = Vec::new();
+        let mut buf_offset = 0;
+        // Rules that matched without pattern matches, these are reported
+        // only once.
+        let mut reported_rules = FxHashSet::default();
+
+        while !cancel.load(Ordering::Relaxed) {
+            let file_len = file.metadata().map_err(open_error)?.len() as usize;
+            let read_len = buf_offset + buf.len();
+
+            if file_len < read_len {
+                buf.clear();
+                buf_offset = 0;
+                reported_rules.clear();
+            }
+
+            if file_len <= read_len {
+                thread::sleep(options.poll_interval);
+                continue;
+            }
+
+            file.seek(SeekFrom::Start((buf_offset + buf.len()) as u64))
+                .map_err(open_error)?;
+
+            // Offset within `buf` where the new bytes start.
+            let new_start = buf.len();
+
+            file.read_to_end(&mut buf).map_err(open_error)?;
+
+            self.set_filepath(Some(path));
+
+            let results = self.scan_impl(ScannedData::Slice(&buf), None)?;
+            let mut results = OwnedScanResults::from(&results);
+
+            results.rebase(buf_offset, buf_offset + new_start, |rule| {
+                reported_rules.insert((
+                    rule.namespace().to_string(),
+                    rule.identifier().to_string(),
+                ))
+            });
+
+            if !results.matching_rules().is_empty() {
+                callback(&results);
+            }
+
+            // Keep only the last bytes of the buffer, the ones that will be
+            // scanned again with the next bytes appended to the file.
+            let discard = buf.len().saturating_sub(options.overlap);
+            buf.drain(..discard);
+            buf_offset += discard;
+        }
+
+        Ok(())
+    }
+
+    /// Scans the files in a directory and its subdirectories, passing the
+    /// results to `sink`.
+    ///
+    /// [`OutputSink::on_match`] is called for each file where some rule
+    /// matched, and [`OutputSink::on_error`] for each file that couldn't be
+    /// scanned. The files in each directory are scanned in alphabetical
+    /// order, before the ones in its subdirectories. Symbolic links are
+    /// not followed. Once all the files have been scanned,
+    /// [`OutputSink::finish`] is called.
+    ///
+    /// The scan stops with an error if some directory can't be read, or if
+    /// `sink` returns an error.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use yara_x::{NdjsonSink, Scanner};
+    /// let rules = yara_x::compile(r#"rule test { condition: true }"#).unwrap();
+    /// let mut scanner = Scanner::new(&rules);
+    ///
+    /// scanner
+    ///     .scan_dir("/path/to/dir", &mut NdjsonSink::new(std::io::stdout()))
+    ///     .unwrap();
+    /// ```
+    pub fn scan_dir<P: AsRef<Path>>(
+        &mut self,
+        dir: P,
+        sink: &mut dyn OutputSink,
+    ) -> io::Result<()> {
+        let mut pending_dirs = vec![dir.as_ref().to_path_buf()];
+
+        while let Some(dir) = pending_dirs.pop() {
+            let mut entries =
+                fs::read_dir(&dir)?.collect::<io::Result<Vec<_>>>()?;
+
+            entries.sort_by_key(|entry| entry.file_name());
+
+            let mut subdirs = Vec::new();
+
+            for entry in entries {
+                let file_type = entry.file_type()?;
+                let path = entry.path();
+                if file_type.is_dir() {
+                    subdirs.push(path);
+                } else if file_type.is_file() {
+                    match self.scan_file(&path) {
+                        Ok(results) if !results.is_empty() => {
+                            sink.on_match(&FileResult::new(&path, &results))?
+                        }
+                        Ok(_) => {}
+                        Err(err) => sink.on_error(&path, &err)?,
+                    }
+                }
+            }
+
+            // Subdirectories are pushed in reverse order, so that they are
+            // popped in alphabetical order.
+            pending_dirs.extend(subdirs.into_iter().rev());
+        }
+
+        sink.finish()
+    }
+
+    /// Scans multiple in-memory buffers in parallel.
+    ///
+    /// The buffers are distributed among `num_threads` worker threads, each
+    /// of them with its own [`Scanner`], but all sharing the same `rules`.
+    /// The scanners are created with their default settings.
+    ///
+    /// [`ScanResults`] borrow from the scanner that produced them, so they
+    /// can't outlive the worker thread. Instead, `f` is called in the worker
+    /// thread with the results of each scan, and whatever `f` returns is
+    /// collected in a vector that follows the same order as `data`. An error
+    /// while scanning one of the buffers doesn't abort the whole batch, it
+    /// is passed to `f` like any other result.
+    ///
+    /// ```
+    /// # use yara_x::{compile, Scanner};
+    /// let rules = compile(r#"rule test { strings: $a = "foo" condition: $a }"#).unwrap();
+    ///
+    /// let matches = Scanner::scan_many_parallel(
+    ///     &rules,
+    ///     &[b"foo", b"bar"],
+    ///     2,
+    ///     |results| results.unwrap().matching_rules().len(),
+    /// );
+    ///
+    /// assert_eq!(matches, vec![1, 0]);
+    /// ```
+    pub fn scan_many_parallel<T, F>(
+        rules: &'r Rules,
+        data: &[&[u8]],
+        num_threads: usize,
+        f: F,
+    ) -> Vec<T>
+    where
+        T: Send,
+        F: Fn(Result<ScanResults<'_, 'r>, ScanError>) -> T + Sync,
+    {
+        let chunk_size = data.len().div_ceil(num_threads.max(1)).max(1);
+
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = data
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    let f = &f;
+                    scope.spawn(move || {
+                        let mut scanner = Scanner::new(rules);
+                        chunk
+                            .iter()
+                            .map(|data| f(scanner.scan(data)))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        })
+    }
+
+    /// Scans `data` in a thread of tokio's blocking thread pool, and returns
+    /// a [`ScanStream`] that produces the matching rules.
+    ///
+    /// The scan is done by a [`Scanner`] with the default settings, and the
+    /// rules are sent to the stream while the conditions are being
+    /// evaluated, as soon as each matching rule is decided. A rule that
+    /// matched is decided when every global rule in the same namespace has
+    /// matched, because until then it can still be discarded, and when the
+    /// search for patterns is done, so that its matches are complete. The
+    /// rules that are not decided before all conditions are evaluated are
+    /// sent when the scan completes. If the scan fails, the stream produces
+    /// the error after the rules already sent.
+    ///
+    /// Dropping the stream aborts the scan within a second, as if the
+    /// timeout was reached, and no more rules are sent.
+    ///
+    /// This function requires the `async` feature, and must be called from
+    /// within a tokio runtime.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use std::future::poll_fn;
+    /// # use std::pin::Pin;
+    /// # use std::sync::Arc;
+    /// # use futures_core::Stream;
+    /// # use yara_x::Scanner;
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let rules = yara_x::compile(
+    ///     r#"rule test { strings: $a = "foo" condition: $a }"#,
+    /// ).unwrap();
+    ///
+    /// let mut stream = Scanner::scan_stream_async(Arc::new(rules), b"foo");
+    ///
+    /// while let Some(rule) =
+    ///     poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await
+    /// {
+    ///     assert_eq!(rule.unwrap().identifier(), "test");
+    /// }
+    /// # }
+    /// ```
+    #[cfg(feature = "async")]
+    pub fn scan_stream_async<T>(rules: Arc<Rules>, data: T) -> ScanStream
+    where
+        T: AsRef<[u8]> + Send + 'static,
+    {
+        ScanStream::spawn(rules, data)
+    }
+
+    /// Aborts the scans done by this scanner when `abort` is set to true,
+    /// as if the timeout was reached.
+    #[cfg(feature = "async")]
+    pub(crate) fn abort_on(&mut self, abort: Arc<AtomicBool>) -> &mut Self {
+        self.wasm_store.data_mut().abort = Some(abort);
+        self
+    }
+
+    /// Registers a callback that is invoked for each non-private rule that
+    /// matched, as soon as the rule can't be discarded anymore.
+    ///
+    /// Unlike [`Scanner::on_rule_match`], the callback is invoked while the
+    /// scan is running. A rule is reported once it matched and all the
+    /// global rules in its namespace matched too, and the search for
+    /// patterns is done. The rules that don't meet these conditions while
+    /// the conditions are being evaluated are reported when the scan
+    /// completes successfully.
+    #[cfg(feature = "async")]
+    pub(crate) fn on_rule_decided<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: for<'a> FnMut(&Rule<'a, 'r>) + 'r,
+    {
+        let ctx = self.wasm_store.data_mut();
+        ctx.decided_rules =
+            Some(DecidedRules::new(ctx.compiled_rules, Box::new(callback)));
+        self
+    }
+
+    /// Sets the value of a global variable.
+    ///
+    /// The variable must has been previously defined by calling
+    /// [`crate::Compiler::define_global`], and the type it has during the
+    /// definition must match the type of the new value (`T`).
+    ///
+    /// The variable will retain the new value in subsequent scans, unless this
+    /// function is called again for setting a new value.
+    pub fn set_global<T: TryInto<Variable>>(
+        &mut self,
+        ident: &str,
+        value: T,
+    ) -> Result<&mut Self, VariableError>
+    where
+        VariableError: From<<T as TryInto<Variable>>::Error>,
+    {
+        self.set_variable(ident, value)?;
+        if ident == "filepath" {
+            self.filepath_set = true;
+        }
+        Ok(self)
+    }
+
+    /// Sets the output data for a YARA module.
+    ///
+    /// Each YARA module generates an output consisting of a data structure that
+    /// contains information about the scanned file. This data structure is
+    /// represented by a Protocol Buffer message. Typically, you won't need to
+    /// provide this data yourself, as the YARA module automatically generates
+    /// different outputs for each file it scans.
+    ///
+    /// However, there are two scenarios in which you may want to provide the
+    /// output for a module yourself:
+    ///
+    /// 1) When the module does not produce any output on its own.
+    /// 2) When you already know the output of the module for the upcoming file
+    ///    to be scanned, and you prefer to reuse this data instead of generating
+    ///    it again.
+    ///
+    /// Case 1) applies to certain modules lacking a main function, thus
+    /// incapable of producing any output on their own. For such modules, you
+    /// must set the output before scanning the associated data. Since the
+    /// module's output typically varies with each scanned file, you need to
+    /// call [`Scanner::set_module_output`] prior to each invocation of
+    /// [`Scanner::scan`]. Once [`Scanner::scan`] is executed, the module's
+    /// output is consumed and will be empty unless set again before the
+    /// subsequent call.
+    ///
+    /// Case 2) applies when you have previously stored the module's output for
+    /// certain scanned data. In such cases, when rescanning the data, you can
+    /// utilize this function to supply the module's output, thereby preventing
+    /// redundant computation by the module. This optimization enhances
     /// performance by eliminating the need for the module to reparse the
     /// scanned data.
     ///
@@ -524,42 +1603,94 @@ impl<'r> Scanner<'r> {
 }
 
 impl<'r> Scanner<'r> {
-    fn load_file(path: &Path) -> Result<ScannedData<'static>, ScanError> {
-        let mut file = fs::File::open(path).map_err(|err| {
-            ScanError::OpenError { path: path.to_path_buf(), source: err }
-        })?;
-
-        let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+    /// Restores the global variables overridden for a single scan to their
+    /// original values.
+    fn restore_globals(&mut self) {
+        let ctx = self.wasm_store.data_mut();
+        for (ident, type_value) in self.overridden_globals.drain(..).rev() {
+            ctx.root_struct.field_by_name_mut(&ident).unwrap().type_value =
+                type_value;
+        }
+    }
 
-        let mut buffered_file;
-        let mapped_file;
+    /// Sets the value of a global variable, like [`Scanner::set_global`].
+    fn set_variable<T: TryInto<Variable>>(
+        &mut self,
+        ident: &str,
+        value: T,
+    ) -> Result<(), VariableError>
+    where
+        VariableError: From<<T as TryInto<Variable>>::Error>,
+    {
+        let ctx = self.wasm_store.data_mut();
 
-        // For files smaller than ~500MB reading the whole file is faster than
-        // using a memory-mapped file.
-        let data = if size < 500_000_000 {
-            buffered_file = Vec::with_capacity(size as usize);
-            file.read_to_end(&mut buffered_file).map_err(|err| {
-                ScanError::OpenError { path: path.to_path_buf(), source: err }
-            })?;
-            ScannedData::Vec(buffered_file)
+        if let Some(field) = ctx.root_struct.field_by_name_mut(ident) {
+            let variable: Variable = value.try_into()?;
+            let type_value: TypeValue = variable.into();
+            // The new type must match the old one.
+            if type_value.eq_type(&field.type_value) {
+                field.type_value = type_value;
+            } else {
+                return Err(VariableError::InvalidType {
+                    variable: ident.to_string(),
+                    expected_type: field.type_value.ty().to_string(),
+                    actual_type: type_value.ty().to_string(),
+                });
+            }
         } else {
-            mapped_file = MmapFile::open(path).map_err(|err| {
-                ScanError::MapError { path: path.to_path_buf(), source: err }
-            })?;
-            ScannedData::Mmap(mapped_file)
-        };
+            return Err(VariableError::Undefined(ident.to_string()));
+        }
 
-        Ok(data)
+        Ok(())
     }
 
-    fn scan_impl<'a, 'opts>(
-        &'a mut self,
-        data: ScannedData<'a>,
-        options: Option<ScanOptions<'opts>>,
-    ) -> Result<ScanResults<'a, 'r>, ScanError> {
-        // Clear information about matches found in a previous scan, if any.
-        self.reset();
+    /// Sets the value of the `filepath` global variable for the next scan,
+    /// if the rules define it as a string. When `path` is `None` the
+    /// variable is left undefined. The previous value is restored when the
+    /// scan finishes.
+    fn set_filepath(&mut self, path: Option<&Path>) {
+        let ctx = self.wasm_store.data_mut();
+        if let Some(field) = ctx.root_struct.field_by_name_mut("filepath") {
+            if let TypeValue::String(_) = field.type_value {
+                let previous = mem::replace(
+                    &mut field.type_value,
+                    match path {
+                        Some(path) => TypeValue::var_string_from(
+                            path.as_os_str().as_encoded_bytes(),
+                        ),
+                        None => TypeValue::String(Value::Unknown),
+                    },
+                );
+                self.overridden_globals
+                    .push(("filepath".to_string(), previous));
+            }
+        }
+    }
+
+    /// Makes sure that `cursor`, if any, can be used for resuming a scan of
+    /// `data` with the current rules.
+    fn check_cursor(
+        &self,
+        cursor: Option<&ScanCursor>,
+        data: &[u8],
+    ) -> Result<(), ScanError> {
+        match cursor {
+            Some(cursor)
+                if !cursor.is_valid_for(
+                    self.wasm_store.data().compiled_rules.content_hash(),
+                    data.len(),
+                ) =>
+            {
+                Err(ScanError::InvalidCursor)
+            }
+            _ => Ok(()),
+        }
+    }
 
+    /// Sets the deadline for the current scan according to the timeout
+    /// set with [`Scanner::set_timeout`], and returns the timeout in
+    /// seconds.
+    fn arm_timeout(&mut self) -> u64 {
         // Timeout in seconds. This is either the value provided by the user or
         // 315.360.000 which is the number of seconds in a year. Using u64::MAX
         // doesn't work because this value is added to the current epoch, and
@@ -579,20 +1710,38 @@ impl<'r> Scanner<'r> {
         // Sets the deadline for the WASM store. The WASM main function will
         // abort if the deadline is reached while the function is being
         // executed.
-        self.wasm_store.set_epoch_deadline(timeout_secs);
-        self.wasm_store
-            .epoch_deadline_callback(|_| Err(ScanError::Timeout.into()));
-
-        // If the user specified some timeout, start the heartbeat thread, if
-        // not previously started. The heartbeat thread increments the WASM
-        // engine epoch and HEARTBEAT_COUNTER every second. There's a single
-        // instance of this thread, independently of the number of concurrent
-        // scans.
-        if self.timeout.is_some() {
+        //
+        // When the scan can be aborted with an abort flag, the deadline is
+        // reached every second instead, and the callback checks both the
+        // flag and the actual deadline.
+        let abortable = self.wasm_store.data().abort.is_some();
+
+        if abortable {
+            self.wasm_store.set_epoch_deadline(1);
+            self.wasm_store.epoch_deadline_callback(|store| {
+                if store.data().deadline_reached() {
+                    Err(ScanError::Timeout.into())
+                } else {
+                    Ok(UpdateDeadline::Continue(1))
+                }
+            });
+        } else {
+            self.wasm_store.set_epoch_deadline(timeout_secs);
+            self.wasm_store
+                .epoch_deadline_callback(|_| Err(ScanError::Timeout.into()));
+        }
+
+        // If the user specified some timeout, or the scan can be aborted,
+        // start the heartbeat thread, if not previously started. The
+        // heartbeat thread increments the WASM engine epoch and
+        // HEARTBEAT_COUNTER every second. There's a single instance of this
+        // thread, independently of the number of concurrent scans.
+        if self.timeout.is_some() || abortable {
             INIT_HEARTBEAT.call_once(|| {
                 thread::spawn(|| loop {
                     thread::sleep(Duration::from_secs(1));
                     ENGINE.increment_epoch();
+                    MINIMAL_ENGINE.increment_epoch();
                     HEARTBEAT_COUNTER
                         .fetch_update(
                             Ordering::SeqCst,
@@ -604,6 +1753,80 @@ impl<'r> Scanner<'r> {
             });
         }
 
+        timeout_secs
+    }
+
+    fn load_file(path: &Path) -> Result<ScannedData<'static>, ScanError> {
+        let mut file = fs::File::open(path).map_err(|err| {
+            ScanError::OpenError { path: path.to_path_buf(), source: err }
+        })?;
+
+        let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+        let mut buffered_file;
+        let mapped_file;
+
+        // For files smaller than ~500MB reading the whole file is faster than
+        // using a memory-mapped file.
+        let data = if size < 500_000_000 {
+            buffered_file = Vec::with_capacity(size as usize);
+            file.read_to_end(&mut buffered_file).map_err(|err| {
+                ScanError::OpenError { path: path.to_path_buf(), source: err }
+            })?;
+            ScannedData::Vec(buffered_file)
+        } else {
+            mapped_file = MmapFile::open(path).map_err(|err| {
+                ScanError::MapError { path: path.to_path_buf(), source: err }
+            })?;
+            ScannedData::Mmap(mapped_file)
+        };
+
+        Ok(data)
+    }
+
+    fn scan_impl<'a, 'opts>(
+        &'a mut self,
+        data: ScannedData<'a>,
+        options: Option<ScanOptions<'opts>>,
+    ) -> Result<ScanResults<'a, 'r>, ScanError> {
+        // Clear information about matches found in a previous scan, if any.
+        self.reset();
+
+        debug_assert!(
+            !(self.wasm_store.data().deterministic && self.timeout.is_some()),
+            "scan with a timeout in deterministic mode"
+        );
+
+        // Restore the state of the partial scan being resumed, if any.
+        if let Some(cursor) = self.cursor.take() {
+            cursor.restore(self.wasm_store.data_mut());
+        }
+
+        // There's no file path for in-memory data, so `filepath` is left
+        // undefined, unless it was set for this scan or by the user.
+        if !self.filepath_set
+            && !self
+                .overridden_globals
+                .iter()
+                .any(|(ident, _)| ident == "filepath")
+        {
+            self.set_filepath(None);
+        }
+
+        let data = match self.preprocess(data) {
+            Ok(data) => data,
+            Err(err) => {
+                self.wasm_store.data_mut().progress = None;
+                self.restore_globals();
+                return Err(err);
+            }
+        };
+
+        self.events.scan_start(data.as_ref().len());
+
+        let timeout_secs = self.arm_timeout();
+        let prefetch_modules = self.prefetch_modules;
+
         // Set the global variable `filesize` to the size of the scanned data.
         self.filesize
             .set(
@@ -622,30 +1845,92 @@ impl<'r> Scanner<'r> {
         // Free all runtime objects left around by previous scans.
         ctx.runtime_objects.clear();
 
-        for module_name in ctx.compiled_rules.imports() {
-            // Lookup the module in the list of built-in modules.
-            let module =
-                modules::BUILTIN_MODULES.get(module_name).unwrap_or_else(
-                    || panic!("module `{}` not found", module_name),
-                );
+        // Lookup the imported modules in the list of built-in modules. Rules
+        // can import a module not available in this build if they were
+        // compiled by a different build. In that case the module's fields
+        // are left without values, so they are undefined.
+        let imported_modules: Vec<_> = ctx
+            .compiled_rules
+            .imports()
+            .filter_map(|name| Some((name, modules::available_module(name)?)))
+            .collect();
+
+        // If the user already provided some output for a module by calling
+        // `Scanner::set_module_output`, use that output. If not, the module's
+        // main function (if the module has a main function) must be called
+        // for getting its output.
+        let mut module_outputs = Vec::with_capacity(imported_modules.len());
+        // With prefetching, the main functions in `main_fns` are called in
+        // another thread. The rest are called in this one, as well as all of
+        // them when prefetching is disabled.
+        let mut main_fns = Vec::new();
+        let mut local_main_fns = Vec::new();
+
+        for (index, (module_name, module)) in
+            imported_modules.iter().enumerate()
+        {
+            let output = ctx
+                .user_provided_module_outputs
+                .remove(module.root_struct_descriptor.full_name());
+
+            if output.is_none() {
+                if let Some(main_fn) = module.main_fn {
+                    let meta = options.as_ref().and_then(|options| {
+                        options.module_metadata.get(module_name).copied()
+                    });
+                    if prefetch_modules
+                        && !modules::THREAD_LOCAL_MODULES.contains(module_name)
+                    {
+                        main_fns.push((index, main_fn, meta));
+                    } else {
+                        local_main_fns.push((index, main_fn, meta));
+                    }
+                }
+            }
 
-            let root_struct_name = module.root_struct_descriptor.full_name();
+            module_outputs.push(output);
+        }
 
-            // If the user already provided some output for the module by
-            // calling `Scanner::set_module_output`, use that output. If not,
-            // call the module's main function (if the module has a main
-            // function) for getting its output.
-            let module_output = if let Some(output) =
-                ctx.user_provided_module_outputs.remove(root_struct_name)
-            {
-                Some(output)
-            } else {
-                let meta = options.as_ref().and_then(|options| {
-                    options.module_metadata.get(module_name).copied()
-                });
+        let scanned_data = data.as_ref();
 
-                module.main_fn.map(|main_fn| main_fn(data.as_ref(), meta))
-            };
+        let call_main_fns = |main_fns: Vec<(usize, MainFn, _)>| {
+            main_fns
+                .into_iter()
+                .map(|(index, main_fn, meta)| {
+                    (index, main_fn(scanned_data, meta))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // The patterns are searched while the other thread calls the main
+        // functions.
+        let mut search_result = Ok(());
+
+        let outputs = if main_fns.is_empty() {
+            call_main_fns(local_main_fns)
+        } else {
+            thread::scope(|scope| {
+                let modules = scope.spawn(move || call_main_fns(main_fns));
+                search_result = ctx.search_for_patterns();
+                ctx.pattern_search_done = search_result.is_ok();
+                let mut outputs = call_main_fns(local_main_fns);
+                outputs.extend(
+                    modules
+                        .join()
+                        .unwrap_or_else(|err| std::panic::resume_unwind(err)),
+                );
+                outputs
+            })
+        };
+
+        for (index, output) in outputs {
+            module_outputs[index] = Some(output);
+        }
+
+        for ((module_name, module), module_output) in
+            imported_modules.into_iter().zip(module_outputs)
+        {
+            let root_struct_name = module.root_struct_descriptor.full_name();
 
             if let Some(module_output) = &module_output {
                 // Make sure that the module is returning a protobuf message of
@@ -714,11 +1999,54 @@ impl<'r> Scanner<'r> {
         // while ScanContext::search_for_patterns is being executed, the result
         // will be Ok(1). If the scan completes successfully the result is
         // Ok(0).`
-        let func_result =
-            self.wasm_main_func.call(self.wasm_store.as_context_mut(), ());
+        //
+        // When all the conditions depend only on whether some patterns
+        // matched, they are evaluated directly instead.
+        //
+        // If the pattern search was done while prefetching the modules' data
+        // and it timed out, the conditions are not evaluated at all.
+        let rules = self.wasm_store.data().compiled_rules;
+        let func_result = match rules.pattern_conditions() {
+            _ if search_result.is_err() => Ok(1),
+            Some(conditions) => Ok(self.eval_pattern_conditions(conditions)),
+            None => self.call_main_funcs(),
+        };
+
+        // Errors must be converted while `current_module` still identifies
+        // the module that produced them.
+        let func_result = func_result.map_err(|err| self.wasm_error(err));
+        self.wasm_store.data_mut().current_module = None;
+
+        // Some module function may have found that the deadline was reached
+        // and returned early, in that case the scan timed out even if the
+        // WASM code completed.
+        let func_result = match func_result {
+            Ok(0) if self.wasm_store.data().deadline_exceeded.get() => Ok(1),
+            result => result,
+        };
+
+        // Global variables overridden only for this scan must recover their
+        // original values.
+        self.restore_globals();
 
         let ctx = self.wasm_store.data_mut();
 
+        // The rules that were not reported while the conditions were being
+        // evaluated are reported now, while the scanned data is available.
+        #[cfg(feature = "async")]
+        if matches!(func_result, Ok(0)) {
+            ctx.report_pending_rules();
+        }
+
+        // The progress callback is used only for the current scan. The last
+        // report says that all the data was scanned, even if the search phase
+        // didn't run, or finished before reaching the end of the data.
+        if let Some(mut progress) = ctx.progress.take() {
+            if matches!(func_result, Ok(0)) {
+                progress.finish(ctx.scanned_data_len);
+            }
+        }
+
         // Set pointer to data back to nil. This means that accessing
         // `scanned_data` from within `ScanResults` is not possible.
         ctx.scanned_data = null();
@@ -747,13 +2075,105 @@ impl<'r> Scanner<'r> {
             }
         }
 
-        match func_result {
+        let result = match func_result {
             Ok(0) => Ok(ScanResults::new(self.wasm_store.data(), data)),
             Ok(1) => Err(ScanError::Timeout),
             Ok(_) => unreachable!(),
-            Err(err) if err.is::<ScanError>() => {
-                Err(err.downcast::<ScanError>().unwrap())
+            Err(err) => Err(err),
+        };
+
+        self.events.scan_complete(result.as_ref());
+
+        result
+    }
+
+    /// Calls the main function of the main WASM module, and then the main
+    /// functions of the modules merged with [`Rules::merge`], if any.
+    ///
+    /// The next module is executed only if the previous one returned 0.
+    /// While a merged module is executed, [`ScanContext::current_module`]
+    /// identifies it.
+    fn call_main_funcs(&mut self) -> Result<i32, anyhow::Error> {
+        self.wasm_store.data_mut().current_module = None;
+
+        let mut result =
+            self.wasm_main_func.call(self.wasm_store.as_context_mut(), ());
+
+        for index in 0..self.wasm_store.data().merged_instances.len() {
+            if !matches!(result, Ok(0)) {
+                break;
+            }
+            let ctx = self.wasm_store.data_mut();
+            let main_func = ctx.merged_instances[index].main_func.clone();
+            ctx.current_module = Some(index);
+            result = main_func.call(self.wasm_store.as_context_mut(), ());
+        }
+
+        result
+    }
+
+    /// Evaluates the rule conditions returned by
+    /// [`Rules::pattern_conditions`] without executing any WASM code.
+    ///
+    /// Returns 0 if the conditions were evaluated, or 1 if the pattern search
+    /// timed out, like the main function of the WASM module.
+    fn eval_pattern_conditions(
+        &mut self,
+        conditions: &'r [PatternCondition],
+    ) -> i32 {
+        let ctx = self.wasm_store.data_mut();
+
+        // The search may have been done already while prefetching the
+        // modules' data.
+        if !ctx.pattern_search_done {
+            if ctx.search_for_patterns().is_err() {
+                return 1;
+            }
+            ctx.pattern_search_done = true;
+        }
+
+        // None of these conditions can be true unless some of the rule's
+        // patterns matched, so only the rules containing a matching pattern
+        // need to be evaluated. They are evaluated in order, as the WASM
+        // code does.
+        let mut candidates = Vec::new();
+
+        for (pattern_id, matches) in ctx.pattern_matches.iter() {
+            if !matches.is_empty() {
+                candidates.extend(
+                    ctx.compiled_rules
+                        .rules_for_pattern(pattern_id)
+                        .iter()
+                        .map(|rule_id| usize::from(*rule_id)),
+                );
+            }
+        }
+
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        for rule_id in candidates {
+            let pattern_matches = &ctx.pattern_matches;
+            let is_match = conditions[rule_id].eval(&|pattern_id| {
+                pattern_matches
+                    .get(pattern_id)
+                    .is_some_and(|matches| !matches.is_empty())
+            });
+            if is_match {
+                ctx.track_rule_match(rule_id.into());
             }
+        }
+
+        0
+    }
+
+    /// Converts an error returned by the WASM code into a [`ScanError`].
+    fn wasm_error(&mut self, err: anyhow::Error) -> ScanError {
+        if err.is::<ScanError>() {
+            return err.downcast::<ScanError>().unwrap();
+        }
+        match err.downcast::<wasmtime::Trap>() {
+            Ok(trap) => self.trap_error(trap),
             Err(err) => panic!(
                 "unexpected error while executing WASM main function: {}",
                 err
@@ -761,6 +2181,80 @@ impl<'r> Scanner<'r> {
         }
     }
 
+    /// Builds the [`ScanError::WasmTrap`] corresponding to `trap`, which
+    /// names the rule that was being evaluated when the trap occurred.
+    fn trap_error(&mut self, trap: wasmtime::Trap) -> ScanError {
+        let ctx = self.wasm_store.data();
+        let rules = ctx.compiled_rules;
+        let merged_module = ctx.merged_module();
+
+        let current_rule = match ctx.current_module {
+            Some(index) => ctx.merged_instances[index].current_rule,
+            None => ctx.current_rule.unwrap(),
+        };
+
+        let rule_id =
+            current_rule.get(self.wasm_store.as_context_mut()).i32().unwrap();
+
+        // A negative ID means that no rule was being evaluated, which should
+        // not happen, as traps occur while evaluating rule conditions.
+        let (namespace, rule) = if rule_id >= 0 {
+            let rule_id = match merged_module {
+                Some(module) => module.rule_id(RuleId::from(rule_id)),
+                None => RuleId::from(rule_id),
+            };
+            let rule_info = rules.get(rule_id);
+            (
+                rules.ident_pool().get(rule_info.namespace_ident_id),
+                rules.ident_pool().get(rule_info.ident_id),
+            )
+        } else {
+            (None, None)
+        };
+
+        ScanError::WasmTrap {
+            namespace: namespace.unwrap_or_default().to_string(),
+            rule: rule.unwrap_or_default().to_string(),
+            message: trap.to_string(),
+        }
+    }
+
+    /// Applies the preprocessor set with [`Scanner::with_preprocessor`] to
+    /// the data, if any. If the preprocessor returns the data untouched,
+    /// the original data is returned without copying it.
+    fn preprocess<'a>(
+        &self,
+        data: ScannedData<'a>,
+    ) -> Result<ScannedData<'a>, ScanError> {
+        let (preprocessor, preserves_offsets) = match &self.preprocessor {
+            Some((preprocessor, preserves_offsets)) => {
+                (preprocessor, *preserves_offsets)
+            }
+            None => return Ok(data),
+        };
+
+        let original = data.as_ref();
+
+        let transformed = match preprocessor(original) {
+            Cow::Borrowed(s)
+                if s.as_ptr() == original.as_ptr()
+                    && s.len() == original.len() =>
+            {
+                return Ok(data)
+            }
+            transformed => transformed.into_owned(),
+        };
+
+        if preserves_offsets && transformed.len() != original.len() {
+            return Err(ScanError::PreprocessorChangedSize {
+                original_size: original.len(),
+                transformed_size: transformed.len(),
+            });
+        }
+
+        Ok(ScannedData::Vec(transformed))
+    }
+
     /// Resets the scanner to its initial state, making it ready for another
     /// scan. This clears all the information generated during the previous
     /// scan.
@@ -776,6 +2270,29 @@ impl<'r> Scanner<'r> {
         // Clear the unconfirmed matches.
         ctx.unconfirmed_matches.clear();
 
+        // The entrypoint must be computed again for the new data.
+        ctx.entrypoint = None;
+
+        // The deadline of the previous scan doesn't apply to the new one.
+        ctx.deadline_exceeded.set(false);
+
+        // The patterns must be searched again in the new data.
+        ctx.pattern_search_done = false;
+
+        // Global rules are evaluated again too.
+        ctx.global_rule_no_matches.clear();
+
+        // Rules reported during the previous scan are reported again.
+        #[cfg(feature = "async")]
+        if let Some(decided_rules) = &mut ctx.decided_rules {
+            decided_rules.reset();
+        }
+
+        // The search for patterns starts at the beginning of the data, unless
+        // a partial scan is being resumed.
+        ctx.search_start = 0;
+        ctx.search_end = None;
+
         // If some pattern or rule matched, clear the matches. Notice that a
         // rule may match without any pattern being matched, because there
         // are rules without patterns, or that match if the pattern is not
@@ -806,6 +2323,20 @@ impl<'r> Scanner<'r> {
 
             // Set to zero all bits in the bitmap.
             bitmap.fill(false);
+
+            // Modules merged with `Rules::merge` have their own bitmaps.
+            let rules = self.wasm_store.data().compiled_rules;
+
+            for (index, module) in rules.merged_modules().iter().enumerate() {
+                let memory =
+                    self.wasm_store.data().merged_instances[index].main_memory;
+                let mem = memory.data_mut(self.wasm_store.as_context_mut());
+
+                mem[base..base
+                    + module.num_rules().div_ceil(8)
+                    + module.num_patterns().div_ceil(8)]
+                    .fill(0);
+            }
         }
     }
 }
@@ -816,11 +2347,14 @@ impl<'r> Scanner<'r> {
 pub struct ScanResults<'a, 'r> {
     ctx: &'a ScanContext<'r>,
     data: ScannedData<'a>,
+    /// Matching non-private rules, built the first time that
+    /// [`ScanResults::rule_matched`] is called.
+    matching_rule_ids: OnceCell<FxHashSet<usize>>,
 }
 
 impl<'a, 'r> ScanResults<'a, 'r> {
     fn new(ctx: &'a ScanContext<'r>, data: ScannedData<'a>) -> Self {
-        Self { ctx, data }
+        Self { ctx, data, matching_rule_ids: OnceCell::new() }
     }
 
     /// Returns an iterator that yields the matching rules in arbitrary order.
@@ -828,6 +2362,251 @@ impl<'a, 'r> ScanResults<'a, 'r> {
         MatchingRules::new(self.ctx, &self.data)
     }
 
+    /// Returns the number of matching rules.
+    ///
+    /// Private rules are not taken into account, as they are never yielded
+    /// by [`ScanResults::matching_rules`].
+    pub fn len(&self) -> usize {
+        self.ctx.non_private_matching_rules.len()
+    }
+
+    /// Returns true if no rule matched.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use yara_x::Scanner;
+    /// let rules = yara_x::compile(r#"rule test { strings: $a = "foo" condition: $a }"#).unwrap();
+    /// let mut scanner = Scanner::new(&rules);
+    ///
+    /// assert!(scanner.scan(b"bar").unwrap().is_empty());
+    ///
+    /// for rule in &scanner.scan(b"foo").unwrap() {
+    ///     assert_eq!(rule.identifier(), "test");
+    /// }
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.ctx.non_private_matching_rules.is_empty()
+    }
+
+    /// Returns an iterator that yields the patterns that have a match
+    /// starting at the given offset, together with the rule they belong to.
+    ///
+    /// Only matching rules are taken into account, and if multiple rules
+    /// share the same pattern, it is yielded once for each of them. The
+    /// result is computed on demand by looking up `offset` in the sorted
+    /// list of matches of every pattern in the matching rules, so the cost
+    /// is O(P * log(M)), where P is the number of patterns in the matching
+    /// rules, and M is the number of matches per pattern.
+    ///
+    /// Rules are yielded in the same order as in
+    /// [`ScanResults::matching_rules`]. The patterns of each rule are sorted
+    /// by the order in which they appear for the first time in the compiled
+    /// rules, which may differ from the order in which they are declared in
+    /// the rule, and then by the length of their matches. This order is the
+    /// same in every scan.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use yara_x::Scanner;
+    /// let rules = yara_x::compile(r#"
+    ///     rule test { strings: $a = "foo" $b = "bar" condition: all of them }
+    /// "#).unwrap();
+    ///
+    /// let mut scanner = Scanner::new(&rules);
+    /// let results = scanner.scan(b"foo bar").unwrap();
+    ///
+    /// let (rule, pattern) = results.matches_at(4).next().unwrap();
+    ///
+    /// assert_eq!(rule.identifier(), "test");
+    /// assert_eq!(pattern.identifier(), "$b");
+    /// ```
+    pub fn matches_at(
+        &'a self,
+        offset: usize,
+    ) -> impl Iterator<Item = (Rule<'a, 'r>, Pattern<'a, 'r>)> + 'a {
+        self.matching_rules().flat_map(move |rule| {
+            let Rule { ctx, data, rules, rule_info } = rule;
+            // Patterns are sorted by `PatternId` and then by the length of
+            // their matches.
+            let mut patterns = rule
+                .patterns()
+                .filter_map(|pattern| {
+                    pattern.match_at(offset).map(|key| (key, pattern))
+                })
+                .collect::<Vec<_>>();
+            patterns.sort_by_key(|((pattern_id, len), _)| {
+                (usize::from(*pattern_id), *len)
+            });
+            patterns.into_iter().map(move |(_, pattern)| {
+                (Rule { ctx, data, rules, rule_info }, pattern)
+            })
+        })
+    }
+
+    /// Returns true if the rule named `name` matched.
+    ///
+    /// `name` can be a rule identifier, like `foo`, or an identifier
+    /// qualified with the rule's namespace, like `bar:foo`. When the
+    /// namespace is not specified, the result is true if some rule with that
+    /// identifier matched, in any namespace. Unknown rules, and private
+    /// rules, which are never included in [`ScanResults::matching_rules`],
+    /// are reported as non-matching.
+    ///
+    /// This doesn't iterate over the matching rules, except the first time
+    /// it is called.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use yara_x::Scanner;
+    /// let rules = yara_x::compile(r#"
+    ///     rule foo { condition: true }
+    ///     rule bar { condition: false }
+    /// "#).unwrap();
+    ///
+    /// let mut scanner = Scanner::new(&rules);
+    /// let results = scanner.scan(b"").unwrap();
+    ///
+    /// assert!(results.rule_matched("foo"));
+    /// assert!(results.rule_matched("default:foo"));
+    /// assert!(!results.rule_matched("bar"));
+    /// ```
+    pub fn rule_matched(&self, name: &str) -> bool {
+        let matching_rule_ids = self.matching_rule_ids.get_or_init(|| {
+            self.ctx
+                .non_private_matching_rules
+                .iter()
+                .map(|rule_id| usize::from(*rule_id))
+                .collect()
+        });
+
+        self.ctx
+            .compiled_rules
+            .rule_ids_by_name(name)
+            .any(|rule_id| matching_rule_ids.contains(&usize::from(rule_id)))
+    }
+
+    /// Returns a SHA-256 hash that identifies the matching rules and their
+    /// matches.
+    ///
+    /// The hash covers the namespace and identifier of every matching rule,
+    /// and the offset and length of each match of its patterns. Scanning the
+    /// same data with the same rules produces the same fingerprint, even in
+    /// different processes or machines, which allows identifying repeated
+    /// detections. Private rules are not taken into account.
+    ///
+    /// Any change in the matches also changes the fingerprint. For instance,
+    /// if some bytes are inserted before a match, the fingerprint changes
+    /// even if the same rules matched. Only the matches reported by the
+    /// scanner are taken into account, so the fingerprint also depends on
+    /// [`Scanner::max_matches_per_pattern`] and [`Scanner::fast_mode`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use yara_x::Scanner;
+    /// let rules = yara_x::compile(r#"
+    ///     rule test { strings: $a = "foo" condition: $a }
+    /// "#).unwrap();
+    ///
+    /// let mut scanner = Scanner::new(&rules);
+    ///
+    /// let fingerprint = scanner.scan(b"foo").unwrap().fingerprint();
+    ///
+    /// assert_eq!(scanner.scan(b"foo").unwrap().fingerprint(), fingerprint);
+    /// assert_ne!(scanner.scan(b" foo").unwrap().fingerprint(), fingerprint);
+    /// ```
+    pub fn fingerprint(&self) -> [u8; 32] {
+        let mut rules: Vec<_> = self.matching_rules().collect();
+
+        // The order in which rules are yielded is not guaranteed, sort them
+        // by namespace and identifier.
+        rules.sort_by_key(|rule| (rule.namespace(), rule.identifier()));
+
+        // Strings are prefixed with their lengths, so that different
+        // sequences of strings can't produce the same input for the hash.
+        let mut hasher = Sha256::new();
+        let update_str = |hasher: &mut Sha256, s: &str| {
+            hasher.update((s.len() as u64).to_le_bytes());
+            hasher.update(s);
+        };
+
+        for rule in rules {
+            update_str(&mut hasher, rule.namespace());
+            update_str(&mut hasher, rule.identifier());
+            for pattern in rule.patterns() {
+                update_str(&mut hasher, pattern.identifier());
+                hasher.update((pattern.matches().len() as u64).to_le_bytes());
+                for m in pattern.matches() {
+                    let range = m.range();
+                    hasher.update((range.start as u64).to_le_bytes());
+                    hasher.update((range.len() as u64).to_le_bytes());
+                }
+            }
+        }
+
+        hasher.finalize().into()
+    }
+
+    /// Returns the global rules that didn't match, preventing other rules
+    /// in their namespaces from matching.
+    ///
+    /// When a global rule doesn't match, no other rule in the same
+    /// namespace can match. Rules that matched before the global rule was
+    /// evaluated have their matches discarded, and rules after it are not
+    /// evaluated at all. Each [`Suppression`] tells the number of rules
+    /// affected by one of these global rules.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use yara_x::Scanner;
+    /// let rules = yara_x::compile(r#"
+    ///     global rule small { condition: filesize < 2 }
+    ///     rule foo { condition: true }
+    ///     rule bar { condition: true }
+    /// "#).unwrap();
+    ///
+    /// let mut scanner = Scanner::new(&rules);
+    /// let results = scanner.scan(b"foo").unwrap();
+    ///
+    /// let suppression = results.suppressions().next().unwrap();
+    ///
+    /// assert_eq!(suppression.global_rule().identifier(), "small");
+    /// assert_eq!(suppression.namespace(), "default");
+    /// assert_eq!(suppression.suppressed_rules(), 2);
+    /// ```
+    pub fn suppressions(
+        &'a self,
+    ) -> impl ExactSizeIterator<Item = Suppression<'a, 'r>> + 'a {
+        let rules = self.ctx.compiled_rules;
+        self.ctx.global_rule_no_matches.iter().map(
+            move |(rule_id, discarded)| {
+                let rule_info = rules.get(*rule_id);
+                // Rules in a namespace have consecutive IDs, the ones that
+                // follow the global rule were not evaluated.
+                let not_evaluated = (usize::from(*rule_id) + 1
+                    ..rules.num_rules())
+                    .take_while(|id| {
+                        rules.get((*id).into()).namespace_id
+                            == rule_info.namespace_id
+                    })
+                    .count();
+                Suppression {
+                    global_rule: Rule {
+                        ctx: Some(self.ctx),
+                        data: Some(&self.data),
+                        rules,
+                        rule_info,
+                    },
+                    suppressed_rules: discarded + not_evaluated,
+                }
+            },
+        )
+    }
+
     /// Returns an iterator that yields the non-matching rules in arbitrary
     /// order.
     pub fn non_matching_rules(&'a self) -> NonMatchingRules<'a, 'r> {
@@ -859,6 +2638,121 @@ impl<'a, 'r> ScanResults<'a, 'r> {
     pub fn module_outputs(&self) -> ModuleOutputs<'a, 'r> {
         ModuleOutputs::new(self.ctx)
     }
+
+    /// Returns a summary of the scan results, with the number of matching
+    /// rules grouped by tag and namespace.
+    ///
+    /// Private rules are not taken into account. Summaries for multiple
+    /// scans can be combined with [`ScanSummary::merge`].
+    pub fn summary(&'a self) -> ScanSummary {
+        let mut summary = ScanSummary::default();
+        for rule in self.matching_rules() {
+            summary.matching_rules += 1;
+            *summary
+                .by_namespace
+                .entry(rule.namespace().to_string())
+                .or_default() += 1;
+            for tag in rule.tags() {
+                *summary
+                    .by_tag
+                    .entry(tag.identifier().to_string())
+                    .or_default() += 1;
+            }
+            for pattern in rule.patterns() {
+                summary.total_matches += pattern.matches().len();
+            }
+        }
+        summary
+    }
+}
+
+impl<'a, 'r> IntoIterator for &'a ScanResults<'a, 'r> {
+    type Item = Rule<'a, 'r>;
+    type IntoIter = MatchingRules<'a, 'r>;
+
+    /// Returns the same iterator as [`ScanResults::matching_rules`].
+    fn into_iter(self) -> Self::IntoIter {
+        self.matching_rules()
+    }
+}
+
+/// Summary of the results of one or more scans.
+///
+/// A summary is obtained from the results of a single scan with
+/// [`ScanResults::summary`], and summaries can be combined together
+/// by means of [`ScanSummary::merge`]. Starting with the default
+/// (empty) summary and merging the summary of each scan produces a
+/// summary for the whole set of scanned files.
+///
+/// ```
+/// # use yara_x::{Scanner, ScanSummary};
+/// let rules = yara_x::compile(r#"
+///     rule foo : bar {
+///       strings:
+///         $a = "foo"
+///       condition:
+///         $a
+///     }
+/// "#).unwrap();
+///
+/// let mut scanner = Scanner::new(&rules);
+/// let mut summary = ScanSummary::default();
+///
+/// for data in [b"foo".as_slice(), b"foofoo", b"baz"] {
+///     summary.merge(&scanner.scan(data).unwrap().summary());
+/// }
+///
+/// assert_eq!(summary.matching_rules(), 2);
+/// assert_eq!(summary.total_matches(), 3);
+/// assert_eq!(summary.by_tag().get("bar"), Some(&2));
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ScanSummary {
+    matching_rules: usize,
+    total_matches: usize,
+    by_tag: BTreeMap<String, usize>,
+    by_namespace: BTreeMap<String, usize>,
+}
+
+impl ScanSummary {
+    /// Number of matching rules.
+    ///
+    /// When the summary covers multiple scans a rule is counted once for
+    /// every scan in which it matched.
+    pub fn matching_rules(&self) -> usize {
+        self.matching_rules
+    }
+
+    /// Total number of pattern matches in the matching rules.
+    pub fn total_matches(&self) -> usize {
+        self.total_matches
+    }
+
+    /// Number of matching rules per tag.
+    ///
+    /// Rules with multiple tags are counted once for every tag, and rules
+    /// without tags are not counted.
+    pub fn by_tag(&self) -> &BTreeMap<String, usize> {
+        &self.by_tag
+    }
+
+    /// Number of matching rules per namespace.
+    pub fn by_namespace(&self) -> &BTreeMap<String, usize> {
+        &self.by_namespace
+    }
+
+    /// Adds the counts in `other` to this summary.
+    pub fn merge(&mut self, other: &ScanSummary) -> &mut Self {
+        self.matching_rules += other.matching_rules;
+        self.total_matches += other.total_matches;
+        for (tag, count) in &other.by_tag {
+            *self.by_tag.entry(tag.clone()).or_default() += count;
+        }
+        for (namespace, count) in &other.by_namespace {
+            *self.by_namespace.entry(namespace.clone()).or_default() += count;
+        }
+        self
+    }
 }
 
 /// Iterator that yields the rules that matched during a scan.
@@ -897,6 +2791,35 @@ impl<'a, 'r> ExactSizeIterator for MatchingRules<'a, 'r> {
     }
 }
 
+/// A global rule that didn't match, and the rules that couldn't match
+/// because of it.
+///
+/// See [`ScanResults::suppressions`].
+pub struct Suppression<'a, 'r> {
+    global_rule: Rule<'a, 'r>,
+    suppressed_rules: usize,
+}
+
+impl<'a, 'r> Suppression<'a, 'r> {
+    /// The global rule that didn't match.
+    pub fn global_rule(&self) -> &Rule<'a, 'r> {
+        &self.global_rule
+    }
+
+    /// Namespace where the other rules were prevented from matching, which
+    /// is the namespace of the global rule.
+    pub fn namespace(&self) -> &'r str {
+        self.global_rule.namespace()
+    }
+
+    /// Number of other rules in the namespace that were prevented from
+    /// matching. This includes private rules, and rules that were not
+    /// evaluated, which may have not matched anyway.
+    pub fn suppressed_rules(&self) -> usize {
+        self.suppressed_rules
+    }
+}
+
 /// Iterator that yields the rules that didn't match during a scan.
 pub struct NonMatchingRules<'a, 'r> {
     ctx: &'a ScanContext<'r>,
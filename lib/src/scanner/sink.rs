@@ -0,0 +1,207 @@
+/*! Destinations for the results of scanning multiple files.
+
+[`Scanner::scan_dir`] scans every file in a directory, and passes the
+results for the files that matched to an [`OutputSink`]. The sinks in this
+module write the results in JSON, NDJSON, or the text format used by the
+original YARA command-line tool, but [`OutputSink`] can be implemented for
+sending the results anywhere else, like a database or a message queue.
+*/
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde_json::json;
+
+use crate::models::Rule;
+use crate::scanner::{ClassicText, ScanError, ScanResults};
+
+#[cfg(doc)]
+use crate::Scanner;
+
+/// The results of scanning a file.
+///
+/// See [`OutputSink::on_match`].
+pub struct FileResult<'a, 'r> {
+    path: &'a Path,
+    results: &'a ScanResults<'a, 'r>,
+}
+
+impl<'a, 'r> FileResult<'a, 'r> {
+    pub(crate) fn new(
+        path: &'a Path,
+        results: &'a ScanResults<'a, 'r>,
+    ) -> Self {
+        Self { path, results }
+    }
+
+    /// Path of the scanned file.
+    pub fn path(&self) -> &'a Path {
+        self.path
+    }
+
+    /// Results of the scan.
+    pub fn results(&self) -> &'a ScanResults<'a, 'r> {
+        self.results
+    }
+
+    /// Returns an object with the path of the scanned file and the rules
+    /// that matched, used by [`JsonSink`] and [`NdjsonSink`].
+    fn to_json(&self) -> serde_json::Value {
+        let rules: Vec<_> = self
+            .results
+            .matching_rules()
+            .map(|rule| rule_to_json(&rule))
+            .collect();
+        json!({
+            "path": self.path.to_string_lossy(),
+            "rules": rules,
+        })
+    }
+}
+
+fn rule_to_json(rule: &Rule) -> serde_json::Value {
+    let tags: Vec<_> = rule.tags().map(|tag| tag.identifier()).collect();
+    json!({
+        "identifier": rule.identifier(),
+        "namespace": rule.namespace(),
+        "tags": tags,
+        "meta": rule.metadata().into_json(),
+    })
+}
+
+/// Receives the results of scanning multiple files.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::io;
+/// # use yara_x::{FileResult, OutputSink};
+/// /// Counts the files where each rule matched.
+/// #[derive(Default)]
+/// struct Counter(std::collections::HashMap<String, usize>);
+///
+/// impl OutputSink for Counter {
+///     fn on_match(&mut self, result: &FileResult) -> io::Result<()> {
+///         for rule in result.results().matching_rules() {
+///             *self.0.entry(rule.identifier().to_string()).or_default() += 1;
+///         }
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait OutputSink {
+    /// Called for each file where some rule matched.
+    fn on_match(&mut self, result: &FileResult) -> io::Result<()>;
+
+    /// Called for each file that couldn't be scanned.
+    ///
+    /// The file is skipped, and the remaining files are scanned as usual.
+    /// By default, errors are ignored.
+    fn on_error(&mut self, path: &Path, err: &ScanError) -> io::Result<()> {
+        let _ = (path, err);
+        Ok(())
+    }
+
+    /// Called once all files have been scanned.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes a JSON array with an object per matching file once all files
+/// have been scanned.
+///
+/// Each object contains the path of the file, and the rules that matched:
+///
+/// ```json
+/// {
+///   "path": "/path/to/file",
+///   "rules": [
+///     {
+///       "identifier": "foo",
+///       "namespace": "default",
+///       "tags": ["bar"],
+///       "meta": [["author", "baz"]]
+///     }
+///   ]
+/// }
+/// ```
+pub struct JsonSink<W: Write> {
+    writer: W,
+    files: Vec<serde_json::Value>,
+}
+
+impl<W: Write> JsonSink<W> {
+    /// Creates a [`JsonSink`] that writes the results to `writer`.
+    pub fn new(writer: W) -> Self {
+        Self { writer, files: Vec::new() }
+    }
+}
+
+impl<W: Write> OutputSink for JsonSink<W> {
+    fn on_match(&mut self, result: &FileResult) -> io::Result<()> {
+        self.files.push(result.to_json());
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let files = serde_json::Value::Array(std::mem::take(&mut self.files));
+        serde_json::to_writer_pretty(&mut self.writer, &files)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()
+    }
+}
+
+/// Writes one line per matching file, each line with a JSON object like
+/// the ones produced by [`JsonSink`].
+///
+/// Unlike [`JsonSink`], each line is written as soon as the file is
+/// scanned.
+pub struct NdjsonSink<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> NdjsonSink<W> {
+    /// Creates a [`NdjsonSink`] that writes the results to `writer`.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write> OutputSink for NdjsonSink<W> {
+    fn on_match(&mut self, result: &FileResult) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, &result.to_json())?;
+        self.writer.write_all(b"\n")
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Writes the results in the format used by the original YARA
+/// command-line tool, as rendered by [`ClassicText`].
+pub struct ClassicTextSink<W: Write> {
+    writer: W,
+    format: ClassicText,
+}
+
+impl<W: Write> ClassicTextSink<W> {
+    /// Creates a [`ClassicTextSink`] that writes the results to `writer`,
+    /// rendered by `format`.
+    pub fn new(writer: W, format: ClassicText) -> Self {
+        Self { writer, format }
+    }
+}
+
+impl<W: Write> OutputSink for ClassicTextSink<W> {
+    fn on_match(&mut self, result: &FileResult) -> io::Result<()> {
+        let path = result.path().to_string_lossy();
+        self.writer
+            .write_all(self.format.render(result.results(), &path).as_bytes())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
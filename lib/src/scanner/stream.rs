@@ -0,0 +1,85 @@
+/*! Scan results delivered as an asynchronous stream.
+
+[`Scanner::scan_stream_async`] scans the data in tokio's blocking thread
+pool, and returns a [`ScanStream`] that produces the matching rules. Each
+rule is sent as soon as it's decided, which happens when the rule matched
+and no global rule in its namespace can discard it anymore, while the
+conditions of the remaining rules are still being evaluated. The rules are
+sent through a bounded channel, and the scanning thread waits while the
+channel is full, so a consumer that processes them slowly doesn't cause an
+unbounded number of rules to pile up in the channel.
+*/
+
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use tokio::sync::mpsc;
+
+use crate::scanner::{OwnedRule, ScanError, Scanner};
+use crate::Rules;
+
+/// Maximum number of matching rules waiting in the channel until the
+/// consumer receives them.
+const CHANNEL_CAPACITY: usize = 32;
+
+/// Stream returned by [`Scanner::scan_stream_async`].
+///
+/// Produces the matching rules in the order they are decided, which may
+/// differ from the order of [`crate::ScanResults::matching_rules`]. If the
+/// scan fails, the stream ends with the error, after the rules that were
+/// decided before the failure. Dropping the stream before it ends aborts
+/// the scan.
+pub struct ScanStream {
+    receiver: mpsc::Receiver<Result<OwnedRule, ScanError>>,
+    abort: Arc<AtomicBool>,
+}
+
+impl ScanStream {
+    /// Starts scanning `data` with `rules` in a thread of the blocking
+    /// thread pool of the current tokio runtime.
+    pub(crate) fn spawn<T>(rules: Arc<Rules>, data: T) -> Self
+    where
+        T: AsRef<[u8]> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        let abort = Arc::new(AtomicBool::new(false));
+        let scan_abort = abort.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let mut scanner = Scanner::new(&rules);
+            let rule_sender = sender.clone();
+
+            scanner.abort_on(scan_abort).on_rule_decided(move |rule| {
+                // The send fails only if the stream was dropped, there's
+                // nobody interested in the remaining rules.
+                let _ = rule_sender.blocking_send(Ok(OwnedRule::from(rule)));
+            });
+
+            if let Err(err) = scanner.scan(data.as_ref()) {
+                let _ = sender.blocking_send(Err(err));
+            }
+        });
+
+        Self { receiver, abort }
+    }
+}
+
+impl Stream for ScanStream {
+    type Item = Result<OwnedRule, ScanError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl Drop for ScanStream {
+    fn drop(&mut self) {
+        self.abort.store(true, Ordering::Relaxed);
+    }
+}
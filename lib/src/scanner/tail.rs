@@ -0,0 +1,53 @@
+/*! Scans of files that keep growing while they are scanned.
+
+Log files, memory dumps and other files that are still being written can
+be monitored with [`Scanner::scan_tail`]. The existing content is scanned
+first, and then the file is polled for appended bytes, which are scanned as
+they appear. [`TailOptions`] controls how often the file is polled and how
+many bytes of the previously scanned content are scanned again together
+with the new ones.
+*/
+
+use std::time::Duration;
+
+#[cfg(doc)]
+use crate::Scanner;
+
+/// Options for [`Scanner::scan_tail`].
+#[derive(Clone, Debug)]
+pub struct TailOptions {
+    pub(crate) overlap: usize,
+    pub(crate) poll_interval: Duration,
+}
+
+impl Default for TailOptions {
+    fn default() -> Self {
+        Self { overlap: 4096, poll_interval: Duration::from_millis(100) }
+    }
+}
+
+impl TailOptions {
+    /// Creates a new [`TailOptions`] with the default values, which are an
+    /// overlap of 4KB and a polling interval of 100ms.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of bytes at the end of the previously scanned content that
+    /// are scanned again together with the appended bytes.
+    ///
+    /// Matches that start before the appended bytes, but continue within
+    /// them, are found only if they start within the overlap. Therefore,
+    /// the overlap should be at least as large as the longest match that
+    /// the rules can produce.
+    pub fn overlap(mut self, bytes: usize) -> Self {
+        self.overlap = bytes;
+        self
+    }
+
+    /// How often the file is checked for appended bytes.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+}
@@ -1,12 +1,27 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use aho_corasick::AhoCorasickKind;
 use pretty_assertions::assert_eq;
 use protobuf::MessageDyn;
 use protobuf::{Message, MessageFull};
 use serde_json::json;
 
+use crate::errors::MergeError;
 use crate::models::MetaValue;
 use crate::mods;
 use crate::variables::VariableError;
-use crate::Scanner;
+use crate::{
+    AutomatonKind, Case, ClassicText, Compiler, Encoding, EveJson, FileResult,
+    LruScanCache, MatchOrigin, NdjsonSink, OutputSink, OwnedScanResults,
+    Rules, ScanCache, ScanCacheKey, ScanCursor, ScanError, ScanResults,
+    ScanSummary, Scanner, TailOptions, Value,
+};
 
 #[test]
 fn iterators() {
@@ -130,6 +145,51 @@ fn metadata() {
     )
 }
 
+#[test]
+fn metadata_from_globals() {
+    let mut compiler = crate::Compiler::new();
+
+    compiler
+        .define_global("author", "John Doe")
+        .unwrap()
+        .define_global("version", 2)
+        .unwrap()
+        .define_global("is_beta", false)
+        .unwrap()
+        .add_source(
+            r#"
+            rule test {
+                meta:
+                    author = author
+                    version = version
+                    is_beta = is_beta
+                condition:
+                    true
+            }
+            "#,
+        )
+        .unwrap();
+
+    let rules = compiler.build();
+    let mut scanner = Scanner::new(&rules);
+
+    // Metadata values are resolved at compile time, changing the global
+    // variable before scanning doesn't affect them.
+    scanner.set_global("author", "Jane Doe").unwrap();
+
+    let results = scanner.scan(b"").expect("scan should not fail");
+    let matching_rule = results.matching_rules().next().unwrap();
+
+    assert_eq!(
+        matching_rule.metadata().collect::<Vec<_>>(),
+        [
+            ("author", MetaValue::String("John Doe")),
+            ("version", MetaValue::Integer(2)),
+            ("is_beta", MetaValue::Bool(false)),
+        ]
+    );
+}
+
 #[test]
 fn xor_matches() {
     let rules = crate::compile(
@@ -164,6 +224,180 @@ fn xor_matches() {
     assert_eq!(matches, [("$a", 0..11, Some(1))])
 }
 
+#[test]
+fn match_origin() {
+    let rules = crate::compile(
+        r#"
+        rule test {
+            strings:
+                $a = "foo" wide
+                $b = "bar" nocase
+                $c = "mississippi" xor
+                $d = "foobar" base64
+                $e = "foobar" base64wide("ZYXWVUTSRQPONMLKJIHGFEDCBAzyxwvutsrqponmlkjihgfedcba9876543210+/")
+                $f = /qux/i
+                $g = { 61 ?? 63 }
+            condition:
+                all of them
+        }
+        "#,
+    )
+    .unwrap();
+
+    let mut scanner = Scanner::new(&rules);
+
+    let results = scanner
+        .scan(
+            b"f\x00o\x00o\x00 bAR lhrrhrrhqqh Zm9vYmFy QuX \
+              A\x00n\x000\x00e\x00B\x00n\x00U\x00b\x00 abc",
+        )
+        .expect("scan should not fail");
+
+    let matching_rule = results.matching_rules().next().unwrap();
+
+    let origins: Vec<_> = matching_rule
+        .patterns()
+        .flat_map(|pattern| {
+            let identifier = pattern.identifier();
+            pattern.matches().map(move |m| (identifier, m.origin()))
+        })
+        .collect();
+
+    assert_eq!(
+        origins,
+        [
+            (
+                "$a",
+                MatchOrigin::Plain { encoding: Encoding::Wide, case: Case::Exact }
+            ),
+            (
+                "$b",
+                MatchOrigin::Plain {
+                    encoding: Encoding::Ascii,
+                    case: Case::NoCase
+                }
+            ),
+            ("$c", MatchOrigin::Xor { encoding: Encoding::Ascii, key: 1 }),
+            (
+                "$d",
+                MatchOrigin::Base64 {
+                    encoding: Encoding::Ascii,
+                    base64wide: false,
+                    alphabet: None
+                }
+            ),
+            (
+                "$e",
+                MatchOrigin::Base64 {
+                    encoding: Encoding::Ascii,
+                    base64wide: true,
+                    alphabet: Some(
+                        "ZYXWVUTSRQPONMLKJIHGFEDCBAzyxwvutsrqponmlkjihgfedcba9876543210+/"
+                    )
+                }
+            ),
+            (
+                "$f",
+                MatchOrigin::Plain {
+                    encoding: Encoding::Ascii,
+                    case: Case::NoCase
+                }
+            ),
+            (
+                "$g",
+                MatchOrigin::Plain {
+                    encoding: Encoding::Ascii,
+                    case: Case::Exact
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn eval_condition() {
+    let data = b"\x2aabcdefghijklmnop";
+
+    assert_eq!(
+        Scanner::eval_condition("filesize > 10", data).unwrap(),
+        Value::Bool(true)
+    );
+
+    assert_eq!(
+        Scanner::eval_condition("filesize > 100", data).unwrap(),
+        Value::Bool(false)
+    );
+
+    assert_eq!(
+        Scanner::eval_condition("uint8(0)", data).unwrap(),
+        Value::Integer(0x2a)
+    );
+
+    assert_eq!(
+        Scanner::eval_condition("uint8(0) * 0.25", data).unwrap(),
+        Value::Float(10.5)
+    );
+
+    assert_eq!(
+        Scanner::eval_condition(r#""foo""#, data).unwrap(),
+        Value::String("foo".into())
+    );
+
+    // Reading past the end of the data produces an undefined value.
+    assert_eq!(
+        Scanner::eval_condition("uint8(100)", data).unwrap(),
+        Value::Undefined
+    );
+
+    assert!(matches!(
+        Scanner::eval_condition("uint8(0) +", data),
+        Err(ScanError::InvalidCondition(_))
+    ));
+
+    // The condition can't close the rule and declare other rules.
+    match Scanner::eval_condition("true }\nrule x { condition: false", data) {
+        Err(ScanError::InvalidCondition(err)) => {
+            assert_eq!(err.code(), "E001")
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+#[cfg(feature = "pe-module")]
+#[test]
+fn unavailable_module() {
+    let rules = crate::compile(
+        r#"
+        import "pe"
+        rule is_dll { condition: pe.is_dll() }
+        rule is_dll_undefined { condition: not defined pe.is_dll() }
+        rule machine_undefined { condition: not defined pe.machine }
+        rule always_true { condition: true }
+        "#,
+    )
+    .unwrap();
+
+    crate::modules::UNAVAILABLE_MODULES
+        .with(|modules| modules.borrow_mut().insert("pe"));
+
+    let mut scanner = Scanner::new(&rules);
+
+    let matching_rules: Vec<_> = scanner
+        .scan(b"MZ")
+        .expect("scan should not fail")
+        .matching_rules()
+        .map(|rule| rule.identifier())
+        .collect();
+
+    crate::modules::UNAVAILABLE_MODULES
+        .with(|modules| modules.borrow_mut().clear());
+
+    assert_eq!(
+        matching_rules,
+        ["is_dll_undefined", "machine_undefined", "always_true"]
+    );
+}
+
 #[cfg(feature = "test_proto2-module")]
 #[test]
 fn reuse_scanner() {
@@ -397,6 +631,196 @@ fn variables_2() {
     );
 }
 
+#[test]
+fn scan_with_externals() {
+    let mut compiler = crate::Compiler::new();
+
+    compiler
+        .define_global("some_int", 0)
+        .unwrap()
+        .define_global("some_str", "")
+        .unwrap()
+        .add_source(
+            r#"
+        rule test {
+            condition:
+                some_int == 1 and
+                some_str == "foo"
+        }
+        "#,
+        )
+        .unwrap();
+
+    let rules = compiler.build();
+    let mut scanner = Scanner::new(&rules);
+
+    let externals = HashMap::from([
+        ("some_int".to_string(), json!(1)),
+        ("some_str".to_string(), json!("foo")),
+    ]);
+
+    assert_eq!(
+        scanner
+            .scan_with_externals(&[], &externals)
+            .expect("scan should not fail")
+            .matching_rules()
+            .len(),
+        1
+    );
+
+    // The default values are restored after the scan.
+    assert_eq!(
+        scanner
+            .scan(&[])
+            .expect("scan should not fail")
+            .matching_rules()
+            .len(),
+        0
+    );
+
+    // Unknown variables produce an error.
+    let externals = HashMap::from([
+        ("some_int".to_string(), json!(1)),
+        ("unknown".to_string(), json!(1)),
+    ]);
+
+    assert!(matches!(
+        scanner.scan_with_externals(&[], &externals).err().unwrap(),
+        ScanError::InvalidGlobal(VariableError::Undefined(_))
+    ));
+
+    // Any variable modified before finding the error is restored.
+    scanner.set_global("some_str", "foo").unwrap();
+    assert_eq!(
+        scanner
+            .scan(&[])
+            .expect("scan should not fail")
+            .matching_rules()
+            .len(),
+        0
+    );
+
+    // Types must match the ones in the definitions.
+    let externals = HashMap::from([("some_int".to_string(), json!("foo"))]);
+
+    assert!(matches!(
+        scanner.scan_with_externals(&[], &externals).err().unwrap(),
+        ScanError::InvalidGlobal(VariableError::InvalidType { .. })
+    ));
+}
+
+#[test]
+fn filepath() {
+    let mut compiler = crate::Compiler::new();
+
+    compiler
+        .define_global("filepath", "")
+        .unwrap()
+        .add_source(
+            r#"
+        rule test_1 {
+            condition:
+                filepath contains "scanner"
+        }
+        rule test_2 {
+            condition:
+                not defined filepath
+        }
+        "#,
+        )
+        .unwrap();
+
+    let rules = compiler.build();
+    let mut scanner = Scanner::new(&rules);
+
+    let scan_results = scanner
+        .scan_file("src/scanner/tests.rs")
+        .expect("scan should not fail");
+
+    let matching_rules: Vec<_> = scan_results.matching_rules().collect();
+
+    assert_eq!(matching_rules.len(), 1);
+    assert_eq!(matching_rules[0].identifier(), "test_1");
+
+    // When scanning in-memory data after scanning a file, `filepath` is
+    // undefined.
+    let scan_results = scanner.scan(b"").expect("scan should not fail");
+    let matching_rules: Vec<_> = scan_results.matching_rules().collect();
+
+    assert_eq!(matching_rules.len(), 1);
+    assert_eq!(matching_rules[0].identifier(), "test_2");
+
+    // But it can be passed as an external variable.
+    let externals =
+        HashMap::from([("filepath".to_string(), json!("/scanner/foo"))]);
+
+    let scan_results = scanner
+        .scan_with_externals(b"", &externals)
+        .expect("scan should not fail");
+    let matching_rules: Vec<_> = scan_results.matching_rules().collect();
+
+    assert_eq!(matching_rules.len(), 1);
+    assert_eq!(matching_rules[0].identifier(), "test_1");
+
+    // Without the external variable it is undefined again.
+    let scan_results = scanner
+        .scan_with_externals(b"", &HashMap::new())
+        .expect("scan should not fail");
+    let matching_rules: Vec<_> = scan_results.matching_rules().collect();
+
+    assert_eq!(matching_rules.len(), 1);
+    assert_eq!(matching_rules[0].identifier(), "test_2");
+}
+
+#[test]
+fn filepath_set_global() {
+    let mut compiler = crate::Compiler::new();
+
+    compiler
+        .define_global("filepath", "")
+        .unwrap()
+        .add_source(
+            r#"
+        rule test_1 {
+            condition:
+                filepath == "/scanner/foo"
+        }
+        rule test_2 {
+            condition:
+                filepath contains "tests.rs"
+        }
+        "#,
+        )
+        .unwrap();
+
+    let rules = compiler.build();
+    let mut scanner = Scanner::new(&rules);
+
+    scanner.set_global("filepath", "/scanner/foo").unwrap();
+
+    // Scanning in-memory data keeps the value set with `set_global`.
+    let scan_results = scanner.scan(b"").expect("scan should not fail");
+    let matching_rules: Vec<_> = scan_results.matching_rules().collect();
+
+    assert_eq!(matching_rules.len(), 1);
+    assert_eq!(matching_rules[0].identifier(), "test_1");
+
+    // Scanning a file overrides it only during the scan.
+    let scan_results = scanner
+        .scan_file("src/scanner/tests.rs")
+        .expect("scan should not fail");
+    let matching_rules: Vec<_> = scan_results.matching_rules().collect();
+
+    assert_eq!(matching_rules.len(), 1);
+    assert_eq!(matching_rules[0].identifier(), "test_2");
+
+    let scan_results = scanner.scan(b"").expect("scan should not fail");
+    let matching_rules: Vec<_> = scan_results.matching_rules().collect();
+
+    assert_eq!(matching_rules.len(), 1);
+    assert_eq!(matching_rules[0].identifier(), "test_1");
+}
+
 #[test]
 fn global_rules() {
     let mut compiler = crate::Compiler::new();
@@ -649,3 +1073,2049 @@ fn namespaces() {
     assert_eq!(matching_rules[1].identifier(), "bar");
     assert_eq!(matching_rules[1].namespace(), "bar");
 }
+
+#[test]
+fn preprocessor() {
+    let rules = crate::compile(
+        r#"
+        rule test {
+            strings:
+                $a = "foobar"
+            condition:
+                $a and filesize == 6
+        }
+        "#,
+    )
+    .unwrap();
+
+    let mut scanner = Scanner::new(&rules);
+
+    // Identity preprocessor.
+    scanner.with_preprocessor(|data| Cow::Borrowed(data), true);
+
+    let scan_results = scanner.scan(b"foobar").expect("scan should not fail");
+    let matches: Vec<_> = scan_results
+        .matching_rules()
+        .flat_map(|rule| rule.patterns().next().unwrap().matches())
+        .map(|m| (m.range(), m.data()))
+        .collect();
+
+    assert_eq!(matches, vec![(0..6, b"foobar".as_slice())]);
+
+    // Preprocessor that removes one byte out of every two. Offsets are
+    // relative to the transformed data.
+    scanner.with_preprocessor(
+        |data| Cow::Owned(data.iter().step_by(2).copied().collect()),
+        false,
+    );
+
+    let scan_results =
+        scanner.scan(b"f.o.o.b.a.r.").expect("scan should not fail");
+    let matches: Vec<_> = scan_results
+        .matching_rules()
+        .flat_map(|rule| rule.patterns().next().unwrap().matches())
+        .map(|m| (m.range(), m.data()))
+        .collect();
+
+    assert_eq!(matches, vec![(0..6, b"foobar".as_slice())]);
+
+    // The same transform can't be declared as offset-preserving.
+    scanner.with_preprocessor(
+        |data| Cow::Owned(data.iter().step_by(2).copied().collect()),
+        true,
+    );
+
+    assert!(matches!(
+        scanner.scan(b"f.o.o.b.a.r.").err().unwrap(),
+        ScanError::PreprocessorChangedSize {
+            original_size: 12,
+            transformed_size: 6
+        }
+    ));
+}
+
+#[test]
+fn preprocessor_error_restores_globals() {
+    let mut compiler = crate::Compiler::new();
+
+    compiler
+        .define_global("some_int", 0)
+        .unwrap()
+        .add_source("rule test { condition: some_int == 1 }")
+        .unwrap();
+
+    let rules = compiler.build();
+    let mut scanner = Scanner::new(&rules);
+
+    scanner.with_preprocessor(|_| Cow::Borrowed(b"".as_slice()), true);
+
+    let externals = HashMap::from([("some_int".to_string(), json!(1))]);
+
+    assert!(matches!(
+        scanner.scan_with_externals(b"foo", &externals).err().unwrap(),
+        ScanError::PreprocessorChangedSize { .. }
+    ));
+
+    // The external variable doesn't remain set after the failed scan.
+    scanner.with_preprocessor(|data| Cow::Borrowed(data), true);
+
+    assert_eq!(scanner.scan(b"foo").unwrap().matching_rules().len(), 0);
+}
+
+#[test]
+fn summary() {
+    let mut compiler = crate::Compiler::new();
+
+    compiler
+        .add_source(
+            r#"
+            rule foo : tag1 tag2 { strings: $a = "foo" condition: $a }
+            rule bar : tag1 { strings: $a = "bar" condition: $a }
+            private rule baz : tag1 { condition: true }
+            "#,
+        )
+        .unwrap()
+        .new_namespace("other")
+        .add_source(
+            r#"
+            rule qux : tag2 { strings: $a = "o" condition: $a }
+            rule quux { condition: false }
+            "#,
+        )
+        .unwrap();
+
+    let rules = compiler.build();
+    let mut scanner = Scanner::new(&rules);
+
+    let summary =
+        scanner.scan(b"foo bar").expect("scan should not fail").summary();
+
+    assert_eq!(summary.matching_rules(), 3);
+    assert_eq!(summary.total_matches(), 4);
+    assert_eq!(
+        summary.by_tag(),
+        &BTreeMap::from([("tag1".to_string(), 2), ("tag2".to_string(), 2)])
+    );
+    assert_eq!(
+        summary.by_namespace(),
+        &BTreeMap::from([
+            ("default".to_string(), 2),
+            ("other".to_string(), 1)
+        ])
+    );
+
+    // Accumulate the summaries for multiple scans.
+    let mut total = ScanSummary::default();
+
+    for data in [b"foo bar".as_slice(), b"bar", b"xyz"] {
+        total.merge(
+            &scanner.scan(data).expect("scan should not fail").summary(),
+        );
+    }
+
+    assert_eq!(total.matching_rules(), 4);
+    assert_eq!(total.total_matches(), 5);
+    assert_eq!(
+        total.by_tag(),
+        &BTreeMap::from([("tag1".to_string(), 3), ("tag2".to_string(), 2)])
+    );
+    assert_eq!(
+        total.by_namespace(),
+        &BTreeMap::from([
+            ("default".to_string(), 3),
+            ("other".to_string(), 1)
+        ])
+    );
+}
+
+#[test]
+fn scan_many_parallel() {
+    let rules = crate::compile(
+        r#"
+rule foo { strings: $a = "foo" condition: $a }
+rule bar { strings: $a = "bar" condition: #a > 1 }
+rule size { condition: filesize % 3 == 0 }
+"#,
+    )
+    .unwrap();
+
+    let buffers: Vec<Vec<u8>> = (0..100)
+        .map(|i| {
+            let mut data = vec![b'x'; i];
+            if i % 2 == 0 {
+                data.extend_from_slice(b"foo");
+            }
+            if i % 5 == 0 {
+                data.extend_from_slice(b"barbar");
+            }
+            data
+        })
+        .collect();
+
+    let buffers: Vec<&[u8]> = buffers.iter().map(|b| b.as_slice()).collect();
+
+    let matching_rules = |results: Result<ScanResults, ScanError>| {
+        results
+            .expect("scan should not fail")
+            .matching_rules()
+            .map(|rule| rule.identifier().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let mut scanner = Scanner::new(&rules);
+
+    let sequential: Vec<_> = buffers
+        .iter()
+        .map(|data| matching_rules(scanner.scan(data)))
+        .collect();
+
+    for num_threads in [1, 4, 200] {
+        assert_eq!(
+            Scanner::scan_many_parallel(
+                &rules,
+                &buffers,
+                num_threads,
+                matching_rules
+            ),
+            sequential
+        );
+    }
+
+    assert!(
+        Scanner::scan_many_parallel(&rules, &[], 4, matching_rules).is_empty()
+    );
+}
+
+#[test]
+fn fast_mode() {
+    let rules = crate::compile(
+        r#"
+rule only_match { strings: $a = "foo" condition: $a }
+rule count { strings: $a = "bar" condition: #a == 3 }
+rule shared_1 { strings: $a = "baz" condition: $a }
+rule shared_2 { strings: $a = "baz" condition: @a[2] == 20 }
+"#,
+    )
+    .unwrap();
+
+    let data = b"foo bar baz foo bar baz foo bar";
+
+    let num_matches = |scanner: &mut Scanner| {
+        scanner
+            .scan(data)
+            .expect("scan should not fail")
+            .matching_rules()
+            .map(|rule| {
+                (
+                    rule.identifier().to_string(),
+                    rule.patterns().next().unwrap().matches().len(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut scanner = Scanner::new(&rules);
+
+    assert_eq!(
+        num_matches(&mut scanner),
+        [
+            ("only_match".to_string(), 3),
+            ("count".to_string(), 3),
+            ("shared_1".to_string(), 2),
+            ("shared_2".to_string(), 2),
+        ]
+    );
+
+    scanner.fast_mode(true);
+
+    // Patterns used only for checking if they matched store a single match.
+    // Patterns that are shared with some rule that needs their offsets or
+    // number of matches store all of them.
+    assert_eq!(
+        num_matches(&mut scanner),
+        [
+            ("only_match".to_string(), 1),
+            ("count".to_string(), 3),
+            ("shared_1".to_string(), 2),
+            ("shared_2".to_string(), 2),
+        ]
+    );
+}
+
+#[test]
+fn matches_at_same_offset() {
+    let rules = crate::compile(
+        r#"
+rule test {
+  strings:
+    $b = "foobar"
+    $a = "foo"
+    $c = "f" ascii wide
+    $d = /fo+/
+    $e = /fo+?/
+  condition:
+    all of them
+}
+"#,
+    )
+    .unwrap();
+
+    let data = b"f\x00foobar";
+
+    let matches = |scanner: &mut Scanner| {
+        let results = scanner.scan(data).expect("scan should not fail");
+        let rule = results.matching_rules().next().unwrap();
+        rule.patterns()
+            .map(|pattern| {
+                (
+                    pattern.identifier().to_string(),
+                    pattern
+                        .matches()
+                        .map(|m| (m.range().start, m.range().end))
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Patterns are reported in declaration order. For each pattern there's
+    // at most one match per offset: the first one found, except for greedy
+    // regexps, where the longest one is reported.
+    let expected = vec![
+        ("$b".to_string(), vec![(2, 8)]),
+        ("$a".to_string(), vec![(2, 5)]),
+        ("$c".to_string(), vec![(0, 1), (2, 3)]),
+        ("$d".to_string(), vec![(2, 5)]),
+        ("$e".to_string(), vec![(2, 4)]),
+    ];
+
+    for _ in 0..10 {
+        assert_eq!(matches(&mut Scanner::new(&rules)), expected);
+    }
+}
+
+#[test]
+fn hex_alternatives_at_same_offset() {
+    let rules = crate::compile(
+        r#"
+rule test {
+  strings:
+    $a = { ( 61 62 63 64 65 66 | 61 62 63 64 65 ) }
+    $b = { ( 61 62 63 64 65 | 61 62 63 64 65 66 ) }
+  condition:
+    all of them
+}
+"#,
+    )
+    .unwrap();
+
+    let mut scanner = Scanner::new(&rules);
+    let results = scanner.scan(b"abcdef").expect("scan should not fail");
+    let rule = results.matching_rules().next().unwrap();
+
+    let matches = rule
+        .patterns()
+        .map(|pattern| {
+            (
+                pattern.identifier().to_string(),
+                pattern
+                    .matches()
+                    .map(|m| (m.range().start, m.range().end))
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // Both alternatives match at offset 0, and the one that is found first
+    // is kept, regardless of its length.
+    assert_eq!(
+        matches,
+        vec![
+            ("$a".to_string(), vec![(0, 6)]),
+            ("$b".to_string(), vec![(0, 5)]),
+        ]
+    );
+}
+
+#[cfg(feature = "test_proto2-module")]
+#[test]
+fn rules_without_patterns_skip_search() {
+    let rules = crate::compile(
+        r#"
+        import "test_proto2"
+        rule module_only {
+            condition:
+                test_proto2.file_size < 10
+        }
+        rule with_patterns {
+            strings:
+                $a = "foo"
+            condition:
+                test_proto2.file_size >= 10 and $a
+        }
+        "#,
+    )
+    .unwrap();
+
+    let mut scanner = Scanner::new(&rules);
+
+    let matching_rules = |scanner: &mut Scanner, data: &[u8]| {
+        scanner
+            .scan(data)
+            .expect("scan should not fail")
+            .matching_rules()
+            .map(|rule| rule.identifier().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    // Only `module_only` needs to be evaluated in full, and it doesn't
+    // reference any pattern, so the search phase is not executed at all.
+    // If it were, the match for `$a` would be there.
+    assert_eq!(matching_rules(&mut scanner, b"foo"), ["module_only"]);
+    assert!(scanner.wasm_store.data().pattern_matches.is_empty());
+
+    // Here `with_patterns` needs to know if `$a` matched, and that triggers
+    // the search phase.
+    assert_eq!(
+        matching_rules(&mut scanner, b"foo bar baz"),
+        ["with_patterns"]
+    );
+    assert!(!scanner.wasm_store.data().pattern_matches.is_empty());
+}
+
+#[test]
+fn scan_results_iteration() {
+    let rules = crate::compile(
+        r#"
+rule foo { strings: $a = "foo" condition: $a }
+private rule bar { strings: $a = "bar" condition: $a }
+rule baz { strings: $a = "baz" condition: $a }
+"#,
+    )
+    .unwrap();
+
+    let mut scanner = Scanner::new(&rules);
+
+    let results = scanner.scan(b"qux").expect("scan should not fail");
+
+    assert!(results.is_empty());
+    assert_eq!(results.len(), 0);
+    assert_eq!((&results).into_iter().count(), 0);
+
+    let results = scanner.scan(b"foo bar baz").expect("scan should not fail");
+
+    // Private rules are not counted.
+    assert!(!results.is_empty());
+    assert_eq!(results.len(), 2);
+
+    let mut identifiers = Vec::new();
+
+    for rule in &results {
+        identifiers.push(rule.identifier());
+    }
+
+    assert_eq!(
+        identifiers,
+        results
+            .matching_rules()
+            .map(|rule| rule.identifier())
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn matches_at() {
+    let rules = crate::compile(
+        r#"
+rule foo { strings: $a = "foo" $b = "foobar" condition: any of them }
+rule bar { strings: $a = "bar" condition: $a }
+rule baz { strings: $a = "foo" condition: $a and filesize > 100 }
+rule qux { strings: $a = "foobar" $b = "foo" condition: all of them }
+"#,
+    )
+    .unwrap();
+
+    let matches_at = |offset| {
+        let mut scanner = Scanner::new(&rules);
+        let results = scanner.scan(b"foobar").expect("scan should not fail");
+        results
+            .matches_at(offset)
+            .map(|(rule, pattern)| {
+                (
+                    rule.identifier().to_string(),
+                    pattern.identifier().to_string(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let pairs = |pairs: &[(&str, &str)]| {
+        pairs
+            .iter()
+            .map(|(rule, pattern)| (rule.to_string(), pattern.to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    // Both patterns in `foo` match at offset 0. The rule `baz` didn't
+    // match, so its patterns are not taken into account. In `qux`, `$b` is
+    // yielded first because it is the same pattern as `$a` in `foo`, which
+    // appears in the rules before `$a` in `qux`.
+    for _ in 0..10 {
+        assert_eq!(
+            matches_at(0),
+            pairs(&[
+                ("foo", "$a"),
+                ("foo", "$b"),
+                ("qux", "$b"),
+                ("qux", "$a")
+            ])
+        );
+    }
+
+    assert_eq!(matches_at(3), pairs(&[("bar", "$a")]));
+    assert_eq!(matches_at(1), pairs(&[]));
+}
+
+#[test]
+fn scan_cached() {
+    let rules =
+        crate::compile(r#"rule test { strings: $a = "foo" condition: $a }"#)
+            .unwrap();
+
+    let mut scanner = Scanner::new(&rules);
+    let mut cache = LruScanCache::new(2);
+
+    let results = scanner.scan_cached(b"foo bar", &mut cache).unwrap();
+
+    assert_eq!(cache.hits(), 0);
+    assert_eq!(cache.misses(), 1);
+    assert_eq!(results.matching_rules().len(), 1);
+    assert_eq!(results.matching_rules()[0].patterns()[0].matches()[0], 0..3);
+
+    // Same rules and data, the results come from the cache.
+    let cached = scanner.scan_cached(b"foo bar", &mut cache).unwrap();
+
+    assert_eq!(cache.hits(), 1);
+    assert_eq!(cache.misses(), 1);
+    assert_eq!(cached, results);
+
+    // Different data, the results are not found in the cache.
+    let results = scanner.scan_cached(b"bar", &mut cache).unwrap();
+
+    assert_eq!(cache.hits(), 1);
+    assert_eq!(cache.misses(), 2);
+    assert!(results.matching_rules().is_empty());
+    assert_eq!(cache.len(), 2);
+
+    // Adding one more result evicts the least recently used one, which is
+    // the one for "foo bar".
+    scanner.scan_cached(b"baz", &mut cache).unwrap();
+
+    assert_eq!(cache.len(), 2);
+    assert!(cache.get(&ScanCacheKey::new(&rules, b"foo bar")).is_none());
+    assert!(cache.get(&ScanCacheKey::new(&rules, b"bar")).is_some());
+
+    // Rules compiled from the same source have the same hash, and the
+    // other way around.
+    let same_rules =
+        crate::compile(r#"rule test { strings: $a = "foo" condition: $a }"#)
+            .unwrap();
+
+    let other_rules =
+        crate::compile(r#"rule test { strings: $a = "bar" condition: $a }"#)
+            .unwrap();
+
+    assert_eq!(rules.content_hash(), same_rules.content_hash());
+    assert_ne!(rules.content_hash(), other_rules.content_hash());
+}
+
+#[test]
+fn deterministic() {
+    let rules = crate::compile(
+        r#"
+rule text {
+  strings:
+    $a = "foo"
+    $b = /ba[rz]/
+    $c = "qux" xor
+  condition:
+    any of them
+}
+
+rule hex {
+  strings:
+    $a = { 66 6F [0-8] 62 61 }
+    $b = { 62 [2-20] 71 }
+  condition:
+    #a > 1 and $b
+}
+
+rule no_match {
+  strings:
+    $a = "not found"
+  condition:
+    $a
+}"#,
+    )
+    .unwrap();
+
+    let data = b"foo bar foo baz qux foo bar\x12\x13\x0a foo bar".repeat(50);
+
+    let mut scanner = Scanner::new(&rules);
+
+    scanner.deterministic(true);
+
+    let serialize = |results: ScanResults| {
+        let non_matching = results
+            .non_matching_rules()
+            .map(|rule| rule.identifier().to_string())
+            .collect::<Vec<_>>();
+        serde_json::to_vec(&(OwnedScanResults::from(&results), non_matching))
+            .unwrap()
+    };
+
+    let expected = serialize(scanner.scan(data.as_slice()).unwrap());
+
+    for _ in 0..100 {
+        assert_eq!(
+            serialize(scanner.scan(data.as_slice()).unwrap()),
+            expected
+        );
+    }
+
+    // When more than one external variable is invalid, the error is always
+    // about the first one in alphabetical order.
+    let externals = HashMap::from_iter(
+        ["d", "a", "e", "c", "b"].map(|ident| (ident.to_string(), json!(1))),
+    );
+
+    for _ in 0..10 {
+        assert_eq!(
+            scanner
+                .scan_with_externals(&[], &externals)
+                .err()
+                .unwrap()
+                .to_string(),
+            ScanError::InvalidGlobal(VariableError::Undefined(
+                "a".to_string()
+            ))
+            .to_string()
+        );
+    }
+}
+
+#[cfg(all(debug_assertions, feature = "time-module"))]
+#[test]
+#[should_panic(expected = "time.now() called in deterministic mode")]
+fn deterministic_time_now() {
+    let rules = crate::compile(
+        r#"
+import "time"
+rule test { condition: time.now() > 0 }"#,
+    )
+    .unwrap();
+
+    let mut scanner = Scanner::new(&rules);
+
+    let _ = scanner.deterministic(true).scan(&[]);
+}
+
+#[test]
+fn scan_with_progress() {
+    let rules = crate::compile(
+        r#"rule test { strings: $a = "foobar" condition: #a == 4096 }"#,
+    )
+    .unwrap();
+
+    let mut data = vec![0_u8; 4 * 1024 * 1024];
+
+    for chunk in data.chunks_mut(1024) {
+        chunk[100..106].copy_from_slice(b"foobar");
+    }
+
+    let reports = Rc::new(RefCell::new(Vec::new()));
+    let mut scanner = Scanner::new(&rules);
+
+    let r = reports.clone();
+    let results = scanner
+        .scan_with_progress(
+            data.as_slice(),
+            64 * 1024,
+            move |scanned, total| r.borrow_mut().push((scanned, total)),
+        )
+        .unwrap();
+
+    assert_eq!(results.matching_rules().len(), 1);
+
+    let reports = reports.borrow();
+
+    assert!(reports.len() >= 64);
+    assert!(reports.windows(2).all(|w| w[0].0 < w[1].0));
+    assert!(reports.iter().all(|(_, total)| *total == Some(data.len())));
+    assert_eq!(reports.last(), Some(&(data.len(), Some(data.len()))));
+
+    // A panic in the callback doesn't affect the scan, and the callback is
+    // not invoked again.
+    let calls = Rc::new(RefCell::new(0));
+    let c = calls.clone();
+    let results = scanner
+        .scan_with_progress(data.as_slice(), 64 * 1024, move |_, _| {
+            *c.borrow_mut() += 1;
+            panic!("progress callback panicked");
+        })
+        .unwrap();
+
+    assert_eq!(results.matching_rules().len(), 1);
+    assert_eq!(*calls.borrow(), 1);
+
+    // The callback is not used by subsequent scans.
+    scanner.scan(data.as_slice()).unwrap();
+    assert_eq!(*calls.borrow(), 1);
+}
+
+#[test]
+fn shared_pattern_rules() {
+    let rules = crate::compile(
+        r#"
+rule foo { strings: $a = "shared" condition: $a }
+rule bar { strings: $b = "other" condition: $b }
+rule baz { strings: $c = "other" $d = "shared" condition: $c and $d }
+"#,
+    )
+    .unwrap();
+
+    let owners = |rules: &crate::Rules| {
+        let mut scanner = Scanner::new(rules);
+        let results = scanner.scan(b"a shared and other pattern").unwrap();
+        results
+            .matching_rules()
+            .flat_map(|rule| {
+                rule.patterns()
+                    .map(|pattern| {
+                        assert_eq!(pattern.matches().len(), 1);
+                        format!(
+                            "{}:{} {:?}",
+                            rule.identifier(),
+                            pattern.identifier(),
+                            pattern
+                                .rules()
+                                .map(|r| r.identifier())
+                                .collect::<Vec<_>>(),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let expected = [
+        r#"foo:$a ["foo", "baz"]"#,
+        r#"bar:$b ["bar", "baz"]"#,
+        r#"baz:$c ["bar", "baz"]"#,
+        r#"baz:$d ["foo", "baz"]"#,
+    ];
+
+    assert_eq!(owners(&rules), expected);
+
+    // The mapping is rebuilt when the rules are deserialized.
+    let rules = crate::Rules::deserialize(rules.serialize().unwrap()).unwrap();
+
+    assert_eq!(owners(&rules), expected);
+}
+
+#[test]
+fn eve_json() {
+    let rules = crate::compile(
+        r#"
+        rule foo : bar baz {
+          strings:
+            $a = "foo"
+            $b = "qux"
+          condition:
+            $a or $b
+        }
+        rule unmatched {
+          condition:
+            false
+        }
+        "#,
+    )
+    .unwrap();
+
+    let mut scanner = Scanner::new(&rules);
+    let results = scanner.scan(b"---foo---foo").unwrap();
+
+    // 2024-02-29T12:34:56.789012 UTC
+    let eve = EveJson::with_clock(|| {
+        std::time::UNIX_EPOCH
+            + std::time::Duration::from_micros(1_709_210_096_789_012)
+    });
+
+    assert_eq!(
+        eve.records(&results),
+        vec![json!({
+            "timestamp": "2024-02-29T12:34:56.789012+0000",
+            "event_type": "yara",
+            "yara": {
+                "rule": "foo",
+                "namespace": "default",
+                "tags": ["bar", "baz"],
+                "patterns": [
+                    { "identifier": "$a", "offsets": [3, 9] }
+                ]
+            }
+        })]
+    );
+}
+
+#[test]
+fn scan_partial() {
+    let rules = crate::compile(
+        r#"
+        rule literal { strings: $a = "foobar" condition: $a }
+        rule regexp { strings: $a = /ba[rz]+qux/ condition: $a }
+        rule chained { strings: $a = { 66 6F 6F [5-60] 71 75 78 } condition: $a }
+        rule xored { strings: $a = "secret" xor condition: $a }
+        rule encoded { strings: $a = "hidden" base64 condition: $a }
+        rule count { strings: $a = "o" condition: #a > 5 }
+        rule anchored { strings: $a = "xx" condition: $a at 0 }
+        "#,
+    )
+    .unwrap();
+
+    let xored: Vec<u8> = b"secret".iter().map(|b| b ^ 0x20).collect();
+
+    let mut data = b"xxfoobarzzqux--foobar-".to_vec();
+    data.extend_from_slice(&xored);
+    data.extend_from_slice(b"--aGlkZGVu----foo-----------qux----o-o");
+
+    let mut scanner = Scanner::new(&rules);
+    let expected = OwnedScanResults::from(&scanner.scan(&data).unwrap());
+
+    assert_eq!(expected.matching_rules().len(), 7);
+
+    // Interrupt the scan at every possible offset, serialize the cursor,
+    // and resume from the deserialized one.
+    for offset in 0..=data.len() {
+        let cursor = scanner.scan_partial(&data, None, offset).unwrap();
+        assert_eq!(cursor.offset(), offset);
+        assert_eq!(cursor.is_complete(), offset == data.len());
+
+        let cursor: ScanCursor =
+            serde_json::from_str(&serde_json::to_string(&cursor).unwrap())
+                .unwrap();
+
+        let results = scanner.scan_resume(&data, cursor).unwrap();
+
+        assert_eq!(OwnedScanResults::from(&results), expected, "{}", offset);
+    }
+
+    // The scan can also advance in several steps.
+    let mut cursor = None;
+    for offset in (0..data.len()).step_by(7) {
+        cursor = Some(scanner.scan_partial(&data, cursor, offset).unwrap());
+    }
+
+    let results = scanner.scan_resume(&data, cursor.unwrap()).unwrap();
+
+    assert_eq!(OwnedScanResults::from(&results), expected);
+
+    // Resuming a complete cursor doesn't search the data again, the matches
+    // come from the cursor.
+    let cursor = scanner.scan_partial(&data, None, data.len()).unwrap();
+    let zeroes = vec![0_u8; data.len()];
+    let results = scanner.scan_resume(&zeroes, cursor).unwrap();
+
+    assert_eq!(OwnedScanResults::from(&results), expected);
+
+    // The cursor can't be used with different data or rules.
+    let cursor = scanner.scan_partial(&data, None, 10).unwrap();
+
+    assert!(matches!(
+        scanner.scan_resume(&data[1..], cursor.clone()),
+        Err(ScanError::InvalidCursor)
+    ));
+
+    let other_rules = crate::compile("rule test { condition: true }").unwrap();
+    let mut other_scanner = Scanner::new(&other_rules);
+
+    assert!(matches!(
+        other_scanner.scan_resume(&data, cursor),
+        Err(ScanError::InvalidCursor)
+    ));
+}
+
+#[test]
+fn wasm_trap() {
+    let mut compiler = Compiler::new();
+
+    compiler
+        .add_source(r#"rule foo { condition: filesize == 0 }"#)
+        .unwrap()
+        .new_namespace("bar")
+        .add_source(
+            r#"
+            rule baz { condition: true }
+            rule qux {
+              condition:
+                (filesize - 9223372036854775807 - 1) \ -1 == 0
+            }
+            "#,
+        )
+        .unwrap();
+
+    let rules = compiler.build();
+    let mut scanner = Scanner::new(&rules);
+
+    match scanner.scan(b"") {
+        Err(ScanError::WasmTrap { namespace, rule, .. }) => {
+            assert_eq!(namespace, "bar");
+            assert_eq!(rule, "qux");
+        }
+        other => panic!("unexpected scan result: {:?}", other.err()),
+    }
+
+    // The scanner can be used again after the trap.
+    assert_eq!(scanner.scan(b"a").unwrap().matching_rules().len(), 1);
+}
+
+#[test]
+fn merge_rules() {
+    fn results(rules: &Rules, data: &[u8]) -> Vec<String> {
+        let mut scanner = Scanner::new(rules);
+        let results = scanner.scan(data).unwrap();
+        let mut matching = results
+            .matching_rules()
+            .map(|rule| {
+                let patterns = rule
+                    .patterns()
+                    .map(|p| {
+                        let ranges: Vec<_> =
+                            p.matches().map(|m| m.range()).collect();
+                        format!("{}{:?}", p.identifier(), ranges)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!(
+                    "{}:{} {}",
+                    rule.namespace(),
+                    rule.identifier(),
+                    patterns
+                )
+            })
+            .collect::<Vec<_>>();
+        matching.sort();
+        matching
+    }
+
+    let mut compiler = Compiler::new();
+
+    compiler
+        .define_global("limit", 10)
+        .unwrap()
+        .add_source(
+            r#"
+            rule a { strings: $a = "foo" condition: $a and filesize < limit * 10 }
+            rule b { strings: $b = { 62 61 (72 | 7A) } condition: #b == 2 and a }
+            "#,
+        )
+        .unwrap()
+        .new_namespace("foo")
+        .add_source(
+            r#"
+            global rule always { condition: filesize > 0 }
+            rule c { strings: $c = /qu+x/ condition: $c at 11 }
+            "#,
+        )
+        .unwrap();
+
+    let rules_a = compiler.build();
+
+    let mut compiler = Compiler::new();
+
+    compiler
+        .define_global("name", "bar")
+        .unwrap()
+        .define_global("limit", 10)
+        .unwrap()
+        .add_source(
+            r#"
+            rule d { strings: $d = "bar" condition: $d and name == "bar" }
+            rule e { strings: $e = "foo" nocase condition: d and #e == 1 }
+            private rule f { condition: "baz" contains "az" }
+            rule g { condition: f and uint8(0) == 0x66 and limit == 10 }
+            rule h { strings: $h = "nope" condition: $h }
+            "#,
+        )
+        .unwrap()
+        .new_namespace("foo")
+        .add_source(
+            r#"
+            global rule never { condition: filesize == 0 }
+            rule i { strings: $i = "baz" condition: $i }
+            "#,
+        )
+        .unwrap();
+
+    let rules_b = compiler.build();
+
+    let data = b"foobar baz qux";
+
+    let mut expected = results(&rules_a, data);
+    expected
+        .extend(results(&rules_b, data).into_iter().map(|r| format!("b_{r}")));
+    expected.sort();
+
+    // Namespace `foo` exists in both sets.
+    let mut merged = Rules::deserialize(rules_a.serialize().unwrap()).unwrap();
+
+    assert_eq!(
+        merged.merge(&rules_b, None),
+        Err(MergeError::DuplicateNamespace {
+            namespace: "default".to_string()
+        })
+    );
+
+    merged.merge(&rules_b, Some("b_")).unwrap();
+
+    assert_eq!(merged.iter().len(), 11);
+    assert_eq!(results(&merged, data), expected);
+
+    // Merged rules can be serialized, and merged again.
+    let merged = Rules::deserialize(merged.serialize().unwrap()).unwrap();
+
+    let mut twice = crate::compile("rule z { condition: true }").unwrap();
+
+    twice.merge(&merged, Some("m_")).unwrap();
+
+    let mut expected_twice = vec!["default:z ".to_string()];
+    expected_twice.extend(expected.iter().map(|r| format!("m_{r}")));
+    expected_twice.sort();
+
+    assert_eq!(results(&twice, data), expected_twice);
+
+    // Global variables must be consistent.
+    let mut compiler = Compiler::new();
+    compiler.define_global("limit", 20).unwrap();
+    compiler.add_source("rule x { condition: limit == 20 }").unwrap();
+
+    let mut rules = Rules::deserialize(rules_a.serialize().unwrap()).unwrap();
+
+    assert_eq!(
+        rules.merge(&compiler.build(), Some("x_")),
+        Err(MergeError::ConflictingGlobal { variable: "limit".to_string() })
+    );
+}
+
+#[test]
+fn automaton_kind() {
+    let rules = crate::compile(
+        r#"
+rule foo {
+  strings:
+    $a = "foo"
+    $b = /ba[rz]+/
+    $c = "qux" xor
+    $d = { 61 ?? 63 }
+    $e = "Corge" nocase wide
+  condition:
+    any of them
+}
+rule bar {
+  strings:
+    $a = "oba"
+  condition:
+    #a == 2
+}
+"#,
+    )
+    .unwrap();
+
+    let data =
+        b"foobarbazz \x11\x15\x18 ac abc C\x00o\x00R\x00g\x00E\x00 foobaz";
+
+    let matches = |kind: AutomatonKind| {
+        let mut scanner = Scanner::new(&rules);
+        scanner.automaton_kind(kind);
+
+        let expected_kind = match kind {
+            AutomatonKind::Auto => None,
+            AutomatonKind::Nfa => Some(AhoCorasickKind::ContiguousNFA),
+            AutomatonKind::Dfa => Some(AhoCorasickKind::DFA),
+        };
+
+        assert_eq!(
+            scanner
+                .wasm_store
+                .data()
+                .ac_automaton
+                .as_ref()
+                .map(|ac| ac.kind()),
+            expected_kind
+        );
+
+        let results = scanner.scan(data).unwrap();
+
+        results
+            .matching_rules()
+            .flat_map(|rule| {
+                rule.patterns()
+                    .flat_map(|pattern| {
+                        pattern
+                            .matches()
+                            .map(|m| {
+                                format!(
+                                    "{}:{} {:?}",
+                                    rule.identifier(),
+                                    pattern.identifier(),
+                                    m.range()
+                                )
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let auto = matches(AutomatonKind::Auto);
+
+    assert_eq!(auto.len(), 10);
+    assert_eq!(matches(AutomatonKind::Nfa), auto);
+    assert_eq!(matches(AutomatonKind::Dfa), auto);
+}
+
+#[test]
+fn classic_text() {
+    let mut compiler = Compiler::new();
+
+    compiler
+        .add_source(
+            r#"
+            rule foo : bar baz {
+              meta:
+                author = "Some \"One\""
+                version = 2
+                stable = true
+              strings:
+                $a = "foo"
+                $b = { 00 01 02 03 }
+                $c = /qu+x\n/
+              condition:
+                any of them
+            }
+            rule unmatched {
+              condition:
+                false
+            }
+            "#,
+        )
+        .unwrap()
+        .new_namespace("other")
+        .add_source(
+            r#"
+            rule qux {
+              strings:
+                $a = "foo" wide
+              condition:
+                $a
+            }
+            "#,
+        )
+        .unwrap();
+
+    let rules = compiler.build();
+    let mut scanner = Scanner::new(&rules);
+    let results = scanner
+        .scan(b"---foo---\x00\x01\x02\x03---quuux\n---f\x00o\x00o\x00")
+        .unwrap();
+
+    let mut mint = goldenfile::Mint::new(".");
+    let mut output =
+        mint.new_goldenfile("src/scanner/testdata/classic_text.out").unwrap();
+
+    let mut classic = ClassicText::new();
+
+    output
+        .write_all(classic.render(&results, "/path/to/file").as_bytes())
+        .unwrap();
+
+    classic
+        .show_tags(true)
+        .show_meta(true)
+        .show_namespace(true)
+        .show_strings(true);
+
+    output
+        .write_all(classic.render(&results, "/path/to/file").as_bytes())
+        .unwrap();
+}
+
+#[test]
+fn skip_pattern_conjunctions() {
+    let conjunctions = r#"
+rule absent {
+  strings:
+    $a = "foo"
+    $b = "bar"
+  condition:
+    $a and $b
+}
+rule partially_absent {
+  strings:
+    $a = "baz"
+    $b = "qux"
+    $c = "bar"
+  condition:
+    $a and ($b and $c)
+}
+rule present {
+  strings:
+    $a = "baz"
+    $b = "qux"
+  condition:
+    $a and $b
+}
+"#;
+
+    // When all the conditions are conjunctions of patterns, they are
+    // evaluated from the results of the pattern search, without running
+    // the WASM code.
+    let rules = crate::compile(conjunctions).unwrap();
+
+    assert!(rules.pattern_conditions().is_some());
+
+    let mut scanner = Scanner::new(&rules);
+    let results = scanner.scan(b"baz qux").unwrap();
+
+    assert_eq!(
+        results.matching_rules().map(|r| r.identifier()).collect::<Vec<_>>(),
+        ["present"]
+    );
+
+    // Otherwise, the evaluation of a conjunction stops at the first pattern
+    // that didn't match, and the rest of the condition is never evaluated.
+    #[cfg(feature = "console-module")]
+    {
+        let rules = crate::compile(
+            format!(
+                r#"
+import "console"
+{conjunctions}
+rule not_a_conjunction {{
+  strings:
+    $a = "foo"
+    $b = "baz"
+  condition:
+    $a and $b and console.log("not_a_conjunction")
+}}
+rule logs {{
+  strings:
+    $a = "baz"
+  condition:
+    $a and console.log("logs")
+}}
+"#
+            )
+            .as_str(),
+        )
+        .unwrap();
+
+        assert!(rules.pattern_conditions().is_none());
+
+        let mut messages = Vec::new();
+        let mut scanner = Scanner::new(&rules);
+
+        scanner.console_log(|message| messages.push(message));
+
+        let results = scanner.scan(b"baz qux").unwrap();
+
+        assert_eq!(
+            results
+                .matching_rules()
+                .map(|r| r.identifier())
+                .collect::<Vec<_>>(),
+            ["present", "logs"]
+        );
+
+        drop(scanner);
+
+        assert_eq!(messages, ["logs"]);
+    }
+}
+
+#[test]
+fn scan_events() {
+    let rules = crate::compile(
+        r#"
+        rule foo { strings: $a = "foo" condition: $a }
+        private rule bar { strings: $a = "bar" condition: $a }
+        rule baz { condition: bar }
+        rule qux { condition: false }
+        "#,
+    )
+    .unwrap();
+
+    let events = RefCell::new(Vec::new());
+    let mut scanner = Scanner::new(&rules);
+
+    scanner
+        .on_scan_start(|len| events.borrow_mut().push(format!("start {len}")))
+        .on_rule_match(|rule| {
+            events.borrow_mut().push(format!("match {}", rule.identifier()))
+        })
+        // This callback panics, but the other ones are invoked anyway.
+        .on_rule_match(|_| panic!("callback panicked"))
+        .on_rule_not_match(|rule| {
+            events.borrow_mut().push(format!("no match {}", rule.identifier()))
+        })
+        .on_scan_complete(|result| {
+            events.borrow_mut().push(match result {
+                Ok(results) => format!("complete {}", results.len()),
+                Err(err) => format!("error {err}"),
+            })
+        });
+
+    let results = scanner.scan(b"foobar").unwrap();
+    assert_eq!(results.matching_rules().len(), 2);
+
+    scanner.scan(b"qux").unwrap();
+
+    drop(scanner);
+
+    assert_eq!(
+        events.into_inner(),
+        [
+            "start 6",
+            "match foo",
+            "match baz",
+            "no match qux",
+            "complete 2",
+            "start 3",
+            "no match foo",
+            "no match baz",
+            "no match qux",
+            "complete 0",
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "hash-module")]
+fn timeout_in_module_function() {
+    let rules = crate::compile(
+        r#"import "hash" rule test { condition: hash.sha256(0, filesize) == "" }"#,
+    )
+    .unwrap();
+
+    let data = vec![0_u8; 4 << 20];
+    let mut scanner = Scanner::new(&rules);
+
+    // With a deadline that has already passed, module functions that
+    // process the data in chunks stop before the first chunk.
+    let ctx = scanner.wasm_store.data_mut();
+    let mut chunks = 0;
+
+    ctx.deadline = 0;
+
+    assert_eq!(ctx.for_each_chunk(&data, |_| chunks += 1), None);
+    assert_eq!(chunks, 0);
+    assert!(ctx.deadline_exceeded.get());
+
+    // A zero timeout has already expired when the scan starts.
+    scanner.set_timeout(Duration::ZERO);
+
+    assert!(matches!(scanner.scan(&data), Err(ScanError::Timeout)));
+}
+
+#[test]
+fn scan_tail() {
+    let rules = crate::compile(
+        r#"
+        rule foo { strings: $a = "foo" condition: $a }
+        rule bar { strings: $a = "bar" condition: $a }
+        "#,
+    )
+    .unwrap();
+
+    let path = std::env::temp_dir()
+        .join(format!("yara-x-scan-tail-{}.log", std::process::id()));
+
+    std::fs::write(&path, format!("foo{}", "-".repeat(100))).unwrap();
+
+    let cancel = AtomicBool::new(false);
+    let options =
+        TailOptions::new().overlap(16).poll_interval(Duration::from_millis(5));
+
+    let mut reported = Vec::new();
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let mut file =
+                std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+            // "bar" is written in two parts, so that it spans the boundary
+            // between two scanned regions.
+            for chunk in ["--ba", "r--", "foo", "----"] {
+                std::thread::sleep(Duration::from_millis(50));
+                file.write_all(chunk.as_bytes()).unwrap();
+            }
+            // Give up after a while if the scanner doesn't finish.
+            for _ in 0..1000 {
+                if cancel.load(Ordering::Relaxed) {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            cancel.store(true, Ordering::Relaxed);
+        });
+
+        Scanner::new(&rules)
+            .scan_tail(&path, &options, &cancel, |results| {
+                for rule in results.matching_rules() {
+                    for pattern in rule.patterns() {
+                        for range in pattern.matches() {
+                            reported.push((
+                                rule.identifier().to_string(),
+                                range.clone(),
+                            ));
+                        }
+                    }
+                }
+                if reported.len() == 3 {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+            })
+            .unwrap();
+    });
+
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        reported,
+        vec![
+            ("foo".to_string(), 0..3),
+            ("bar".to_string(), 105..108),
+            ("foo".to_string(), 110..113),
+        ]
+    );
+}
+
+#[test]
+fn rule_matched() {
+    let mut compiler = Compiler::new();
+
+    compiler
+        .add_source(
+            r#"
+            rule foo { strings: $a = "foo" condition: $a }
+            rule bar { strings: $a = "bar" condition: $a }
+            private rule baz { condition: true }
+            "#,
+        )
+        .unwrap()
+        .new_namespace("other")
+        .add_source(r#"rule bar { condition: true }"#)
+        .unwrap();
+
+    let rules = compiler.build();
+    let mut scanner = Scanner::new(&rules);
+    let results = scanner.scan(b"foo").unwrap();
+
+    assert!(results.rule_matched("foo"));
+    assert!(results.rule_matched("default:foo"));
+    assert!(!results.rule_matched("other:foo"));
+
+    // `bar` matches only in the `other` namespace.
+    assert!(results.rule_matched("bar"));
+    assert!(!results.rule_matched("default:bar"));
+    assert!(results.rule_matched("other:bar"));
+
+    // Private rules are not reported as matching.
+    assert!(!results.rule_matched("baz"));
+
+    assert!(!results.rule_matched("unknown"));
+    assert!(!results.rule_matched("unknown:foo"));
+    assert!(!results.rule_matched(""));
+}
+
+#[test]
+fn pattern_conditions() {
+    let source = r#"
+        rule and_rule { strings: $a = "foo" $b = "bar" condition: $a and $b }
+        rule or_rule { strings: $a = "foo" $b = "qux" condition: $a or $b }
+        rule nested_rule {
+          strings: $a = "foo" $b = "bar" $c = "baz"
+          condition: $a and ($b or $c)
+        }
+        rule any_rule { strings: $a = "qux" $b = "bar" condition: any of them }
+        rule all_rule { strings: $a = "foo" $b = "qux" condition: all of them }
+        rule tuple_rule {
+          strings: $a = "foo" $b = "bar" $c = "qux"
+          condition: any of ($a and $c, $b)
+        }
+        private rule private_rule { strings: $a = "bar" condition: $a }
+        rule nocase_rule { strings: $a = "FOO" nocase condition: $a }
+        rule wide_rule { strings: $a = "foo" wide condition: $a }
+        "#;
+
+    let mut compiler = Compiler::new();
+    compiler.add_source(source).unwrap();
+    let rules = compiler.build();
+
+    // Every condition can be evaluated without WASM code.
+    assert!(rules.pattern_conditions().is_some());
+
+    // A rule with some other kind of condition forces the evaluation of all
+    // conditions with WASM.
+    let mut compiler = Compiler::new();
+    compiler
+        .add_source(source)
+        .unwrap()
+        .add_source(r#"rule wasm { condition: filesize > 1000 }"#)
+        .unwrap();
+    let wasm_rules = compiler.build();
+
+    assert!(wasm_rules.pattern_conditions().is_none());
+
+    let mut scanner = Scanner::new(&rules);
+    let mut wasm_scanner = Scanner::new(&wasm_rules);
+
+    for data in [
+        b"".as_slice(),
+        b"foo",
+        b"foobar",
+        b"foobaz",
+        b"fooqux",
+        b"bar",
+        b"f\x00o\x00o\x00",
+    ] {
+        let results = scanner.scan(data).unwrap();
+        let wasm_results = wasm_scanner.scan(data).unwrap();
+
+        let matching: Vec<_> =
+            results.matching_rules().map(|r| r.identifier()).collect();
+        let wasm_matching: Vec<_> =
+            wasm_results.matching_rules().map(|r| r.identifier()).collect();
+
+        assert_eq!(matching, wasm_matching, "data: {:?}", data);
+
+        let non_matching: Vec<_> =
+            results.non_matching_rules().map(|r| r.identifier()).collect();
+        let wasm_non_matching: Vec<_> = wasm_results
+            .non_matching_rules()
+            .map(|r| r.identifier())
+            .filter(|ident| *ident != "wasm")
+            .collect();
+
+        assert_eq!(non_matching, wasm_non_matching, "data: {:?}", data);
+    }
+
+    let results = scanner.scan(b"foobar").unwrap();
+
+    assert_eq!(
+        results.matching_rules().map(|r| r.identifier()).collect::<Vec<_>>(),
+        [
+            "and_rule",
+            "or_rule",
+            "nested_rule",
+            "any_rule",
+            "tuple_rule",
+            "nocase_rule"
+        ]
+    );
+}
+
+#[test]
+fn suppressions() {
+    let mut compiler = Compiler::new();
+
+    compiler
+        .add_source(
+            r#"
+            rule foo { condition: true }
+            private rule bar { condition: true }
+            global rule small { condition: filesize < 2 }
+            rule baz { condition: false }
+            "#,
+        )
+        .unwrap()
+        .new_namespace("other")
+        .add_source(
+            r#"
+            global rule tiny { condition: filesize < 1 }
+            rule qux { condition: true }
+            "#,
+        )
+        .unwrap()
+        .new_namespace("unaffected")
+        .add_source(r#"rule quux { condition: true }"#)
+        .unwrap();
+
+    let rules = compiler.build();
+    let mut scanner = Scanner::new(&rules);
+    let results = scanner.scan(b"foo").unwrap();
+
+    let suppressions: Vec<_> = results
+        .suppressions()
+        .map(|s| {
+            (s.global_rule().identifier(), s.namespace(), s.suppressed_rules())
+        })
+        .collect();
+
+    // `foo` and `bar` matched before `small` was evaluated, and `baz` was
+    // not evaluated.
+    assert_eq!(suppressions, [("small", "default", 3), ("tiny", "other", 1)]);
+
+    assert_eq!(
+        results.matching_rules().map(|r| r.identifier()).collect::<Vec<_>>(),
+        ["quux"]
+    );
+
+    // Suppressions are not carried over to the next scan.
+    let results = scanner.scan(b"").unwrap();
+    assert_eq!(results.suppressions().len(), 0);
+}
+
+#[test]
+fn scan_vectored() {
+    let rules = crate::compile(
+        r#"
+        rule test {
+          strings:
+            $a = "foobar"
+          condition:
+            $a and filesize == 12
+        }
+        "#,
+    )
+    .unwrap();
+
+    let mut scanner = Scanner::new(&rules);
+
+    // The pattern is split across the two slices.
+    let results = scanner.scan_vectored(&[b"...foo", b"bar..."]).unwrap();
+    let rule = results.matching_rules().next().unwrap();
+    let pattern = rule.patterns().next().unwrap();
+    let matches: Vec<_> = pattern.matches().map(|m| m.range()).collect();
+
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0], 3..9);
+
+    // Empty slices don't affect the offsets.
+    let results =
+        scanner.scan_vectored(&[b"", b"......", b"", b"foobar"]).unwrap();
+    let rule = results.matching_rules().next().unwrap();
+    let pattern = rule.patterns().next().unwrap();
+    let matches: Vec<_> = pattern.matches().map(|m| m.range()).collect();
+
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0], 6..12);
+
+    let results = scanner.scan_vectored(&[b"", b"foobarfoobar"]).unwrap();
+    assert_eq!(results.len(), 1);
+
+    let results = scanner.scan_vectored(&[]).unwrap();
+    assert!(results.is_empty());
+}
+
+#[test]
+fn scan_dir() {
+    #[derive(Default)]
+    struct CountingSink {
+        matches: Vec<String>,
+        finished: bool,
+    }
+
+    impl OutputSink for CountingSink {
+        fn on_match(&mut self, result: &FileResult) -> std::io::Result<()> {
+            let name = result.path().file_name().unwrap();
+            self.matches.push(name.to_string_lossy().into_owned());
+            Ok(())
+        }
+
+        fn finish(&mut self) -> std::io::Result<()> {
+            self.finished = true;
+            Ok(())
+        }
+    }
+
+    let rules = crate::compile(
+        r#"
+        rule foo { strings: $a = "foo" condition: $a }
+        rule bar { strings: $a = "bar" condition: $a }
+        "#,
+    )
+    .unwrap();
+
+    let dir = std::env::temp_dir()
+        .join(format!("yara-x-scan-dir-{}", std::process::id()));
+
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("a"), "foobar").unwrap();
+    std::fs::write(dir.join("b"), "baz").unwrap();
+    std::fs::write(dir.join("c"), "bar").unwrap();
+    std::fs::write(dir.join("sub").join("d"), "foo").unwrap();
+
+    let mut scanner = Scanner::new(&rules);
+    let mut sink = CountingSink::default();
+
+    scanner.scan_dir(&dir, &mut sink).unwrap();
+
+    // One call per matching file, even if multiple rules matched.
+    assert_eq!(sink.matches, ["a", "c", "d"]);
+    assert!(sink.finished);
+
+    let mut output = Vec::new();
+    scanner
+        .scan_dir(dir.join("sub"), &mut NdjsonSink::new(&mut output))
+        .unwrap();
+
+    let record: serde_json::Value =
+        serde_json::from_slice(output.as_slice()).unwrap();
+
+    assert_eq!(record["path"], dir.join("sub").join("d").to_str().unwrap());
+    assert_eq!(record["rules"][0]["identifier"], "foo");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fingerprint() {
+    let rules = crate::compile(
+        r#"
+        rule foo { strings: $a = "foo" condition: $a }
+        rule bar { strings: $a = "bar" condition: $a }
+        private rule baz { strings: $a = "baz" condition: $a }
+        "#,
+    )
+    .unwrap();
+
+    let mut scanner = Scanner::new(&rules);
+
+    let fingerprint = scanner.scan(b"foo bar").unwrap().fingerprint();
+
+    // Same rules and data, same fingerprint, even with a different scanner.
+    assert_eq!(scanner.scan(b"foo bar").unwrap().fingerprint(), fingerprint);
+    assert_eq!(
+        Scanner::new(&rules).scan(b"foo bar").unwrap().fingerprint(),
+        fingerprint
+    );
+
+    // Private rules are not taken into account.
+    assert_eq!(
+        scanner.scan(b"foo bar baz").unwrap().fingerprint(),
+        fingerprint
+    );
+
+    // The same rules match, but at different offsets.
+    assert_ne!(scanner.scan(b"foo  bar").unwrap().fingerprint(), fingerprint);
+
+    // The same rules match, but some pattern matches more times.
+    assert_ne!(
+        scanner.scan(b"foo foo bar").unwrap().fingerprint(),
+        fingerprint
+    );
+
+    // Some other rules match.
+    assert_ne!(scanner.scan(b"foo").unwrap().fingerprint(), fingerprint);
+    assert_ne!(
+        scanner.scan(b"").unwrap().fingerprint(),
+        scanner.scan(b"foo").unwrap().fingerprint()
+    );
+}
+
+#[test]
+#[cfg(feature = "pe-module")]
+fn prefetch_modules() {
+    let rules = crate::compile(
+        r#"
+import "pe"
+rule mz {
+  strings:
+    $mz = "MZ"
+  condition:
+    $mz at 0
+}
+rule dll {
+  strings:
+    $a = "This program cannot be run in DOS mode"
+  condition:
+    $a and pe.is_dll()
+}
+rule sections {
+  condition:
+    for any section in pe.sections : (section.name == ".text")
+}
+rule not_pe {
+  condition:
+    not pe.is_pe
+}
+"#,
+    )
+    .unwrap();
+
+    let mut scanner = Scanner::new(&rules);
+    let mut prefetching_scanner = Scanner::new(&rules);
+    prefetching_scanner.prefetch_modules(true);
+
+    let mut paths: Vec<_> = std::fs::read_dir("src/modules/pe/tests/testdata")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.to_string_lossy().ends_with(".in.zip"))
+        .collect();
+
+    paths.sort();
+
+    let mut files: Vec<_> = paths
+        .iter()
+        .map(crate::modules::tests::create_binary_from_zipped_ihex)
+        .collect();
+
+    files.push(b"not a PE file".to_vec());
+
+    let mut matches = 0;
+
+    for data in files.iter() {
+        let results = scanner.scan(data).unwrap();
+        let prefetched_results = prefetching_scanner.scan(data).unwrap();
+
+        assert_eq!(results.fingerprint(), prefetched_results.fingerprint());
+        // Compared with reflection, because the serialized outputs can
+        // differ in the order of map entries.
+        let pe = results.module_output("pe").unwrap();
+        let prefetched_pe = prefetched_results.module_output("pe").unwrap();
+        assert!(pe.descriptor_dyn().eq(pe, prefetched_pe));
+
+        matches += results.matching_rules().len();
+    }
+
+    assert!(matches > files.len());
+
+    // The output provided by the user is used instead of the one produced
+    // by the module.
+    let mut pe = crate::modules::protos::pe::PE::new();
+    pe.set_is_pe(false);
+
+    prefetching_scanner.set_module_output(Box::new(pe)).unwrap();
+
+    let results = prefetching_scanner.scan(&files[0]).unwrap();
+    assert!(results.rule_matched("not_pe"));
+    assert!(results.rule_matched("mz"));
+}
+
+#[cfg(feature = "async")]
+async fn next_rule(
+    stream: &mut crate::ScanStream,
+) -> Option<Result<crate::OwnedRule, ScanError>> {
+    use futures_core::Stream;
+    std::future::poll_fn(|cx| std::pin::Pin::new(&mut *stream).poll_next(cx))
+        .await
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn scan_stream_async() {
+    let rules = std::sync::Arc::new(
+        crate::compile(
+            r#"
+rule foo { strings: $a = "foo" condition: $a }
+rule bar { strings: $a = "bar" condition: #a == 2 }
+rule baz { strings: $a = "baz" condition: $a }
+private rule hidden { strings: $a = "foo" condition: $a }
+rule filesize_rule { condition: filesize > 0 }
+"#,
+        )
+        .unwrap(),
+    );
+
+    let data = b"foo bar baz bar";
+
+    let mut stream = Scanner::scan_stream_async(rules.clone(), data);
+    let mut streamed = Vec::new();
+
+    while let Some(rule) = next_rule(&mut stream).await {
+        streamed.push(rule.unwrap());
+    }
+
+    let mut scanner = Scanner::new(&rules);
+    let expected: Vec<_> = scanner
+        .scan(data)
+        .unwrap()
+        .matching_rules()
+        .map(|rule| crate::OwnedRule::from(&rule))
+        .collect();
+
+    assert_eq!(expected.len(), 4);
+    assert_eq!(streamed, expected);
+    assert_eq!(streamed[1].patterns()[0].matches(), &[4..7, 12..15]);
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn scan_stream_async_drop() {
+    let mut source = String::new();
+
+    for i in 0..1000 {
+        source.push_str(&format!(
+            "rule rule_{i} {{ strings: $a = \"foo\" condition: $a }}\n"
+        ));
+    }
+
+    let rules = std::sync::Arc::new(crate::compile(source.as_str()).unwrap());
+
+    // Receive only the first rule, and drop the stream while the blocking
+    // task still has many rules to send.
+    let mut stream = Scanner::scan_stream_async(rules.clone(), b"foo");
+    let first = next_rule(&mut stream).await.unwrap().unwrap();
+    assert_eq!(first.identifier(), "rule_0");
+    drop(stream);
+
+    // The blocking task doesn't wait forever for the stream to receive the
+    // rules, it releases its reference to the rules when it finishes.
+    let start = std::time::Instant::now();
+    while std::sync::Arc::strong_count(&rules) > 1 {
+        assert!(start.elapsed() < Duration::from_secs(60));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    // Once the abort flag is set, scans end as if the timeout was reached.
+    let abort = std::sync::Arc::new(AtomicBool::new(false));
+    let mut scanner = Scanner::new(&rules);
+    scanner.abort_on(abort.clone());
+
+    assert_eq!(scanner.scan(b"foo").unwrap().matching_rules().len(), 1000);
+    abort.store(true, Ordering::Relaxed);
+    assert!(matches!(scanner.scan(b"foo"), Err(ScanError::Timeout)));
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn scan_stream_async_cancel() {
+    let rules = std::sync::Arc::new(
+        crate::compile(
+            r#"
+rule first { condition: true }
+rule slow {
+  condition:
+    for any i in (0..10000000000000) : (uint8(i % filesize) == 0xFF)
+}
+"#,
+        )
+        .unwrap(),
+    );
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+
+    let (first, stopped) = runtime.block_on(async {
+        // The first rule is received while the condition of the second one
+        // is still being evaluated, which would take forever.
+        let mut stream = Scanner::scan_stream_async(rules.clone(), b"foo");
+        let first = tokio::time::timeout(
+            Duration::from_secs(60),
+            next_rule(&mut stream),
+        )
+        .await;
+
+        drop(stream);
+
+        // Dropping the stream stops the scan, and the blocking task
+        // releases its reference to the rules.
+        let start = std::time::Instant::now();
+        while std::sync::Arc::strong_count(&rules) > 1
+            && start.elapsed() < Duration::from_secs(60)
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        (first, std::sync::Arc::strong_count(&rules) == 1)
+    });
+
+    // Don't wait for the blocking task if the scan is still running.
+    runtime.shutdown_background();
+
+    let first = first
+        .expect("the first rule must be received before the scan ends")
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(first.identifier(), "first");
+    assert!(stopped, "the scan didn't stop after dropping the stream");
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn scan_stream_async_global_rules() {
+    let mut compiler = crate::Compiler::new();
+
+    compiler
+        .add_source(
+            r#"
+rule a { condition: true }
+global rule global_a { condition: filesize > 0 }
+rule b { condition: true }
+"#,
+        )
+        .unwrap()
+        .new_namespace("other")
+        .add_source(
+            r#"
+rule c { condition: true }
+global rule global_c { condition: filesize > 100 }
+rule d { condition: true }
+"#,
+        )
+        .unwrap();
+
+    let rules = std::sync::Arc::new(compiler.build());
+    let mut stream = Scanner::scan_stream_async(rules.clone(), b"foo");
+    let mut streamed = Vec::new();
+
+    while let Some(rule) = next_rule(&mut stream).await {
+        streamed.push(rule.unwrap().identifier().to_string());
+    }
+
+    // The rules in the second namespace are discarded by the global rule
+    // that doesn't match, so they are not sent to the stream.
+    assert_eq!(streamed, ["a", "global_a", "b"]);
+}
+
+#[test]
+#[cfg(all(feature = "hash-module", feature = "pe-module"))]
+fn prefetch_modules_with_thread_local_state() {
+    let rules = crate::compile(
+        r#"
+import "hash"
+import "pe"
+rule foo {
+  strings:
+    $a = "foo"
+  condition:
+    $a and not pe.is_pe and
+    hash.md5(0, filesize) == "acbd18db4cc2f85cedef654fccc4a4d8"
+}
+rule bar {
+  strings:
+    $a = "bar"
+  condition:
+    $a and not pe.is_pe and
+    hash.md5(0, filesize) == "37b51d194a7513e45b56f6524f2d51f2"
+}
+"#,
+    )
+    .unwrap();
+
+    let mut scanner = Scanner::new(&rules);
+    scanner.prefetch_modules(true);
+
+    // The hashes computed while scanning the first file must not be used
+    // for the second one.
+    let results = scanner.scan(b"foo").unwrap();
+    assert!(results.rule_matched("foo"));
+
+    let results = scanner.scan(b"bar").unwrap();
+    assert!(!results.rule_matched("foo"));
+    assert!(results.rule_matched("bar"));
+}
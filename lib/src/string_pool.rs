@@ -62,6 +62,15 @@ where
     pub fn get(&self, id: T) -> Option<&str> {
         self.pool.get(Symbol::from(id.into()))
     }
+
+    /// Interns all the strings in `other`, and returns a vector where the
+    /// N-th item is the ID that the string with ID = N in `other` has in
+    /// this pool.
+    pub fn merge(&mut self, other: &Self) -> Vec<T> {
+        (0..other.pool.len() as u32)
+            .map(|id| self.get_or_intern(other.get(T::from(id)).unwrap()))
+            .collect()
+    }
 }
 
 impl<T> Serialize for StringPool<T>
@@ -197,6 +206,17 @@ where
                     .expect("using BStringPool::get_str with a string that is not valid UTF-8")
             })
     }
+
+    /// Interns all the strings in `other`, and returns a vector where the
+    /// N-th item is the ID that the string with ID = N in `other` has in
+    /// this pool.
+    pub fn merge(&mut self, other: &Self) -> Vec<T> {
+        (0..other.pool.len() as u32)
+            .map(|id| {
+                self.get_or_intern(other.get_bytes(T::from(id)).unwrap())
+            })
+            .collect()
+    }
 }
 
 impl<T> Serialize for BStringPool<T>
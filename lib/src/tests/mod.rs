@@ -203,6 +203,11 @@ fn arithmetic_operations() {
     condition_true!("-0o10 == -8");
     condition_true!("0o100 == 64");
     condition_true!("0o755 == 493");
+    condition_true!("1KB == 1024");
+    condition_true!("5MB == 5242880");
+    condition_true!("16GB == 17179869184");
+    condition_true!("8589934591GB == 9223372035781033984");
+    condition_true!("-2GB == -2147483648");
     condition_true!("1 + 2 + 3 == 6");
     condition_true!("2 - 1 - 1 == 0");
     condition_true!("2 * 3 * 4 == 24");
@@ -303,6 +308,52 @@ fn string_operations() {
     condition_true!(r#""🙈🙉🙊" matches /(?u)^...$/"#);
 }
 
+#[test]
+fn data_slices() {
+    condition_true!(r#"(0, 4) matches /^MZ\x90\x00$/"#, b"MZ\x90\x00foo");
+    condition_false!(r#"(0, 4) matches /foo/"#, b"MZ\x90\x00foo");
+    condition_true!(r#"(4, 3) matches /^foo$/"#, b"MZ\x90\x00foo");
+    condition_true!(r#"(filesize - 3, 3) == "foo""#, b"MZ\x90\x00foo");
+    condition_true!(r#"(0, 0) == """#, b"MZ\x90\x00foo");
+
+    // Slices that are not fully contained in the data are undefined.
+    condition_false!(r#"(4, 4) matches /foo/"#, b"MZ\x90\x00foo");
+    condition_false!(r#"(filesize, 1) matches /.*/"#, b"MZ\x90\x00foo");
+    condition_false!(
+        r#"not defined ((4, 3) matches /foo/)"#,
+        b"MZ\x90\x00foo"
+    );
+    condition_true!(r#"not defined ((4, 4) matches /foo/)"#, b"MZ\x90\x00foo");
+}
+
+#[test]
+fn buffer_checks() {
+    let pe = b"MZ\x90\x00\x03\x00\x00\x00PE\x00\x00";
+
+    condition_true!(r#"buffer startswith { 4D 5A }"#, pe);
+    condition_true!(r#"buffer startswith { 4D 5A 90 00 03 }"#, pe);
+    condition_true!(r#"buffer startswith { 4D ?? 9? ~01 }"#, pe);
+    condition_true!(r#"buffer endswith { 50 45 00 00 }"#, pe);
+    condition_true!(r#"buffer endswith { 5? ?5 ~FF 00 }"#, pe);
+    condition_false!(r#"buffer startswith { 7F 45 4C 46 }"#, pe);
+    condition_false!(r#"buffer startswith { 4D ?? 8? }"#, pe);
+    condition_false!(r#"buffer startswith { 4D 5A ~90 }"#, pe);
+    condition_false!(r#"buffer endswith { 4D 5A }"#, pe);
+
+    // The data must be long enough for containing the whole pattern, even
+    // if the pattern ends with wildcards.
+    condition_true!(r#"buffer startswith { 4D 5A ?? }"#, b"MZ\x00");
+    condition_false!(r#"buffer startswith { 4D 5A ?? }"#, b"MZ");
+    condition_false!(r#"buffer endswith { ?? 4D 5A }"#, b"MZ");
+    condition_false!(r#"buffer startswith { 4D 5A }"#, b"");
+
+    condition_true!(
+        r#"buffer startswith { 4D 5A } and filesize == 3"#,
+        b"MZ!"
+    );
+    condition_true!(r#"not buffer endswith { 4D 5A }"#, b"MZ!");
+}
+
 #[test]
 fn boolean_operations() {
     condition_true!("true");
@@ -414,6 +465,69 @@ fn intxx() {
     condition_false!("int32(17) != 0", &data);
 }
 
+#[test]
+fn intxx_byte_order() {
+    let data = [
+        0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    ];
+
+    condition_true!("int8(0, LITTLE_ENDIAN) == int8(0)", &data);
+    condition_true!("int8(0, BIG_ENDIAN) == int8be(0)", &data);
+    condition_true!("int8(1, LITTLE_ENDIAN) == int8(1)", &data);
+    condition_true!("int8(1, BIG_ENDIAN) == int8be(1)", &data);
+    condition_true!("int8(10, LITTLE_ENDIAN) == int8(10)", &data);
+    condition_true!("int8(10, BIG_ENDIAN) == int8be(10)", &data);
+
+    condition_true!("int16(0, LITTLE_ENDIAN) == int16(0)", &data);
+    condition_true!("int16(0, BIG_ENDIAN) == int16be(0)", &data);
+    condition_true!("int16(1, LITTLE_ENDIAN) == int16(1)", &data);
+    condition_true!("int16(1, BIG_ENDIAN) == int16be(1)", &data);
+    condition_true!("int16(10, LITTLE_ENDIAN) == int16(10)", &data);
+    condition_true!("int16(10, BIG_ENDIAN) == int16be(10)", &data);
+
+    condition_true!("int32(0, LITTLE_ENDIAN) == int32(0)", &data);
+    condition_true!("int32(0, BIG_ENDIAN) == int32be(0)", &data);
+    condition_true!("int32(1, LITTLE_ENDIAN) == int32(1)", &data);
+    condition_true!("int32(1, BIG_ENDIAN) == int32be(1)", &data);
+    condition_true!("int32(10, LITTLE_ENDIAN) == int32(10)", &data);
+    condition_true!("int32(10, BIG_ENDIAN) == int32be(10)", &data);
+
+    condition_true!("uint8(0, LITTLE_ENDIAN) == uint8(0)", &data);
+    condition_true!("uint8(0, BIG_ENDIAN) == uint8be(0)", &data);
+    condition_true!("uint8(1, LITTLE_ENDIAN) == uint8(1)", &data);
+    condition_true!("uint8(1, BIG_ENDIAN) == uint8be(1)", &data);
+    condition_true!("uint8(10, LITTLE_ENDIAN) == uint8(10)", &data);
+    condition_true!("uint8(10, BIG_ENDIAN) == uint8be(10)", &data);
+
+    condition_true!("uint16(0, LITTLE_ENDIAN) == uint16(0)", &data);
+    condition_true!("uint16(0, BIG_ENDIAN) == uint16be(0)", &data);
+    condition_true!("uint16(1, LITTLE_ENDIAN) == uint16(1)", &data);
+    condition_true!("uint16(1, BIG_ENDIAN) == uint16be(1)", &data);
+    condition_true!("uint16(10, LITTLE_ENDIAN) == uint16(10)", &data);
+    condition_true!("uint16(10, BIG_ENDIAN) == uint16be(10)", &data);
+
+    condition_true!("uint32(0, LITTLE_ENDIAN) == uint32(0)", &data);
+    condition_true!("uint32(0, BIG_ENDIAN) == uint32be(0)", &data);
+    condition_true!("uint32(1, LITTLE_ENDIAN) == uint32(1)", &data);
+    condition_true!("uint32(1, BIG_ENDIAN) == uint32be(1)", &data);
+    condition_true!("uint32(10, LITTLE_ENDIAN) == uint32(10)", &data);
+    condition_true!("uint32(10, BIG_ENDIAN) == uint32be(10)", &data);
+
+    condition_true!("uint32(0, BIG_ENDIAN) == 0x01020304", &data);
+    condition_true!("uint16(1, LITTLE_ENDIAN) == 0x0302", &data);
+    condition_true!("int16(10, BIG_ENDIAN) == -1", &data);
+    condition_true!(
+        "uint32(uint8(0, BIG_ENDIAN), BIG_ENDIAN) == 0x02030405",
+        &data
+    );
+
+    condition_false!("uint32(17, BIG_ENDIAN) == 0", &data);
+    condition_false!("uint32(17, BIG_ENDIAN) != 0", &data);
+    condition_false!("int16(19, LITTLE_ENDIAN) == 0", &data);
+    condition_false!("int16(19, LITTLE_ENDIAN) != 0", &data);
+}
+
 #[test]
 fn for_in() {
     condition_true!("for any i in (0..1): ( 1 )");
@@ -1444,6 +1558,33 @@ fn regexp_patterns_4() {
     );
 }
 
+#[test]
+fn regexp_word_boundaries() {
+    pattern_match!(r"/\bcmd\b/", b"cmd ", b"cmd");
+    pattern_match!(r"/\bcmd\b/", b" cmd", b"cmd");
+    pattern_match!(r"/\bcmd\b/", b"cmd", b"cmd");
+    pattern_match!(r"/\bcmd\b/", b"run cmd.exe", b"cmd");
+    pattern_false!(r"/\bcmd\b/", b"command");
+    pattern_false!(r"/\bcmd\b/", b"xcmd");
+    pattern_false!(r"/\bcmd\b/", b"cmd1");
+    pattern_match!(r"/\Bcmd\B/", b"xcmdx", b"cmd");
+    pattern_false!(r"/\Bcmd\B/", b"cmd");
+    pattern_false!(r"/\Bcmd\B/", b" cmd ");
+
+    // Word characters are ASCII letters, digits and underscore. Any other
+    // byte, including non-ASCII ones, is a non-word character.
+    pattern_match!(r"/\bcmd\b/", b"\xe9cmd\xe9", b"cmd");
+    pattern_match!(r"/\bcmd\b/", b"\x00cmd\xff", b"cmd");
+    pattern_false!(r"/\Bcmd/", b"\xe9cmd");
+
+    // The same semantics apply to regular expressions used in conditions.
+    condition_true!(r#""cmd" matches /\bcmd\b/"#);
+    condition_true!(r#""\xe9cmd" matches /\bcmd\b/"#);
+    condition_false!(r#""command" matches /\bcmd\b/"#);
+    condition_false!(r#""xcmd" matches /\bcmd\b/"#);
+    condition_true!(r#""xcmdx" matches /\Bcmd\B/"#);
+}
+
 #[test]
 fn regexp_patterns_5() {
     rule_true!(
@@ -2183,6 +2324,54 @@ fn match_count() {
     );
 }
 
+#[test]
+fn match_count_in_range() {
+    let data = b"foo..foofoo....foo.......foo.fo.foo..........foo";
+
+    // Count the matches in each range by brute force, and compare with
+    // the result of `#a in (lower..upper)`.
+    for (lower, upper) in
+        [(0, 0), (0, 10), (3, 8), (5, 28), (6, 32), (33, 33), (100, 200)]
+    {
+        let expected = data
+            .windows(3)
+            .enumerate()
+            .filter(|(offset, window)| {
+                *window == b"foo" && (lower..=upper).contains(offset)
+            })
+            .count();
+
+        rule_true!(
+            format!(
+                r#"
+                rule test {{
+                    strings:
+                        $a = "foo"
+                    condition:
+                        #a in ({lower}..{upper}) == {expected}
+                }}
+                "#
+            )
+            .as_str(),
+            data
+        );
+    }
+
+    // When the lower bound is greater than the upper bound the result is
+    // zero.
+    rule_true!(
+        r#"
+        rule test {
+            strings:
+                $a = "foo"
+            condition:
+                #a in (filesize..0) == 0
+        }
+        "#,
+        data
+    );
+}
+
 #[test]
 fn match_offset() {
     rule_true!(
@@ -2295,6 +2484,84 @@ fn match_offset() {
     );
 }
 
+#[test]
+fn match_offset_arithmetic() {
+    rule_true!(
+        r#"
+        rule test {
+            strings:
+                $a = "foo"
+            condition:
+                uint32(@a + 3) == 0x64636261 and
+                uint8(@a[2] + 4) == 0x31 and
+                @a[2] - @a == 8 and
+                (@a + 3) * 2 == 6
+        }
+        "#,
+        b"fooabcd fooX1"
+    );
+
+    // The results are undefined when the match doesn't exist, or when the
+    // read goes beyond the end of the data.
+    rule_true!(
+        r#"
+        rule test {
+            strings:
+                $a = "foo"
+                $b = "bar"
+            condition:
+                not defined uint32(@b + 4) and
+                not defined (@a[3] + 4) and
+                not defined uint32(@a[2] + 4)
+        }
+        "#,
+        b"fooabcd fooX1"
+    );
+}
+
+#[test]
+fn match_offset_int_read() {
+    rule_true!(
+        r#"
+        rule test {
+            strings:
+                $a = "MARK"
+            condition:
+                uint32(@a + 8) == 0x1234 and
+                uint16be(@a[2] + 8) == 0xABCD
+        }
+        "#,
+        b"xxMARK....\x34\x12\x00\x00..MARK....\xAB\xCD"
+    );
+
+    // When the pattern doesn't match, `@a` is undefined and so is the
+    // whole expression.
+    rule_false!(
+        r#"
+        rule test {
+            strings:
+                $a = "MARK"
+            condition:
+                uint32(@a + 8) == 0x1234
+        }
+        "#,
+        b"xxMAR.....\x34\x12\x00\x00"
+    );
+
+    rule_true!(
+        r#"
+        rule test {
+            strings:
+                $a = "MARK"
+            condition:
+                not defined uint32(@a + 8) and
+                not defined (uint32(@a + 8) == 0x1234)
+        }
+        "#,
+        b"xxMAR.....\x34\x12\x00\x00"
+    );
+}
+
 #[test]
 fn match_length() {
     rule_true!(
@@ -2968,6 +3235,51 @@ fn filesize() {
     );
 }
 
+#[test]
+#[cfg(all(feature = "pe-module", feature = "elf-module"))]
+fn entrypoint() {
+    let pe = crate::modules::tests::create_binary_from_zipped_ihex(
+        "src/modules/pe/tests/testdata/00a1067fc96eb2c1d440bb5b44b32f43b9900fdd3a65c985d65a63b8f1535ef5.in.zip",
+    );
+
+    let elf = crate::modules::tests::create_binary_from_zipped_ihex(
+        "src/modules/elf/tests/testdata/f040356a60cde1047266c841237b03e5d157c0628f5a348e64f63962265d1434.in.zip",
+    );
+
+    rule_true!(r#"rule test { condition: entrypoint == 2376 }"#, &pe);
+    rule_true!(r#"rule test { condition: entrypoint == 4348 }"#, &elf);
+
+    // The result is the same when the modules are imported.
+    rule_true!(
+        r#"
+        import "pe"
+        rule test { condition: entrypoint == pe.entry_point }"#,
+        &pe
+    );
+
+    rule_true!(
+        r#"
+        import "elf"
+        rule test { condition: entrypoint == elf.entry_point }"#,
+        &elf
+    );
+
+    rule_true!(
+        r#"
+        rule test {
+          strings:
+            $a = { 48 83 EC 28 E8 }
+          condition:
+            $a at entrypoint
+        }"#,
+        &pe
+    );
+
+    // For files that are not PE nor ELF `entrypoint` is undefined.
+    rule_true!(r#"rule test { condition: not defined entrypoint }"#, b"foo");
+    rule_false!(r#"rule test { condition: entrypoint >= 0 }"#, b"foo");
+}
+
 #[test]
 fn for_of() {
     rule_true!(
@@ -3143,6 +3455,102 @@ fn of() {
     );
 }
 
+#[test]
+fn of_in_range() {
+    rule_true!(
+        r#"
+        rule test {
+          strings:
+            $a = "foo"
+            $b = "bar"
+          condition:
+            all of ($a, $b) in (0..50)
+        }
+        "#,
+        b"foo--bar"
+    );
+
+    rule_false!(
+        r#"
+        rule test {
+          strings:
+            $a = "foo"
+            $b = "bar"
+          condition:
+            all of ($a, $b) in (0..50)
+        }
+        "#,
+        &[b"foo".as_slice(), &[b'-'; 60], b"bar"].concat()
+    );
+
+    // Only the matches inside the range count, even if the pattern also
+    // matches somewhere else.
+    rule_true!(
+        r#"
+        rule test {
+          strings:
+            $a = "foo"
+            $b = "bar"
+            $c = "baz"
+          condition:
+            2 of them in (10..20) and not all of them in (10..20)
+        }
+        "#,
+        b"baz-------foo--bar"
+    );
+
+    rule_true!(
+        r#"
+        rule test {
+          strings:
+            $a = "foo"
+            $b = "bar"
+          condition:
+            none of them in (3..10)
+        }
+        "#,
+        b"foo---------bar"
+    );
+
+    // An undefined bound makes the whole expression undefined, so both the
+    // expression and its negation are false.
+    rule_false!(
+        r#"
+        rule test {
+          strings:
+            $a = "foo"
+          condition:
+            any of them in (0..uint32(100))
+        }
+        "#,
+        b"foo"
+    );
+
+    rule_false!(
+        r#"
+        rule test {
+          strings:
+            $a = "foo"
+          condition:
+            not any of them in (0..uint32(100))
+        }
+        "#,
+        b"foo"
+    );
+
+    rule_false!(
+        r#"
+        rule test {
+          strings:
+            $a = "foo"
+          condition:
+            not any of them at uint32(100)
+        }
+        "#,
+        b"foo"
+    );
+}
+
 #[test]
 fn rule_reuse_1() {
     let rules = crate::compile(
@@ -3334,3 +3742,36 @@ fn test_defined_3() {
     condition_true!(r#"not test_proto3.bool_undef"#);
     condition_true!(r#"test_proto3.string_undef == """#);
 }
+
+#[test]
+#[cfg(feature = "test_proto2-module")]
+fn string_equality() {
+    condition_true!(r#"test_proto2.string_foo == "foo""#);
+    condition_false!(r#"test_proto2.string_foo != "foo""#);
+    condition_true!(r#"test_proto2.string_foo != "bar""#);
+    condition_true!(r#"test_proto2.string_foo == test_proto2.string_foo"#);
+    condition_true!(r#"test_proto2.string_foo != test_proto2.string_bar"#);
+
+    // Strings with a common prefix but different lengths are not equal.
+    condition_false!(r#"test_proto2.string_foo == "fo""#);
+    condition_false!(r#"test_proto2.string_foo == "foo\x00""#);
+    condition_true!(r#"test_proto2.string_foo != "foobar""#);
+
+    // The comparison is case-sensitive.
+    condition_false!(r#"test_proto2.string_foo == "FOO""#);
+    condition_true!(r#"test_proto2.string_foo iequals "FOO""#);
+
+    // Slices of the scanned data can be compared with module strings.
+    condition_true!(r#"(0, 3) == test_proto2.string_foo"#, b"foobar");
+    condition_true!(r#"(3, 3) != test_proto2.string_foo"#, b"foobar");
+
+    // If any of the operands is undefined, the result is undefined.
+    condition_false!(r#"test_proto2.string_undef == "foo""#);
+    condition_false!(r#"test_proto2.string_undef != "foo""#);
+    condition_false!(
+        r#"test_proto2.string_undef == test_proto2.string_undef"#
+    );
+    condition_false!(r#"(0, 4) != test_proto2.string_foo"#, b"foo");
+    condition_true!(r#"not defined (test_proto2.string_undef == "")"#);
+    condition_true!(r#"not defined ((0, 4) == "foo")"#, b"foo");
+}
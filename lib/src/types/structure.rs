@@ -175,6 +175,13 @@ impl Struct {
         self.field_by_name(name).is_some()
     }
 
+    /// Returns an iterator that yields the name and the value of each field,
+    /// in insertion order.
+    #[inline]
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &StructField)> {
+        self.fields.iter().map(|(name, field)| (name.as_str(), field))
+    }
+
     /// Get a field by index.
     #[inline]
     pub fn field_by_index(&self, index: usize) -> Option<&StructField> {
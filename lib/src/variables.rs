@@ -70,6 +70,45 @@ pub enum VariableError {
     },
 }
 
+/// Type of a variable.
+///
+/// This is the type of the value passed to
+/// [`crate::Compiler::define_global`]. Values passed to
+/// [`crate::Scanner::set_global`] for the same variable must have the same
+/// type.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ValueType {
+    /// Boolean variable.
+    Bool,
+    /// Integer variable.
+    Integer,
+    /// Float variable.
+    Float,
+    /// String variable.
+    String,
+    /// Structure, created from a JSON object.
+    Struct,
+    /// Array, created from a JSON array.
+    Array,
+}
+
+impl ValueType {
+    pub(crate) fn from_type_value(type_value: &TypeValue) -> Self {
+        match type_value {
+            TypeValue::Bool(_) => Self::Bool,
+            TypeValue::Integer(_) => Self::Integer,
+            TypeValue::Float(_) => Self::Float,
+            TypeValue::String(_) => Self::String,
+            TypeValue::Struct(_) => Self::Struct,
+            TypeValue::Array(_) => Self::Array,
+            _ => unreachable!(
+                "unexpected type for a variable: {:?}",
+                type_value
+            ),
+        }
+    }
+}
+
 impl TryFrom<bool> for Variable {
     type Error = VariableError;
     fn try_from(value: bool) -> Result<Self, Self::Error> {
@@ -2,8 +2,11 @@ use crate::compiler::RuleId;
 use crate::wasm;
 use rustc_hash::FxHashMap;
 use std::mem;
-use walrus::ir::{Block, InstrSeqId, UnaryOp};
-use walrus::ValType::{F64, I32, I64};
+use walrus::ir::{
+    dfs_pre_order_mut, Block, Call, Instr, InstrSeq, InstrSeqId, UnaryOp,
+    VisitorMut,
+};
+use walrus::ValType::{F32, F64, I32, I64};
 use walrus::{FunctionBuilder, FunctionId, InstrSeqBuilder};
 
 use super::WasmSymbols;
@@ -127,6 +130,7 @@ pub(crate) struct WasmModuleBuilder {
     namespaces_per_func: usize,
     rules_per_func: usize,
     global_rule: bool,
+    minimal_features: bool,
 }
 
 impl WasmModuleBuilder {
@@ -156,6 +160,7 @@ impl WasmModuleBuilder {
         global_var!(module, filesize, I64);
         global_var!(module, pattern_search_done, I32);
         global_var!(module, timeout_occurred, I32);
+        global_var!(module, current_rule, I32);
 
         let (main_memory, _) = module.add_import_memory(
             "yara_x",
@@ -173,6 +178,7 @@ impl WasmModuleBuilder {
             filesize,
             pattern_search_done,
             timeout_occurred,
+            current_rule,
             i64_tmp_a: module.locals.add(I64),
             i64_tmp_b: module.locals.add(I64),
             i32_tmp: module.locals.add(I32),
@@ -199,6 +205,10 @@ impl WasmModuleBuilder {
         main_func.func_body().i32_const(0);
         main_func.func_body().global_set(timeout_occurred);
 
+        // `current_rule` is set to -1 until the first rule is evaluated.
+        main_func.func_body().i32_const(-1);
+        main_func.func_body().global_set(current_rule);
+
         let namespace_block = namespace_func.dangling_instr_seq(None).id();
 
         Self {
@@ -215,6 +225,7 @@ impl WasmModuleBuilder {
             namespaces_per_func: 10,
             rules_per_func: 10,
             global_rule: false,
+            minimal_features: false,
         }
     }
 
@@ -236,6 +247,13 @@ impl WasmModuleBuilder {
         self
     }
 
+    /// Restricts the module to the minimal set of WebAssembly features
+    /// described in [`crate::Compiler::minimal_wasm_features`].
+    pub fn minimal_features(&mut self, yes: bool) -> &mut Self {
+        self.minimal_features = yes;
+        self
+    }
+
     /// Configure the number of YARA rules that will be put in each WASM
     /// function.
     pub fn rules_per_func(&mut self, n: usize) -> &mut Self {
@@ -261,7 +279,12 @@ impl WasmModuleBuilder {
         self.rule_id = rule_id;
         self.global_rule = global;
 
-        self.rules_func.func_body()
+        // Record the rule being evaluated, so that the scanner can tell which
+        // rule caused an error in case the WASM code traps.
+        let current_rule = self.wasm_symbols.current_rule;
+        let mut instr = self.rules_func.func_body();
+        instr.i32_const(rule_id.into()).global_set(current_rule);
+        instr
     }
 
     /// This finishes the code for a rule.
@@ -339,11 +362,90 @@ impl WasmModuleBuilder {
             self.main_func.finish(Vec::new(), &mut self.module.funcs);
 
         self.module.exports.add("main", main_func);
-        self.module
+
+        if !self.minimal_features {
+            return self.module;
+        }
+
+        Self::lower_multi_value_calls(&mut self.module, &self.wasm_exports);
+
+        // Remove the functions that are not called anymore, together with
+        // their types, which may have multiple results.
+        walrus::passes::gc::run(&mut self.module);
+
+        // The configuration of a `walrus::Module` only has effect while
+        // parsing, so the module is emitted and parsed again with a
+        // configuration that rejects any feature that is not stable, and
+        // that doesn't emit the custom sections added by `walrus`.
+        let mut config = walrus::ModuleConfig::new();
+
+        config
+            .only_stable_features(true)
+            .generate_name_section(false)
+            .generate_producers_section(false);
+
+        config
+            .parse(self.module.emit_wasm().as_slice())
+            .expect("WASM module is not valid")
     }
 }
 
 impl WasmModuleBuilder {
+    /// Replaces the calls to functions that return multiple results with
+    /// calls to their single-result variants, followed by calls to the
+    /// `extra_result_*` functions that retrieve the remaining results. After
+    /// this, the module doesn't rely on the multi-value proposal.
+    fn lower_multi_value_calls(
+        module: &mut walrus::Module,
+        wasm_exports: &FxHashMap<String, FunctionId>,
+    ) {
+        let mut lowered_calls = FxHashMap::default();
+
+        for export in super::WASM_EXPORTS {
+            let results = export.func.walrus_results();
+            if results.len() < 2 {
+                continue;
+            }
+
+            let fully_qualified_name = export.fully_qualified_mangled_name();
+
+            let ty = module
+                .types
+                .add(export.func.walrus_args().as_slice(), &results[..1]);
+
+            let (single_result_func, _) = module.add_import_func(
+                export.single_result_module().as_str(),
+                fully_qualified_name.as_str(),
+                ty,
+            );
+
+            let extra_result_funcs: Vec<_> = results[1..]
+                .iter()
+                .map(|ty| {
+                    wasm_exports[match ty {
+                        I32 => wasm::export__extra_result_i32.mangled_name,
+                        I64 => wasm::export__extra_result_i64.mangled_name,
+                        F32 => wasm::export__extra_result_f32.mangled_name,
+                        F64 => wasm::export__extra_result_f64.mangled_name,
+                        _ => unreachable!("unexpected result type: {:?}", ty),
+                    }]
+                })
+                .collect();
+
+            lowered_calls.insert(
+                wasm_exports[&fully_qualified_name],
+                (single_result_func, extra_result_funcs),
+            );
+        }
+
+        let mut visitor = MultiValueCallLowering { lowered_calls };
+
+        for (_, func) in module.funcs.iter_local_mut() {
+            let entry = func.entry_block();
+            dfs_pre_order_mut(&mut visitor, func, entry);
+        }
+    }
+
     /// Given a function mangled name returns its id.
     ///
     /// # Panics
@@ -421,3 +523,39 @@ impl WasmModuleBuilder {
         }
     }
 }
+
+/// Visitor that replaces calls to functions that return multiple results.
+/// See [`WasmModuleBuilder::lower_multi_value_calls`].
+struct MultiValueCallLowering {
+    /// Keys are the functions that return multiple results, values are their
+    /// single-result variants and the functions that retrieve the remaining
+    /// results.
+    lowered_calls: FxHashMap<FunctionId, (FunctionId, Vec<FunctionId>)>,
+}
+
+impl VisitorMut for MultiValueCallLowering {
+    fn start_instr_seq_mut(&mut self, seq: &mut InstrSeq) {
+        let mut instrs = Vec::with_capacity(seq.instrs.len());
+
+        for (instr, loc) in seq.instrs.drain(..) {
+            let lowered = match &instr {
+                Instr::Call(Call { func }) => self.lowered_calls.get(func),
+                _ => None,
+            };
+            match lowered {
+                Some((single_result_func, extra_result_funcs)) => {
+                    instrs.push((
+                        Instr::Call(Call { func: *single_result_func }),
+                        loc,
+                    ));
+                    for func in extra_result_funcs {
+                        instrs.push((Instr::Call(Call { func: *func }), loc));
+                    }
+                }
+                None => instrs.push((instr, loc)),
+            }
+        }
+
+        seq.instrs = instrs;
+    }
+}
@@ -87,7 +87,8 @@ use linkme::distributed_slice;
 use rustc_hash::FxHashMap;
 use smallvec::{smallvec, SmallVec};
 use wasmtime::{
-    AsContextMut, Caller, Config, Engine, FuncType, Linker, ValRaw,
+    AsContextMut, Caller, Config, Engine, FuncType, Linker, Module, Val,
+    ValRaw, ValType,
 };
 
 use yara_x_macros::wasm_export;
@@ -96,9 +97,10 @@ use crate::compiler::{LiteralId, PatternId, RegexpId, RuleId};
 use crate::modules::BUILTIN_MODULES;
 use crate::scanner::{RuntimeObjectHandle, ScanContext, ScanError};
 use crate::types::{
-    Array, Func, FuncSignature, Map, Struct, TypeValue, Value,
+    Array, Func, FuncSignature, MangledFnName, Map, Struct, TypeValue, Value,
 };
 use crate::wasm::string::RuntimeString;
+use crate::{models, modules};
 
 pub(crate) mod builder;
 pub(crate) mod string;
@@ -118,6 +120,10 @@ pub(crate) const LOOKUP_INDEXES_END: i32 = LOOKUP_INDEXES_START + 1024;
 /// bit is set, it indicates that the rule with RuleId = N matched.
 pub(crate) const MATCHING_RULES_BITMAP_BASE: i32 = LOOKUP_INDEXES_END;
 
+/// Suffix added to the module name of functions that return only the first
+/// of their results. See [`WasmExport::single_result_module`].
+const SINGLE_RESULT_SUFFIX: &str = "#single_result";
+
 /// Global slice that contains an entry for each function that is callable from
 /// WASM code. Functions with attributes `#[wasm_export]` and `#[module_export]`
 /// are automatically added to this slice. See https://github.com/dtolnay/linkme
@@ -154,14 +160,30 @@ impl WasmExport {
     /// The fully qualified name includes not only the function's name, but
     /// also the module's name (e.g: `my_module.my_struct.my_func@ii@i`)
     pub fn fully_qualified_mangled_name(&self) -> String {
-        for (module_name, module) in BUILTIN_MODULES.iter() {
-            if let Some(rust_module_name) = module.rust_module_name {
-                if self.rust_module_path.contains(rust_module_name) {
-                    return format!("{}.{}", module_name, self.mangled_name);
-                }
+        match self.module_name() {
+            Some(module_name) => {
+                format!("{}.{}", module_name, self.mangled_name)
             }
+            None => self.mangled_name.to_owned(),
         }
-        self.mangled_name.to_owned()
+    }
+
+    /// Returns the name of the YARA module that exports this function, or
+    /// `None` if the function is not exported by a module.
+    pub fn module_name(&self) -> Option<&'static str> {
+        BUILTIN_MODULES.iter().find_map(|(module_name, module)| {
+            module
+                .rust_module_name
+                .filter(|name| self.rust_module_path.contains(name))
+                .map(|_| *module_name)
+        })
+    }
+
+    /// Returns the name of the WASM module where the variant of this function
+    /// that returns only its first result is imported from. See
+    /// [`new_linker`].
+    pub fn single_result_module(&self) -> String {
+        format!("{}{}", self.rust_module_path, SINGLE_RESULT_SUFFIX)
     }
 
     /// Returns true if this export comes from YARA itself, not for a YARA
@@ -261,7 +283,7 @@ pub(crate) trait WasmExportedFn {
 }
 
 type TrampolineFn = Box<
-    dyn Fn(Caller<'_, ScanContext>, &mut [ValRaw]) -> anyhow::Result<()>
+    dyn Fn(&mut Caller<'_, ScanContext>, &mut [ValRaw]) -> anyhow::Result<()>
         + Send
         + Sync
         + 'static,
@@ -316,31 +338,50 @@ impl WasmArg<bool> for ValRaw {
     }
 }
 
+// IDs received from a module merged with `Rules::merge` are local to that
+// module, and must be translated to the IDs in the merged rules.
+
 impl WasmArg<RuleId> for ValRaw {
     #[inline]
-    fn raw_into(self, _: &mut ScanContext) -> RuleId {
-        RuleId::from(self.get_i32())
+    fn raw_into(self, ctx: &mut ScanContext) -> RuleId {
+        let rule_id = RuleId::from(self.get_i32());
+        match ctx.merged_module() {
+            Some(module) => module.rule_id(rule_id),
+            None => rule_id,
+        }
     }
 }
 
 impl WasmArg<PatternId> for ValRaw {
     #[inline]
-    fn raw_into(self, _: &mut ScanContext) -> PatternId {
-        PatternId::from(self.get_i32())
+    fn raw_into(self, ctx: &mut ScanContext) -> PatternId {
+        let pattern_id = PatternId::from(self.get_i32());
+        match ctx.merged_module() {
+            Some(module) => module.pattern_id(pattern_id),
+            None => pattern_id,
+        }
     }
 }
 
 impl WasmArg<LiteralId> for ValRaw {
     #[inline]
-    fn raw_into(self, _: &mut ScanContext) -> LiteralId {
-        LiteralId::from(self.get_i32())
+    fn raw_into(self, ctx: &mut ScanContext) -> LiteralId {
+        let literal_id = LiteralId::from(self.get_i32());
+        match ctx.merged_module() {
+            Some(module) => module.literal_id(literal_id),
+            None => literal_id,
+        }
     }
 }
 
 impl WasmArg<RegexpId> for ValRaw {
     #[inline]
-    fn raw_into(self, _: &mut ScanContext) -> RegexpId {
-        RegexpId::from(self.get_i32())
+    fn raw_into(self, ctx: &mut ScanContext) -> RegexpId {
+        let regexp_id = RegexpId::from(self.get_i32());
+        match ctx.merged_module() {
+            Some(module) => module.regexp_id(regexp_id),
+            None => regexp_id,
+        }
     }
 }
 
@@ -638,7 +679,7 @@ macro_rules! impl_wasm_exported_fn {
             #[allow(unused_mut)]
             fn trampoline(&'static self) -> TrampolineFn {
                 Box::new(
-                    |mut caller: Caller<'_, ScanContext>,
+                    |caller: &mut Caller<'_, ScanContext>,
                      args_and_results: &mut [ValRaw]|
                      -> anyhow::Result<()> {
                         let mut i = 0;
@@ -647,7 +688,7 @@ macro_rules! impl_wasm_exported_fn {
                             i += 1;
                         )*
 
-                        let result = (self.target_fn)(&mut caller, $($args),*);
+                        let result = (self.target_fn)(caller, $($args),*);
                         let result = result.values(caller.data_mut());
 
                         let result_slice = result.as_slice();
@@ -698,6 +739,11 @@ pub(crate) struct WasmSymbols {
     /// phase.
     pub timeout_occurred: walrus::GlobalId,
 
+    /// Global variable that contains the [`RuleId`] of the rule whose
+    /// condition is being evaluated, or -1 if no rule has been evaluated
+    /// yet.
+    pub current_rule: walrus::GlobalId,
+
     /// Local variables used for temporary storage.
     pub i64_tmp_a: walrus::LocalId,
     pub i64_tmp_b: walrus::LocalId,
@@ -724,17 +770,46 @@ lazy_static! {
         config
     };
     pub(crate) static ref ENGINE: Engine = Engine::new(&CONFIG).unwrap();
-    pub(crate) static ref LINKER: Linker<ScanContext<'static>> = new_linker();
+    /// Configuration that allows only a minimal set of WebAssembly features.
+    /// Used for compiling and running the code emitted when
+    /// [`crate::Compiler::minimal_wasm_features`] is enabled.
+    pub(crate) static ref MINIMAL_CONFIG: Config = {
+        let mut config = CONFIG.clone();
+        // Reference types can't be disabled unless `wasmtime` is built with
+        // its `gc` feature, and they depend on bulk memory operations, so
+        // these two remain enabled. The code emitted for the minimal feature
+        // set doesn't use any of them, though.
+        config
+            .wasm_simd(false)
+            .wasm_relaxed_simd(false)
+            .wasm_multi_value(false)
+            .wasm_multi_memory(false)
+            .wasm_memory64(false)
+            .wasm_tail_call(false)
+            .wasm_extended_const(false);
+        config
+    };
+    pub(crate) static ref MINIMAL_ENGINE: Engine =
+        Engine::new(&MINIMAL_CONFIG).unwrap();
+    pub(crate) static ref LINKER: Linker<ScanContext<'static>> =
+        new_linker(&ENGINE);
 }
 
-pub(crate) fn new_linker<'r>() -> Linker<ScanContext<'r>> {
-    let mut linker = Linker::<ScanContext<'r>>::new(&ENGINE);
+pub(crate) fn new_linker<'r>(engine: &Engine) -> Linker<ScanContext<'r>> {
+    let mut linker = Linker::<ScanContext<'r>>::new(engine);
     for export in WASM_EXPORTS {
-        let func_type = FuncType::new(
-            &ENGINE,
-            export.func.wasmtime_args(),
-            export.func.wasmtime_results(),
-        );
+        // Functions exported by modules that are not available are not
+        // defined, see `define_missing_imports`.
+        if export
+            .module_name()
+            .is_some_and(|name| modules::available_module(name).is_none())
+        {
+            continue;
+        }
+        let args = export.func.wasmtime_args();
+        let results = export.func.wasmtime_results();
+        let func_type = FuncType::new(engine, args.clone(), results.clone());
+        let trampoline = export.func.trampoline();
         // Using `func_new_unchecked` instead of `func_new` makes function
         // calls from WASM to Rust around 3x faster.
         unsafe {
@@ -743,7 +818,50 @@ pub(crate) fn new_linker<'r>() -> Linker<ScanContext<'r>> {
                     export.rust_module_path,
                     export.fully_qualified_mangled_name().as_str(),
                     func_type,
-                    export.func.trampoline(),
+                    move |mut caller, args_and_results| {
+                        trampoline(&mut caller, args_and_results)
+                    },
+                )
+                .unwrap();
+        }
+
+        if results.len() < 2 {
+            continue;
+        }
+
+        // Functions that return multiple values have a variant that returns
+        // only the first one, and puts the remaining ones aside so that they
+        // can be retrieved with the `extra_result_*` functions. This variant
+        // is used by code that can't rely on the multi-value proposal, see
+        // `crate::Compiler::minimal_wasm_features`.
+        let num_args = args.len();
+        let num_results = results.len();
+        let func_type = FuncType::new(engine, args, [results[0].clone()]);
+        let trampoline = export.func.trampoline();
+
+        unsafe {
+            linker
+                .func_new_unchecked(
+                    export.single_result_module().as_str(),
+                    export.fully_qualified_mangled_name().as_str(),
+                    func_type,
+                    move |mut caller, args_and_results| {
+                        let mut values: SmallVec<[ValRaw; 8]> = smallvec![
+                            ValRaw::i32(0);
+                            num_args.max(num_results)
+                        ];
+                        values[..num_args]
+                            .copy_from_slice(&args_and_results[..num_args]);
+                        trampoline(&mut caller, values.as_mut_slice())?;
+                        args_and_results[0] = values[0];
+                        // The extra results are retrieved in order, so they
+                        // are pushed in reverse order.
+                        caller
+                            .data_mut()
+                            .extra_results
+                            .extend(values[1..num_results].iter().rev());
+                        Ok(())
+                    },
                 )
                 .unwrap();
         }
@@ -752,6 +870,75 @@ pub(crate) fn new_linker<'r>() -> Linker<ScanContext<'r>> {
     linker
 }
 
+/// Defines a stub in `linker` for each function imported by `module` that
+/// is not defined yet.
+///
+/// Rules can be compiled by a YARA-X build that includes some module, and
+/// used by another build where the module is not available, like one where
+/// the module's feature was disabled. The functions exported by such modules
+/// don't exist, and the WASM module can't be instantiated unless its imports
+/// are replaced by stubs. Stubs for functions that may return an undefined
+/// result always return undefined, which makes any expression that uses the
+/// result undefined. Stubs for the remaining functions return zero.
+pub(crate) fn define_missing_imports<'r>(
+    linker: &mut Linker<ScanContext<'r>>,
+    mut store: impl AsContextMut<Data = ScanContext<'r>>,
+    module: &Module,
+) {
+    for import in module.imports() {
+        let func_type = match import.ty() {
+            wasmtime::ExternType::Func(func_type) => func_type,
+            _ => continue,
+        };
+
+        if linker.get_by_import(&mut store, &import).is_some() {
+            continue;
+        }
+
+        let result_may_be_undef =
+            MangledFnName::from(import.name()).result_may_be_undef();
+
+        // The variants that return only the first result put the undefined
+        // flag aside, see `new_linker`.
+        let single_result = import.module().ends_with(SINGLE_RESULT_SUFFIX);
+
+        let results: Vec<Val> = func_type
+            .results()
+            .map(|ty| match ty {
+                ValType::I32 => Val::I32(0),
+                ValType::I64 => Val::I64(0),
+                ValType::F32 => Val::F32(0),
+                ValType::F64 => Val::F64(0),
+                ty => unreachable!("unexpected result type: {:?}", ty),
+            })
+            .collect();
+
+        linker
+            .func_new(
+                import.module(),
+                import.name(),
+                func_type,
+                move |mut caller, _, out| {
+                    out.clone_from_slice(results.as_slice());
+                    if single_result && result_may_be_undef {
+                        caller.data_mut().extra_results.push(ValRaw::i32(1));
+                        return Ok(());
+                    }
+                    // Functions that may return an undefined result
+                    // return a pair (value, is_undef), where `is_undef`
+                    // is the last result.
+                    if result_may_be_undef {
+                        if let Some(is_undef) = out.last_mut() {
+                            *is_undef = Val::I32(1);
+                        }
+                    }
+                    Ok(())
+                },
+            )
+            .unwrap();
+    }
+}
+
 /// Invoked from WASM before starting the evaluation of the rule identified
 /// by the given [`RuleId`]. This only happens when the "logging" feature is
 /// enabled.
@@ -771,13 +958,54 @@ pub(crate) fn log_rule_eval_start(
 pub(crate) fn search_for_patterns(
     caller: &mut Caller<'_, ScanContext>,
 ) -> bool {
-    match caller.data_mut().search_for_patterns() {
-        Ok(_) => true,
+    let ctx = caller.data_mut();
+    // When rules are merged, each WASM module asks for the search on its
+    // own, but it must be done only once.
+    if ctx.pattern_search_done {
+        return true;
+    }
+    match ctx.search_for_patterns() {
+        Ok(_) => {
+            ctx.pattern_search_done = true;
+            true
+        }
         Err(ScanError::Timeout) => false,
         Err(_) => unreachable!(),
     }
 }
 
+/// Invoked from WASM for obtaining the value of `entrypoint`.
+///
+/// See [`ScanContext::entrypoint`].
+#[wasm_export]
+pub(crate) fn entrypoint(caller: &mut Caller<'_, ScanContext>) -> Option<i64> {
+    caller.data_mut().entrypoint()
+}
+
+/// Invoked from WASM for obtaining a slice of the scanned data, like in
+/// `(0, 16) matches /foo/`.
+///
+/// Returns `None` if the slice is not fully contained within the scanned
+/// data.
+#[wasm_export]
+pub(crate) fn data_slice(
+    caller: &mut Caller<'_, ScanContext>,
+    offset: i64,
+    length: i64,
+) -> Option<RuntimeString> {
+    let offset: usize = offset.try_into().ok()?;
+    let length: usize = length.try_into().ok()?;
+    let data = caller.data().scanned_data();
+    let slice = data.get(offset..offset.checked_add(length)?)?;
+    // Slices of the scanned data are limited in length, larger slices
+    // must be copied.
+    if length < u16::MAX as usize {
+        Some(RuntimeString::ScannedDataSlice { offset, length })
+    } else {
+        Some(RuntimeString::new(slice))
+    }
+}
+
 /// Invoked from WASM to notify when a rule matches.
 #[wasm_export]
 pub(crate) fn rule_match(
@@ -787,6 +1015,44 @@ pub(crate) fn rule_match(
     caller.data_mut().track_rule_match(rule_id);
 }
 
+/// Invoked from WASM with the value of the condition evaluated by
+/// [`crate::Scanner::eval_condition`], when the condition is bool.
+#[wasm_export]
+pub(crate) fn eval_result_bool(
+    caller: &mut Caller<'_, ScanContext>,
+    value: bool,
+) {
+    caller.data_mut().eval_result = Some(models::Value::Bool(value));
+}
+
+/// Like [`eval_result_bool`], but for integer conditions.
+#[wasm_export]
+pub(crate) fn eval_result_integer(
+    caller: &mut Caller<'_, ScanContext>,
+    value: i64,
+) {
+    caller.data_mut().eval_result = Some(models::Value::Integer(value));
+}
+
+/// Like [`eval_result_bool`], but for float conditions.
+#[wasm_export]
+pub(crate) fn eval_result_float(
+    caller: &mut Caller<'_, ScanContext>,
+    value: f64,
+) {
+    caller.data_mut().eval_result = Some(models::Value::Float(value));
+}
+
+/// Like [`eval_result_bool`], but for string conditions.
+#[wasm_export]
+pub(crate) fn eval_result_string(
+    caller: &mut Caller<'_, ScanContext>,
+    value: RuntimeString,
+) {
+    let value = value.as_bstr(caller.data()).to_owned();
+    caller.data_mut().eval_result = Some(models::Value::String(value));
+}
+
 /// Invoked from WASM to notify when a global rule doesn't match.
 #[wasm_export]
 pub(crate) fn global_rule_no_match(
@@ -796,6 +1062,32 @@ pub(crate) fn global_rule_no_match(
     caller.data_mut().track_global_rule_no_match(rule_id);
 }
 
+/// Invoked from WASM for retrieving the next result of the last function
+/// called through its single-result variant, when that result is an `i32`.
+/// See [`new_linker`].
+#[wasm_export]
+pub(crate) fn extra_result_i32(caller: &mut Caller<'_, ScanContext>) -> i32 {
+    caller.data_mut().extra_results.pop().unwrap().get_i32()
+}
+
+/// Like [`extra_result_i32`], but for results of type `i64`.
+#[wasm_export]
+pub(crate) fn extra_result_i64(caller: &mut Caller<'_, ScanContext>) -> i64 {
+    caller.data_mut().extra_results.pop().unwrap().get_i64()
+}
+
+/// Like [`extra_result_i32`], but for results of type `f32`.
+#[wasm_export]
+pub(crate) fn extra_result_f32(caller: &mut Caller<'_, ScanContext>) -> f32 {
+    f32::from_bits(caller.data_mut().extra_results.pop().unwrap().get_f32())
+}
+
+/// Like [`extra_result_i32`], but for results of type `f64`.
+#[wasm_export]
+pub(crate) fn extra_result_f64(caller: &mut Caller<'_, ScanContext>) -> f64 {
+    f64::from_bits(caller.data_mut().extra_results.pop().unwrap().get_f64())
+}
+
 /// Invoked from WASM to ask whether a pattern matches at a given file
 /// offset.
 ///
@@ -831,9 +1123,7 @@ pub(crate) fn is_pat_match_in(
     upper_bound: i64,
 ) -> bool {
     if let Some(matches) = caller.data().pattern_matches.get(pattern_id) {
-        matches
-            .matches_in_range(lower_bound as isize..=upper_bound as isize)
-            .is_positive()
+        matches.is_match_in_range(lower_bound as isize..=upper_bound as isize)
     } else {
         false
     }
@@ -966,7 +1256,7 @@ fn lookup_field(
     let mut store_ctx = caller.as_context_mut();
 
     let mem_ptr =
-        store_ctx.data_mut().main_memory.unwrap().data_ptr(&mut store_ctx);
+        store_ctx.data_mut().current_memory().data_ptr(&mut store_ctx);
 
     let lookup_indexes_ptr =
         unsafe { mem_ptr.offset(LOOKUP_INDEXES_START as isize) };
@@ -978,6 +1268,13 @@ fn lookup_field(
         )
     };
 
+    // Indexes in the root structure must be translated when the code
+    // belongs to a module merged with `Rules::merge`.
+    let merged_module = match structure {
+        Some(_) => None,
+        None => store_ctx.data().merged_module(),
+    };
+
     // If the passed structure is None, it means that we should start the
     // at the root structure.
     let mut structure =
@@ -985,7 +1282,7 @@ fn lookup_field(
 
     let mut final_field = None;
 
-    for field_index in lookup_indexes {
+    for (i, field_index) in lookup_indexes.iter().enumerate() {
         // Integers in WASM memory are always stored as little-endian
         // regardless of the endianness of the host platform. If we
         // are in a big-endian platform the integers needs to be swapped
@@ -996,6 +1293,11 @@ fn lookup_field(
             *field_index
         };
 
+        let field_index = match merged_module {
+            Some(module) if i == 0 => module.root_field(field_index),
+            _ => field_index,
+        };
+
         let field = structure
             .field_by_index(field_index as usize)
             .unwrap_or_else(|| {
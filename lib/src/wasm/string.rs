@@ -139,6 +139,15 @@ impl RuntimeString {
         ctx: &mut ScanContext,
     ) -> RuntimeStringWasm {
         match self {
+            // Literal IDs received from WASM code in merged modules are
+            // translated, so the module can't receive them back. The
+            // literal is passed as a copy instead.
+            Self::Literal(id) if ctx.current_module.is_some() => {
+                let s = ctx.compiled_rules.lit_pool().get(id).unwrap();
+                let handle: i64 =
+                    ctx.store_string(Rc::new(BString::from(s))).into();
+                handle << 2 | 1
+            }
             Self::Literal(id) => i64::from(id) << 2,
             Self::Rc(s) => {
                 let handle: i64 = ctx.store_string(s).into();
@@ -170,7 +179,15 @@ impl RuntimeString {
         s: RuntimeStringWasm,
     ) -> Self {
         match s & 0x3 {
-            0 => Self::Literal(LiteralId::from((s >> 2) as u32)),
+            0 => {
+                let literal_id = LiteralId::from((s >> 2) as u32);
+                match ctx.merged_module() {
+                    Some(module) => {
+                        Self::Literal(module.literal_id(literal_id))
+                    }
+                    None => Self::Literal(literal_id),
+                }
+            }
             1 => {
                 let handle = RuntimeObjectHandle::from(s >> 2);
                 let s = cast!(
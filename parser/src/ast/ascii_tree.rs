@@ -190,6 +190,18 @@ pub(crate) fn expr_ascii_tree(expr: &Expr) -> Tree {
             "matches".to_string(),
             vec![expr_ascii_tree(&expr.lhs), expr_ascii_tree(&expr.rhs)],
         ),
+        Expr::BufferStartsWith(b) => Node(
+            "buffer startswith".to_string(),
+            vec![hex_tokens_ascii_tree(&b.tokens)],
+        ),
+        Expr::BufferEndsWith(b) => Node(
+            "buffer endswith".to_string(),
+            vec![hex_tokens_ascii_tree(&b.tokens)],
+        ),
+        Expr::DataSlice(s) => Node(
+            "data_slice".to_string(),
+            vec![expr_ascii_tree(&s.offset), expr_ascii_tree(&s.length)],
+        ),
         Expr::PatternMatch(s) => {
             if let Some(anchor) = &s.anchor {
                 match anchor {
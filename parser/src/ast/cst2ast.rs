@@ -446,13 +446,13 @@ impl<'src> Builder<'src> {
     fn rule_decl(&mut self) -> Result<Rule<'src>, BuilderError> {
         self.begin(RULE_DECL)?;
 
-        let flags = if let Event::Begin(RULE_MODS) = self.peek() {
+        let (flags, mods_span) = if let Event::Begin(RULE_MODS) = self.peek() {
             self.rule_mods()?
         } else {
-            RuleFlags::none()
+            (RuleFlags::none(), None)
         };
 
-        self.expect(RULE_KW)?;
+        let rule_kw_span = self.expect(RULE_KW)?;
 
         let identifier = self.identifier()?;
 
@@ -483,31 +483,44 @@ impl<'src> Builder<'src> {
         let condition = self.boolean_expr()?;
 
         self.end(CONDITION_BLK)?;
-        self.expect(R_BRACE)?;
+        let r_brace_span = self.expect(R_BRACE)?;
         self.end(RULE_DECL)?;
 
-        Ok(Rule { flags, identifier, tags, meta, patterns, condition })
+        let span = mods_span.unwrap_or(rule_kw_span).combine(&r_brace_span);
+
+        Ok(Rule { span, flags, identifier, tags, meta, patterns, condition })
     }
 
-    fn rule_mods(&mut self) -> Result<RuleFlags, BuilderError> {
+    /// Returns the flags set by the rule modifiers, together with the span
+    /// that covers all the modifiers.
+    fn rule_mods(
+        &mut self,
+    ) -> Result<(RuleFlags, Option<Span>), BuilderError> {
         self.begin(RULE_MODS)?;
 
         let mut flags = RuleFlags::none();
+        let mut span: Option<Span> = None;
 
         loop {
-            match self.next()? {
-                Event::Token { kind: GLOBAL_KW, .. } => {
-                    flags.set(RuleFlag::Global)
+            let token_span = match self.next()? {
+                Event::Token { kind: GLOBAL_KW, span } => {
+                    flags.set(RuleFlag::Global);
+                    span
                 }
-                Event::Token { kind: PRIVATE_KW, .. } => {
-                    flags.set(RuleFlag::Private)
+                Event::Token { kind: PRIVATE_KW, span } => {
+                    flags.set(RuleFlag::Private);
+                    span
                 }
                 Event::End(RULE_MODS) => break,
                 event => panic!("unexpected {:?}", event),
-            }
+            };
+            span = Some(match span {
+                Some(span) => span.combine(&token_span),
+                None => token_span,
+            });
         }
 
-        Ok(flags)
+        Ok((flags, span))
     }
 
     fn rule_tags(&mut self) -> Result<Vec<Ident<'src>>, BuilderError> {
@@ -603,6 +616,9 @@ impl<'src> Builder<'src> {
                 self.expect(FALSE_KW)?;
                 MetaValue::Bool(false)
             }
+            Event::Token { kind: IDENT, .. } => {
+                MetaValue::Ident(self.identifier()?)
+            }
             event => panic!("unexpected {:?}", event),
         };
 
@@ -638,10 +654,20 @@ impl<'src> Builder<'src> {
                 }))
             }
             Event::Begin(HEX_PATTERN) => {
-                let tokens = self.hex_pattern()?;
+                let (tokens, braces_span) = self.hex_pattern()?;
                 let modifiers = self.pattern_mods_opt()?;
 
+                // The span goes from the pattern identifier to the closing
+                // brace, or to the last modifier, if any. The span of the
+                // tokens can't be used because it doesn't include the braces.
+                let span = if modifiers.is_empty() {
+                    identifier.span().combine(&braces_span)
+                } else {
+                    identifier.span().combine(&modifiers.span())
+                };
+
                 Pattern::Hex(Box::new(HexPattern {
+                    span,
                     identifier,
                     tokens,
                     modifiers,
@@ -690,6 +716,9 @@ impl<'src> Builder<'src> {
                 Event::Token { kind: NOCASE_KW, span } => {
                     modifiers.push(PatternModifier::Nocase { span });
                 }
+                Event::Token { kind: CASE_KW, span } => {
+                    modifiers.push(PatternModifier::Case { span });
+                }
                 Event::Token { kind: XOR_KW, mut span } => {
                     let mut start = 0;
                     let mut end = 255;
@@ -753,16 +782,18 @@ impl<'src> Builder<'src> {
         Ok(PatternModifiers::new(modifiers))
     }
 
-    fn hex_pattern(&mut self) -> Result<HexTokens, BuilderError> {
+    /// Returns the tokens in the hex pattern, and the span that goes from
+    /// the opening brace to the closing one.
+    fn hex_pattern(&mut self) -> Result<(HexTokens, Span), BuilderError> {
         self.begin(HEX_PATTERN)?;
-        self.expect(L_BRACE)?;
+        let l_brace_span = self.expect(L_BRACE)?;
 
         let sub_pattern = self.hex_sub_pattern()?;
 
-        self.expect(R_BRACE)?;
+        let r_brace_span = self.expect(R_BRACE)?;
         self.end(HEX_PATTERN)?;
 
-        Ok(sub_pattern)
+        Ok((sub_pattern, l_brace_span.combine(&r_brace_span)))
     }
 
     fn hex_sub_pattern(&mut self) -> Result<HexTokens, BuilderError> {
@@ -935,6 +966,7 @@ impl<'src> Builder<'src> {
                     anchor: self.anchor()?,
                 }))
             }
+            Event::Token { kind: IDENT, .. } => self.buffer_check()?,
             Event::Begin(FOR_EXPR) => self.for_expr()?,
             Event::Begin(OF_EXPR) => self.of_expr()?,
             Event::Begin(WITH_EXPR) => self.with_expr()?,
@@ -947,6 +979,52 @@ impl<'src> Builder<'src> {
         Ok(expr)
     }
 
+    /// Builds the AST for `buffer startswith { ... }` and
+    /// `buffer endswith { ... }` expressions.
+    fn buffer_check(&mut self) -> Result<Expr<'src>, BuilderError> {
+        let ident = self.identifier()?;
+
+        if ident.name != "buffer" {
+            self.errors.push(Error::SyntaxError {
+                message: format!("expecting `buffer`, found `{}`", ident.name),
+                span: ident.span(),
+            });
+            return Err(BuilderError::Abort);
+        }
+
+        let starts_with = match self.next()? {
+            Event::Token { kind: STARTSWITH_KW, .. } => true,
+            Event::Token { kind: ENDSWITH_KW, .. } => false,
+            event => panic!("unexpected {:?}", event),
+        };
+
+        let (tokens, hex_span) = self.hex_pattern()?;
+
+        // The pattern must have a fixed length, so jumps and alternatives
+        // are not accepted.
+        if tokens.tokens.iter().any(|token| {
+            matches!(token, HexToken::Jump(_) | HexToken::Alternative(_))
+        }) {
+            self.errors.push(Error::SyntaxError {
+                message: "jumps and alternatives are not allowed here"
+                    .to_string(),
+                span: hex_span,
+            });
+            return Err(BuilderError::Abort);
+        }
+
+        let check = Box::new(BufferCheck {
+            span: ident.span().combine(&hex_span),
+            tokens,
+        });
+
+        Ok(if starts_with {
+            Expr::BufferStartsWith(check)
+        } else {
+            Expr::BufferEndsWith(check)
+        })
+    }
+
     fn for_expr(&mut self) -> Result<Expr<'src>, BuilderError> {
         self.begin(FOR_EXPR)?;
 
@@ -1377,10 +1455,21 @@ impl<'src> Builder<'src> {
                 }))
             }
             Event::Token { kind: L_PAREN, .. } => {
-                self.expect(L_PAREN)?;
+                let l_paren_span = self.expect(L_PAREN)?;
                 let expr = self.expr()?;
-                self.expect(R_PAREN)?;
-                expr
+                if let Event::Token { kind: COMMA, .. } = self.peek() {
+                    self.expect(COMMA)?;
+                    let length = self.expr()?;
+                    let r_paren_span = self.expect(R_PAREN)?;
+                    Expr::DataSlice(Box::new(DataSlice {
+                        span: l_paren_span.combine(&r_paren_span),
+                        offset: expr,
+                        length,
+                    }))
+                } else {
+                    self.expect(R_PAREN)?;
+                    expr
+                }
             }
             Event::Token { kind: IDENT, .. } => {
                 let mut idents =
@@ -1467,6 +1556,11 @@ impl<'src> Builder<'src> {
             multiplier = 1024 * 1024;
         }
 
+        if let Some(without_suffix) = literal.strip_suffix("GB") {
+            literal = without_suffix;
+            multiplier = 1024 * 1024 * 1024;
+        }
+
         let value = if literal.starts_with("0x") {
             T::from_str_radix(literal.strip_prefix("0x").unwrap(), 16)
         } else if literal.starts_with("0o") {
@@ -99,6 +99,7 @@ pub struct Import<'src> {
 /// A YARA rule.
 #[derive(Debug)]
 pub struct Rule<'src> {
+    span: Span,
     pub flags: RuleFlags,
     pub identifier: Ident<'src>,
     pub tags: Option<Vec<Ident<'src>>>,
@@ -133,6 +134,9 @@ pub enum MetaValue<'src> {
     Float(f64),
     String(&'src str),
     Bytes(BString),
+    /// An identifier that refers to a global variable defined at compile
+    /// time, the actual value is resolved by the compiler.
+    Ident(Ident<'src>),
 }
 
 impl<'src> Display for MetaValue<'src> {
@@ -143,6 +147,7 @@ impl<'src> Display for MetaValue<'src> {
             Self::Float(v) => write!(f, "{:.1}", v),
             Self::String(v) => write!(f, "\"{}\"", v),
             Self::Bytes(v) => write!(f, "\"{}\"", v),
+            Self::Ident(v) => write!(f, "{}", v.name),
         }
     }
 }
@@ -190,6 +195,27 @@ pub struct IdentWithIndex<'src> {
     pub index: Option<Expr<'src>>,
 }
 
+/// A slice of the scanned data, (e.g. `(0, 16)`).
+///
+/// The slice starts at `offset` and has `length` bytes.
+#[derive(Debug)]
+pub struct DataSlice<'src> {
+    span: Span,
+    pub offset: Expr<'src>,
+    pub length: Expr<'src>,
+}
+
+/// A hex pattern that the scanned data must start or end with (e.g.
+/// `buffer startswith { 4D 5A }`).
+///
+/// The hex pattern can contain bytes, masked bytes and negated bytes, but
+/// not jumps nor alternatives, as its length must be fixed.
+#[derive(Debug)]
+pub struct BufferCheck {
+    span: Span,
+    pub tokens: HexTokens,
+}
+
 /// Types of patterns (a.k.a. strings) that can appear in a YARA rule.
 ///
 /// Possible types are: text patterns, hex patterns and regular expressions.
@@ -237,6 +263,7 @@ pub struct RegexpPattern<'src> {
 /// A hex pattern (a.k.a. hex string) in a YARA rule.
 #[derive(Debug, Default)]
 pub struct HexPattern<'src> {
+    span: Span,
     pub identifier: Ident<'src>,
     pub tokens: HexTokens,
     pub modifiers: PatternModifiers<'src>,
@@ -485,6 +512,18 @@ pub enum Expr<'src> {
     /// Pattern length expression (e.g. `!`, `!a`, `!a[1]`)
     PatternLength(Box<IdentWithIndex<'src>>),
 
+    /// A slice of the scanned data, given by offset and length (e.g.
+    /// `(0, 16)`)
+    DataSlice(Box<DataSlice<'src>>),
+
+    /// A check for the bytes at the start of the scanned data (e.g.
+    /// `buffer startswith { 4D 5A }`)
+    BufferStartsWith(Box<BufferCheck>),
+
+    /// A check for the bytes at the end of the scanned data (e.g.
+    /// `buffer endswith { 00 00 }`)
+    BufferEndsWith(Box<BufferCheck>),
+
     /// Array or dictionary lookup expression (e.g. `array[1]`, `dict["key"]`)
     Lookup(Box<Lookup<'src>>),
 
@@ -662,6 +701,13 @@ impl<'src> PatternModifiers<'src> {
             .find(|m| matches!(m, PatternModifier::Nocase { .. }))
     }
 
+    #[inline]
+    pub fn case(&self) -> Option<&PatternModifier<'src>> {
+        self.modifiers
+            .iter()
+            .find(|m| matches!(m, PatternModifier::Case { .. }))
+    }
+
     #[inline]
     pub fn xor(&self) -> Option<&PatternModifier<'src>> {
         self.modifiers
@@ -691,6 +737,7 @@ pub enum PatternModifier<'src> {
     Ascii { span: Span },
     Wide { span: Span },
     Nocase { span: Span },
+    Case { span: Span },
     Private { span: Span },
     Fullword { span: Span },
     Base64 { span: Span, alphabet: Option<LiteralString<'src>> },
@@ -704,6 +751,7 @@ impl PatternModifier<'_> {
             PatternModifier::Ascii { .. } => "ascii",
             PatternModifier::Wide { .. } => "wide",
             PatternModifier::Nocase { .. } => "nocase",
+            PatternModifier::Case { .. } => "case",
             PatternModifier::Private { .. } => "private",
             PatternModifier::Fullword { .. } => "fullword",
             PatternModifier::Base64 { .. } => "base64",
@@ -725,6 +773,9 @@ impl Display for PatternModifier<'_> {
             PatternModifier::Nocase { .. } => {
                 write!(f, "nocase")
             }
+            PatternModifier::Case { .. } => {
+                write!(f, "case")
+            }
             PatternModifier::Private { .. } => {
                 write!(f, "private")
             }
@@ -948,6 +999,157 @@ impl<'src> From<Vec<Expr<'src>>> for NAryExpr<'src> {
     }
 }
 
+impl<'src> Expr<'src> {
+    /// Returns the number of nodes in the expression's tree, including the
+    /// expression itself.
+    ///
+    /// Pattern sets, quantifiers and anchors are not counted as nodes, but
+    /// the expressions contained in them are.
+    pub fn num_nodes(&self) -> usize {
+        1 + self
+            .children()
+            .into_iter()
+            .map(|child| child.num_nodes())
+            .sum::<usize>()
+    }
+
+    /// Returns the expressions that are direct children of this one.
+    ///
+    /// This includes the expressions in quantifiers, anchors, ranges and
+    /// pattern indexes, like `10` in `10 of them` or `0` in `$a at 0`.
+    pub fn children(&self) -> Vec<&Expr<'src>> {
+        let mut children = Vec::new();
+        match self {
+            Expr::True { .. }
+            | Expr::False { .. }
+            | Expr::Filesize { .. }
+            | Expr::Entrypoint { .. }
+            | Expr::LiteralString(_)
+            | Expr::LiteralInteger(_)
+            | Expr::LiteralFloat(_)
+            | Expr::Regexp(_)
+            | Expr::Ident(_)
+            | Expr::BufferStartsWith(_)
+            | Expr::BufferEndsWith(_) => {}
+
+            Expr::PatternMatch(p) => {
+                Self::anchor_children(&p.anchor, &mut children)
+            }
+            Expr::PatternCount(p) => {
+                Self::range_children(&p.range, &mut children)
+            }
+            Expr::PatternOffset(p) | Expr::PatternLength(p) => {
+                children.extend(p.index.as_ref())
+            }
+            Expr::DataSlice(s) => children.extend([&s.offset, &s.length]),
+            Expr::Lookup(l) => children.extend([&l.primary, &l.index]),
+            Expr::FuncCall(f) => {
+                children.push(&f.callable);
+                children.extend(f.args.iter());
+            }
+
+            Expr::Defined(expr)
+            | Expr::Not(expr)
+            | Expr::Minus(expr)
+            | Expr::BitwiseNot(expr) => children.push(&expr.operand),
+
+            Expr::Shl(expr)
+            | Expr::Shr(expr)
+            | Expr::BitwiseAnd(expr)
+            | Expr::BitwiseOr(expr)
+            | Expr::BitwiseXor(expr)
+            | Expr::Eq(expr)
+            | Expr::Ne(expr)
+            | Expr::Lt(expr)
+            | Expr::Gt(expr)
+            | Expr::Le(expr)
+            | Expr::Ge(expr)
+            | Expr::Contains(expr)
+            | Expr::IContains(expr)
+            | Expr::StartsWith(expr)
+            | Expr::IStartsWith(expr)
+            | Expr::EndsWith(expr)
+            | Expr::IEndsWith(expr)
+            | Expr::IEquals(expr)
+            | Expr::Matches(expr) => children.extend([&expr.lhs, &expr.rhs]),
+
+            Expr::And(expr)
+            | Expr::Or(expr)
+            | Expr::Add(expr)
+            | Expr::Sub(expr)
+            | Expr::Mul(expr)
+            | Expr::Div(expr)
+            | Expr::Mod(expr)
+            | Expr::FieldAccess(expr) => children.extend(expr.operands()),
+
+            Expr::Of(of) => {
+                Self::quantifier_children(&of.quantifier, &mut children);
+                Self::anchor_children(&of.anchor, &mut children);
+                if let OfItems::BoolExprTuple(exprs) = &of.items {
+                    children.extend(exprs.iter());
+                }
+            }
+            Expr::ForOf(f) => {
+                Self::quantifier_children(&f.quantifier, &mut children);
+                children.push(&f.condition);
+            }
+            Expr::ForIn(f) => {
+                Self::quantifier_children(&f.quantifier, &mut children);
+                match &f.iterable {
+                    Iterable::Range(range) => children
+                        .extend([&range.lower_bound, &range.upper_bound]),
+                    Iterable::ExprTuple(exprs) => {
+                        children.extend(exprs.iter())
+                    }
+                    Iterable::Expr(expr) => children.push(expr),
+                }
+                children.push(&f.condition);
+            }
+            Expr::With(w) => {
+                children.extend(w.declarations.iter().map(|d| &d.expression));
+                children.push(&w.condition);
+            }
+        };
+        children
+    }
+
+    fn quantifier_children<'a>(
+        quantifier: &'a Quantifier<'src>,
+        children: &mut Vec<&'a Expr<'src>>,
+    ) {
+        match quantifier {
+            Quantifier::None { .. }
+            | Quantifier::All { .. }
+            | Quantifier::Any { .. } => {}
+            Quantifier::Percentage(expr) | Quantifier::Expr(expr) => {
+                children.push(expr)
+            }
+        }
+    }
+
+    fn range_children<'a>(
+        range: &'a Option<Range<'src>>,
+        children: &mut Vec<&'a Expr<'src>>,
+    ) {
+        if let Some(range) = range {
+            children.extend([&range.lower_bound, &range.upper_bound]);
+        }
+    }
+
+    fn anchor_children<'a>(
+        anchor: &'a Option<MatchAnchor<'src>>,
+        children: &mut Vec<&'a Expr<'src>>,
+    ) {
+        match anchor {
+            None => {}
+            Some(MatchAnchor::At(at)) => children.push(&at.expr),
+            Some(MatchAnchor::In(i)) => {
+                children.extend([&i.range.lower_bound, &i.range.upper_bound])
+            }
+        }
+    }
+}
+
 /// Trait implemented by every node in the AST that has an associated span.
 ///
 /// [`WithSpan::span`] returns a [`Span`] that indicates the starting and ending
@@ -1033,6 +1235,18 @@ impl WithSpan for IdentWithIndex<'_> {
     }
 }
 
+impl WithSpan for DataSlice<'_> {
+    fn span(&self) -> Span {
+        self.span.clone()
+    }
+}
+
+impl WithSpan for BufferCheck {
+    fn span(&self) -> Span {
+        self.span.clone()
+    }
+}
+
 impl WithSpan for IdentWithRange<'_> {
     fn span(&self) -> Span {
         self.span.clone()
@@ -1088,6 +1302,12 @@ impl WithSpan for Iterable<'_> {
     }
 }
 
+impl WithSpan for Rule<'_> {
+    fn span(&self) -> Span {
+        self.span.clone()
+    }
+}
+
 impl WithSpan for Import<'_> {
     fn span(&self) -> Span {
         self.span.clone()
@@ -1122,11 +1342,7 @@ impl WithSpan for TextPattern<'_> {
 
 impl WithSpan for HexPattern<'_> {
     fn span(&self) -> Span {
-        if self.modifiers.is_empty() {
-            self.identifier.span().combine(&self.tokens.span())
-        } else {
-            self.identifier.span().combine(&self.modifiers.span())
-        }
+        self.span.clone()
     }
 }
 
@@ -1172,6 +1388,7 @@ impl WithSpan for PatternModifier<'_> {
             PatternModifier::Ascii { span }
             | PatternModifier::Wide { span }
             | PatternModifier::Nocase { span }
+            | PatternModifier::Case { span }
             | PatternModifier::Private { span }
             | PatternModifier::Fullword { span }
             | PatternModifier::Base64 { span, .. }
@@ -1318,6 +1535,8 @@ impl WithSpan for Expr<'_> {
             Expr::PatternCount(p) => p.span(),
             Expr::PatternLength(p) => p.span(),
             Expr::PatternOffset(p) => p.span(),
+            Expr::DataSlice(s) => s.span(),
+            Expr::BufferStartsWith(b) | Expr::BufferEndsWith(b) => b.span(),
             Expr::ForOf(f) => f.span(),
             Expr::ForIn(f) => f.span(),
             Expr::Of(o) => o.span(),
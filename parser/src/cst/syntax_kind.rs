@@ -12,6 +12,7 @@ pub enum SyntaxKind {
     AT_KW,
     BASE64_KW,
     BASE64WIDE_KW,
+    CASE_KW,
     CONDITION_KW,
     CONTAINS_KW,
     DEFINED_KW,
@@ -183,6 +184,7 @@ impl SyntaxKind {
             SyntaxKind::AT_KW => TokenId::AT_KW,
             SyntaxKind::BASE64_KW => TokenId::BASE64_KW,
             SyntaxKind::BASE64WIDE_KW => TokenId::BASE64WIDE_KW,
+            SyntaxKind::CASE_KW => TokenId::CASE_KW,
             SyntaxKind::CONDITION_KW => TokenId::CONDITION_KW,
             SyntaxKind::CONTAINS_KW => TokenId::CONTAINS_KW,
             SyntaxKind::DEFINED_KW => TokenId::DEFINED_KW,
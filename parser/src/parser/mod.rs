@@ -548,6 +548,20 @@ impl<'src> ParserImpl<'src> {
             ),
         };
 
+        // Contextual keywords are identifiers for the tokenizer, so they
+        // must be recognized here.
+        let token_match = token_match.or_else(|| {
+            if token_id != Some(TokenId::IDENT) {
+                return None;
+            }
+            let ident = self.tokens.source().get(token_span.range())?;
+            expected_tokens.0.iter().find(|kind| {
+                CONTEXTUAL_KEYWORDS
+                    .iter()
+                    .any(|(kw_kind, kw)| kw_kind == *kind && *kw == ident)
+            })
+        });
+
         match (self.not_depth, token_match) {
             // The expected token was found, but we are inside a "not".
             // When we are inside a "not", any "expect" is negated, and
@@ -1147,7 +1161,8 @@ impl<'src> ParserImpl<'src> {
     ///     `false`     |
     ///     INTEGER_LIT |
     ///     FLOAT_LIT   |
-    ///     STRING_LIT
+    ///     STRING_LIT  |
+    ///     IDENT
     /// )
     /// ``
     fn meta_def(&mut self) -> &mut Self {
@@ -1158,7 +1173,7 @@ impl<'src> ParserImpl<'src> {
             .alt(|p| {
                 p.opt_expect(t!(MINUS)).expect(t!(INTEGER_LIT | FLOAT_LIT))
             })
-            .alt(|p| p.expect(t!(STRING_LIT | TRUE_KW | FALSE_KW)))
+            .alt(|p| p.expect(t!(STRING_LIT | TRUE_KW | FALSE_KW | IDENT)))
             .end_alt()
             .end()
     }
@@ -1214,6 +1229,7 @@ impl<'src> ParserImpl<'src> {
     ///   `ascii`                                                  |
     ///   `wide`                                                   |
     ///   `nocase`                                                 |
+    ///   `case`                                                   |
     ///   `private`                                                |
     ///   `fullword`                                               |
     ///   `base64` | `base64wide` ( `(` STRING_LIT `)` )?          |
@@ -1234,6 +1250,7 @@ impl<'src> ParserImpl<'src> {
                     t!(ASCII_KW
                         | WIDE_KW
                         | NOCASE_KW
+                        | CASE_KW
                         | PRIVATE_KW
                         | FULLWORD_KW),
                     DESC,
@@ -1363,6 +1380,7 @@ impl<'src> ParserImpl<'src> {
     ///    `false`                |
     ///    `not` BOOLEAN_TERM     |
     ///    `defined` BOOLEAN_TERM |
+    ///    IDENT (`startswith` | `endswith`) HEX_PATTERN |
     ///    `(` BOOLEAN_EXPR `)`
     /// )
     /// ``
@@ -1390,6 +1408,17 @@ impl<'src> ParserImpl<'src> {
             .alt(|p| p.for_expr())
             .alt(|p| p.of_expr())
             .alt(|p| p.with_expr())
+            .alt(|p| {
+                // `buffer startswith { ... }` and `buffer endswith { ... }`.
+                // The identifier is verified to be `buffer` while building
+                // the AST.
+                p.expect_d(t!(IDENT), DESC)
+                    .expect_d(
+                        t!(STARTSWITH_KW | ENDSWITH_KW),
+                        Some("operator"),
+                    )
+                    .then(|p| p.hex_pattern())
+            })
             .alt(|p| {
                 p.expr().zero_or_more(|p| {
                     p.expect_d(
@@ -1518,7 +1547,7 @@ impl<'src> ParserImpl<'src> {
     ///     PATTERN_LENGTH (`[` EXPR `]`)?     |
     ///     `-` TERM                           |
     ///     `~` TERM                           |
-    ///     `(` EXPR `)`                       |
+    ///     `(` EXPR (`,` EXPR)? `)`           |
     ///     IDENT (`.` IDENT)*
     /// )
     /// ``
@@ -1554,6 +1583,7 @@ impl<'src> ParserImpl<'src> {
                 .alt(|p| {
                     p.expect_d(t!(L_PAREN), DESC)
                         .then(|p| p.expr())
+                        .opt(|p| p.expect(t!(COMMA)).then(|p| p.expr()))
                         .expect(t!(R_PAREN))
                 })
                 .alt(|p| {
@@ -1772,6 +1802,11 @@ struct Bookmark {
     output: syntax_stream::Bookmark,
 }
 
+/// Keywords that are recognized only where the grammar expects them, like
+/// `case` after a pattern. Anywhere else they are identifiers, so they can be
+/// used as rule names, tags, etc.
+const CONTEXTUAL_KEYWORDS: &[(SyntaxKind, &[u8])] = &[(CASE_KW, b"case")];
+
 /// A set of tokens passed to the [`ParserImpl::expect`]
 /// function.
 ///
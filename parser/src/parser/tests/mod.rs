@@ -1,4 +1,4 @@
-use crate::ast::{Error, AST};
+use crate::ast::{Error, WithSpan, AST};
 use rayon::prelude::*;
 use std::fs;
 use std::io::BufWriter;
@@ -111,3 +111,38 @@ rule test_2 { condition: true }";
     assert_eq!(&ast.errors()[0], &Error::InvalidUTF8(Span(33..34)));
     assert_eq!(ast.rules().len(), 1);
 }
+
+#[test]
+fn multi_line_hex_pattern_span() {
+    let rules = "rule test {
+  strings:
+    $a = { 4D 5A
+           [2-4] ( 01 | 02 )
+           90 }
+  condition:
+    $a
+}";
+
+    let ast = AST::from(Parser::new(rules.as_bytes()));
+    let pattern = ast.rules()[0].patterns.as_ref().unwrap();
+
+    let start = rules.find("$a").unwrap();
+    let end = rules.find("90 }").unwrap() + "90 }".len();
+
+    // The span covers the whole pattern, from the identifier to the closing
+    // brace, including the newlines in between.
+    assert_eq!(pattern[0].span(), Span(start as u32..end as u32));
+
+    let crate::ast::Pattern::Hex(hex) = &pattern[0] else {
+        panic!("expecting hex pattern")
+    };
+
+    // The span of the tokens goes from the first byte to the last one.
+    let tokens_start = rules.find("4D").unwrap();
+    let tokens_end = rules.find("90 }").unwrap() + "90".len();
+
+    assert_eq!(
+        hex.tokens.span(),
+        Span(tokens_start as u32..tokens_end as u32)
+    );
+}
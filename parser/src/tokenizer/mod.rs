@@ -471,7 +471,7 @@ enum NormalToken<'src> {
            (
              0x[a-fA-F0-9]+ |           # hexadecimal number
              0o[0-7]+       |           # octal number
-             [0-9]+(KB|MB)?             # decimal number followed by optional KB or MB
+             [0-9]+(KB|MB|GB)?          # decimal number followed by optional KB, MB or GB
            )
         "#,
         |token| token.slice())
@@ -13,6 +13,7 @@ pub(crate) enum TokenId {
     AT_KW,
     BASE64_KW,
     BASE64WIDE_KW,
+    CASE_KW,
     CONDITION_KW,
     CONTAINS_KW,
     DEFINED_KW,
@@ -122,6 +123,7 @@ impl TokenId {
             TokenId::AT_KW => "`at`",
             TokenId::BASE64_KW => "`base64`",
             TokenId::BASE64WIDE_KW => "`base64wide`",
+            TokenId::CASE_KW => "`case`",
             TokenId::CONDITION_KW => "`condition`",
             TokenId::CONTAINS_KW => "`contains`",
             TokenId::DEFINED_KW => "`defined`",